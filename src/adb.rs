@@ -0,0 +1,559 @@
+// ============================================================================
+// ADB client
+// The ADB wire protocol spoken straight to adbd, over USB (usbfs) or TCP, so
+// pushing media needs neither platform-tools nor a running adb server. Every
+// message is a 24-byte header plus payload; files go through the sync
+// service (SEND, RECV, STAT, LIST), commands through shell.
+// ============================================================================
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, Ordering}, Mutex, MutexGuard},
+    time::{Duration, UNIX_EPOCH},
+};
+use serde::{Deserialize, Serialize};
+use anyhow::{Context, Result};
+
+use crate::adb_auth::AdbKey;
+use crate::adb_usb::{UsbAdbDevice, UsbLink};
+
+const A_CNXN: u32 = 0x4e58_4e43;
+const A_AUTH: u32 = 0x4854_5541;
+const A_OPEN: u32 = 0x4e45_504f;
+const A_OKAY: u32 = 0x5941_4b4f;
+const A_CLSE: u32 = 0x4553_4c43;
+const A_WRTE: u32 = 0x4554_5257;
+const A_STLS: u32 = 0x534c_5453;
+
+/// Protocol version that drops payload checksums (we still send them for older adbd)
+const VERSION: u32 = 0x0100_0001;
+const MAX_PAYLOAD: usize = 256 * 1024;
+const AUTH_TOKEN: u32 = 1;
+const AUTH_SIGNATURE: u32 = 2;
+const AUTH_RSAPUBLICKEY: u32 = 3;
+
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time to tap "Allow" on the device's USB debugging prompt
+const AUTH_PROMPT_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often a streaming command checks whether it was cancelled
+const CANCEL_POLL: Duration = Duration::from_millis(250);
+/// Largest DATA chunk the sync service takes
+const SYNC_DATA_MAX: usize = 64 * 1024;
+const DEFAULT_TCP_PORT: u16 = 5555;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdbConfig {
+    /// `host[:port]` of adbd over TCP (after `adb tcpip 5555`), empty for USB
+    pub address: String,
+    /// USB serial number of the device to use, empty picks the cooler automatically
+    pub serial: String,
+    /// cmdType sent over serial when no ADB device is on USB, for units that boot
+    /// with ADB off. Empty never sends one.
+    pub enable_cmd_type: String,
+    /// JSON body of the enable command
+    pub enable_body: String,
+    /// How long the ADB interface gets to show up after the enable command
+    pub enable_wait_secs: u64,
+}
+
+impl Default for AdbConfig {
+    fn default() -> Self {
+        Self {
+            address: String::new(),
+            serial: String::new(),
+            enable_cmd_type: String::new(),
+            enable_body: "{}".to_string(),
+            enable_wait_secs: 20,
+        }
+    }
+}
+
+impl AdbConfig {
+    fn path() -> PathBuf {
+        crate::paths::state_dir().join("adb.json")
+    }
+
+    /// The device picked last time
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// The USB device to talk to: the one with `serial`, the only one, or
+    /// among several the one that also has a serial port (the cooler)
+    pub fn usb_device(&self) -> Result<UsbAdbDevice> {
+        let devices = crate::adb_usb::devices();
+        if !self.serial.is_empty() {
+            return devices
+                .into_iter()
+                .find(|device| device.serial == self.serial)
+                .with_context(|| format!("ADB device {} is not connected", self.serial));
+        }
+        if devices.len() <= 1 {
+            return devices.into_iter().next().context("No ADB device on USB (is USB debugging enabled on the cooler?)");
+        }
+
+        let names: Vec<String> = devices.iter().map(UsbAdbDevice::describe).collect();
+        let mut coolers: Vec<UsbAdbDevice> = devices.into_iter().filter(UsbAdbDevice::has_serial_port).collect();
+        anyhow::ensure!(
+            coolers.len() == 1,
+            "{} ADB devices connected ({}), pick one under Device Settings → ADB device",
+            names.len(),
+            names.join(", ")
+        );
+        let cooler = coolers.remove(0);
+        log::info!("Several ADB devices connected, using {} (it also has a serial port)", cooler.describe());
+        Ok(cooler)
+    }
+}
+
+trait Link: Read + Write + Send {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+}
+
+impl Link for TcpStream {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(timeout))
+    }
+}
+
+impl Link for UsbLink {
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        UsbLink::set_timeout(self, timeout);
+        Ok(())
+    }
+}
+
+struct Packet {
+    command: u32,
+    arg0: u32,
+    arg1: u32,
+    data: Vec<u8>,
+}
+
+/// Sync STAT result
+#[derive(Debug, Clone, Copy)]
+pub struct RemoteStat {
+    pub mode: u32,
+    pub size: u64,
+}
+
+/// One sync LIST entry
+#[derive(Debug, Clone)]
+pub struct RemoteEntry {
+    pub name: String,
+    pub mode: u32,
+    pub size: u64,
+    /// Seconds since the epoch
+    pub mtime: u32,
+}
+
+impl RemoteEntry {
+    pub fn is_file(&self) -> bool {
+        self.mode & 0o170000 == 0o100000
+    }
+}
+
+/// One connection at a time: adbd over USB is a single claimed interface, and
+/// the media browser and a push shouldn't interleave on TCP either
+static SESSION: Mutex<()> = Mutex::new(());
+
+pub struct AdbClient {
+    link: Box<dyn Link>,
+    /// Largest payload the device accepts
+    max_payload: usize,
+    next_id: u32,
+    /// What the device announced in its CNXN, e.g. `device::ro.product.name=…;`
+    pub banner: String,
+    _session: MutexGuard<'static, ()>,
+}
+
+impl AdbClient {
+    /// Connect and authenticate, over TCP when `config.address` is set, otherwise USB
+    pub fn connect(config: &AdbConfig) -> Result<Self> {
+        let session = SESSION.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let address = config.address.trim();
+        let (link, target): (Box<dyn Link>, String) = if address.is_empty() {
+            let device = config.usb_device()?;
+            (Box::new(UsbLink::open(&device)?), device.describe())
+        } else {
+            let address = if address.contains(':') { address.to_string() } else { format!("{}:{}", address, DEFAULT_TCP_PORT) };
+            let stream = TcpStream::connect(&address).with_context(|| format!("Failed to connect to adbd at {}", address))?;
+            stream.set_nodelay(true)?;
+            (Box::new(stream), address)
+        };
+
+        let mut client = Self { link, max_payload: MAX_PAYLOAD, next_id: 1, banner: String::new(), _session: session };
+        client.link.set_timeout(IO_TIMEOUT)?;
+        client.handshake().with_context(|| format!("ADB handshake with {} failed", target))?;
+        log::info!("ADB connected to {}", target);
+        Ok(client)
+    }
+
+    fn handshake(&mut self) -> Result<()> {
+        self.send(A_CNXN, VERSION, MAX_PAYLOAD as u32, b"host::\0")?;
+
+        let mut keys: Vec<AdbKey> = Vec::new();
+        let mut tried = 0;
+        let mut offered_public_key = false;
+        loop {
+            let packet = self.recv().context("No answer to the connection request")?;
+            match packet.command {
+                A_CNXN => {
+                    self.max_payload = (packet.arg1 as usize).clamp(4096, MAX_PAYLOAD);
+                    self.banner = String::from_utf8_lossy(&packet.data).trim_end_matches('\0').to_string();
+                    self.link.set_timeout(IO_TIMEOUT)?;
+                    return Ok(());
+                }
+                A_AUTH if packet.arg0 == AUTH_TOKEN => {
+                    if keys.is_empty() {
+                        keys = crate::adb_auth::load_keys()?;
+                    }
+                    if let Some(key) = keys.get(tried) {
+                        tried += 1;
+                        self.send(A_AUTH, AUTH_SIGNATURE, 0, &key.sign(&packet.data)?)?;
+                    } else if !offered_public_key {
+                        // None of our keys is trusted yet, ask the user to allow the first
+                        log::warn!("Allow USB debugging for this computer on the cooler's screen");
+                        let mut public_key = keys[0].public_key().into_bytes();
+                        public_key.push(0);
+                        self.send(A_AUTH, AUTH_RSAPUBLICKEY, 0, &public_key)?;
+                        self.link.set_timeout(AUTH_PROMPT_TIMEOUT)?;
+                        offered_public_key = true;
+                    } else {
+                        anyhow::bail!("Device rejected the ADB key");
+                    }
+                }
+                A_STLS => anyhow::bail!("Device wants a TLS (wireless debugging) connection, use `adb tcpip` mode instead"),
+                other => log::debug!("Ignoring ADB {} during handshake", command_name(other)),
+            }
+        }
+    }
+
+    fn send(&mut self, command: u32, arg0: u32, arg1: u32, data: &[u8]) -> io::Result<()> {
+        let checksum = data.iter().fold(0u32, |sum, &b| sum.wrapping_add(b as u32));
+        let mut header = [0u8; 24];
+        for (i, word) in [command, arg0, arg1, data.len() as u32, checksum, !command].iter().enumerate() {
+            header[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        self.link.write_all(&header)?;
+        if !data.is_empty() {
+            self.link.write_all(data)?;
+        }
+        self.link.flush()
+    }
+
+    fn recv(&mut self) -> Result<Packet> {
+        let mut header = [0u8; 24];
+        self.link.read_exact(&mut header)?;
+        self.recv_rest(header)
+    }
+
+    /// Like `recv`, but None if nothing arrives within `wait`. Nothing is lost
+    /// then: the header is one USB transfer, and a TCP read that times out read nothing.
+    fn recv_within(&mut self, wait: Duration) -> Result<Option<Packet>> {
+        let mut header = [0u8; 24];
+        self.link.set_timeout(wait)?;
+        let read = self.link.read(&mut header);
+        self.link.set_timeout(IO_TIMEOUT)?;
+        let read = match read {
+            Ok(0) => anyhow::bail!("ADB connection closed"),
+            Ok(read) => read,
+            Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        self.link.read_exact(&mut header[read..])?;
+        self.recv_rest(header).map(Some)
+    }
+
+    fn recv_rest(&mut self, header: [u8; 24]) -> Result<Packet> {
+        let word = |i: usize| u32::from_le_bytes(header[i * 4..i * 4 + 4].try_into().unwrap());
+        let (command, len) = (word(0), word(3) as usize);
+        anyhow::ensure!(word(5) == !command, "Corrupt ADB message header");
+        anyhow::ensure!(len <= MAX_PAYLOAD, "ADB {} payload too large ({} bytes)", command_name(command), len);
+
+        let mut data = vec![0u8; len];
+        self.link.read_exact(&mut data)?;
+        Ok(Packet { command, arg0: word(1), arg1: word(2), data })
+    }
+
+    /// Open a service stream (`sync:`, `shell:…`, `exec:…`)
+    fn open(&mut self, service: &str) -> Result<AdbStream<'_>> {
+        let local_id = self.next_id;
+        self.next_id += 1;
+        let mut destination = service.as_bytes().to_vec();
+        destination.push(0);
+        self.send(A_OPEN, local_id, 0, &destination)?;
+
+        loop {
+            let packet = self.recv()?;
+            if packet.arg1 != local_id {
+                continue;
+            }
+            match packet.command {
+                A_OKAY => {
+                    return Ok(AdbStream { client: self, local_id, remote_id: packet.arg0, pending: VecDeque::new(), closed: false });
+                }
+                A_CLSE => anyhow::bail!("Device refused the ADB service {}", service),
+                _ => {}
+            }
+        }
+    }
+
+    /// Run a command with `sh` on the device and collect what it prints (stdout and stderr)
+    pub fn shell(&mut self, command: &str) -> Result<String> {
+        let mut stream = self.open(&format!("shell:{}", command))?;
+        let output = stream.read_to_end()?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// Like `shell`, but hands output to `on_output` as it arrives and waits as long
+    /// as it takes. Returns false if `cancel` was set before the command finished;
+    /// the stream is closed then.
+    pub fn shell_streaming(&mut self, command: &str, cancel: &AtomicBool, mut on_output: impl FnMut(&[u8])) -> Result<bool> {
+        let mut stream = self.open(&format!("shell:{}", command))?;
+        loop {
+            if !stream.pending.is_empty() {
+                let chunk: Vec<u8> = stream.pending.drain(..).collect();
+                on_output(&chunk);
+            }
+            if stream.closed {
+                return Ok(true);
+            }
+            if cancel.load(Ordering::Relaxed) {
+                return Ok(false);
+            }
+            stream.poll_within(CANCEL_POLL)?;
+        }
+    }
+
+    /// Reboot the device (`reboot:` service), the connection drops with it
+    pub fn reboot(mut self) -> Result<()> {
+        let stream = self.open("reboot:")?;
+        drop(stream);
+        Ok(())
+    }
+
+    /// Run a command without a pty and return its output byte for byte (for binary output)
+    pub fn exec(&mut self, command: &str) -> Result<Vec<u8>> {
+        let mut stream = self.open(&format!("exec:{}", command))?;
+        stream.read_to_end()
+    }
+
+    /// None if `remote` doesn't exist
+    pub fn stat(&mut self, remote: &str) -> Result<Option<RemoteStat>> {
+        let mut sync = self.open("sync:")?;
+        sync.write(&sync_request(b"STAT", remote.as_bytes()))?;
+        let reply = sync.read_exact(16)?;
+        anyhow::ensure!(&reply[..4] == b"STAT", "Unexpected sync reply {}", String::from_utf8_lossy(&reply[..4]));
+        let word = |i: usize| u32::from_le_bytes(reply[i..i + 4].try_into().unwrap());
+        let stat = RemoteStat { mode: word(4), size: word(8) as u64 };
+        sync.write(&sync_request(b"QUIT", &[]))?;
+        Ok((stat.mode != 0).then_some(stat))
+    }
+
+    /// Entries of the directory `remote`, without `.` and `..`
+    pub fn list(&mut self, remote: &str) -> Result<Vec<RemoteEntry>> {
+        let mut sync = self.open("sync:")?;
+        sync.write(&sync_request(b"LIST", remote.as_bytes()))?;
+        let mut entries = Vec::new();
+        loop {
+            let reply = sync.read_exact(20)?;
+            let word = |i: usize| u32::from_le_bytes(reply[i..i + 4].try_into().unwrap());
+            match &reply[..4] {
+                b"DENT" => {
+                    let name = String::from_utf8_lossy(&sync.read_exact(word(16) as usize)?).into_owned();
+                    if name != "." && name != ".." {
+                        entries.push(RemoteEntry { name, mode: word(4), size: word(8) as u64, mtime: word(12) });
+                    }
+                }
+                b"DONE" => break,
+                other => anyhow::bail!("Unexpected sync reply {}", String::from_utf8_lossy(other)),
+            }
+        }
+        sync.write(&sync_request(b"QUIT", &[]))?;
+        Ok(entries)
+    }
+
+    /// Copy the device file `remote` into `out`, returns the byte count
+    pub fn pull(&mut self, remote: &str, out: &mut impl Write) -> Result<u64> {
+        let mut sync = self.open("sync:")?;
+        sync.write(&sync_request(b"RECV", remote.as_bytes()))?;
+        let mut total = 0u64;
+        loop {
+            let reply = sync.read_exact(8)?;
+            let len = u32::from_le_bytes(reply[4..8].try_into().unwrap()) as usize;
+            match &reply[..4] {
+                b"DATA" => {
+                    anyhow::ensure!(len <= SYNC_DATA_MAX, "Sync DATA chunk too large ({} bytes)", len);
+                    out.write_all(&sync.read_exact(len)?)?;
+                    total += len as u64;
+                }
+                b"DONE" => break,
+                b"FAIL" => {
+                    let message = sync.read_exact(len)?;
+                    anyhow::bail!("Device can't send {}: {}", remote, String::from_utf8_lossy(&message));
+                }
+                other => anyhow::bail!("Unexpected sync reply {}", String::from_utf8_lossy(other)),
+            }
+        }
+        sync.write(&sync_request(b"QUIT", &[]))?;
+        Ok(total)
+    }
+
+    /// Copy `local` to the device path `remote` (rw-r--r--). `progress` gets
+    /// the bytes sent so far and the file size after every chunk.
+    pub fn push(&mut self, local: &Path, remote: &str, mut progress: impl FnMut(u64, u64)) -> Result<()> {
+        let mut file = File::open(local).with_context(|| format!("Failed to open {}", local.display()))?;
+        let metadata = file.metadata()?;
+        let total = metadata.len();
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |age| age.as_secs() as u32);
+
+        let mut sync = self.open("sync:")?;
+        sync.write(&sync_request(b"SEND", format!("{},{}", remote, 0o100644).as_bytes()))?;
+        let mut chunk = vec![0u8; SYNC_DATA_MAX];
+        let mut sent = 0u64;
+        loop {
+            let read = file.read(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            sync.write(&sync_request(b"DATA", &chunk[..read]))?;
+            sent += read as u64;
+            progress(sent, total);
+            crate::systemd::watchdog();
+        }
+        sync.write(&sync_header(b"DONE", mtime))?;
+
+        let reply = sync.read_exact(8)?;
+        let len = u32::from_le_bytes(reply[4..8].try_into().unwrap()) as usize;
+        match &reply[..4] {
+            b"OKAY" => {}
+            b"FAIL" => {
+                let message = sync.read_exact(len)?;
+                anyhow::bail!("Device refused {}: {}", remote, String::from_utf8_lossy(&message));
+            }
+            other => anyhow::bail!("Unexpected sync reply {}", String::from_utf8_lossy(other)),
+        }
+        sync.write(&sync_request(b"QUIT", &[]))?;
+        Ok(())
+    }
+}
+
+/// One open service, closed on drop
+struct AdbStream<'a> {
+    client: &'a mut AdbClient,
+    local_id: u32,
+    remote_id: u32,
+    /// Received but not yet read
+    pending: VecDeque<u8>,
+    closed: bool,
+}
+
+impl AdbStream<'_> {
+    /// Send `data`, waiting for the device to acknowledge each packet
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        for chunk in data.chunks(self.client.max_payload) {
+            self.client.send(A_WRTE, self.local_id, self.remote_id, chunk)?;
+            while !self.poll()? {
+                anyhow::ensure!(!self.closed, "Device closed the ADB stream");
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle the next message for this stream, true when it's the OKAY for our last write
+    fn poll(&mut self) -> Result<bool> {
+        let packet = self.client.recv()?;
+        self.handle(packet)
+    }
+
+    /// `poll` that gives up after `wait` without a message, for streams that may go quiet
+    fn poll_within(&mut self, wait: Duration) -> Result<()> {
+        if let Some(packet) = self.client.recv_within(wait)? {
+            self.handle(packet)?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: Packet) -> Result<bool> {
+        if packet.arg1 != self.local_id {
+            log::debug!("Ignoring ADB {} for stream {}", command_name(packet.command), packet.arg1);
+            return Ok(false);
+        }
+        match packet.command {
+            A_OKAY => return Ok(true),
+            A_WRTE => {
+                self.pending.extend(&packet.data);
+                self.client.send(A_OKAY, self.local_id, self.remote_id, &[])?;
+            }
+            A_CLSE => self.closed = true,
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    fn read_exact(&mut self, len: usize) -> Result<Vec<u8>> {
+        while self.pending.len() < len {
+            anyhow::ensure!(!self.closed, "Device closed the ADB stream early");
+            self.poll()?;
+        }
+        Ok(self.pending.drain(..len).collect())
+    }
+
+    fn read_to_end(&mut self) -> Result<Vec<u8>> {
+        while !self.closed {
+            self.poll()?;
+        }
+        Ok(self.pending.drain(..).collect())
+    }
+}
+
+impl Drop for AdbStream<'_> {
+    fn drop(&mut self) {
+        if !self.closed {
+            let _ = self.client.send(A_CLSE, self.local_id, self.remote_id, &[]);
+        }
+    }
+}
+
+fn sync_header(id: &[u8; 4], value: u32) -> Vec<u8> {
+    let mut out = id.to_vec();
+    out.extend_from_slice(&value.to_le_bytes());
+    out
+}
+
+fn sync_request(id: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = sync_header(id, payload.len() as u32);
+    out.extend_from_slice(payload);
+    out
+}
+
+/// `arg` as one word for the device's `sh`
+pub fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+fn command_name(command: u32) -> String {
+    String::from_utf8_lossy(&command.to_le_bytes()).into_owned()
+}
@@ -0,0 +1,136 @@
+// ============================================================================
+// ADB authentication
+// adbd lets a host in once it signs a random token with an RSA key the user
+// allowed on the device. The adb tool's ~/.android/adbkey is offered when it
+// exists, so an already trusted host needs no prompt, but it is only ever
+// read. Without it we generate our own key next to our config. The RSA itself
+// is the `rsa` crate's; adbd's public key format is put together here.
+// ============================================================================
+
+use std::{fs, io::Write, os::unix::fs::OpenOptionsExt, path::{Path, PathBuf}};
+use anyhow::{Context, Result};
+use base64::Engine;
+use rsa::{
+    BigUint, Pkcs1v15Sign, RsaPrivateKey,
+    pkcs1::DecodeRsaPrivateKey,
+    pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding},
+    traits::PublicKeyParts,
+};
+
+/// adbd only stores 2048-bit keys
+const KEY_BITS: usize = 2048;
+const KEY_WORDS: usize = KEY_BITS / 32;
+/// DER DigestInfo for SHA-1, adbd verifies the token as if it were a SHA-1 digest
+const SHA1_PREFIX: [u8; 15] = [0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a, 0x05, 0x00, 0x04, 0x14];
+/// AUTH tokens are SHA-1 sized
+const TOKEN_SIZE: usize = 20;
+
+pub struct AdbKey {
+    key: RsaPrivateKey,
+}
+
+impl AdbKey {
+    /// PKCS#8 (`BEGIN PRIVATE KEY`, what adb writes) or PKCS#1 (`BEGIN RSA PRIVATE KEY`) PEM
+    pub fn load(path: &Path) -> Result<Self> {
+        let pem = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let key = if pem.contains("BEGIN RSA PRIVATE KEY") {
+            RsaPrivateKey::from_pkcs1_pem(&pem).map_err(|e| anyhow::anyhow!("{}", e))
+        } else {
+            RsaPrivateKey::from_pkcs8_pem(&pem).map_err(|e| anyhow::anyhow!("{}", e))
+        }
+        .with_context(|| format!("Unusable ADB key {}", path.display()))?;
+        anyhow::ensure!(key.n().bits() == KEY_BITS, "{} is not a {}-bit RSA key", path.display(), KEY_BITS);
+        Ok(Self { key })
+    }
+
+    /// PKCS#1 v1.5 signature of the AUTH token
+    pub fn sign(&self, token: &[u8]) -> Result<Vec<u8>> {
+        anyhow::ensure!(token.len() == TOKEN_SIZE, "AUTH token is {} bytes, expected {}", token.len(), TOKEN_SIZE);
+        let scheme = Pkcs1v15Sign { hash_len: Some(TOKEN_SIZE), prefix: SHA1_PREFIX.into() };
+        self.key.sign(scheme, token).map_err(|e| anyhow::anyhow!("Signing the AUTH token failed: {}", e))
+    }
+
+    /// Public key as adbd keeps it in /data/misc/adb/adb_keys: base64 of
+    /// {words, -1/n[0] mod 2^32, n, R^2 mod n, e} (little-endian, R = 2^2048), then `user@host`
+    pub fn public_key(&self) -> String {
+        let n = self.key.n();
+        let n_words = words(n);
+        // Newton's iteration doubles the correct low bits each round, 5 rounds reach 32
+        let mut inverse = n_words[0];
+        for _ in 0..5 {
+            inverse = inverse.wrapping_mul(2u32.wrapping_sub(n_words[0].wrapping_mul(inverse)));
+        }
+        let rr = (BigUint::from(1u8) << (2 * KEY_BITS)) % n;
+        let e = words(self.key.e())[0];
+
+        let mut raw = Vec::with_capacity(12 + KEY_BITS / 4);
+        raw.extend_from_slice(&(KEY_WORDS as u32).to_le_bytes());
+        raw.extend_from_slice(&inverse.wrapping_neg().to_le_bytes());
+        for word in n_words.iter().chain(&words(&rr)) {
+            raw.extend_from_slice(&word.to_le_bytes());
+        }
+        raw.extend_from_slice(&e.to_le_bytes());
+        format!("{} {}", base64_encode(&raw), user_at_host())
+    }
+}
+
+/// Little-endian 32-bit words, padded to the key size
+fn words(value: &BigUint) -> Vec<u32> {
+    let mut bytes = value.to_bytes_le();
+    bytes.resize(KEY_BITS / 8, 0);
+    bytes.chunks_exact(4).map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]])).collect()
+}
+
+/// The adb tool's key, never written to
+pub fn adb_key_path() -> PathBuf {
+    crate::paths::android_dir().join("adbkey")
+}
+
+/// Ours, generated when the adb tool has none
+pub fn own_key_path() -> PathBuf {
+    crate::paths::config_dir().join("adbkey")
+}
+
+/// Keys to offer: the adb tool's, ours (created when there's no other), then
+/// any in `$ADB_VENDOR_KEYS`
+pub fn load_keys() -> Result<Vec<AdbKey>> {
+    let adb = adb_key_path();
+    let own = own_key_path();
+    if !adb.exists() && !own.exists() {
+        generate(&own).with_context(|| format!("Failed to create ADB key {}", own.display()))?;
+    }
+
+    let vendor = std::env::var("ADB_VENDOR_KEYS").unwrap_or_default();
+    let mut keys = Vec::new();
+    for path in [adb, own].into_iter().filter(|path| path.exists()).chain(vendor.split(':').filter(|p| !p.is_empty()).map(Into::into)) {
+        match AdbKey::load(&path) {
+            Ok(key) => keys.push(key),
+            Err(e) => log::warn!("Skipping ADB key: {:#}", e),
+        }
+    }
+    anyhow::ensure!(!keys.is_empty(), "No usable ADB key");
+    Ok(keys)
+}
+
+/// New 2048-bit key at `path` (PKCS#8 PEM, as adb writes it) plus `path.pub`
+fn generate(path: &Path) -> Result<()> {
+    log::info!("Generating ADB key {}", path.display());
+    let key = RsaPrivateKey::new(&mut rsa::rand_core::OsRng, KEY_BITS).context("RSA key generation failed")?;
+    let pem = key.to_pkcs8_pem(LineEnding::LF).map_err(|e| anyhow::anyhow!("{}", e))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?.write_all(pem.as_bytes())?;
+    fs::write(path.with_extension("pub"), format!("{}\n", AdbKey { key }.public_key()))?;
+    Ok(())
+}
+
+fn user_at_host() -> String {
+    let user = std::env::var("USER").unwrap_or_else(|_| "unknown".to_string());
+    let host = fs::read_to_string("/proc/sys/kernel/hostname").unwrap_or_else(|_| "unknown".to_string());
+    format!("{}@{}", user, host.trim())
+}
+
+pub fn base64_encode(data: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(data)
+}
@@ -0,0 +1,213 @@
+// ============================================================================
+// ADB shell panel
+// One-shot `shell:` commands against the cooler for poking around its
+// filesystem and services. The working directory is carried from command to
+// command, output streams in as it arrives and is copied to the log.
+// ============================================================================
+
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+};
+use anyhow::Result;
+
+use crate::adb::{shell_quote, AdbClient, AdbConfig};
+
+/// Commands kept on screen
+const MAX_ENTRIES: usize = 50;
+/// Commands remembered for Up/Down, also across runs
+const MAX_HISTORY: usize = 200;
+/// Output kept per command, the start is dropped beyond this
+const MAX_OUTPUT: usize = 256 * 1024;
+/// Output lines copied to the log per command
+const MAX_LOGGED_LINES: usize = 200;
+/// Precedes exit status and working directory on the last line of the output
+const STATUS_MARKER: &str = "\x1ftryx-status ";
+
+pub struct ShellEntry {
+    pub command: String,
+    /// Directory the command ran in
+    pub cwd: String,
+    pub output: String,
+    /// None while running
+    pub status: Option<Result<i32, String>>,
+}
+
+pub struct AdbShell {
+    pub input: String,
+    pub cwd: String,
+    /// Newest last
+    pub entries: VecDeque<ShellEntry>,
+    history: Vec<String>,
+    /// Position while browsing the history with Up/Down
+    history_pos: Option<usize>,
+    pub cancel: Arc<AtomicBool>,
+    pub worker: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Default for AdbShell {
+    fn default() -> Self {
+        Self {
+            input: String::new(),
+            cwd: "/".to_string(),
+            entries: VecDeque::new(),
+            history: load_history(),
+            history_pos: None,
+            cancel: Default::default(),
+            worker: None,
+        }
+    }
+}
+
+impl AdbShell {
+    pub fn running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|w| !w.is_finished())
+    }
+
+    /// Take the input line as a new entry, None when it's empty
+    pub fn begin(&mut self) -> Option<(String, String)> {
+        let command = self.input.trim().to_string();
+        if command.is_empty() {
+            return None;
+        }
+        self.input.clear();
+        self.history_pos = None;
+        if self.history.last() != Some(&command) {
+            self.history.push(command.clone());
+            if self.history.len() > MAX_HISTORY {
+                self.history.remove(0);
+            }
+            save_history(&self.history);
+        }
+
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(ShellEntry { command: command.clone(), cwd: self.cwd.clone(), output: String::new(), status: None });
+        self.cancel.store(false, Ordering::Relaxed);
+        Some((command, self.cwd.clone()))
+    }
+
+    /// More output of the running command
+    pub fn append(&mut self, text: &str) {
+        if let Some(entry) = self.entries.back_mut() {
+            entry.output.push_str(text);
+            if entry.output.len() > MAX_OUTPUT {
+                let mut cut = entry.output.len() - MAX_OUTPUT;
+                while !entry.output.is_char_boundary(cut) {
+                    cut += 1;
+                }
+                entry.output.drain(..cut);
+            }
+        }
+    }
+
+    /// The command ended: take the status line off the output and follow `cd`
+    pub fn finish(&mut self, result: Result<(), String>) {
+        let Some(entry) = self.entries.back_mut() else {
+            return;
+        };
+        let status = match result {
+            Err(e) => Err(e),
+            Ok(()) => match entry.output.rfind(STATUS_MARKER) {
+                Some(at) => {
+                    let line = entry.output[at + STATUS_MARKER.len()..].trim().to_string();
+                    entry.output.truncate(at);
+                    let (code, cwd) = line.split_once(' ').unwrap_or((line.as_str(), ""));
+                    if !cwd.is_empty() {
+                        self.cwd = cwd.to_string();
+                    }
+                    code.parse().map_err(|_| format!("Unexpected status line: {}", line))
+                }
+                None => Err("Ended without an exit status".to_string()),
+            },
+        };
+        let trimmed = entry.output.trim_end().len();
+        entry.output.truncate(trimmed);
+
+        log::info!("adb shell {}$ {}", entry.cwd, entry.command);
+        let lines: Vec<&str> = entry.output.lines().collect();
+        for line in lines.iter().take(MAX_LOGGED_LINES) {
+            log::info!("  {}", line);
+        }
+        if lines.len() > MAX_LOGGED_LINES {
+            log::info!("  … {} more lines in the shell panel", lines.len() - MAX_LOGGED_LINES);
+        }
+        match &status {
+            Ok(0) => {}
+            Ok(code) => log::warn!("adb shell: {} exited with {}", entry.command, code),
+            Err(e) => log::warn!("adb shell: {}: {}", entry.command, e),
+        }
+        entry.status = Some(status);
+    }
+
+    /// Up/Down in the input line, `older` for Up
+    pub fn browse_history(&mut self, older: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+        let pos = match (self.history_pos, older) {
+            (None, true) => self.history.len() - 1,
+            (None, false) => return,
+            (Some(pos), true) => pos.saturating_sub(1),
+            (Some(pos), false) if pos + 1 < self.history.len() => pos + 1,
+            (Some(_), false) => {
+                self.history_pos = None;
+                self.input.clear();
+                return;
+            }
+        };
+        self.history_pos = Some(pos);
+        self.input = self.history[pos].clone();
+    }
+}
+
+/// Run `command` in `cwd` and stream what it prints to `on_output`. The last
+/// line carries the exit status and the directory the command ended in.
+pub fn run(adb: &AdbConfig, command: &str, cwd: &str, cancel: &AtomicBool, mut on_output: impl FnMut(String)) -> Result<()> {
+    let script = format!(
+        "cd {} 2>/dev/null\n{}\nprintf '\\n{}%s %s\\n' \"$?\" \"$PWD\"",
+        shell_quote(cwd),
+        command,
+        STATUS_MARKER.replace('\x1f', "\\037")
+    );
+    let mut client = AdbClient::connect(adb)?;
+    // Keep split UTF-8 sequences together across packets
+    let mut partial = Vec::new();
+    let finished = client.shell_streaming(&script, cancel, |chunk| {
+        partial.extend_from_slice(chunk);
+        let valid = match std::str::from_utf8(&partial) {
+            Ok(_) => partial.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => partial.len(),
+        };
+        let text: Vec<u8> = partial.drain(..valid).collect();
+        // The pty turns \n into \r\n
+        on_output(String::from_utf8_lossy(&text).replace('\r', ""));
+    })?;
+    anyhow::ensure!(finished, "Stopped");
+    Ok(())
+}
+
+fn history_path() -> PathBuf {
+    crate::paths::state_dir().join("adb_shell_history")
+}
+
+fn load_history() -> Vec<String> {
+    std::fs::read_to_string(history_path())
+        .map(|content| content.lines().filter(|line| !line.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn save_history(history: &[String]) {
+    let path = history_path();
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    // One command per line, multi-line commands are flattened
+    let content: Vec<String> = history.iter().map(|command| command.replace('\n', " ")).collect();
+    if let Err(e) = std::fs::write(&path, content.join("\n") + "\n") {
+        log::warn!("Failed to save shell history: {}", e);
+    }
+}
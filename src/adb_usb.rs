@@ -0,0 +1,262 @@
+// ADB over USB through usbfs: find the interface adbd exposes (class ff,
+// subclass 42, protocol 01) in sysfs, claim it and run bulk transfers on it
+
+use std::{fs::{self, File, OpenOptions}, io, os::fd::AsRawFd, path::{Path, PathBuf}};
+use anyhow::{Context, Result};
+
+const ADB_INTERFACE: (u32, u32, u32) = (0xff, 0x42, 0x01);
+/// Per-transfer cap, older kernels reject larger usbfs bulk transfers
+const MAX_BULK: usize = 16 * 1024;
+
+#[repr(C)]
+struct BulkTransfer {
+    ep: u32,
+    len: u32,
+    timeout: u32,
+    data: *mut libc::c_void,
+}
+
+const fn ioctl_code(dir: u64, nr: u64, size: usize) -> u64 {
+    dir << 30 | (size as u64) << 16 | (b'U' as u64) << 8 | nr
+}
+
+const USBDEVFS_BULK: u64 = ioctl_code(3, 2, size_of::<BulkTransfer>());
+const USBDEVFS_CLAIMINTERFACE: u64 = ioctl_code(2, 15, size_of::<u32>());
+const USBDEVFS_RELEASEINTERFACE: u64 = ioctl_code(2, 16, size_of::<u32>());
+
+/// An ADB interface found in sysfs
+#[derive(Debug, Clone)]
+pub struct UsbAdbDevice {
+    /// iSerial, what `adb devices` lists
+    pub serial: String,
+    pub product: String,
+    /// /dev/bus/usb/BBB/DDD
+    pub node: PathBuf,
+    /// The USB device in /sys/bus/usb/devices
+    pub sysfs: PathBuf,
+    interface: u32,
+    ep_in: u8,
+    ep_out: u8,
+    max_packet: usize,
+}
+
+impl UsbAdbDevice {
+    pub fn describe(&self) -> String {
+        match (self.product.is_empty(), self.serial.is_empty()) {
+            (false, false) => format!("{} ({})", self.product, self.serial),
+            (false, true) => self.product.clone(),
+            _ => self.node.display().to_string(),
+        }
+    }
+
+    /// Whether this is the device behind the serial port `tty`
+    pub fn relation_to(&self, tty: &str) -> Relation {
+        let Some(tty_device) = tty_usb_device(tty) else {
+            return Relation::Unknown;
+        };
+        // A shared root hub (usb1, usb2…) says nothing, every port of the bus hangs off it
+        let hub = tty_device.parent().filter(|hub| {
+            hub.join("busnum").exists() && !hub.file_name().is_some_and(|name| name.to_string_lossy().starts_with("usb"))
+        });
+        if tty_device == self.sysfs {
+            Relation::SameDevice
+        } else if hub.is_some() && hub == self.sysfs.parent() {
+            Relation::SameHub
+        } else {
+            Relation::Unrelated
+        }
+    }
+
+    /// The same USB device also has a serial interface, like the cooler (phones don't)
+    pub fn has_serial_port(&self) -> bool {
+        let Ok(entries) = fs::read_dir(&self.sysfs) else {
+            return false;
+        };
+        entries
+            .flatten()
+            .filter(|entry| entry.file_name().to_string_lossy().contains(':'))
+            .any(|interface| {
+                // cdc_acm puts the node under tty/, usb-serial drivers directly in the interface
+                let path = interface.path();
+                path.join("tty").is_dir()
+                    || fs::read_dir(&path)
+                        .map(|entries| entries.flatten().any(|entry| entry.file_name().to_string_lossy().starts_with("ttyUSB")))
+                        .unwrap_or(false)
+            })
+    }
+}
+
+/// How an ADB device relates to a serial port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    /// Interfaces of one USB device, the usual composite cooler
+    SameDevice,
+    /// Separate devices behind the same hub, as with a hub inside the cooler
+    SameHub,
+    /// Nothing in common, probably a phone or another gadget
+    Unrelated,
+    /// The port isn't a USB device (simulator, pty)
+    Unknown,
+}
+
+/// The USB device in sysfs that provides the tty `device` (/dev/ttyACM0, or a by-id link to it)
+pub fn tty_usb_device(device: &str) -> Option<PathBuf> {
+    let node = fs::canonicalize(device).ok()?;
+    let name = node.file_name()?;
+    let mut dir = fs::canonicalize(Path::new("/sys/class/tty").join(name).join("device")).ok()?;
+    // ttyACM sits on the interface, usb-serial ports one level further down
+    while !dir.join("busnum").exists() {
+        dir = dir.parent()?.to_path_buf();
+    }
+    Some(dir)
+}
+
+/// Every ADB interface currently plugged in
+pub fn devices() -> Vec<UsbAdbDevice> {
+    let Ok(entries) = fs::read_dir("/sys/bus/usb/devices") else {
+        return Vec::new();
+    };
+    let mut devices: Vec<UsbAdbDevice> = entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().contains(':'))
+        .filter_map(|entry| parse_interface(&entry.path()))
+        .collect();
+    devices.sort_by(|a, b| a.node.cmp(&b.node));
+    devices
+}
+
+fn attr(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name)).ok().map(|value| value.trim().to_string())
+}
+
+fn hex_attr(dir: &Path, name: &str) -> Option<u32> {
+    attr(dir, name).and_then(|value| u32::from_str_radix(&value, 16).ok())
+}
+
+fn parse_interface(dir: &Path) -> Option<UsbAdbDevice> {
+    let class = (hex_attr(dir, "bInterfaceClass")?, hex_attr(dir, "bInterfaceSubClass")?, hex_attr(dir, "bInterfaceProtocol")?);
+    if class != ADB_INTERFACE {
+        return None;
+    }
+
+    let mut ep_in = None;
+    let mut ep_out = None;
+    let mut max_packet = 512;
+    for entry in fs::read_dir(dir).ok()?.flatten() {
+        let ep = entry.path();
+        if !entry.file_name().to_string_lossy().starts_with("ep_") || attr(&ep, "type").as_deref() != Some("Bulk") {
+            continue;
+        }
+        let address = hex_attr(&ep, "bEndpointAddress")? as u8;
+        match attr(&ep, "direction").as_deref() {
+            Some("in") => ep_in = Some(address),
+            Some("out") => {
+                ep_out = Some(address);
+                max_packet = hex_attr(&ep, "wMaxPacketSize").map_or(max_packet, |size| size as usize);
+            }
+            _ => {}
+        }
+    }
+
+    let device = fs::canonicalize(dir).ok()?.parent()?.to_path_buf();
+    let bus: u32 = attr(&device, "busnum")?.parse().ok()?;
+    let address: u32 = attr(&device, "devnum")?.parse().ok()?;
+    Some(UsbAdbDevice {
+        serial: attr(&device, "serial").unwrap_or_default(),
+        product: attr(&device, "product").unwrap_or_default(),
+        interface: hex_attr(dir, "bInterfaceNumber")?,
+        node: PathBuf::from(format!("/dev/bus/usb/{:03}/{:03}", bus, address)),
+        sysfs: device,
+        ep_in: ep_in?,
+        ep_out: ep_out?,
+        max_packet: max_packet.max(1),
+    })
+}
+
+/// Claimed ADB interface, released on drop
+pub struct UsbLink {
+    file: File,
+    interface: u32,
+    ep_in: u8,
+    ep_out: u8,
+    max_packet: usize,
+    timeout_ms: u32,
+}
+
+impl UsbLink {
+    pub fn open(device: &UsbAdbDevice) -> Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(&device.node).with_context(|| {
+            format!("Failed to open {} (a udev rule granting access to the device is needed)", device.node.display())
+        })?;
+
+        let mut interface = device.interface;
+        if unsafe { libc::ioctl(file.as_raw_fd(), USBDEVFS_CLAIMINTERFACE as _, &mut interface) } != 0 {
+            let e = io::Error::last_os_error();
+            if e.raw_os_error() == Some(libc::EBUSY) {
+                anyhow::bail!("{} is in use, probably by an adb server (stop it with `adb kill-server`)", device.describe());
+            }
+            return Err(e).with_context(|| format!("Failed to claim the ADB interface of {}", device.describe()));
+        }
+
+        Ok(Self {
+            file,
+            interface,
+            ep_in: device.ep_in,
+            ep_out: device.ep_out,
+            max_packet: device.max_packet,
+            timeout_ms: 10_000,
+        })
+    }
+
+    pub fn set_timeout(&mut self, timeout: std::time::Duration) {
+        self.timeout_ms = timeout.as_millis().min(u32::MAX as u128) as u32;
+    }
+
+    fn bulk(&self, ep: u8, data: *mut libc::c_void, len: usize) -> io::Result<usize> {
+        let mut transfer = BulkTransfer { ep: ep as u32, len: len as u32, timeout: self.timeout_ms, data };
+        let ret = unsafe { libc::ioctl(self.file.as_raw_fd(), USBDEVFS_BULK as _, &mut transfer) };
+        if ret < 0 {
+            let e = io::Error::last_os_error();
+            return Err(match e.raw_os_error() {
+                Some(libc::ETIMEDOUT) => io::Error::new(io::ErrorKind::TimedOut, "USB transfer timed out"),
+                _ => e,
+            });
+        }
+        Ok(ret as usize)
+    }
+}
+
+impl io::Read for UsbLink {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(MAX_BULK);
+        self.bulk(self.ep_in, buf.as_mut_ptr().cast(), len)
+    }
+}
+
+impl io::Write for UsbLink {
+    /// The whole buffer as one transfer, ended with a zero-length packet when it fills the last one
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.chunks(MAX_BULK) {
+            let sent = self.bulk(self.ep_out, chunk.as_ptr() as *mut _, chunk.len())?;
+            if sent != chunk.len() {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "short USB write"));
+            }
+        }
+        if !buf.is_empty() && buf.len().is_multiple_of(self.max_packet) {
+            self.bulk(self.ep_out, std::ptr::null_mut(), 0)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for UsbLink {
+    fn drop(&mut self) {
+        unsafe {
+            libc::ioctl(self.file.as_raw_fd(), USBDEVFS_RELEASEINTERFACE as _, &mut self.interface);
+        }
+    }
+}
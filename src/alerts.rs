@@ -0,0 +1,197 @@
+// Threshold alerts: when a reading crosses a user-set limit (CPU over 90°C, the
+// disk 95% full), swap the panel to a warning color or image, optionally
+// flashing, and restore the normal screen once every reading has recovered
+
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::screen_setup::ScreenConfig;
+use crate::sysinfo::SysInfo;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AlertConfig {
+    pub enabled: bool,
+    pub rules: Vec<AlertRule>,
+    /// Panel color while alerting
+    pub color: String,
+    /// Media already on the device to show while alerting, empty keeps the current one
+    pub media: String,
+    /// Alternate between the warning color and black
+    pub flash: bool,
+    /// How far below its threshold a reading must drop to count as recovered,
+    /// so a value hovering at the limit doesn't toggle the screen every beat
+    pub hysteresis: f64,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rules: vec![
+                AlertRule { metric: AlertMetric::CpuTemperature, above: 90.0 },
+                AlertRule { metric: AlertMetric::DiskUsage, above: 95.0 },
+            ],
+            color: "#ff0000".to_string(),
+            media: String::new(),
+            flash: false,
+            hysteresis: 3.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub metric: AlertMetric,
+    pub above: f64,
+}
+
+impl AlertRule {
+    /// `CPU temperature 93 > 90°C`
+    fn describe(&self, value: f64) -> String {
+        format!("{} {:.0} > {}{}", self.metric.label(), value, self.above, self.metric.unit())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AlertMetric {
+    CpuTemperature,
+    GpuTemperature,
+    CpuLoad,
+    GpuLoad,
+    MemoryLoad,
+    /// Root filesystem, % full
+    DiskUsage,
+    DiskTemperature,
+    CoolantTemperature,
+}
+
+impl AlertMetric {
+    pub const ALL: [AlertMetric; 8] = [
+        AlertMetric::CpuTemperature,
+        AlertMetric::GpuTemperature,
+        AlertMetric::CpuLoad,
+        AlertMetric::GpuLoad,
+        AlertMetric::MemoryLoad,
+        AlertMetric::DiskUsage,
+        AlertMetric::DiskTemperature,
+        AlertMetric::CoolantTemperature,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AlertMetric::CpuTemperature => "CPU temperature",
+            AlertMetric::GpuTemperature => "GPU temperature",
+            AlertMetric::CpuLoad => "CPU load",
+            AlertMetric::GpuLoad => "GPU load",
+            AlertMetric::MemoryLoad => "Memory load",
+            AlertMetric::DiskUsage => "Disk usage",
+            AlertMetric::DiskTemperature => "Disk temperature",
+            AlertMetric::CoolantTemperature => "Coolant temperature",
+        }
+    }
+
+    pub fn unit(self) -> &'static str {
+        match self {
+            AlertMetric::CpuTemperature | AlertMetric::GpuTemperature | AlertMetric::DiskTemperature | AlertMetric::CoolantTemperature => {
+                "°C"
+            }
+            AlertMetric::CpuLoad | AlertMetric::GpuLoad | AlertMetric::MemoryLoad | AlertMetric::DiskUsage => "%",
+        }
+    }
+
+    /// None when the sample doesn't have it (no AIO, a GPU that doesn't report
+    /// its load) or a sensor reads 0, which means it's missing rather than that cold
+    pub fn value(self, info: &SysInfo) -> Option<f64> {
+        let value = match self {
+            AlertMetric::CpuTemperature => info.cpu.temperature as f64,
+            AlertMetric::GpuTemperature => info.gpu.temperature as f64,
+            AlertMetric::CpuLoad => info.cpu.load as f64,
+            AlertMetric::GpuLoad if !info.gpu.load_known => return None,
+            AlertMetric::GpuLoad => info.gpu.load as f64,
+            AlertMetric::MemoryLoad => info.memory.load as f64,
+            AlertMetric::DiskUsage => info.disk.load as f64,
+            AlertMetric::DiskTemperature => info.disk.temperature as f64,
+            AlertMetric::CoolantTemperature => info.liquid.as_ref()?.temperature as f64,
+        };
+        (value > 0.0).then_some(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertEvent {
+    /// Rules that just fired, described
+    Enter(Vec<String>),
+    /// Next flash phase while alerting
+    Flash,
+    Exit,
+}
+
+pub struct Alerts {
+    config: AlertConfig,
+    /// Per rule, whether it's over its threshold
+    firing: Vec<bool>,
+    /// Showing black rather than the warning color
+    dark: bool,
+    last_flash: Option<Instant>,
+}
+
+/// One flash phase
+const FLASH_PERIOD: Duration = Duration::from_secs(1);
+
+impl Alerts {
+    pub fn new(config: AlertConfig) -> Self {
+        let firing = vec![false; config.rules.len()];
+        Self { config, firing, dark: false, last_flash: None }
+    }
+
+    pub fn active(&self) -> bool {
+        self.firing.iter().any(|firing| *firing)
+    }
+
+    /// Feed the latest sample, returns a transition when one happens
+    pub fn update(&mut self, info: &SysInfo, now: Instant) -> Option<AlertEvent> {
+        if !self.config.enabled {
+            return None;
+        }
+        let was_active = self.active();
+        let mut fired = Vec::new();
+        for (rule, firing) in self.config.rules.iter().zip(self.firing.iter_mut()) {
+            let Some(value) = rule.metric.value(info) else {
+                continue;
+            };
+            if !*firing && value > rule.above {
+                *firing = true;
+                fired.push(rule.describe(value));
+            } else if *firing && value <= rule.above - self.config.hysteresis {
+                *firing = false;
+            }
+        }
+
+        match (was_active, self.active()) {
+            (false, true) => {
+                self.dark = false;
+                self.last_flash = Some(now);
+                Some(AlertEvent::Enter(fired))
+            }
+            (true, false) => Some(AlertEvent::Exit),
+            (true, true) if self.config.flash && self.last_flash.is_none_or(|at| now.duration_since(at) >= FLASH_PERIOD) => {
+                self.dark = !self.dark;
+                self.last_flash = Some(now);
+                Some(AlertEvent::Flash)
+            }
+            _ => None,
+        }
+    }
+
+    /// `config` in the warning color (black in a flash's dark phase), with the
+    /// warning image instead of the current media when one is set. Overlays stay
+    /// so the offending reading is on screen. Returns the config and the media
+    /// file to show.
+    pub fn alert_config(&self, config: &ScreenConfig, current_media: &str) -> (ScreenConfig, String) {
+        let mut warning = config.clone();
+        warning.color = if self.dark { "#000000".to_string() } else { self.config.color.clone() };
+        let media = if self.config.media.is_empty() { current_media } else { &self.config.media };
+        (warning, media.to_string())
+    }
+}
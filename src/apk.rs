@@ -0,0 +1,307 @@
+// ============================================================================
+// Display app updates
+// Read package name and version out of an APK (zip → binary AndroidManifest.xml),
+// compare with what the cooler has installed, then push it to /data/local/tmp
+// and `pm install -r` it over ADB
+// ============================================================================
+
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+use anyhow::{Context, Result};
+
+use crate::adb::{shell_quote, AdbClient, AdbConfig};
+
+/// Where the APK is staged for `pm`, readable by the package manager
+const STAGING_PATH: &str = "/data/local/tmp/tryx-update.apk";
+/// Manifests are small, anything bigger is not an APK we understand
+const MAX_MANIFEST_SIZE: usize = 4 * 1024 * 1024;
+
+// Binary XML chunk types
+const RES_STRING_POOL: u16 = 0x0001;
+const RES_XML: u16 = 0x0003;
+const RES_XML_START_ELEMENT: u16 = 0x0102;
+const RES_XML_RESOURCE_MAP: u16 = 0x0180;
+const UTF8_FLAG: u32 = 1 << 8;
+/// Res_value data types
+const TYPE_STRING: u8 = 0x03;
+const TYPE_INT_DEC: u8 = 0x10;
+const TYPE_INT_HEX: u8 = 0x11;
+/// android:versionCode / android:versionName, for manifests with stripped attribute names
+const ATTR_VERSION_CODE: u32 = 0x0101_021b;
+const ATTR_VERSION_NAME: u32 = 0x0101_021c;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApkInfo {
+    pub package: String,
+    pub version_code: u64,
+    pub version_name: String,
+}
+
+impl ApkInfo {
+    pub fn version(&self) -> String {
+        format!("{} ({})", self.version_name, self.version_code)
+    }
+}
+
+/// A picked APK and how it compares with the installed app
+#[derive(Debug, Clone)]
+pub struct UpdatePlan {
+    pub path: PathBuf,
+    pub apk: ApkInfo,
+    /// None when the package isn't installed
+    pub installed: Option<ApkInfo>,
+}
+
+impl UpdatePlan {
+    pub fn is_downgrade(&self) -> bool {
+        self.installed.as_ref().is_some_and(|installed| self.apk.version_code < installed.version_code)
+    }
+
+    pub fn is_reinstall(&self) -> bool {
+        self.installed.as_ref().is_some_and(|installed| self.apk.version_code == installed.version_code)
+    }
+}
+
+#[derive(Default)]
+pub struct AppUpdate {
+    pub plan: Option<UpdatePlan>,
+    pub error: Option<String>,
+    /// Needed to install an older version code (`pm install -d`)
+    pub allow_downgrade: bool,
+    pub worker: Option<std::thread::JoinHandle<()>>,
+}
+
+impl AppUpdate {
+    pub fn running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|w| !w.is_finished())
+    }
+
+    pub fn set_plan(&mut self, result: Result<UpdatePlan, String>) {
+        match result {
+            Ok(plan) => {
+                self.plan = Some(plan);
+                self.error = None;
+            }
+            Err(e) => {
+                self.plan = None;
+                self.error = Some(e);
+            }
+        }
+        self.allow_downgrade = false;
+    }
+}
+
+/// Read `path` and look up the installed version of its package
+pub fn inspect(adb: &AdbConfig, path: &Path) -> Result<UpdatePlan> {
+    let apk = read_info(path)?;
+    let installed = installed_version(&mut AdbClient::connect(adb)?, &apk.package)?;
+    Ok(UpdatePlan { path: path.to_path_buf(), apk, installed })
+}
+
+/// Push and install `plan`, returning the version the device reports afterwards
+pub fn install(adb: &AdbConfig, plan: &UpdatePlan, allow_downgrade: bool, progress: impl FnMut(u64, u64)) -> Result<ApkInfo> {
+    // The file may have changed since it was inspected
+    let apk = read_info(&plan.path)?;
+    anyhow::ensure!(apk == plan.apk, "{} changed since it was checked, pick it again", plan.path.display());
+    if plan.is_downgrade() {
+        anyhow::ensure!(allow_downgrade, "{} is older than the installed version, allow the downgrade first", apk.version());
+    }
+
+    let mut client = AdbClient::connect(adb)?;
+    client.push(&plan.path, STAGING_PATH, progress)?;
+    let flags = if allow_downgrade { "-r -d" } else { "-r" };
+    let output = client.shell(&format!("pm install {} {}", flags, shell_quote(STAGING_PATH)));
+    let _ = client.shell(&format!("rm -f {}", shell_quote(STAGING_PATH)));
+    let output = output?;
+    log::info!("pm install: {}", output.trim());
+    // pm prints "Success", or "Failure [INSTALL_FAILED_…]"
+    anyhow::ensure!(output.lines().any(|line| line.trim() == "Success"), "Install failed: {}", output.trim());
+
+    let installed = installed_version(&mut client, &apk.package)?.context("The package is missing after installing it")?;
+    anyhow::ensure!(
+        installed.version_code == apk.version_code,
+        "The device still reports {} after installing {}",
+        installed.version(),
+        apk.version()
+    );
+    Ok(installed)
+}
+
+/// versionCode and versionName from `dumpsys package`, None if not installed
+pub fn installed_version(client: &mut AdbClient, package: &str) -> Result<Option<ApkInfo>> {
+    let output = client.shell(&format!("dumpsys package {}", shell_quote(package)))?;
+    let field = |key: &str| {
+        output.split_whitespace().find_map(|word| word.strip_prefix(key)).map(str::to_string)
+    };
+    let Some(version_code) = field("versionCode=").and_then(|code| code.parse().ok()) else {
+        return Ok(None);
+    };
+    Ok(Some(ApkInfo {
+        package: package.to_string(),
+        version_code,
+        version_name: field("versionName=").unwrap_or_default(),
+    }))
+}
+
+/// Package and version from the APK's manifest
+pub fn read_info(path: &Path) -> Result<ApkInfo> {
+    let manifest = read_zip_entry(path, "AndroidManifest.xml").with_context(|| format!("{} is not an APK", path.display()))?;
+    parse_manifest(&manifest).with_context(|| format!("Failed to read the manifest of {}", path.display()))
+}
+
+fn u16_at(data: &[u8], at: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(data.get(at..at + 2).context("Truncated data")?.try_into()?))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(data.get(at..at + 4).context("Truncated data")?.try_into()?))
+}
+
+/// One file out of a zip, found through the central directory
+fn read_zip_entry(path: &Path, name: &str) -> Result<Vec<u8>> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    // End of central directory: last record, followed by a comment of up to 64 KiB
+    let search_from = data.len().saturating_sub(22 + 65_535);
+    let eocd = (search_from..data.len().saturating_sub(21))
+        .rev()
+        .find(|&at| data[at..at + 4] == *b"PK\x05\x06")
+        .context("No zip end of central directory")?;
+    let entries = u16_at(&data, eocd + 10)? as usize;
+    let mut at = u32_at(&data, eocd + 16)? as usize;
+
+    for _ in 0..entries {
+        anyhow::ensure!(u32_at(&data, at)? == 0x0201_4b50, "Corrupt zip central directory");
+        let method = u16_at(&data, at + 10)?;
+        let compressed = u32_at(&data, at + 20)? as usize;
+        let size = u32_at(&data, at + 24)? as usize;
+        let name_len = u16_at(&data, at + 28)? as usize;
+        let extra_len = u16_at(&data, at + 30)? as usize;
+        let comment_len = u16_at(&data, at + 32)? as usize;
+        let local = u32_at(&data, at + 42)? as usize;
+        let entry_name = data.get(at + 46..at + 46 + name_len).context("Truncated zip")?;
+        at += 46 + name_len + extra_len + comment_len;
+        if entry_name != name.as_bytes() {
+            continue;
+        }
+
+        anyhow::ensure!(size <= MAX_MANIFEST_SIZE, "{} is too large ({} bytes)", name, size);
+        anyhow::ensure!(u32_at(&data, local)? == 0x0403_4b50, "Corrupt zip local header");
+        let start = local + 30 + u16_at(&data, local + 26)? as usize + u16_at(&data, local + 28)? as usize;
+        let raw = data.get(start..start + compressed).context("Truncated zip entry")?;
+        return match method {
+            0 => Ok(raw.to_vec()),
+            8 => {
+                let mut out = Vec::with_capacity(size);
+                flate2::read::DeflateDecoder::new(raw).take(MAX_MANIFEST_SIZE as u64).read_to_end(&mut out)?;
+                Ok(out)
+            }
+            other => anyhow::bail!("Unsupported zip compression method {}", other),
+        };
+    }
+    anyhow::bail!("No {} in the archive", name)
+}
+
+/// Strings of a binary XML string pool chunk starting at `chunk`
+fn parse_string_pool(data: &[u8], chunk: usize) -> Result<Vec<String>> {
+    let count = u32_at(data, chunk + 8)? as usize;
+    let flags = u32_at(data, chunk + 16)?;
+    let strings_start = chunk + u32_at(data, chunk + 20)? as usize;
+    let offsets = chunk + u16_at(data, chunk + 2)? as usize;
+
+    // Lengths take one unit, or two with the high bit set
+    let len8 = |at: &mut usize| -> Result<usize> {
+        let first = *data.get(*at).context("Truncated string pool")? as usize;
+        *at += 1;
+        if first & 0x80 == 0 {
+            return Ok(first);
+        }
+        let second = *data.get(*at).context("Truncated string pool")? as usize;
+        *at += 1;
+        Ok((first & 0x7f) << 8 | second)
+    };
+
+    let mut strings = Vec::with_capacity(count);
+    for i in 0..count {
+        let mut at = strings_start + u32_at(data, offsets + i * 4)? as usize;
+        let string = if flags & UTF8_FLAG != 0 {
+            let _utf16_len = len8(&mut at)?;
+            let len = len8(&mut at)?;
+            String::from_utf8_lossy(data.get(at..at + len).context("Truncated string pool")?).into_owned()
+        } else {
+            let mut len = u16_at(data, at)? as usize;
+            at += 2;
+            if len & 0x8000 != 0 {
+                len = (len & 0x7fff) << 16 | u16_at(data, at)? as usize;
+                at += 2;
+            }
+            let units: Vec<u16> = (0..len).map(|j| u16_at(data, at + j * 2)).collect::<Result<_>>()?;
+            String::from_utf16_lossy(&units)
+        };
+        strings.push(string);
+    }
+    Ok(strings)
+}
+
+/// `package`, `versionCode` and `versionName` of the `<manifest>` element
+fn parse_manifest(data: &[u8]) -> Result<ApkInfo> {
+    anyhow::ensure!(u16_at(data, 0)? == RES_XML, "Not a binary XML file");
+    let mut strings = Vec::new();
+    let mut resource_ids: Vec<u32> = Vec::new();
+    let mut at = u16_at(data, 2)? as usize;
+
+    while at + 8 <= data.len() {
+        let kind = u16_at(data, at)?;
+        let size = u32_at(data, at + 4)? as usize;
+        anyhow::ensure!(size >= 8, "Corrupt binary XML chunk");
+        match kind {
+            RES_STRING_POOL => strings = parse_string_pool(data, at)?,
+            RES_XML_RESOURCE_MAP => {
+                resource_ids = (at + 8..at + size).step_by(4).map(|i| u32_at(data, i)).collect::<Result<_>>()?;
+            }
+            RES_XML_START_ELEMENT => {
+                let string = |index: u32| strings.get(index as usize).cloned().unwrap_or_default();
+                if string(u32_at(data, at + 20)?) != "manifest" {
+                    at += size;
+                    continue;
+                }
+                let attributes = at + u16_at(data, at + 2)? as usize + u16_at(data, at + 24)? as usize;
+                let attribute_size = u16_at(data, at + 26)? as usize;
+                let attribute_count = u16_at(data, at + 28)? as usize;
+
+                let mut package = None;
+                let mut version_code = None;
+                let mut version_name = None;
+                for i in 0..attribute_count {
+                    let attribute = attributes + i * attribute_size;
+                    let name_index = u32_at(data, attribute + 4)?;
+                    let raw = u32_at(data, attribute + 8)?;
+                    let kind = *data.get(attribute + 15).context("Truncated attribute")?;
+                    let value = u32_at(data, attribute + 16)?;
+                    let text = || if raw != u32::MAX { string(raw) } else if kind == TYPE_STRING { string(value) } else { value.to_string() };
+                    let number = || if kind == TYPE_INT_DEC || kind == TYPE_INT_HEX { Some(value) } else { text().parse().ok() };
+
+                    let resource = resource_ids.get(name_index as usize).copied();
+                    match string(name_index).as_str() {
+                        "package" => package = Some(text()),
+                        "versionCode" => version_code = number(),
+                        "versionName" => version_name = Some(text()),
+                        _ if resource == Some(ATTR_VERSION_CODE) => version_code = number(),
+                        _ if resource == Some(ATTR_VERSION_NAME) => version_name = Some(text()),
+                        _ => {}
+                    }
+                }
+                return Ok(ApkInfo {
+                    package: package.context("The manifest has no package name")?,
+                    version_code: version_code.context("The manifest has no versionCode")? as u64,
+                    version_name: version_name.unwrap_or_default(),
+                });
+            }
+            _ => {}
+        }
+        at += size;
+    }
+    anyhow::bail!("No <manifest> element")
+}
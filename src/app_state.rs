@@ -4,10 +4,51 @@
 pub enum AppMessage {
     Log(String),
     Progress(f32, String),
+    /// The sysinfo payload just sent to the device
+    SysInfo(Box<crate::sysinfo::SysInfo>),
     Success(String),
     Error(String),
+    /// Serial port is held by another process, offer a retry
+    PortBusy(String),
+    State(crate::screen_setup::ConnectionState),
+    /// A serial node (re)appeared under /dev
+    DeviceAppeared(String),
+    /// Config + media made it onto the device
+    Applied(Box<crate::screen_setup::LastApplied>),
+    /// Stage timings of the transfer that just finished
+    Report(crate::timing::TransferReport),
+    /// Read-back result, one line per mismatched field (empty = verified)
+    Verification(Vec<String>),
+    /// Body of the device's `conn` handshake
+    Handshake(serde_json::Value),
+    /// A protocol console send and its replies
+    Console(Box<crate::console::ConsoleExchange>),
+    /// Contents of the device's media directory
+    MediaListed(Result<Vec<crate::media_browser::MediaFile>, String>),
+    MediaThumbnail(String, crate::media_browser::Thumbnail),
+    /// PNG of the device's screen, or why there is none
+    ScreenCapture(Result<std::sync::Arc<[u8]>, String>),
+    /// Output of the running ADB shell command, then how it ended
+    ShellOutput(String),
+    ShellFinished(Result<(), String>),
+    /// Display app pids and log lines from the running logcat
+    Logcat(crate::logcat::LogcatEvent),
+    /// A picked APK compared with the installed app, then the install result
+    ApkInspected(Result<Box<crate::apk::UpdatePlan>, String>),
+    ApkInstalled(Result<crate::apk::ApkInfo, String>),
+    /// getprop and friends from the Android side
+    DeviceInfo(Result<Box<crate::device_info::AndroidInfo>, String>),
+    /// Results of the setup checks
+    SetupChecked(Vec<crate::env_check::Check>),
 }
 
+/// Log lines kept for the status history
+const MAX_LOG_MESSAGES: usize = 100;
+/// Discrete events handled per frame, the rest wait for the next one
+const MAX_EVENTS_PER_FRAME: usize = 32;
+/// How often the settings are compared with what's in config.toml
+const SETTINGS_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
 /// Main App Structure
 pub struct AioCoolerApp {
 
@@ -16,123 +57,1202 @@ pub struct AioCoolerApp {
     pub screen_config: crate::screen_setup::ScreenConfig,
 
 
-    pub is_processing: bool,
+    pub connection_state: crate::screen_setup::ConnectionState,
     pub progress: f32,
     pub status_message: String,
-    pub log_messages: Vec<String>,
+    pub log_messages: std::collections::VecDeque<String>,
+    pub port_conflict: Option<String>,
+    /// Report left by a previous crash, shown once on launch
+    pub crash_report: Option<std::path::PathBuf>,
+    pub last_applied: Option<crate::screen_setup::LastApplied>,
+    pub last_report: Option<crate::timing::TransferReport>,
+    /// None until the device config has been read back and compared
+    pub config_mismatches: Option<Vec<String>>,
+    pub capability_store: crate::capabilities::CapabilityStore,
+    /// What the connected firmware supports, used to grey out options
+    pub capabilities: crate::capabilities::Capabilities,
+    /// Time scale for capture replay, 1.0 = original timing
+    pub replay_speed: f32,
+    /// Fake device on a pty, dropping it stops it
+    pub simulator: Option<crate::simulator::Simulator>,
+    /// Custom protocol profiles and the one picked for each device
+    pub profile_store: crate::protocol_profile::ProfileStore,
+    pub console: crate::console::ConsoleState,
+    /// What is stored on the device, listed over ADB
+    pub media: crate::media_browser::MediaBrowser,
+    pub preview: crate::screen_preview::ScreenPreview,
+    pub setup_check: crate::env_check::SetupCheck,
+    pub shell: crate::adb_shell::AdbShell,
+    pub logcat: crate::logcat::LogcatViewer,
+    pub app_update: crate::apk::AppUpdate,
+    pub device_info: crate::device_info::DeviceInfo,
+    pub device_control: crate::device_control::DeviceControl,
+    /// Body of this session's `conn` handshake
+    pub handshake: Option<serde_json::Value>,
+    /// Last sysinfo payload sent this session
+    pub last_sysinfo: Option<crate::sysinfo::SysInfo>,
+    /// Device controls queue commands here while the heartbeat holds the port
+    pub device_commands: (
+        crossbeam::channel::Sender<crate::data::DeviceCommand>,
+        crossbeam::channel::Receiver<crate::data::DeviceCommand>,
+    ),
+
 
+    pub message_sender: Option<crate::message_bus::MessageSender>,
+    pub message_receiver: crate::message_bus::MessageReceiver,
+    /// A daemon holds the port, transfers go through it instead
+    pub daemon: crate::ipc::DaemonProbe,
+    /// Keeps other windows out, None if it couldn't be claimed
+    pub instance: Option<crate::instance::Lock>,
+    /// A new window took over, so closing leaves the display to it
+    pub handed_off: bool,
+    /// Broker for the daemon's MQTT bridge, kept in config.toml
+    pub mqtt: crate::mqtt::MqttConfig,
+    /// The daemon's WebSocket status stream, kept in config.toml
+    pub websocket: crate::websocket::WebSocketConfig,
+    /// Shell commands around transfers, kept in config.toml
+    pub hooks: crate::hooks::HooksConfig,
+    /// What starts at login
+    pub autostart: crate::autostart::Autostart,
+    /// New profile name being typed
+    pub profile_name: String,
+    /// Profiles named by rules or game detection that can't be shown, with the reason
+    pub profile_problems: std::collections::HashMap<String, String>,
+    /// config.toml as last written, to only write it again when something changed
+    pub saved_settings: String,
+    pub settings_checked: Option<std::time::Instant>,
 
-    pub message_sender: Option<crossbeam::channel::Sender<AppMessage>>,
-    pub message_receiver: crossbeam::channel::Receiver<AppMessage>,
+    /// Set on exit so a running transfer stops its keepalive loop
+    pub cancel: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    pub worker: Option<std::thread::JoinHandle<()>>,
 }
 
 impl Default for AioCoolerApp {
     fn default() -> Self {
-        let (tx, rx) = crossbeam::channel::unbounded();
+        let (tx, rx) = crate::message_bus::channel();
+        let capability_store = crate::capabilities::CapabilityStore::load();
+        let settings = crate::settings::Settings::load();
+        let saved_settings = settings.to_toml().unwrap_or_default();
+        let profile_problems = crate::scripting::profile_problems(&settings.screen);
         Self {
-            serial_device: "/dev/ttyACM0".to_string(),
-            selected_image: None,
-            screen_config: crate::screen_setup::ScreenConfig::default(),
-            is_processing: false,
+            serial_device: settings.serial_device,
+            selected_image: settings.selected_image,
+            screen_config: crate::screen_setup::ScreenConfig {
+                adb: crate::adb::AdbConfig::load(),
+                ..settings.screen
+            },
+            connection_state: Default::default(),
             progress: 0.0,
             status_message: "Ready".to_string(),
-            log_messages: Vec::new(),
+            log_messages: Default::default(),
+            port_conflict: None,
+            crash_report: crate::diagnostics::take_last_crash(),
+            last_applied: crate::screen_setup::LastApplied::load(),
+            last_report: None,
+            config_mismatches: None,
+            capabilities: capability_store.current(),
+            capability_store,
+            replay_speed: settings.replay_speed,
+            simulator: None,
+            profile_store: crate::protocol_profile::ProfileStore::load(),
+            console: Default::default(),
+            media: Default::default(),
+            preview: Default::default(),
+            setup_check: Default::default(),
+            shell: Default::default(),
+            logcat: Default::default(),
+            app_update: Default::default(),
+            device_info: Default::default(),
+            device_control: Default::default(),
+            handshake: None,
+            last_sysinfo: None,
+            device_commands: crossbeam::channel::unbounded(),
             message_sender: Some(tx),
             message_receiver: rx,
+            daemon: Default::default(),
+            instance: None,
+            handed_off: false,
+            mqtt: settings.mqtt,
+            websocket: settings.websocket,
+            hooks: settings.hooks,
+            profile_name: String::new(),
+            profile_problems,
+            autostart: crate::autostart::Autostart::read(),
+            saved_settings,
+            settings_checked: None,
+            cancel: Default::default(),
+            worker: None,
         }
     }
 }
 
 impl AioCoolerApp {
+    /// Use `name` for the current serial device from the next session on
+    pub fn select_protocol_profile(&mut self, name: &str) {
+        self.profile_store.assign(&self.serial_device, name);
+        if let Err(e) = self.profile_store.save() {
+            log::warn!("Failed to save protocol profiles: {:#}", e);
+        }
+    }
+
+    /// Select a profile's image and take over its screen config, the ADB device stays
+    pub fn load_profile(&mut self, name: &str) {
+        match crate::profiles::load(name) {
+            Ok(profile) => {
+                self.selected_image = Some(profile.image);
+                self.screen_config = crate::screen_setup::ScreenConfig { adb: self.screen_config.adb.clone(), ..profile.config };
+                self.profile_name = name.to_string();
+                self.status_message = format!("Loaded profile {}, transfer to show it", name);
+            }
+            Err(e) => self.status_message = format!("{:#}", e),
+        }
+    }
+
+    /// The profile in the name field, if its image is the one selected, so the
+    /// hooks can tell a transfer switches to it
+    fn loaded_profile(&self) -> Option<String> {
+        let name = self.profile_name.trim();
+        let profile = crate::profiles::load(name).ok()?;
+        (Some(&profile.image) == self.selected_image.as_ref()).then(|| name.to_string())
+    }
+
+    pub fn save_profile(&mut self) {
+        let Some(image) = self.selected_image.clone() else {
+            return;
+        };
+        let name = self.profile_name.trim().to_string();
+        let profile = crate::profiles::Profile { image, config: self.screen_config.clone() };
+        self.status_message = match crate::profiles::save(&name, &profile) {
+            Ok(()) => format!("Saved profile {}", name),
+            Err(e) => format!("{:#}", e),
+        };
+        self.check_profiles();
+    }
+
+    pub fn delete_profile(&mut self) {
+        let name = self.profile_name.trim().to_string();
+        self.status_message = match crate::profiles::delete(&name) {
+            Ok(()) => format!("Deleted profile {}", name),
+            Err(e) => format!("{:#}", e),
+        };
+        self.check_profiles();
+    }
+
+    /// Remember the ADB device choice across runs
+    pub fn save_adb_config(&self) {
+        if let Err(e) = self.screen_config.adb.save() {
+            log::warn!("Failed to save ADB settings: {:#}", e);
+        }
+    }
+
+    /// Write config.toml if anything in it changed, looked at once a second
+    /// unless `now`
+    pub fn save_settings(&mut self, now: bool) {
+        if !now && self.settings_checked.is_some_and(|at| at.elapsed() < SETTINGS_CHECK_INTERVAL) {
+            return;
+        }
+        self.settings_checked = Some(std::time::Instant::now());
+        let settings = crate::settings::Settings {
+            serial_device: self.serial_device.clone(),
+            selected_image: self.selected_image.clone(),
+            replay_speed: self.replay_speed,
+            screen: self.screen_config.clone(),
+            mqtt: self.mqtt.clone(),
+            websocket: self.websocket.clone(),
+            hooks: self.hooks.clone(),
+        };
+        let content = match settings.to_toml() {
+            Ok(content) => content,
+            Err(e) => {
+                log::warn!("Failed to serialize settings: {:#}", e);
+                return;
+            }
+        };
+        if content == self.saved_settings {
+            return;
+        }
+        if let Err(e) = crate::settings::Settings::write(&content) {
+            log::warn!("Failed to save settings: {:#}", e);
+        }
+        // Not retried every second after a failure, only on the next change
+        self.saved_settings = content;
+        self.check_profiles();
+    }
+
+    /// Look again at whether the profiles the rules and game detection name can be shown
+    pub fn check_profiles(&mut self) {
+        self.profile_problems = crate::scripting::profile_problems(&self.screen_config);
+    }
+
+    /// Bounded per-frame work: one progress value, at most MAX_LOG_MESSAGES
+    /// lines and MAX_EVENTS_PER_FRAME events, regardless of how chatty workers are
     pub fn process_messages(&mut self) {
-        while let Ok(msg) = self.message_receiver.try_recv() {
-            match msg {
-                AppMessage::Log(text) => {
-                    self.log_messages.push(text);
-                    if self.log_messages.len() > 100 {
-                        self.log_messages.remove(0);
+        let batch = self.message_receiver.drain(MAX_EVENTS_PER_FRAME);
+
+        if let Some((progress, status)) = batch.progress {
+            self.progress = progress;
+            self.status_message = status;
+        }
+        if let Some(info) = batch.sysinfo {
+            self.last_sysinfo = Some(*info);
+        }
+
+        if batch.dropped_logs > 0 {
+            self.push_log(format!("... {} log lines dropped", batch.dropped_logs));
+        }
+        let skip = batch.logs.len().saturating_sub(MAX_LOG_MESSAGES);
+        for line in batch.logs.into_iter().skip(skip) {
+            self.push_log(line);
+        }
+
+        for msg in batch.events {
+            self.handle_message(msg);
+        }
+    }
+
+    fn push_log(&mut self, line: String) {
+        if self.log_messages.len() >= MAX_LOG_MESSAGES {
+            self.log_messages.pop_front();
+        }
+        self.log_messages.push_back(line);
+    }
+
+    fn handle_message(&mut self, msg: AppMessage) {
+        match msg {
+            AppMessage::Log(text) => self.push_log(text),
+            AppMessage::Progress(progress, status) => {
+                self.progress = progress;
+                self.status_message = status;
+            }
+            AppMessage::SysInfo(info) => self.last_sysinfo = Some(*info),
+            AppMessage::Success(msg) => {
+                self.progress = 1.0;
+                self.status_message = msg;
+            }
+            AppMessage::Error(msg) => {
+                crate::diagnostics::record_log(&format!("Error: {}", msg));
+                self.progress = 0.0;
+                self.status_message = format!("Error: {}", msg);
+            }
+            AppMessage::PortBusy(msg) => {
+                self.progress = 0.0;
+                self.status_message = "Error: serial port is busy".to_string();
+                self.port_conflict = Some(msg);
+            }
+            AppMessage::State(state) => {
+                self.connection_state = state;
+            }
+            AppMessage::DeviceAppeared(device) => {
+                let hotplug = &self.screen_config.hotplug;
+                // A running heartbeat reconnects by itself
+                if hotplug.auto_start && !self.worker_running() && hotplug.matches(&device, &self.serial_device) {
+                    if device != self.serial_device {
+                        log::info!("Cooler is now {}", device);
+                        self.serial_device = device;
+                    }
+                    if hotplug.reapply && self.last_applied.is_some() {
+                        self.reapply_last();
+                    } else {
+                        self.start_stream();
                     }
                 }
-                AppMessage::Progress(progress, status) => {
-                    self.progress = progress;
-                    self.status_message = status;
+            }
+            AppMessage::Applied(mut last) => {
+                // Learn the cooler's USB id so hotplug finds it on any port
+                if self.screen_config.hotplug.usb_id.is_empty()
+                    && let Some(id) = crate::hotplug::usb_id(&self.serial_device)
+                {
+                    log::info!("Cooler USB id: {}", id);
+                    self.screen_config.hotplug.usb_id = id.clone();
+                    last.config.hotplug.usb_id = id;
+                }
+                if let Err(e) = last.save() {
+                    log::warn!("Failed to persist last applied config: {:#}", e);
+                }
+                self.last_applied = Some(*last);
+                // Using a profile puts its image on the device
+                self.check_profiles();
+            }
+            AppMessage::Report(report) => {
+                self.last_report = Some(report);
+            }
+            AppMessage::Verification(mismatches) => {
+                self.config_mismatches = Some(mismatches);
+            }
+            AppMessage::Handshake(body) => {
+                let version = crate::capabilities::handshake_version(&body).unwrap_or_else(|| "unknown".to_string());
+                let caps = crate::capabilities::Capabilities::from_handshake(&body);
+                self.capability_store.learn(&version, caps.clone());
+                if let Err(e) = self.capability_store.save() {
+                    log::warn!("Failed to save capabilities: {:#}", e);
                 }
-                AppMessage::Success(msg) => {
-                    self.is_processing = false;
+                self.capabilities = caps;
+                self.handshake = Some(body);
+            }
+            AppMessage::Console(exchange) => {
+                self.console.record(*exchange);
+            }
+            AppMessage::MediaListed(result) => {
+                if let Err(e) = &result {
+                    log::error!("Failed to list device media: {}", e);
+                }
+                self.media.set_listing(result);
+            }
+            AppMessage::MediaThumbnail(name, thumbnail) => {
+                self.media.set_thumbnail(&name, thumbnail);
+            }
+            AppMessage::ScreenCapture(result) => {
+                if let Err(e) = &result {
+                    log::error!("Screen capture failed: {}", e);
+                }
+                self.preview.set_capture(result);
+            }
+            AppMessage::ShellOutput(text) => self.shell.append(&text),
+            AppMessage::ShellFinished(result) => self.shell.finish(result),
+            AppMessage::Logcat(event) => self.logcat.handle(event),
+            AppMessage::ApkInspected(result) => {
+                if let Err(e) = &result {
+                    log::error!("APK check failed: {}", e);
+                }
+                self.app_update.set_plan(result.map(|plan| *plan));
+            }
+            AppMessage::ApkInstalled(result) => match result {
+                Ok(installed) => {
+                    self.status_message = format!("Installed {} {}", installed.package, installed.version());
+                    log::info!("{}", self.status_message);
                     self.progress = 1.0;
-                    self.status_message = msg;
+                    if let Some(plan) = &mut self.app_update.plan {
+                        plan.installed = Some(installed);
+                    }
                 }
-                AppMessage::Error(msg) => {
-                    self.is_processing = false;
+                Err(e) => {
+                    self.status_message = format!("Error: {}", e);
+                    log::error!("APK install failed: {}", e);
                     self.progress = 0.0;
-                    self.status_message = format!("Error: {}", msg);
                 }
+            },
+            AppMessage::DeviceInfo(result) => {
+                if let Err(e) = &result {
+                    log::error!("Device info query failed: {}", e);
+                }
+                self.device_info.set_android(result);
+            }
+            AppMessage::SetupChecked(results) => {
+                for check in results.iter().filter(|check| check.status != crate::env_check::Status::Ok) {
+                    log::warn!("Setup check {}: {}", check.name, check.detail);
+                }
+                self.setup_check.set_results(results);
             }
         }
     }
 
-    pub fn start_transfer(&mut self) {
-        if self.is_processing {
+    /// Forward hotplug events into the message loop and wake the UI so they get handled
+    pub fn start_device_watcher(&self, ctx: egui::Context) {
+        let tx = self.message_sender.clone().unwrap();
+        crate::hotplug::watch(move |device| {
+            let _ = tx.send(AppMessage::DeviceAppeared(device));
+            ctx.request_repaint();
+        });
+    }
+
+    /// At login the cooler is already there, so handle it as if it had just been plugged in
+    pub fn start_if_present(&mut self) {
+        let hotplug = &self.screen_config.hotplug;
+        let mut present: Vec<String> = crate::serial::list_tty_devices().into_iter().collect();
+        present.sort();
+        // The configured port first, in case another node matches as well
+        if let Some(device) = std::iter::once(self.serial_device.clone())
+            .chain(present)
+            .find(|device| std::path::Path::new(device).exists() && hotplug.matches(device, &self.serial_device))
+        {
+            self.handle_message(AppMessage::DeviceAppeared(device));
+        }
+    }
+
+    pub fn set_autostart_window(&mut self, enable: bool) {
+        match crate::autostart::set_window(enable) {
+            Ok(()) => self.autostart.window = enable,
+            Err(e) => self.status_message = format!("Error: {:#}", e),
+        }
+    }
+
+    pub fn set_autostart_service(&mut self, enable: bool) {
+        match crate::systemd::enable_service(enable, &self.serial_device) {
+            Ok(()) => {
+                self.autostart.service = Some(enable);
+                self.status_message = if enable {
+                    "The daemon starts at the next login".to_string()
+                } else {
+                    "The daemon no longer starts at login".to_string()
+                };
+            }
+            Err(e) => self.status_message = format!("Error: {:#}", e),
+        }
+    }
+
+    /// Re-send the last applied config (media is already on the device) and resume keepalives
+    pub fn reapply_last(&mut self) {
+        if self.is_busy() {
             return;
         }
+        let Some(last) = self.last_applied.clone() else {
+            return;
+        };
+        // The daemon re-applies on its own
+        if self.daemon.running() {
+            return;
+        }
+        self.stop_worker();
 
-        let Some(image_path) = self.selected_image.clone() else {
-            self.status_message = "No image selected".to_string();
+        log::info!("Re-applying last configuration ({})", last.media);
+        self.cancel.store(false, std::sync::atomic::Ordering::Relaxed);
+        self.status_message = "Device reconnected, re-applying configuration...".to_string();
+
+        let serial_device = self.serial_device.clone();
+        let retry = self.screen_config.retry.clone();
+        let tx = self.message_sender.clone().unwrap();
+        let cancel = self.cancel.clone();
+        let commands = self.device_commands.1.clone();
+
+        self.worker = Some(std::thread::spawn(move || {
+            // Let the device finish booting its serial service
+            std::thread::sleep(std::time::Duration::from_secs(2));
+
+            let controller = crate::AioCoolerController::new(&serial_device)
+                .with_cancel(cancel)
+                .with_retry(retry)
+                .with_events(tx.clone())
+                .with_commands(commands);
+            if let Err(e) = controller.send_image_commands(&last.media, 0, "", None, &last.config) {
+                let _ = tx.send(AppMessage::Error(format!("Re-apply failed: {:#}", e)));
+                return;
+            }
+            let _ = tx.send(AppMessage::Success("Configuration re-applied".to_string()));
+            if let Err(e) = controller.heartbeat(&last.config, &last.media) {
+                let _ = tx.send(AppMessage::Error(format!("Heartbeat stopped: {:#}", e)));
+            }
+        }));
+    }
+
+    /// Stream sysinfo to the device without sending a screen config, as after a
+    /// hotplug when re-applying is off
+    pub fn start_stream(&mut self) {
+        if self.is_busy() || self.daemon.running() {
             return;
+        }
+        self.stop_worker();
+
+        log::info!("Starting the sysinfo stream on {}", self.serial_device);
+        self.cancel.store(false, std::sync::atomic::Ordering::Relaxed);
+        self.status_message = "Device connected, streaming sysinfo...".to_string();
+
+        let serial_device = self.serial_device.clone();
+        let config = self.screen_config.clone();
+        let tx = self.message_sender.clone().unwrap();
+        let cancel = self.cancel.clone();
+        let commands = self.device_commands.1.clone();
+
+        self.worker = Some(std::thread::spawn(move || {
+            // Let the device finish booting its serial service
+            std::thread::sleep(std::time::Duration::from_secs(2));
+
+            let controller = crate::AioCoolerController::new(&serial_device)
+                .with_cancel(cancel)
+                .with_retry(config.retry.clone())
+                .with_events(tx.clone())
+                .with_commands(commands);
+            if let Err(e) = controller.start_stream(&config) {
+                let _ = tx.send(AppMessage::Error(format!("Starting the stream failed: {:#}", e)));
+                return;
+            }
+            if let Err(e) = controller.heartbeat(&config, "") {
+                let _ = tx.send(AppMessage::Error(format!("Heartbeat stopped: {:#}", e)));
+            }
+        }));
+    }
+
+    /// Re-send the TX frames of a capture file to the device
+    pub fn start_replay(&mut self, path: std::path::PathBuf) {
+        if self.is_busy() {
+            return;
+        }
+        self.stop_worker();
+        let frames = match crate::replay::load(&path) {
+            Ok(frames) => frames,
+            Err(e) => {
+                self.status_message = format!("Error: {:#}", e);
+                log::error!("Failed to load capture: {:#}", e);
+                return;
+            }
         };
 
-        self.is_processing = true;
+        log::info!("Replaying {} frames from {}", frames.len(), path.display());
+        self.cancel.store(false, std::sync::atomic::Ordering::Relaxed);
         self.progress = 0.0;
-        self.status_message = "Starting transfer...".to_string();
+        self.status_message = format!("Replaying {}...", path.display());
 
         let serial_device = self.serial_device.clone();
-        let config = self.screen_config.clone();
+        let speed = self.replay_speed;
+        let serial = self.screen_config.serial.clone();
+        let retry = self.screen_config.retry.clone();
         let tx = self.message_sender.clone().unwrap();
+        let cancel = self.cancel.clone();
+
+        self.worker = Some(std::thread::spawn(move || {
+            let controller = crate::AioCoolerController::new(&serial_device)
+                .with_cancel(cancel)
+                .with_retry(retry)
+                .with_events(tx.clone());
+            match controller.replay(&frames, speed, &serial) {
+                Ok(()) => {
+                    let _ = tx.send(AppMessage::Success("Replay finished".to_string()));
+                }
+                Err(e) => {
+                    let _ = tx.send(AppMessage::Error(format!("Replay failed: {:#}", e)));
+                }
+            }
+        }));
+    }
 
-        std::thread::spawn(move || {
-            let result = (|| -> anyhow::Result<(), anyhow::Error> {
-                let _ = tx.send(AppMessage::Progress(0.1, "Calculating MD5...".to_string()));
-                let _ = tx.send(AppMessage::Log("Calculating file MD5...".to_string()));
+    /// Send what's in the protocol console and collect the replies
+    pub fn start_console_send(&mut self) {
+        if self.worker_running() {
+            return;
+        }
+        let frame = match self.console.build() {
+            Ok(frame) => frame,
+            Err(e) => {
+                self.status_message = format!("Error: {:#}", e);
+                return;
+            }
+        };
+        self.stop_worker();
+        self.cancel.store(false, std::sync::atomic::Ordering::Relaxed);
+        self.status_message = format!("Console: sent {} {}", frame.method, frame.cmd_type);
 
-                let file_md5 = crate::AioCoolerController::calculate_md5(&image_path)?;
-                let file_size = std::fs::metadata(&image_path)?.len();
+        let serial_device = self.serial_device.clone();
+        let listen = self.console.listen();
+        let serial = self.screen_config.serial.clone();
+        let retry = self.screen_config.retry.clone();
+        let tx = self.message_sender.clone().unwrap();
+        let cancel = self.cancel.clone();
 
-                let extension = image_path
-                    .extension()
-                    .and_then(|e| e.to_str())
-                    .unwrap_or("png");
-                let remote_name = crate::AioCoolerController::generate_filename(extension);
+        self.worker = Some(std::thread::spawn(move || {
+            let controller = crate::AioCoolerController::new(&serial_device)
+                .with_cancel(cancel)
+                .with_retry(retry)
+                .with_events(tx.clone());
+            let result = controller.exchange(&frame, listen, &serial);
+            let exchange = crate::console::ConsoleExchange::new(&frame, result);
+            let status = match &exchange.error {
+                Some(e) => format!("Console: {}", e),
+                None => format!("Console: {} repl{}", exchange.replies.len(), if exchange.replies.len() == 1 { "y" } else { "ies" }),
+            };
+            let _ = tx.send(AppMessage::Console(Box::new(exchange)));
+            let _ = tx.send(AppMessage::Log(status));
+        }));
+    }
 
-                let _ = tx.send(AppMessage::Log(format!(
-                    "File: {} ({} bytes, MD5: {})",
-                    image_path.display(),
-                    file_size,
-                    file_md5
-                )));
+    /// List the device's media over ADB, then fetch thumbnails in the background
+    pub fn start_media_refresh(&mut self) {
+        self.start_media_worker(Vec::new());
+    }
 
-                let _ = tx.send(AppMessage::Progress(0.2, "Pushing to device via ADB...".to_string()));
-                let _ = tx.send(AppMessage::Log("Starting ADB push...".to_string()));
+    /// Ticked files that may be deleted: everything but what the display shows
+    pub fn deletable_selection(&self) -> Vec<String> {
+        let on_screen = self.last_applied.as_ref().map(|last| last.media.as_str());
+        self.media.selected.iter().filter(|name| Some(name.as_str()) != on_screen).cloned().collect()
+    }
 
-                let controller = crate::AioCoolerController::new(&serial_device);
-                controller.adb_push(&image_path, &remote_name)?;
+    /// Remove the ticked files from the device, then list again
+    pub fn delete_selected_media(&mut self) {
+        self.media.confirm_delete = false;
+        let names = self.deletable_selection();
+        if !names.is_empty() {
+            self.start_media_worker(names);
+        }
+    }
 
-                let _ = tx.send(AppMessage::Progress(0.5, "Sending serial commands...".to_string()));
-                let _ = tx.send(AppMessage::Log("Sending serial commands...".to_string()));
+    /// Copy the ticked files from the device into `dir`
+    pub fn download_selected_media(&mut self, dir: std::path::PathBuf) {
+        let names: Vec<String> = self.media.selected.iter().cloned().collect();
+        if names.is_empty() || self.media.running() {
+            return;
+        }
+        let adb = self.screen_config.adb.clone();
+        let tx = self.message_sender.clone().unwrap();
 
-                controller.send_image_commands(&remote_name, file_size, &file_md5, &config)?;
+        self.media.worker = Some(std::thread::spawn(move || {
+            let count = names.len();
+            let result = crate::media_browser::download(&adb, &names, &dir, |i, name, fraction| {
+                let _ = tx.send(AppMessage::Progress(
+                    (i as f32 + fraction) / count as f32,
+                    format!("Downloading {} ({}/{})...", name, i + 1, count),
+                ));
+            });
+            let _ = match result {
+                Ok(saved) => tx.send(AppMessage::Success(format!("Downloaded {} file(s) to {}", saved.len(), dir.display()))),
+                Err(e) => tx.send(AppMessage::Error(format!("{:#}", e))),
+            };
+        }));
+    }
 
-                let _ = tx.send(AppMessage::Log("Transfer complete!".to_string()));
-                Ok(())
+    fn start_media_worker(&mut self, delete: Vec<String>) {
+        if self.media.running() {
+            return;
+        }
+        let adb = self.screen_config.adb.clone();
+        let loaded = self.media.loaded_thumbnails();
+        let tx = self.message_sender.clone().unwrap();
+
+        self.media.worker = Some(std::thread::spawn(move || {
+            let result = (|| -> anyhow::Result<Vec<crate::media_browser::MediaFile>> {
+                if !delete.is_empty() {
+                    crate::media_browser::delete(&adb, &delete)?;
+                }
+                crate::media_browser::list(&adb)
             })();
+            let files = match result {
+                Ok(files) => files,
+                Err(e) => {
+                    let _ = tx.send(AppMessage::MediaListed(Err(format!("{:#}", e))));
+                    return;
+                }
+            };
+
+            if !delete.is_empty() {
+                let left: Vec<&str> = delete
+                    .iter()
+                    .filter(|name| files.iter().any(|file| &&file.name == name))
+                    .map(String::as_str)
+                    .collect();
+                if left.is_empty() {
+                    let _ = tx.send(AppMessage::Success(format!("Deleted {} file(s) from the device", delete.len())));
+                } else {
+                    let _ = tx.send(AppMessage::Error(format!("Could not delete {}", left.join(", "))));
+                }
+            }
+
+            // Forget cached pushes that are no longer on the device
+            let names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+            let mut cache = crate::media_cache::MediaCache::load();
+            cache.retain_only(&names);
+            if let Err(e) = cache.save() {
+                log::warn!("Failed to save media cache: {:#}", e);
+            }
+
+            let _ = tx.send(AppMessage::MediaListed(Ok(files.clone())));
+            for file in files
+                .iter()
+                .filter(|file| matches!(file.thumbnail, crate::media_browser::Thumbnail::Pending) && !loaded.contains(&file.name))
+            {
+                let thumbnail = crate::media_browser::thumbnail(&adb, file);
+                let _ = tx.send(AppMessage::MediaThumbnail(file.name.clone(), thumbnail));
+            }
+        }));
+    }
+
+    /// Grab what the display shows with screencap over ADB
+    pub fn start_screen_capture(&mut self) {
+        if self.preview.running() {
+            return;
+        }
+        self.preview.mark_started();
+        let adb = self.screen_config.adb.clone();
+        let tx = self.message_sender.clone().unwrap();
+
+        self.preview.worker = Some(std::thread::spawn(move || {
+            let result = crate::screen_preview::capture(&adb).map_err(|e| format!("{:#}", e));
+            let _ = tx.send(AppMessage::ScreenCapture(result));
+        }));
+    }
+
+    /// Run the shell panel's input line on the device
+    pub fn start_shell_command(&mut self) {
+        if self.shell.running() {
+            return;
+        }
+        let Some((command, cwd)) = self.shell.begin() else {
+            return;
+        };
+        let adb = self.screen_config.adb.clone();
+        let cancel = self.shell.cancel.clone();
+        let tx = self.message_sender.clone().unwrap();
+
+        self.shell.worker = Some(std::thread::spawn(move || {
+            let result = crate::adb_shell::run(&adb, &command, &cwd, &cancel, |text| {
+                let _ = tx.send(AppMessage::ShellOutput(text));
+            });
+            let _ = tx.send(AppMessage::ShellFinished(result.map_err(|e| format!("{:#}", e))));
+        }));
+    }
+
+    /// Follow the device's logcat until the viewer is stopped
+    pub fn start_logcat(&mut self) {
+        if self.logcat.running() {
+            return;
+        }
+        self.logcat.begin();
+        let adb = self.screen_config.adb.clone();
+        let cancel = self.logcat.cancel.clone();
+        let tx = self.message_sender.clone().unwrap();
+
+        self.logcat.worker = Some(std::thread::spawn(move || {
+            let result = crate::logcat::stream(&adb, &cancel, |event| {
+                let _ = tx.send(AppMessage::Logcat(event));
+            });
+            let _ = tx.send(AppMessage::Logcat(crate::logcat::LogcatEvent::Stopped(result.map_err(|e| format!("{:#}", e)))));
+        }));
+    }
+
+    /// Ask the Android side what it is
+    pub fn start_device_info(&mut self) {
+        if self.device_info.running() {
+            return;
+        }
+        let adb = self.screen_config.adb.clone();
+        let tx = self.message_sender.clone().unwrap();
+
+        self.device_info.worker = Some(std::thread::spawn(move || {
+            let result = crate::device_info::query(&adb).map(Box::new).map_err(|e| format!("{:#}", e));
+            let _ = tx.send(AppMessage::DeviceInfo(result));
+        }));
+    }
+
+    /// Turn the panel on or off, in the running session or over a port of its own
+    pub fn set_screen_power(&mut self, on: bool) {
+        let command = crate::data::DeviceCommand::ScreenPower(on);
+        let done = if on { "Screen on" } else { "Screen off" };
+        if self.heartbeat_running() {
+            let _ = self.device_commands.0.send(command);
+            self.status_message = done.to_string();
+            return;
+        }
+        if self.worker_running() {
+            self.status_message = "Busy, try again once the transfer is done".to_string();
+            return;
+        }
+        let serial_device = self.serial_device.clone();
+        let serial = self.screen_config.serial.clone();
+        let retry = self.screen_config.retry.clone();
+        let tx = self.message_sender.clone().unwrap();
+
+        self.worker = Some(std::thread::spawn(move || {
+            let controller = crate::AioCoolerController::new(&serial_device).with_retry(retry).with_events(tx.clone());
+            let _ = match controller.send_once(&command, &serial) {
+                Ok(()) => tx.send(AppMessage::Success(done.to_string())),
+                Err(e) => tx.send(AppMessage::Error(format!("{:#}", e))),
+            };
+        }));
+    }
+
+    /// Force-stop and relaunch the display app on the cooler
+    pub fn restart_display_app(&mut self) {
+        if self.device_control.running() {
+            return;
+        }
+        let adb = self.screen_config.adb.clone();
+        let tx = self.message_sender.clone().unwrap();
+        self.status_message = "Restarting the display app...".to_string();
+
+        self.device_control.worker = Some(std::thread::spawn(move || {
+            let _ = match crate::device_control::restart_display_app(&adb) {
+                Ok(package) => tx.send(AppMessage::Success(format!("Restarted {}", package))),
+                Err(e) => tx.send(AppMessage::Error(format!("{:#}", e))),
+            };
+        }));
+    }
+
+    /// Reboot the Android side, the device watcher re-applies once it's back
+    pub fn reboot_device(&mut self) {
+        self.device_control.confirm_reboot = false;
+        if self.device_control.running() {
+            return;
+        }
+        let adb = self.screen_config.adb.clone();
+        let tx = self.message_sender.clone().unwrap();
+
+        self.device_control.worker = Some(std::thread::spawn(move || {
+            let _ = match crate::device_control::reboot(&adb) {
+                Ok(()) => tx.send(AppMessage::Success("Cooler is rebooting".to_string())),
+                Err(e) => tx.send(AppMessage::Error(format!("{:#}", e))),
+            };
+        }));
+    }
+
+    /// Set the cooler's clock and timezone to this machine's
+    pub fn sync_device_clock(&mut self) {
+        if self.device_control.running() {
+            return;
+        }
+        let adb = self.screen_config.adb.clone();
+        let tx = self.message_sender.clone().unwrap();
+
+        self.device_control.worker = Some(std::thread::spawn(move || {
+            let _ = match crate::device_control::sync_clock(&adb) {
+                Ok(sync) => tx.send(AppMessage::Success(sync.describe())),
+                Err(e) => tx.send(AppMessage::Error(format!("{:#}", e))),
+            };
+        }));
+    }
+
+    /// Read an APK and look up the installed version of its package
+    pub fn inspect_apk(&mut self, path: std::path::PathBuf) {
+        if self.app_update.running() {
+            return;
+        }
+        let adb = self.screen_config.adb.clone();
+        let tx = self.message_sender.clone().unwrap();
+
+        self.app_update.worker = Some(std::thread::spawn(move || {
+            let result = crate::apk::inspect(&adb, &path).map(Box::new).map_err(|e| format!("{:#}", e));
+            let _ = tx.send(AppMessage::ApkInspected(result));
+        }));
+    }
+
+    /// Push and install the inspected APK
+    pub fn install_apk(&mut self) {
+        let Some(plan) = self.app_update.plan.clone() else {
+            return;
+        };
+        if self.app_update.running() {
+            return;
+        }
+        let allow_downgrade = self.app_update.allow_downgrade;
+        let adb = self.screen_config.adb.clone();
+        let tx = self.message_sender.clone().unwrap();
+        self.progress = 0.0;
+
+        self.app_update.worker = Some(std::thread::spawn(move || {
+            let result = crate::apk::install(&adb, &plan, allow_downgrade, |sent, total| {
+                let _ = tx.send(AppMessage::Progress(
+                    0.9 * sent as f32 / total.max(1) as f32,
+                    format!("Pushing {}... {}%", plan.apk.package, sent * 100 / total.max(1)),
+                ));
+            });
+            let _ = tx.send(AppMessage::Progress(0.9, "Installing...".to_string()));
+            let _ = tx.send(AppMessage::ApkInstalled(result.map_err(|e| format!("{:#}", e))));
+        }));
+    }
+
+    /// Check serial and ADB access in the background, results end up in `setup_check`
+    pub fn start_setup_check(&mut self) {
+        if self.setup_check.running() {
+            return;
+        }
+        let serial_device = self.serial_device.clone();
+        let transfer_method = self.screen_config.transfer_method;
+        let adb = self.screen_config.adb.clone();
+        let tx = self.message_sender.clone().unwrap();
+
+        self.setup_check.worker = Some(std::thread::spawn(move || {
+            let results = crate::env_check::run(&serial_device, transfer_method, &adb);
+            let _ = tx.send(AppMessage::SetupChecked(results));
+        }));
+    }
+
+    /// Start the pty device simulator and point the serial device at it
+    pub fn start_simulator(&mut self) {
+        match crate::simulator::Simulator::start() {
+            Ok(simulator) => {
+                self.serial_device = simulator.path().display().to_string();
+                self.status_message = format!("Simulator on {}", self.serial_device);
+                self.simulator = Some(simulator);
+            }
+            Err(e) => {
+                self.status_message = format!("Error: {:#}", e);
+                log::error!("Failed to start simulator: {:#}", e);
+            }
+        }
+    }
+
+    /// Stop whatever is talking to the simulator first, then the simulator
+    pub fn stop_simulator(&mut self) {
+        self.stop_worker();
+        self.simulator = None;
+        self.status_message = "Simulator stopped".to_string();
+    }
+
+    /// Tooltip for options the connected firmware doesn't support
+    pub fn unsupported_hint(&self, feature: &str) -> String {
+        match &self.capability_store.last_version {
+            Some(version) => format!("{} is not supported by firmware {}", feature, version),
+            None => format!("{} is not supported by this firmware", feature),
+        }
+    }
+
+    pub fn worker_running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|w| !w.is_finished())
+    }
+
+    /// A worker thread is doing real work (hashing, pushing or applying a config)
+    pub fn is_busy(&self) -> bool {
+        self.worker_running() && !self.heartbeat_running()
+    }
+
+    /// The config is applied and the worker is only sending keepalives
+    pub fn heartbeat_running(&self) -> bool {
+        self.worker_running()
+            && matches!(
+                self.connection_state,
+                crate::screen_setup::ConnectionState::Streaming | crate::screen_setup::ConnectionState::Reconnecting
+            )
+    }
+
+    /// Idle, or only keeping the display alive (a new transfer replaces the heartbeat)
+    pub fn can_start_session(&self) -> bool {
+        !self.is_busy() && (self.connection_state.can_transfer() || self.heartbeat_running())
+    }
+
+    pub fn can_transfer(&self) -> bool {
+        self.can_start_session() && self.selected_image.is_some()
+    }
+
+    /// Cancel the worker and wait for it, still handling its messages meanwhile
+    /// so one blocked on a full queue can finish
+    fn stop_worker(&mut self) {
+        self.cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            while !worker.is_finished() {
+                self.process_messages();
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            let _ = worker.join();
+            self.process_messages();
+        }
+    }
+
+    /// Ask the running transfer to wind down after its current command
+    pub fn stop(&mut self) {
+        self.cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+        self.status_message = "Stopping...".to_string();
+    }
+
+    pub fn start_transfer(&mut self) {
+        if self.is_busy() {
+            return;
+        }
+        self.stop_worker();
+
+        let Some(image_path) = self.selected_image.clone() else {
+            self.status_message = "No image selected".to_string();
+            return;
+        };
+
+        self.cancel.store(false, std::sync::atomic::Ordering::Relaxed);
+        self.port_conflict = None;
+        self.config_mismatches = None;
+        self.progress = 0.0;
+        self.status_message = "Starting transfer...".to_string();
+
+        if self.daemon.running() {
+            let image = std::fs::canonicalize(&image_path).unwrap_or(image_path);
+            let profile = self.loaded_profile();
+            self.start_daemon_request(crate::ipc::Request::Send { image, config: Box::new(self.screen_config.clone()), profile });
+            return;
+        }
+
+        let serial_device = self.serial_device.clone();
+        let config = self.screen_config.clone();
+        let tx = self.message_sender.clone().unwrap();
+        let cancel = self.cancel.clone();
+        let commands = self.device_commands.1.clone();
+        let profile = self.loaded_profile();
+
+        self.worker = Some(std::thread::spawn(move || {
+            let controller = crate::AioCoolerController::new(&serial_device)
+                .with_cancel(cancel)
+                .with_retry(config.retry.clone())
+                .with_adb(config.adb.clone())
+                .with_events(tx.clone())
+                .with_commands(commands);
+            let mut report = crate::timing::TransferReport::default();
+
+            let result = transfer_image(&controller, &image_path, &config, &tx, &mut report, profile.as_deref());
+
+            report.extend(controller.take_timings());
+            log::info!("{}", report.summary());
+            let _ = tx.send(AppMessage::Report(report));
 
             match result {
-                Ok(()) => {
+                Ok(remote_name) => {
                     let _ = tx.send(AppMessage::Success("Transfer complete!".to_string()));
+                    if let Err(e) = controller.heartbeat(&config, &remote_name) {
+                        let _ = tx.send(AppMessage::Error(format!("Heartbeat stopped: {:#}", e)));
+                    }
                 }
                 Err(e) => {
-                    let _ = tx.send(AppMessage::Error(format!("{:#}", e)));
+                    if let Some(busy) = e.downcast_ref::<crate::serial::PortBusyError>() {
+                        log::warn!("{}", busy);
+                        let _ = tx.send(AppMessage::PortBusy(busy.to_string()));
+                    } else {
+                        let _ = tx.send(AppMessage::Error(format!("{:#}", e)));
+                    }
                 }
             }
-        });
+        }));
+    }
+
+    /// Act on what another window or the daemon asked through the instance lock
+    pub fn handle_instance_commands(&mut self, ctx: &eframe::egui::Context) {
+        let Some(lock) = &self.instance else {
+            return;
+        };
+        let commands: Vec<_> = lock.commands.try_iter().collect();
+        for (command, done) in commands {
+            match command {
+                crate::instance::Command::Focus => {
+                    ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Minimized(false));
+                    ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Focus);
+                }
+                crate::instance::Command::Release => {
+                    self.stop_worker();
+                    // Look for the daemon's socket again rather than trusting the cached answer
+                    self.daemon = Default::default();
+                    log::info!("The daemon took over the serial port");
+                    self.status_message = "The daemon took over the serial port".to_string();
+                }
+                crate::instance::Command::Quit => {
+                    self.stop_worker();
+                    self.handed_off = true;
+                    log::info!("Another window is taking over");
+                    ctx.send_viewport_cmd(eframe::egui::ViewportCommand::Close);
+                }
+            }
+            let _ = done.send(());
+        }
+    }
+
+    /// Show the current screen config through the daemon, with the media it already shows
+    pub fn apply_via_daemon(&mut self) {
+        if self.is_busy() {
+            return;
+        }
+        self.stop_worker();
+        self.config_mismatches = None;
+        self.progress = 0.0;
+        self.status_message = "Applying configuration...".to_string();
+        self.start_daemon_request(crate::ipc::Request::Apply { config: Box::new(self.screen_config.clone()) });
+    }
+
+    /// Hand `request` to the daemon on a worker, its replies arriving like a local transfer's
+    fn start_daemon_request(&mut self, request: crate::ipc::Request) {
+        let tx = self.message_sender.clone().unwrap();
+        self.worker = Some(std::thread::spawn(move || {
+            let _ = match crate::ipc::request(&request, &tx) {
+                Ok(message) => tx.send(AppMessage::Success(format!("{} (daemon)", message))),
+                Err(e) => tx.send(AppMessage::Error(format!("Daemon: {:#}", e))),
+            };
+        }));
+    }
+
+    /// Stop any running transfer, then leave the display the way the profile asks
+    pub fn shutdown(&mut self) {
+        self.stop_worker();
+        // The daemon or the new window keeps the display going
+        if self.daemon.running() || self.handed_off {
+            return;
+        }
+
+        let controller = crate::AioCoolerController::new(&self.serial_device).with_retry(self.screen_config.retry.clone());
+        if let Err(e) = controller.shutdown(&self.screen_config) {
+            log::error!("Shutdown action failed: {:#}", e);
+        }
+        crate::sniffer::stop();
     }
 }
+
+/// Push `image_path` (or reuse the copy already on the device) and apply
+/// `config` with it, between the hooks in config.toml. Shared by the GUI
+/// worker, `send` on the command line and the daemon. `profile` is the profile
+/// being switched to, for the hooks. Returns the media's name on the device.
+pub fn transfer_image(
+    controller: &crate::AioCoolerController,
+    image_path: &std::path::PathBuf,
+    config: &crate::screen_setup::ScreenConfig,
+    tx: &crate::message_bus::MessageSender,
+    report: &mut crate::timing::TransferReport,
+    profile: Option<&str>,
+) -> anyhow::Result<String> {
+    let hooks = crate::settings::Settings::load().hooks;
+    let transfer = crate::hooks::Transfer { image: image_path, profile, device: controller.serial_device() };
+    hooks.before(&transfer);
+    let result = push_and_apply(controller, image_path, config, tx, report);
+    hooks.after(&transfer, &result);
+    result
+}
+
+fn push_and_apply(
+    controller: &crate::AioCoolerController,
+    image_path: &std::path::PathBuf,
+    config: &crate::screen_setup::ScreenConfig,
+    tx: &crate::message_bus::MessageSender,
+    report: &mut crate::timing::TransferReport,
+) -> anyhow::Result<String> {
+    let _ = tx.send(AppMessage::Progress(0.1, "Calculating MD5...".to_string()));
+    let _ = tx.send(AppMessage::Log("Calculating file MD5...".to_string()));
+
+    let file_md5 = report.time("hash (md5)", || crate::AioCoolerController::calculate_md5(image_path))?;
+    let file_size = std::fs::metadata(image_path)?.len();
+
+    let _ = tx.send(AppMessage::Log(format!(
+        "File: {} ({} bytes, MD5: {})",
+        image_path.display(),
+        file_size,
+        file_md5
+    )));
+
+    // Skip the push when this exact file is still on the device
+    let mut cache = crate::media_cache::MediaCache::load();
+    let cached = report.time("preprocess (cache lookup)", || {
+        cache
+            .get(&file_md5)
+            .filter(|media| media.size == file_size)
+            .map(|media| media.remote_name.clone())
+            .filter(|name| matches!(controller.remote_file_size(name), Ok(Some(size)) if size == file_size))
+    });
+
+    // Serial uploads happen inside the serial session
+    let mut upload = None;
+    let remote_name = match cached {
+        Some(name) => {
+            let _ = tx.send(AppMessage::Log(format!("Already on device as {}, skipping push", name)));
+            name
+        }
+        None => {
+            cache.remove(&file_md5);
+            let extension = image_path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("png");
+            let remote_name = crate::AioCoolerController::generate_filename(extension);
+
+            if config.transfer_method == crate::screen_setup::TransferMethod::Adb {
+                let _ = tx.send(AppMessage::Progress(0.2, "Pushing to device via ADB...".to_string()));
+                let _ = tx.send(AppMessage::Log("Starting ADB push...".to_string()));
+                report.time("adb push", || controller.adb_push(image_path, &remote_name, &config.serial))?;
+            } else {
+                upload = Some(image_path);
+            }
+            remote_name
+        }
+    };
+
+    let _ = tx.send(AppMessage::Progress(0.5, "Sending serial commands...".to_string()));
+    let _ = tx.send(AppMessage::Log("Sending serial commands...".to_string()));
+
+    controller.send_image_commands(&remote_name, file_size, &file_md5, upload, config)?;
+
+    // mediaDelete removed everything else, so only this file is still cached
+    cache.insert(&file_md5, &remote_name, file_size);
+    if !config.keep_old_media {
+        cache.retain_only(&[&remote_name]);
+    }
+    if let Err(e) = cache.save() {
+        log::warn!("Failed to save media cache: {:#}", e);
+    }
+    let _ = tx.send(AppMessage::Applied(Box::new(crate::screen_setup::LastApplied::new(config, &remote_name))));
+
+    let _ = tx.send(AppMessage::Log("Transfer complete!".to_string()));
+    Ok(remote_name)
+}
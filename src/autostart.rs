@@ -0,0 +1,70 @@
+// Starting with the session: an XDG autostart entry that opens the window
+// minimized at login, or the daemon's systemd user unit enabled instead. The
+// toggles in the device settings write and remove these.
+
+use std::path::PathBuf;
+use anyhow::{Context, Result};
+
+const ENTRY_NAME: &str = "tryx-panorama.desktop";
+
+/// What starts at login, read once and updated by the toggles
+pub struct Autostart {
+    pub window: bool,
+    /// None when there's no systemctl to ask
+    pub service: Option<bool>,
+}
+
+impl Autostart {
+    pub fn read() -> Self {
+        let service = std::process::Command::new("systemctl")
+            .arg("--version")
+            .output()
+            .is_ok()
+            .then(crate::systemd::service_enabled);
+        Self { window: entry_path().exists(), service }
+    }
+}
+
+pub fn entry_path() -> PathBuf {
+    crate::paths::autostart_dir().join(ENTRY_NAME)
+}
+
+/// Write or remove the autostart entry for the window
+pub fn set_window(enable: bool) -> Result<()> {
+    let path = entry_path();
+    if !enable {
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove {}", path.display()))
+            }
+            _ => Ok(()),
+        };
+    }
+    let exe = std::env::current_exe().context("Could not find this executable")?;
+    let entry = format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=Tryx Panorama Display Controller\n\
+         Comment=Set up the AIO cooler display at login\n\
+         Exec={} --start-minimized\n\
+         Terminal=false\n\
+         X-GNOME-Autostart-enabled=true\n",
+        quote_exec(&exe.to_string_lossy())
+    );
+    std::fs::create_dir_all(crate::paths::autostart_dir())?;
+    std::fs::write(&path, entry).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// An `Exec` argument as the desktop entry spec wants it quoted
+fn quote_exec(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    // Field codes start with %, which quoting doesn't protect
+    quoted.replace('%', "%%")
+}
@@ -0,0 +1,75 @@
+// What the daemon is doing, for any number of watchers: the MQTT bridge and
+// WebSocket clients. Sending never blocks, a watcher that falls behind misses
+// updates and one that has gone away is dropped. New watchers start with the
+// latest connection state and sysinfo.
+
+use std::sync::{Arc, Mutex};
+use crossbeam::channel::{self, Receiver, Sender, TrySendError};
+use serde::Serialize;
+
+use crate::screen_setup::ConnectionState;
+use crate::sysinfo::SysInfo;
+
+/// Updates a watcher may have queued before older ones are dropped
+const WATCHER_CAPACITY: usize = 64;
+
+/// `{"type": "progress", "progress": 0.4, "status": "..."}` and so on
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Status {
+    /// Serial session state, with the reason once it failed
+    Connection { state: String, error: Option<String> },
+    /// The payload just sent to the device
+    Sysinfo { sysinfo: Box<SysInfo> },
+    /// Transfer progress, 0 to 1
+    Progress { progress: f32, status: String },
+    /// A transfer or config change finished
+    Done { ok: bool, message: String },
+    /// The media now on screen
+    Applied { media: String },
+}
+
+impl Status {
+    pub fn connection(state: &ConnectionState) -> Self {
+        let error = match state {
+            ConnectionState::Error(e) => Some(e.clone()),
+            _ => None,
+        };
+        Status::Connection { state: state.label().to_string(), error }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    watchers: Vec<Sender<Status>>,
+    connection: Option<Status>,
+    sysinfo: Option<Status>,
+}
+
+#[derive(Clone, Default)]
+pub struct Broadcast {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Broadcast {
+    /// Everything sent from now on, after the latest state and sysinfo
+    pub fn subscribe(&self) -> Receiver<Status> {
+        let (tx, rx) = channel::bounded(WATCHER_CAPACITY);
+        let mut shared = self.shared.lock().unwrap();
+        for status in [&shared.connection, &shared.sysinfo].into_iter().flatten() {
+            let _ = tx.try_send(status.clone());
+        }
+        shared.watchers.push(tx);
+        rx
+    }
+
+    pub fn send(&self, status: Status) {
+        let mut shared = self.shared.lock().unwrap();
+        match &status {
+            Status::Connection { .. } => shared.connection = Some(status.clone()),
+            Status::Sysinfo { .. } => shared.sysinfo = Some(status.clone()),
+            _ => {}
+        }
+        shared.watchers.retain(|watcher| !matches!(watcher.try_send(status.clone()), Err(TrySendError::Disconnected(_))));
+    }
+}
@@ -0,0 +1,106 @@
+// ============================================================================
+// Firmware Capabilities
+// What each firmware revision can actually do, learned from the `conn` handshake
+// ============================================================================
+
+use std::{collections::HashMap, path::PathBuf};
+use serde::{Deserialize, Serialize};
+use anyhow::{Context, Result};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Capabilities {
+    pub video: bool,
+    pub brightness: bool,
+    pub filters: bool,
+    pub badges: bool,
+    pub sysinfo_overlay: bool,
+    pub slideshow: bool,
+}
+
+/// Unknown firmware gets the benefit of the doubt
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            video: true,
+            brightness: true,
+            filters: true,
+            badges: true,
+            sysinfo_overlay: true,
+            slideshow: true,
+        }
+    }
+}
+
+impl Capabilities {
+    /// Parse the `conn` handshake body. Flags the device doesn't mention keep
+    /// the defaults so older firmware isn't needlessly restricted.
+    pub fn from_handshake(body: &serde_json::Value) -> Self {
+        let mut caps = Self::default();
+        let flag = |key: &str| -> Option<bool> {
+            let value = body.get("support").and_then(|s| s.get(key)).or_else(|| body.get(key))?;
+            value.as_bool().or_else(|| value.as_i64().map(|v| v != 0))
+        };
+
+        if let Some(v) = flag("video") { caps.video = v; }
+        if let Some(v) = flag("brightness") { caps.brightness = v; }
+        if let Some(v) = flag("filter") { caps.filters = v; }
+        if let Some(v) = flag("badge") { caps.badges = v; }
+        if let Some(v) = flag("sysinfo") { caps.sysinfo_overlay = v; }
+        if let Some(v) = flag("slideshow") { caps.slideshow = v; }
+        caps
+    }
+}
+
+/// Firmware version as reported in the handshake
+pub fn handshake_version(body: &serde_json::Value) -> Option<String> {
+    ["version", "firmwareVersion", "appVersion"]
+        .iter()
+        .find_map(|key| body.get(*key))
+        .map(|v| v.as_str().map(str::to_string).unwrap_or_else(|| v.to_string()))
+}
+
+/// Capabilities per firmware version, remembered across runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CapabilityStore {
+    pub by_version: HashMap<String, Capabilities>,
+    pub last_version: Option<String>,
+}
+
+impl CapabilityStore {
+    fn path() -> PathBuf {
+        crate::paths::state_dir().join("capabilities.json")
+    }
+
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Record what a handshake told us about this firmware
+    pub fn learn(&mut self, version: &str, caps: Capabilities) {
+        log::info!("Firmware {} capabilities: {:?}", version, caps);
+        self.by_version.insert(version.to_string(), caps);
+        self.last_version = Some(version.to_string());
+    }
+
+    /// Capabilities of the most recently seen firmware
+    pub fn current(&self) -> Capabilities {
+        self.last_version
+            .as_ref()
+            .and_then(|v| self.by_version.get(v))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,446 @@
+// ============================================================================
+// Command line
+// Subcommands for scripting what the GUI does, on the same controller code:
+// send an image, print sysinfo, run the daemon, edit the saved config, manage
+// profiles, list the device's media, describe the device, report the daemon's
+// serial link statistics, install the service, check the setup, sync the
+// clock and run the protocol self-test. No subcommand opens the GUI.
+// The older `--daemon`, `--dump-sysinfo`, `--self-test`, `--install-service`,
+// `--check-setup` and `--sync-clock` flags are hidden options mapped onto the
+// same subcommands.
+// ============================================================================
+
+use std::{path::PathBuf, thread, time::Duration};
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use serde_json::Value;
+
+use crate::adb::AdbConfig;
+use crate::app_state::AppMessage;
+use crate::screen_setup::{AioCoolerController, LastApplied, ScreenConfig};
+
+#[derive(Parser)]
+#[command(version, about = "Tryx Panorama AIO display controller")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Close the window that's already open and take its place
+    #[arg(long)]
+    pub take_over: bool,
+    /// Open minimized and show the last config on the cooler right away, for autostart
+    #[arg(long)]
+    pub start_minimized: bool,
+    #[command(flatten)]
+    legacy: LegacyFlags,
+}
+
+impl Cli {
+    /// The subcommand, or what an older flag asks for
+    pub fn take_command(&mut self) -> Option<Command> {
+        self.command.take().or_else(|| self.legacy.command())
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Upload an image or video and show it with the saved screen config
+    Send {
+        image: PathBuf,
+        #[arg(long, default_value = crate::daemon::DEFAULT_DEVICE)]
+        device: String,
+    },
+    /// Print the sysinfo payload as it's sent to the device
+    Sysinfo {
+        /// Keep printing at the sample interval, one JSON object per line
+        #[arg(long)]
+        follow: bool,
+        #[arg(long)]
+        csv: bool,
+        /// Append to this file instead of printing
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Keep the display updating without the GUI
+    Daemon {
+        #[arg(long, default_value = crate::daemon::DEFAULT_DEVICE)]
+        device: String,
+        #[arg(long)]
+        verbose: bool,
+    },
+    /// Show or change the saved screen config
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Named images with their screen config
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+    /// Files in the device's media directory (over ADB)
+    Media {
+        #[command(subcommand)]
+        action: MediaAction,
+    },
+    /// The cooler's Android side (over ADB)
+    Device {
+        #[command(subcommand)]
+        action: DeviceAction,
+    },
+    /// Serial link statistics of the running daemon's session
+    Status {
+        /// Print them as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write the systemd user unit that runs the daemon
+    InstallService {
+        #[arg(long, default_value = crate::daemon::DEFAULT_DEVICE)]
+        device: String,
+    },
+    /// Check port access, ADB and permissions, and print how to fix what fails
+    CheckSetup,
+    /// Set the cooler's clock and time zone to this computer's (over ADB)
+    SyncClock,
+    /// Round-trip randomized messages through the protocol code
+    SelfTest {
+        #[arg(long, default_value_t = crate::self_test::DEFAULT_COUNT)]
+        count: usize,
+        /// Reproduce an earlier run, the seed is printed with any failure
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Also exchange messages and an upload with the device simulator
+        #[arg(long)]
+        simulator: bool,
+        /// Deflate the bodies
+        #[arg(long)]
+        compress: bool,
+    },
+}
+
+/// Flags from before the subcommands, kept working for scripts and installed
+/// units but left out of `--help`
+#[derive(clap::Args)]
+struct LegacyFlags {
+    #[arg(long, hide = true)]
+    daemon: bool,
+    #[arg(long, hide = true, num_args = 0..=1, require_equals = true, default_missing_value = "-")]
+    dump_sysinfo: Option<String>,
+    #[arg(long, hide = true, num_args = 0..=1, require_equals = true, default_missing_value = "5000")]
+    self_test: Option<usize>,
+    #[arg(long, hide = true)]
+    install_service: bool,
+    #[arg(long, hide = true)]
+    check_setup: bool,
+    #[arg(long, hide = true)]
+    sync_clock: bool,
+    #[arg(long, hide = true)]
+    device: Option<String>,
+    #[arg(long, hide = true)]
+    verbose: bool,
+    #[arg(long, hide = true)]
+    follow: bool,
+    #[arg(long, hide = true)]
+    csv: bool,
+    #[arg(long, hide = true)]
+    seed: Option<u64>,
+    #[arg(long, hide = true)]
+    simulator: bool,
+    #[arg(long, hide = true)]
+    compress: bool,
+}
+
+impl LegacyFlags {
+    fn command(&self) -> Option<Command> {
+        let device = || self.device.clone().unwrap_or_else(|| crate::daemon::DEFAULT_DEVICE.to_string());
+        if let Some(count) = self.self_test {
+            return Some(Command::SelfTest { count, seed: self.seed, simulator: self.simulator, compress: self.compress });
+        }
+        if self.daemon {
+            return Some(Command::Daemon { device: device(), verbose: self.verbose });
+        }
+        if let Some(path) = &self.dump_sysinfo {
+            let output = (!path.is_empty() && path != "-").then(|| PathBuf::from(path));
+            return Some(Command::Sysinfo { follow: self.follow, csv: self.csv, output });
+        }
+        if self.install_service {
+            return Some(Command::InstallService { device: device() });
+        }
+        if self.check_setup {
+            return Some(Command::CheckSetup);
+        }
+        self.sync_clock.then_some(Command::SyncClock)
+    }
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Print the saved config as JSON
+    Show,
+    /// Set a field by its dotted path, e.g. `heartbeat.interval_ms 2000` or
+    /// `sensors.units.temperature '"Fahrenheit"'`. Values are JSON, or plain
+    /// text for strings.
+    Set { key: String, value: String },
+}
+
+#[derive(Subcommand)]
+pub enum ProfileAction {
+    List,
+    /// Save `image` with the saved screen config as `name`
+    Save { name: String, image: PathBuf },
+    /// Send a profile's image and show it with its config
+    Use {
+        name: String,
+        #[arg(long, default_value = crate::daemon::DEFAULT_DEVICE)]
+        device: String,
+    },
+    Delete { name: String },
+}
+
+#[derive(Subcommand)]
+pub enum MediaAction {
+    List,
+}
+
+#[derive(Subcommand)]
+pub enum DeviceAction {
+    Info,
+}
+
+pub fn run(command: Command) -> i32 {
+    let result = match command {
+        Command::Sysinfo { follow, csv, output } => {
+            let format = if csv { crate::sysinfo_dump::Format::Csv } else { crate::sysinfo_dump::Format::Json };
+            return crate::sysinfo_dump::run(&crate::sysinfo_dump::Options { path: output, follow, format });
+        }
+        Command::Daemon { device, verbose } => return crate::daemon::run(&crate::daemon::Options { device, verbose }),
+        Command::Send { image, device } => {
+            crate::daemon::log_to_stderr(false);
+            send(&image, saved_config(), &device, None)
+        }
+        Command::Profile { action } => profile(action),
+        Command::Config { action: ConfigAction::Show } => show_config(),
+        Command::Config { action: ConfigAction::Set { key, value } } => set_config(&key, &value),
+        Command::Media { action: MediaAction::List } => list_media(),
+        Command::Device { action: DeviceAction::Info } => device_info(),
+        Command::Status { json } => status(json),
+        Command::InstallService { device } => return crate::systemd::run_install(&device),
+        Command::CheckSetup => return crate::env_check::run_cli(),
+        Command::SyncClock => sync_clock(),
+        Command::SelfTest { count, seed, simulator, compress } => {
+            let seed = seed.unwrap_or_else(crate::self_test::clock_seed);
+            return crate::self_test::run(&crate::self_test::Options { count, seed, simulator, compress });
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            1
+        }
+    }
+}
+
+/// The config last applied, with the ADB settings saved on their own, else the defaults
+pub fn saved_config() -> ScreenConfig {
+    let config = LastApplied::load().map(|last| last.config).unwrap_or_default();
+    ScreenConfig { adb: AdbConfig::load(), ..config }
+}
+
+/// Print what a worker reports: log lines to stderr, and persist the applied config
+fn print_messages(batch: crate::message_bus::MessageBatch) {
+    for line in batch.logs {
+        eprintln!("{}", line);
+    }
+    for event in batch.events {
+        match event {
+            AppMessage::Applied(last) => {
+                if let Err(e) = last.save() {
+                    log::warn!("Failed to persist last applied config: {:#}", e);
+                }
+            }
+            AppMessage::PortBusy(message) => eprintln!("{}", message),
+            _ => {}
+        }
+    }
+}
+
+/// Run `work` on a thread, printing its messages until it's done
+fn with_messages<R: Send + 'static>(work: impl FnOnce(crate::message_bus::MessageSender) -> Result<R> + Send + 'static) -> Result<R> {
+    let (tx, rx) = crate::message_bus::channel();
+    let worker = thread::spawn(move || work(tx));
+    while !worker.is_finished() {
+        print_messages(rx.drain(usize::MAX));
+        thread::sleep(Duration::from_millis(50));
+    }
+    print_messages(rx.drain(usize::MAX));
+    worker.join().map_err(|_| anyhow::anyhow!("Worker thread panicked"))?
+}
+
+/// The GUI's transfer on a worker, or the daemon's when one holds the port.
+/// Prints the media's name on the device when done.
+fn send(image: &std::path::Path, config: ScreenConfig, device: &str, profile: Option<String>) -> Result<()> {
+    anyhow::ensure!(image.is_file(), "{} is not a file", image.display());
+    let image = std::fs::canonicalize(image)?;
+    if crate::ipc::daemon_running() {
+        let message = with_messages(move |tx| crate::ipc::request(&crate::ipc::Request::Send { image, config: Box::new(config), profile }, &tx))?;
+        eprintln!("{}", message);
+        let last = LastApplied::load().context("The daemon didn't save what it applied")?;
+        println!("{}", last.media);
+        return Ok(());
+    }
+    let controller = AioCoolerController::new(device)
+        .with_retry(config.retry.clone())
+        .with_adb(config.adb.clone());
+    let remote_name = with_messages(move |tx| {
+        let controller = controller.with_events(tx.clone());
+        let mut report = crate::timing::TransferReport::default();
+        let result = crate::app_state::transfer_image(&controller, &image, &config, &tx, &mut report, profile.as_deref());
+        report.extend(controller.take_timings());
+        log::info!("{}", report.summary());
+        result
+    })?;
+    println!("{}", remote_name);
+    Ok(())
+}
+
+fn profile(action: ProfileAction) -> Result<()> {
+    match action {
+        ProfileAction::List => {
+            for name in crate::profiles::list() {
+                println!("{}", name);
+            }
+        }
+        ProfileAction::Save { name, image } => {
+            anyhow::ensure!(image.is_file(), "{} is not a file", image.display());
+            let profile = crate::profiles::Profile { image: std::fs::canonicalize(&image)?, config: saved_config() };
+            crate::profiles::save(&name, &profile)?;
+        }
+        ProfileAction::Use { name, device } => {
+            let profile = crate::profiles::load(&name)?;
+            crate::daemon::log_to_stderr(false);
+            send(&profile.image, ScreenConfig { adb: AdbConfig::load(), ..profile.config }, &device, Some(name))?;
+        }
+        ProfileAction::Delete { name } => crate::profiles::delete(&name)?,
+    }
+    Ok(())
+}
+
+fn show_config() -> Result<()> {
+    let last = LastApplied::load().context("No saved configuration; apply one from the GUI or with `send` first")?;
+    println!("{}", serde_json::to_string_pretty(&last.config)?);
+    Ok(())
+}
+
+/// A running daemon shows the change at once, otherwise it takes effect the
+/// next time the config is applied
+fn set_config(key: &str, raw: &str) -> Result<()> {
+    let mut last = LastApplied::load().context("No saved configuration; apply one from the GUI or with `send` first")?;
+    let mut config = serde_json::to_value(&last.config)?;
+    let field = key
+        .split('.')
+        .try_fold(&mut config, |value, part| match value {
+            Value::Array(items) => items.get_mut(part.parse::<usize>().ok()?),
+            value => value.get_mut(part),
+        })
+        .with_context(|| format!("No config field `{}`", key))?;
+    *field = serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()));
+    last.config = serde_json::from_value(config).with_context(|| format!("Invalid value for `{}`", key))?;
+    if crate::ipc::daemon_running() {
+        let config = Box::new(last.config.clone());
+        // The daemon saves it once the device took it
+        let message = with_messages(move |tx| crate::ipc::request(&crate::ipc::Request::Apply { config }, &tx))?;
+        eprintln!("{}", message);
+        return Ok(());
+    }
+    last.save()
+}
+
+fn list_media() -> Result<()> {
+    for file in crate::media_browser::list(&AdbConfig::load())? {
+        let modified = file.modified.map_or(String::new(), |time| time.format("%Y-%m-%d %H:%M").to_string());
+        println!("{:<40} {:>10} {}", file.name, crate::media_browser::format_size(file.size), modified);
+    }
+    Ok(())
+}
+
+/// Only the daemon's session can be asked, the window shows its own under 🔧 Debug
+fn status(json: bool) -> Result<()> {
+    let status = crate::ipc::status()?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
+    let link = &status.link;
+    println!("Session:          {:.0} s", link.session_secs);
+    println!("Frames sent/recv: {} / {}", link.frames_sent, link.frames_received);
+    println!("Bytes sent/recv:  {} / {}", link.bytes_sent, link.bytes_received);
+    println!("CRC errors:       {}", link.crc_errors);
+    println!("Malformed frames: {}", link.malformed_frames);
+    println!("Resyncs:          {} ({} bytes skipped)", link.resyncs, link.discarded_bytes);
+    println!("Retransmissions:  {}", link.retransmissions);
+    println!("Avg round-trip:   {}", link.avg_rtt_ms.map_or("—".to_string(), |ms| format!("{:.1} ms", ms)));
+    for (cmd_type, command) in &status.protocol.commands {
+        let rtt = command.avg_rtt_ms().map_or("—".to_string(), |ms| format!("{:.1} ms", ms));
+        println!("  {:<20} sent {} answered {} retries {} failed {} rtt {}", cmd_type, command.sent, command.answered, command.retries, command.failed, rtt);
+    }
+    Ok(())
+}
+
+fn sync_clock() -> Result<()> {
+    let sync = crate::device_control::sync_clock(&AdbConfig::load()).context("Failed to sync the clock")?;
+    println!("{}", sync.describe());
+    Ok(())
+}
+
+fn device_info() -> Result<()> {
+    let android = crate::device_info::query(&AdbConfig::load())?;
+    let info = crate::device_info::DeviceInfo { android: Some(android), ..Default::default() };
+    let firmware = crate::capabilities::CapabilityStore::load().last_version;
+    println!("{}", info.report(firmware.as_deref(), None));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use super::*;
+
+    fn command(args: &[&str]) -> Option<Command> {
+        Cli::try_parse_from(std::iter::once("tryx_panorama_linux").chain(args.iter().copied())).unwrap().take_command()
+    }
+
+    #[test]
+    fn definition_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn older_flags_map_to_subcommands() {
+        assert!(command(&[]).is_none());
+        assert!(matches!(
+            command(&["--daemon", "--device=/dev/ttyACM1", "--verbose"]),
+            Some(Command::Daemon { device, verbose: true }) if device == "/dev/ttyACM1"
+        ));
+        assert!(matches!(
+            command(&["--self-test=200", "--seed=42", "--simulator"]),
+            Some(Command::SelfTest { count: 200, seed: Some(42), simulator: true, compress: false })
+        ));
+        assert!(matches!(
+            command(&["--self-test"]),
+            Some(Command::SelfTest { count: crate::self_test::DEFAULT_COUNT, seed: None, .. })
+        ));
+        assert!(matches!(command(&["--dump-sysinfo", "--follow"]), Some(Command::Sysinfo { follow: true, output: None, .. })));
+        assert!(matches!(
+            command(&["--dump-sysinfo=sensors.csv", "--csv"]),
+            Some(Command::Sysinfo { csv: true, output: Some(path), .. }) if path == std::path::Path::new("sensors.csv")
+        ));
+        assert!(matches!(
+            command(&["--install-service"]),
+            Some(Command::InstallService { device }) if device == crate::daemon::DEFAULT_DEVICE
+        ));
+        assert!(matches!(command(&["--check-setup"]), Some(Command::CheckSetup)));
+        assert!(matches!(command(&["--sync-clock"]), Some(Command::SyncClock)));
+    }
+}
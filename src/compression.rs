@@ -0,0 +1,88 @@
+// Optional zlib compression of message bodies. A compressed body carries
+// `ContentEncoding=deflate`; firmware that rejects it gets the plain message
+// instead and compression stays off for the rest of the session.
+
+use std::{io::{Read, Write}, sync::Mutex};
+use serde::{Deserialize, Serialize};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+pub const ENCODING: &str = "deflate";
+/// Keep compression only when it saves at least this share of the body
+const MIN_SAVING: f64 = 0.1;
+/// Refuse to inflate beyond this, a corrupt or hostile stream could be huge.
+/// Fragmented messages are held to the same limit.
+pub const MAX_INFLATED: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    /// Off by default, stock firmware hasn't been seen accepting it
+    pub enabled: bool,
+    /// Smaller bodies go out as they are
+    pub min_size: usize,
+    /// zlib level, 1 (fast) to 9 (small)
+    pub level: u32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self { enabled: false, min_size: 4096, level: 6 }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    config: CompressionConfig,
+    /// The device refused a compressed message this session
+    rejected: bool,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+/// Apply `config` for a new session, giving compression another chance
+pub fn configure(config: &CompressionConfig) {
+    *STATE.lock().unwrap() = Some(State { config: config.clone(), rejected: false });
+}
+
+/// Stop compressing until the next session
+pub fn reject(cmd_type: &str) {
+    let mut state = STATE.lock().unwrap();
+    let state = state.get_or_insert_with(Default::default);
+    if !state.rejected {
+        log::warn!("Device rejected a compressed {} message, sending uncompressed from now on", cmd_type);
+        state.rejected = true;
+    }
+}
+
+/// Deflated body, or None when compression is off, the body is small or it doesn't shrink
+pub fn compress(body: &[u8]) -> Option<Vec<u8>> {
+    let (level, min_size) = {
+        let state = STATE.lock().unwrap();
+        let state = state.as_ref()?;
+        if !state.config.enabled || state.rejected {
+            return None;
+        }
+        (state.config.level.clamp(1, 9), state.config.min_size)
+    };
+    if body.len() < min_size {
+        return None;
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::with_capacity(body.len() / 2), Compression::new(level));
+    encoder.write_all(body).ok()?;
+    let compressed = encoder.finish().ok()?;
+    if compressed.len() as f64 > body.len() as f64 * (1.0 - MIN_SAVING) {
+        return None;
+    }
+    log::debug!("Body compressed {} -> {} bytes", body.len(), compressed.len());
+    Some(compressed)
+}
+
+pub fn decompress(body: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut inflated = Vec::with_capacity(body.len() * 3);
+    ZlibDecoder::new(body).take(MAX_INFLATED + 1).read_to_end(&mut inflated)?;
+    if inflated.len() as u64 > MAX_INFLATED {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "inflated body too large"));
+    }
+    Ok(inflated)
+}
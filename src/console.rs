@@ -0,0 +1,155 @@
+// ============================================================================
+// Protocol console
+// Hand-built messages for mapping undocumented commands: a cmdType with a JSON
+// body, or raw hex, sent framed and shown next to the decoded replies
+// ============================================================================
+
+use std::{collections::VecDeque, time::Duration};
+use anyhow::{Context, Result};
+
+use crate::data::{build_raw, build_request, OutgoingFrame, ResponseMessage};
+
+/// Exchanges kept in the history
+const MAX_HISTORY: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ConsoleMode {
+    #[default]
+    Json,
+    Hex,
+}
+
+pub struct ConsoleState {
+    pub mode: ConsoleMode,
+    pub method: String,
+    pub cmd_type: String,
+    pub body: String,
+    pub hex: String,
+    /// The hex is a complete frame (markers, length, CRC) and goes out untouched
+    pub framed: bool,
+    pub listen_ms: u64,
+    /// Newest first
+    pub history: VecDeque<ConsoleExchange>,
+}
+
+impl Default for ConsoleState {
+    fn default() -> Self {
+        Self {
+            mode: ConsoleMode::Json,
+            method: "POST".to_string(),
+            cmd_type: String::new(),
+            body: "{}".to_string(),
+            hex: String::new(),
+            framed: false,
+            listen_ms: 2000,
+            history: VecDeque::new(),
+        }
+    }
+}
+
+impl ConsoleState {
+    /// Frame what's in the editor
+    pub fn build(&self) -> Result<OutgoingFrame> {
+        match self.mode {
+            ConsoleMode::Json => {
+                let cmd_type = self.cmd_type.trim();
+                anyhow::ensure!(!cmd_type.is_empty(), "cmdType is empty");
+                anyhow::ensure!(!cmd_type.contains(char::is_whitespace), "cmdType can't contain spaces");
+                let body = if self.body.trim().is_empty() { "{}" } else { self.body.as_str() };
+                let value: serde_json::Value = serde_json::from_str(body).context("Body is not valid JSON")?;
+                Ok(build_request(&self.method, cmd_type, &value)?)
+            }
+            ConsoleMode::Hex => {
+                let bytes = parse_hex(&self.hex)?;
+                anyhow::ensure!(!bytes.is_empty(), "No hex bytes entered");
+                if self.framed {
+                    Ok(OutgoingFrame {
+                        method: "RAW".to_string(),
+                        cmd_type: "frame".to_string(),
+                        seq_number: -1,
+                        msg_id: -1,
+                        bytes,
+                        fallback: None,
+                    })
+                } else {
+                    Ok(build_raw(&bytes)?)
+                }
+            }
+        }
+    }
+
+    pub fn listen(&self) -> Duration {
+        Duration::from_millis(self.listen_ms)
+    }
+
+    pub fn record(&mut self, exchange: ConsoleExchange) {
+        if self.history.len() >= MAX_HISTORY {
+            self.history.pop_back();
+        }
+        self.history.push_front(exchange);
+    }
+}
+
+/// One send and what came back
+#[derive(Debug)]
+pub struct ConsoleExchange {
+    /// "METHOD cmdType Seq=…"
+    pub request: String,
+    pub sent: Vec<u8>,
+    pub replies: Vec<ResponseMessage>,
+    pub error: Option<String>,
+}
+
+impl ConsoleExchange {
+    pub fn new(frame: &OutgoingFrame, result: Result<Vec<ResponseMessage>>) -> Self {
+        let (replies, error) = match result {
+            Ok(replies) => (replies, None),
+            Err(e) => (Vec::new(), Some(format!("{:#}", e))),
+        };
+        Self {
+            request: format!("{} {} Seq={}", frame.method, frame.cmd_type, frame.seq_number),
+            sent: frame.bytes.clone(),
+            replies,
+            error,
+        }
+    }
+}
+
+/// Hex bytes in any common spelling: `5a 00 1f`, `5A:00:1F`, `0x5a,0x00` or `5a001f`
+pub fn parse_hex(text: &str) -> Result<Vec<u8>> {
+    let digits: String = text
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ':')
+        .map(|token| token.trim_start_matches("0x").trim_start_matches("0X"))
+        .collect();
+    anyhow::ensure!(digits.chars().all(|c| c.is_ascii_hexdigit()), "Not a hex string: {}", text.trim());
+    anyhow::ensure!(digits.len().is_multiple_of(2), "Odd number of hex digits");
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&digits[i..i + 2], 16)?))
+        .collect()
+}
+
+/// Space-separated hex, wrapped every 16 bytes
+pub fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .map(|line| line.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Request line, headers and body of a reply, the body pretty-printed when it's JSON
+pub fn describe(reply: &ResponseMessage) -> String {
+    let mut headers: Vec<_> = reply.headers.iter().collect();
+    headers.sort();
+    let headers: Vec<String> = headers.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+
+    let body = match serde_json::from_slice::<serde_json::Value>(&reply.body) {
+        Ok(json) => serde_json::to_string_pretty(&json).unwrap_or_default(),
+        Err(_) => match std::str::from_utf8(&reply.body) {
+            Ok(text) => text.to_string(),
+            Err(_) => hex_dump(&reply.body),
+        },
+    };
+    format!("{} {} {}\n{}\n\n{}", reply.method, reply.cmd_type, reply.version, headers.join("\n"), body)
+}
@@ -0,0 +1,217 @@
+// Custom sensors: shell commands whose numeric output fills a payload field,
+// for readings only the user knows how to get (a router, a UPS over SNMP, a
+// script around a vendor tool). Each command runs on its own thread at its own
+// interval; samples take whatever it printed last.
+
+use std::{
+    collections::HashMap,
+    io::Read,
+    process::{Command, Stdio},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::sysinfo::{FanInfo, LiquidInfo, SysInfo};
+
+/// A command still running after this long is killed
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CustomSensor {
+    pub name: String,
+    /// Run with `sh -c`; the first number it prints is the value
+    pub command: String,
+    pub field: Field,
+    pub interval_secs: u32,
+}
+
+impl Default for CustomSensor {
+    fn default() -> Self {
+        Self { name: "Custom".to_string(), command: String::new(), field: Field::default(), interval_secs: 10 }
+    }
+}
+
+/// Payload field a custom sensor overrides
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Field {
+    /// Only shown in the settings
+    #[default]
+    None,
+    CpuTemperature,
+    CpuLoad,
+    GpuTemperature,
+    GpuLoad,
+    MemoryTemperature,
+    DiskTemperature,
+    BoardTemperature,
+    PchTemperature,
+    CoolantTemperature,
+    PumpSpeed,
+    /// Sent as another entry in `fans`, named after the sensor
+    Fan,
+}
+
+impl Field {
+    pub const ALL: [Field; 12] = [
+        Field::None,
+        Field::CpuTemperature,
+        Field::CpuLoad,
+        Field::GpuTemperature,
+        Field::GpuLoad,
+        Field::MemoryTemperature,
+        Field::DiskTemperature,
+        Field::BoardTemperature,
+        Field::PchTemperature,
+        Field::CoolantTemperature,
+        Field::PumpSpeed,
+        Field::Fan,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Field::None => "Not sent",
+            Field::CpuTemperature => "CPU temperature",
+            Field::CpuLoad => "CPU load",
+            Field::GpuTemperature => "GPU temperature",
+            Field::GpuLoad => "GPU load",
+            Field::MemoryTemperature => "Memory temperature",
+            Field::DiskTemperature => "Disk temperature",
+            Field::BoardTemperature => "Motherboard temperature",
+            Field::PchTemperature => "Chipset temperature",
+            Field::CoolantTemperature => "Coolant temperature",
+            Field::PumpSpeed => "Pump speed",
+            Field::Fan => "Fan (RPM)",
+        }
+    }
+}
+
+/// What a command printed last
+#[derive(Debug, Clone, Default)]
+pub struct Reading {
+    pub value: Option<f64>,
+    pub error: Option<String>,
+    at: Option<Instant>,
+    running: bool,
+}
+
+/// Keyed by command, so renaming a sensor keeps its reading
+static READINGS: Mutex<Option<HashMap<String, Reading>>> = Mutex::new(None);
+
+/// Start the commands that are due and write the latest values into `info`
+pub fn apply(sensors: &[CustomSensor], info: &mut SysInfo) {
+    let mut readings = READINGS.lock().unwrap();
+    let readings = readings.get_or_insert_with(Default::default);
+    for sensor in sensors.iter().filter(|sensor| !sensor.command.trim().is_empty()) {
+        let reading = readings.entry(sensor.command.clone()).or_default();
+        let interval = Duration::from_secs(sensor.interval_secs.max(1) as u64);
+        if !reading.running && reading.at.is_none_or(|at| at.elapsed() >= interval) {
+            reading.running = true;
+            let command = sensor.command.clone();
+            std::thread::spawn(move || {
+                let result = run(&command);
+                let mut readings = READINGS.lock().unwrap();
+                let reading = readings.get_or_insert_with(Default::default).entry(command.clone()).or_default();
+                reading.running = false;
+                reading.at = Some(Instant::now());
+                match result {
+                    Ok(value) => {
+                        reading.value = Some(value);
+                        reading.error = None;
+                    }
+                    Err(e) => {
+                        if reading.error.as_ref() != Some(&e) {
+                            log::warn!("Custom sensor `{}`: {}", command, e);
+                        }
+                        reading.value = None;
+                        reading.error = Some(e);
+                    }
+                }
+            });
+        }
+        if let Some(value) = reading.value {
+            set_field(info, sensor, value);
+        }
+    }
+}
+
+/// Latest reading of `command`, for the settings
+pub fn reading(command: &str) -> Reading {
+    READINGS.lock().unwrap().as_ref().and_then(|readings| readings.get(command).cloned()).unwrap_or_default()
+}
+
+fn set_field(info: &mut SysInfo, sensor: &CustomSensor, value: f64) {
+    let byte = value.round().clamp(0.0, u8::MAX as f64) as u8;
+    let rpm = value.round().max(0.0) as u32;
+    match sensor.field {
+        Field::None => {}
+        Field::CpuTemperature => info.cpu.temperature = byte,
+        Field::CpuLoad => {
+            info.cpu.load = byte;
+            info.cpu.usage = byte;
+        }
+        Field::GpuTemperature => info.gpu.temperature = byte,
+        Field::GpuLoad => {
+            info.gpu.load = byte;
+            info.gpu.load_known = true;
+        }
+        Field::MemoryTemperature => info.memory.temperature = byte,
+        Field::DiskTemperature => info.disk.temperature = byte,
+        Field::BoardTemperature => info.motherboard.temperature = byte,
+        Field::PchTemperature => info.motherboard.pch_temperature = byte,
+        Field::CoolantTemperature => info.liquid.get_or_insert_with(LiquidInfo::default).temperature = byte,
+        Field::PumpSpeed => info.liquid.get_or_insert_with(LiquidInfo::default).pump = rpm,
+        Field::Fan => info.fans.push(FanInfo { on_board: true, name: sensor.name.clone(), value: rpm }),
+    }
+}
+
+/// Run `command` and take the first number in its output
+fn run(command: &str) -> Result<f64, String> {
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start: {}", e))?;
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) => break status,
+            None if started.elapsed() > TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("Timed out after {:?}", TIMEOUT));
+            }
+            None => std::thread::sleep(Duration::from_millis(50)),
+        }
+    };
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    if let Some(mut pipe) = child.stdout.take() {
+        let _ = pipe.read_to_string(&mut stdout);
+    }
+    if let Some(mut pipe) = child.stderr.take() {
+        let _ = pipe.read_to_string(&mut stderr);
+    }
+    if !status.success() {
+        let detail = stderr.trim();
+        return Err(if detail.is_empty() { status.to_string() } else { format!("{}: {}", status, detail) });
+    }
+    first_number(&stdout).ok_or_else(|| format!("No number in output: {:?}", stdout.trim()))
+}
+
+/// `45.5°C`, `temp=45.5`, `-3` → the number
+fn first_number(text: &str) -> Option<f64> {
+    let start = text.find(|c: char| c.is_ascii_digit())?;
+    // Keep a minus sign right before the digits
+    let start = if text[..start].ends_with('-') { start - 1 } else { start };
+    let rest = &text[start..];
+    let end = rest
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| !(c.is_ascii_digit() || *c == '.'))
+        .map_or(rest.len(), |(i, _)| i);
+    rest[..end].parse().ok()
+}
@@ -0,0 +1,377 @@
+// ============================================================================
+// Headless daemon
+// `daemon` runs without a window: it loads the last configuration applied
+// from the GUI, re-applies it whenever the cooler shows up and streams sysinfo
+// keepalives until SIGINT/SIGTERM, then runs the configured exit action.
+// `--device PATH` picks the serial port, `--verbose` logs each beat.
+// Logs go to stderr, which journald picks up under systemd. As a `Type=notify`
+// service it reports readiness and status and pings the watchdog. The GUI and
+// `send` hand it transfers over the IPC socket while it holds the port.
+// SIGHUP, or any change to the saved config, switches the running heartbeat
+// to the new config without reopening the port. When configured it also
+// bridges state, sysinfo and commands to MQTT and streams status over a
+// WebSocket.
+// ============================================================================
+
+use std::{
+    io::Write,
+    sync::{
+        Arc,
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use crossbeam::channel::Receiver;
+
+use crate::app_state::AppMessage;
+use crate::data::DeviceCommand;
+use crate::ipc::{Job, Request};
+use crate::message_bus::MessageSender;
+use crate::screen_setup::{AioCoolerController, LastApplied, ScreenConfig};
+use crate::transport::{SerialTransport, Transport};
+
+pub const DEFAULT_DEVICE: &str = "/dev/ttyACM0";
+/// Before trying again after the cooler refused the config or the heartbeat gave up
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+/// How often the device node and the stop flag are checked while waiting
+const POLL: Duration = Duration::from_millis(500);
+
+pub struct Options {
+    pub device: String,
+    pub verbose: bool,
+}
+
+/// Set from the signal handler, which may only touch atomics
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(_: libc::c_int) {
+    STOP.store(true, Ordering::Relaxed);
+}
+
+/// Set on SIGHUP, cleared once the config has been read again
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_hangup(_: libc::c_int) {
+    RELOAD.store(true, Ordering::Relaxed);
+}
+
+/// The session talking to the cooler now. Each gets its own cancel flag so a
+/// cancel can't leak into the next one.
+struct Session {
+    cancel: Arc<AtomicBool>,
+    /// Only the heartbeat gives way to an IPC request, a transfer finishes first
+    interruptible: bool,
+}
+
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+
+/// Where log lines go besides stderr while an IPC request runs
+static JOB_EVENTS: Mutex<Option<MessageSender>> = Mutex::new(None);
+
+fn new_session() -> Arc<AtomicBool> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    *SESSION.lock().unwrap() = Some(Session { cancel: cancel.clone(), interruptible: false });
+    // A signal that came in before the session was registered
+    if STOP.load(Ordering::Relaxed) {
+        cancel.store(true, Ordering::Relaxed);
+    }
+    cancel
+}
+
+fn cancel_session(for_request: bool) {
+    if let Some(session) = SESSION.lock().unwrap().as_ref()
+        && (session.interruptible || !for_request)
+    {
+        session.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// From here on a request cancels the session, at once if one is waiting
+fn allow_interrupt(jobs: &Receiver<Job>) {
+    if let Some(session) = SESSION.lock().unwrap().as_mut() {
+        session.interruptible = true;
+        if !jobs.is_empty() {
+            session.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Without these the heartbeat sends a few updates and stops, or gives up on a replug
+fn daemon_config(config: &ScreenConfig) -> ScreenConfig {
+    let mut config = config.clone();
+    config.heartbeat.enabled = true;
+    config.heartbeat.auto_reconnect = true;
+    config
+}
+
+pub fn run(options: &Options) -> i32 {
+    log_to_stderr(options.verbose);
+    crate::diagnostics::install_panic_hook();
+
+    let last = LastApplied::load();
+    crate::log_file::open("daemon", &last.as_ref().map(|last| last.config.log_file.clone()).unwrap_or_default());
+    let Some(mut last) = last else {
+        log::error!("No saved configuration; apply one from the GUI first");
+        return 1;
+    };
+
+    unsafe {
+        libc::signal(libc::SIGINT, on_signal as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, on_signal as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGHUP, on_hangup as *const () as libc::sighandler_t);
+    }
+    // The controller only knows its own flag, so forward the signal to it
+    thread::spawn(|| {
+        while !STOP.load(Ordering::Relaxed) {
+            thread::sleep(POLL);
+        }
+        cancel_session(false);
+    });
+
+    let _lock = match crate::instance::Lock::acquire(crate::instance::Role::Daemon) {
+        Ok(lock) => Some(lock),
+        Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+            log::error!("Another daemon is already running");
+            return 1;
+        }
+        Err(e) => {
+            log::warn!("Failed to claim the instance lock: {}", e);
+            None
+        }
+    };
+    let (reload_tx, reload) = crossbeam::channel::unbounded();
+    thread::spawn(move || watch_config(reload_tx));
+    let broadcast = crate::broadcast::Broadcast::default();
+    let (commands_tx, commands) = crossbeam::channel::unbounded();
+    crate::mqtt::spawn(broadcast.clone(), commands_tx);
+    crate::websocket::spawn(broadcast.clone());
+    let links = Links { reload, broadcast, commands };
+    let (jobs_tx, jobs) = crossbeam::channel::unbounded();
+    if let Err(e) = crate::ipc::serve(jobs_tx, || cancel_session(true)) {
+        log::warn!("{:#}; the GUI won't be able to send through this daemon", e);
+    }
+    // Listening first, so the window finds the socket once it lets go
+    match crate::instance::ask(crate::instance::Role::Gui, crate::instance::Command::Release) {
+        Ok(true) => log::info!("The open window released the serial port"),
+        Ok(false) => {}
+        Err(e) => log::warn!("Couldn't ask the open window to release the port: {:#}", e),
+    }
+
+    log::info!("Daemon started for {} ({})", options.device, last.media);
+    crate::systemd::notify("READY=1");
+    while !STOP.load(Ordering::Relaxed) {
+        let job = jobs.try_recv().ok();
+        if !SerialTransport::is_present(&options.device) {
+            match job {
+                Some(job) => {
+                    let _ = job.events.send(AppMessage::Error(format!("{} is not connected", options.device)));
+                }
+                None => {
+                    crate::systemd::status(&format!("Waiting for {}", options.device));
+                    log::debug!("Waiting for {}", options.device);
+                    sleep_unless_stopped(POLL, &jobs);
+                }
+            }
+            continue;
+        }
+
+        let applied = match job {
+            Some(job) => run_job(job, &options.device, &mut last, &links),
+            None => {
+                // Let the device finish booting its serial service
+                sleep_unless_stopped(Duration::from_secs(2), &jobs);
+                if STOP.load(Ordering::Relaxed) || !jobs.is_empty() {
+                    continue;
+                }
+                // The last heartbeat may have switched to a reloaded config, the file has it
+                links.reload.try_iter().for_each(drop);
+                if let Some(saved) = LastApplied::load() {
+                    last = saved;
+                }
+                reapply(&options.device, &last, &links)
+            }
+        };
+        let result = applied.and_then(|controller| {
+            crate::systemd::status(&format!("Showing {} on {}", last.media, options.device));
+            allow_interrupt(&jobs);
+            controller.heartbeat(&daemon_config(&last.config), &last.media)
+        });
+        match result {
+            Ok(()) => log::info!("Heartbeat stopped"),
+            Err(e) => log::error!("{:#}", e),
+        }
+        if !STOP.load(Ordering::Relaxed) && jobs.is_empty() {
+            crate::systemd::status(&format!("Retrying in {:?}", RETRY_DELAY));
+            log::info!("Retrying in {:?}", RETRY_DELAY);
+            sleep_unless_stopped(RETRY_DELAY, &jobs);
+        }
+    }
+
+    log::info!("Stopping");
+    crate::systemd::notify("STOPPING=1\nSTATUS=Running the exit action");
+    crate::ipc::remove_socket();
+    let config = daemon_config(&last.config);
+    let controller = AioCoolerController::new(&options.device).with_retry(config.retry.clone());
+    if let Err(e) = controller.shutdown(&config) {
+        log::error!("Shutdown action failed: {:#}", e);
+    }
+    crate::sniffer::stop();
+    0
+}
+
+/// What every session's controller is hooked up to
+struct Links {
+    reload: Receiver<LastApplied>,
+    /// State, sysinfo and progress for MQTT and WebSocket clients
+    broadcast: crate::broadcast::Broadcast,
+    /// Screen power from MQTT, sent between keepalives
+    commands: Receiver<DeviceCommand>,
+}
+
+impl Links {
+    fn controller(&self, device: &str, config: &ScreenConfig) -> AioCoolerController {
+        AioCoolerController::new(device)
+            .with_cancel(new_session())
+            .with_retry(config.retry.clone())
+            .with_reload(self.reload.clone())
+            .with_broadcast(self.broadcast.clone())
+            .with_commands(self.commands.clone())
+    }
+}
+
+/// Show the saved media with the saved config. The session stays open for the heartbeat.
+fn reapply(device: &str, last: &LastApplied, links: &Links) -> anyhow::Result<AioCoolerController> {
+    let config = daemon_config(&last.config);
+    let controller = links.controller(device, &config);
+    log::info!("Applying {}", last.media);
+    crate::systemd::status(&format!("Applying {}", last.media));
+    controller.send_image_commands(&last.media, 0, "", None, &config)?;
+    links.broadcast.send(crate::broadcast::Status::Applied { media: last.media.clone() });
+    Ok(controller)
+}
+
+/// Carry out a client's request, reporting to it like a GUI worker would. On
+/// success `last` is what's on screen now and the session stays open.
+fn run_job(job: Job, device: &str, last: &mut LastApplied, links: &Links) -> anyhow::Result<AioCoolerController> {
+    let Job { request, events } = job;
+    let events = events.with_broadcast(links.broadcast.clone());
+    *JOB_EVENTS.lock().unwrap() = Some(events.clone());
+    let (config, result) = match request {
+        Request::Send { image, config, profile } => {
+            let config = daemon_config(&config);
+            log::info!("Client sent {}", image.display());
+            crate::systemd::status(&format!("Sending {}", image.display()));
+            let controller = links.controller(device, &config)
+                .with_adb(config.adb.clone())
+                .with_events(events.clone());
+            let mut report = crate::timing::TransferReport::default();
+            let result = crate::app_state::transfer_image(&controller, &image, &config, &events, &mut report, profile.as_deref())
+                .map(|media| (controller, media, "Transfer complete!".to_string()));
+            (config, result)
+        }
+        Request::Apply { config } => {
+            let config = daemon_config(&config);
+            log::info!("Client changed the config of {}", last.media);
+            crate::systemd::status(&format!("Applying {}", last.media));
+            let controller = links.controller(device, &config).with_events(events.clone());
+            let result = controller.send_image_commands(&last.media, 0, "", None, &config).map(|()| {
+                let _ = events.send(AppMessage::Applied(Box::new(LastApplied::new(&config, &last.media))));
+                (controller, last.media.clone(), "Configuration applied".to_string())
+            });
+            (config, result)
+        }
+        // The listener answers these itself, they never become jobs
+        Request::Status => (last.config.clone(), Err(anyhow::anyhow!("Status requests aren't jobs"))),
+    };
+    *JOB_EVENTS.lock().unwrap() = None;
+
+    match result {
+        Ok((controller, media, message)) => {
+            *last = LastApplied::new(&config, &media);
+            if let Err(e) = last.save() {
+                log::warn!("Failed to persist last applied config: {:#}", e);
+            }
+            let _ = events.send(AppMessage::Success(message));
+            Ok(controller)
+        }
+        Err(e) => {
+            let _ = events.send(AppMessage::Error(format!("{:#}", e)));
+            Err(e)
+        }
+    }
+}
+
+/// Hand the saved config to the heartbeat on SIGHUP or when the file changes.
+/// Our own saves come through too; the heartbeat skips a config it already runs.
+fn watch_config(reload: crossbeam::channel::Sender<LastApplied>) {
+    let path = LastApplied::path();
+    let changes = crate::file_watch::watch(&path)
+        .inspect_err(|e| log::warn!("Not watching {}, only SIGHUP reloads it: {:#}", path.display(), e))
+        .ok();
+    loop {
+        let changed = match &changes {
+            Some(changes) => changes.recv_timeout(POLL).is_ok(),
+            None => {
+                thread::sleep(POLL);
+                false
+            }
+        };
+        if RELOAD.swap(false, Ordering::Relaxed) {
+            log::info!("SIGHUP, reading {} again", path.display());
+        } else if !changed {
+            continue;
+        }
+        match LastApplied::load() {
+            Some(mut next) => {
+                crate::log_file::open("daemon", &next.config.log_file);
+                next.config = daemon_config(&next.config);
+                let _ = reload.send(next);
+            }
+            None => log::warn!("Keeping the current config, {} can't be read", path.display()),
+        }
+    }
+}
+
+fn sleep_unless_stopped(duration: Duration, jobs: &Receiver<Job>) {
+    let deadline = std::time::Instant::now() + duration;
+    while !STOP.load(Ordering::Relaxed) && jobs.is_empty() && std::time::Instant::now() < deadline {
+        crate::systemd::watchdog();
+        thread::sleep(POLL.min(deadline.saturating_duration_since(std::time::Instant::now())));
+    }
+}
+
+/// Log to stderr instead of the GUI's log panel, debug lines only when `verbose`
+pub fn log_to_stderr(verbose: bool) {
+    let level = if verbose { log::LevelFilter::Debug } else { log::LevelFilter::Info };
+    if crate::log_file::install(StderrLogger, level).is_err() {
+        eprintln!("A logger is already installed");
+    }
+}
+
+/// `2026-10-18 14:03:11 INFO  Heartbeat every 1s`
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    /// Filtered by the level given to `log_file::install`
+    fn enabled(&self, _metadata: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        let _ = writeln!(
+            std::io::stderr().lock(),
+            "{} {:<5} {}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            record.level(),
+            record.args()
+        );
+        if let Some(events) = JOB_EVENTS.lock().unwrap().as_ref() {
+            let _ = events.send(AppMessage::Log(record.args().to_string()));
+        }
+    }
+
+    fn flush(&self) {}
+}
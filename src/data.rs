@@ -4,25 +4,35 @@
 // ============================================================================
 
 use std::{
+    collections::HashMap,
     fmt::{self, Write as _},
-    io::Write,
-    time::{SystemTime, UNIX_EPOCH},
+    io,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-const FRAME_MARKER: u8 = 0x5A;
-const ESCAPE_MARKER: u8 = 0x5B;
+use serde::Serialize;
+
+use crate::protocol_profile::ProtocolProfile;
+use crate::transport::Transport;
+
 const CRLF: &str = "\r\n";
+/// Largest body slice per fragment. Escaping can double every byte, so this plus
+/// the headers still fits the u16 length field in the worst case.
+const MAX_FRAGMENT_BODY: usize = 30_000;
+/// Half-received fragmented messages kept at once
+const MAX_PARTIAL_MESSAGES: usize = 8;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum ContentType {
     Json,
-    // Binary,
+    Binary,
     // Text,
 }
 
 impl ContentType {
-    fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &'static str {
         match self {
             ContentType::Json => "json",
+            ContentType::Binary => "binary",
         }
     }
 }
@@ -30,6 +40,7 @@ impl ContentType {
 /// Attempt #3 to fix build_message to make it more ergonomic
 #[derive(Debug)]
 pub struct CommandMessageBuilder<'a> {
+    method: &'a str,
     cmd_type: &'a str,
     body: &'a str,
     seq_number: Option<i64>,
@@ -44,6 +55,8 @@ pub struct CommandMessageBuilder<'a> {
 
 #[derive(Debug)]
 pub struct CommandMessage<'a> {
+    /// Request verb: POST to push state, GET to query it, STATE for sysinfo
+    pub method: &'a str,
     pub cmd_type: &'a str,
     pub seq_number: i64,
     pub ack_number: i64,
@@ -60,6 +73,7 @@ pub struct CommandMessage<'a> {
 impl<'a> CommandMessageBuilder<'a> {
     pub fn new(cmd_type: &'a str, body: &'a str) -> Self {
         CommandMessageBuilder {
+            method: "POST",
             cmd_type,
             body,
             seq_number: None,
@@ -73,6 +87,11 @@ impl<'a> CommandMessageBuilder<'a> {
         }
     }
 
+    pub fn method(mut self, method: &'a str) -> Self {
+        self.method = method;
+        self
+    }
+
     pub fn seq_number(mut self, seq: i64) -> Self {
         self.seq_number = Some(seq);
         self
@@ -89,10 +108,11 @@ impl<'a> CommandMessageBuilder<'a> {
             .unwrap()
             .as_millis();
 
-        let seq = self.seq_number.unwrap_or((now % 100_000) as i64);
+        let seq = self.seq_number.unwrap_or_else(crate::sequence::next);
         let ts = now as i64;
 
         CommandMessage {
+            method: self.method,
             cmd_type: self.cmd_type,
             seq_number: seq,
             ack_number: self.ack_number,
@@ -115,10 +135,11 @@ impl<'a> CommandMessage<'a> {
             .unwrap()
             .as_millis();
 
-        let seq = (now % 100_000) as i64;
+        let seq = crate::sequence::next();
         let ts = now as i64;
 
         CommandMessage {
+            method: "POST",
             cmd_type,
             seq_number: seq,
             ack_number: -1,
@@ -142,19 +163,19 @@ impl<'a> CommandMessage<'a> {
     }
 
     /// Build message content in HTTP-like format:
-    /// POST cmdType version\r\n
+    /// METHOD cmdType version\r\n
     /// Key=Value\r\n
     /// ...\r\n
     /// \r\n
     /// {json}
-    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>, anyhow::Error> {
+    pub fn to_bytes(&self) -> ProtocolResult<Vec<u8>> {
         // This feels disgusting // TODO: Please for the love of god, I need to find a better solution
         let mut msg = String::with_capacity(
-            "POST  1\r\n\r\n".len() + self.cmd_type.len() + self.body.len() + 128,
+            "  1\r\n\r\n".len() + self.method.len() + self.cmd_type.len() + self.body.len() + 128,
         );
 
         // Request line
-        write!(&mut msg, "POST {} 1{CRLF}", self.cmd_type)?;
+        write!(&mut msg, "{} {} 1{CRLF}", self.method, self.cmd_type)?;
 
         // Headers
         Self::write_header(&mut msg, "SeqNumber", self.seq_number)?;
@@ -177,20 +198,22 @@ impl<'a> CommandMessage<'a> {
 }
 
 /// Command message attempt #4 as a temporary test
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CommandMessageWithMethod<'a> {
     pub method: &'a str,
     pub cmd_type: &'a str,
     pub seq_number: i64,
     pub ack_number: i64,
     pub content_type: ContentType,
-    pub body: &'a str,
+    pub body: &'a [u8],
     pub date: i64,
-    pub file_name: i64,
+    pub file_name: Option<&'a str>,
     pub file_size: i64,
-    pub content_range: i64,
+    pub content_range: Option<String>,
     pub counter: i64,
     pub msg_id: i64,
+    /// `ContentEncoding` header, only sent when the body is compressed
+    pub content_encoding: Option<&'static str>,
 }
 
 impl<'a> CommandMessageWithMethod<'a> {
@@ -203,61 +226,95 @@ impl<'a> CommandMessageWithMethod<'a> {
         Self {
             method,
             cmd_type,
-            seq_number: (now % 100_000) as i64,
+            seq_number: crate::sequence::next(),
             ack_number: -1,
             content_type: ContentType::Json,
-            body,
+            body: body.as_bytes(),
             date: now as i64,
-            file_name: -1,
+            file_name: None,
             file_size: -1,
-            content_range: -1,
+            content_range: None,
             counter: -1,
             msg_id: -1,
+            content_encoding: None,
         }
     }
 
-    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
-        let mut msg = String::with_capacity(256 + self.body.len());
+    /// Response to a device-initiated message, as the APK service sends them:
+    /// same cmdType, its SeqNumber in AckNumber and its msgId echoed. The reply
+    /// still gets a fresh SeqNumber of its own. Method defaults to POST.
+    pub fn reply_to(request: &'a ResponseMessage, body: &'a str) -> Self {
+        let mut msg = Self::new("POST", &request.cmd_type, body);
+        msg.ack_number = request.seq_number;
+        msg.msg_id = request.msg_id;
+        msg
+    }
+
+    /// Raw file bytes for a serial upload, described by the FileName/FileSize/ContentRange headers
+    pub fn file_chunk(cmd_type: &'a str, file_name: &'a str, file_size: u64, offset: u64, data: &'a [u8]) -> Self {
+        let mut msg = Self::new("POST", cmd_type, "");
+        msg.content_type = ContentType::Binary;
+        msg.body = data;
+        msg.file_name = Some(file_name);
+        msg.file_size = file_size as i64;
+        msg.content_range = Some(format!("{}-{}", offset, offset + data.len() as u64));
+        msg
+    }
+
+    pub fn to_bytes(&self) -> ProtocolResult<Vec<u8>> {
+        let mut msg = String::with_capacity(256);
 
         // Request line: METHOD cmdType version
         write!(&mut msg, "{} {} 1{CRLF}", self.method, self.cmd_type)?;
 
-        // Headers
-        write!(&mut msg, "SeqNumber={}{CRLF}", self.seq_number)?;
-        write!(&mut msg, "AckNumber={}{CRLF}", self.ack_number)?;
-        write!(&mut msg, "ContentLength={}{CRLF}", self.body.len())?;
-        write!(&mut msg, "ContentType={}{CRLF}", self.content_type.as_str())?;
-        write!(&mut msg, "FileName={}{CRLF}", self.file_name)?;
-        write!(&mut msg, "FileSize={}{CRLF}", self.file_size)?;
-        write!(&mut msg, "ContentRange={}{CRLF}", self.content_range)?;
-        write!(&mut msg, "Counter={}{CRLF}", self.counter)?;
-        write!(&mut msg, "Date={}{CRLF}", self.date)?;
-        write!(&mut msg, "msgId={}{CRLF}", self.msg_id)?;
+        // Headers, the set and order come from the protocol profile
+        for name in &crate::protocol_profile::active().headers {
+            write!(&mut msg, "{}=", name)?;
+            match name.as_str() {
+                "SeqNumber" => write!(&mut msg, "{}", self.seq_number)?,
+                "AckNumber" => write!(&mut msg, "{}", self.ack_number)?,
+                "ContentLength" => write!(&mut msg, "{}", self.body.len())?,
+                "ContentType" => msg.push_str(self.content_type.as_str()),
+                "FileName" => msg.push_str(self.file_name.unwrap_or("-1")),
+                "FileSize" => write!(&mut msg, "{}", self.file_size)?,
+                "ContentRange" => msg.push_str(self.content_range.as_deref().unwrap_or("-1")),
+                "Counter" => write!(&mut msg, "{}", self.counter)?,
+                "Date" => write!(&mut msg, "{}", self.date)?,
+                "msgId" => write!(&mut msg, "{}", self.msg_id)?,
+                _ => msg.push_str("-1"),
+            }
+            msg.push_str(CRLF);
+        }
+        if let Some(encoding) = self.content_encoding {
+            write!(&mut msg, "ContentEncoding={}{CRLF}", encoding)?;
+        }
 
         // Blank line + body
         msg.push_str(CRLF);
-        msg.push_str(self.body);
 
-        Ok(msg.into_bytes())
+        let mut bytes = msg.into_bytes();
+        bytes.extend_from_slice(self.body);
+        Ok(bytes)
     }
 }
 
-/// Escape special bytes in the data
+/// Escape special bytes in the data, with the default profile:
 /// 0x5A -> 0x5B 0x01
 /// 0x5B -> 0x5B 0x02
-fn escape_data(data: &[u8]) -> Vec<u8> {
+fn escape_data(profile: &ProtocolProfile, data: &[u8]) -> Vec<u8> {
+    let mut table = [None; 256];
+    for &(byte, code) in &profile.escapes {
+        table[byte as usize] = Some(code);
+    }
+
     let mut result = Vec::with_capacity(data.len() * 2);
     for &b in data {
-        match b {
-            0x5A => {
-                result.push(ESCAPE_MARKER);
-                result.push(0x01);
-            }
-            0x5B => {
-                result.push(ESCAPE_MARKER);
-                result.push(0x02);
+        match table[b as usize] {
+            Some(code) => {
+                result.push(profile.escape_marker);
+                result.push(code);
             }
-            _ => result.push(b),
+            None => result.push(b),
         }
     }
     result
@@ -270,58 +327,838 @@ fn calc_crc(data: &[u8]) -> u8 {
 
 /// Frame builder
 /// [0x5A][length:2bytes BE][escaped_message][CRC:1byte][0x5A]
-fn build_frame(message: &[u8]) -> Vec<u8> {
-    let escaped = escape_data(message);
+fn build_frame(profile: &ProtocolProfile, message: &[u8]) -> Vec<u8> {
+    let escaped = escape_data(profile, message);
     let length = escaped.len() as u16;
 
     let mut frame = Vec::with_capacity(escaped.len() + 5);
-    frame.push(FRAME_MARKER); // Start marker
+    frame.push(profile.frame_marker); // Start marker
     frame.extend_from_slice(&length.to_be_bytes()); // 2-byte length, big-endian
     frame.extend_from_slice(&escaped); // Escaped message
     frame.push(calc_crc(&escaped)); // CRC of escaped data
-    frame.push(FRAME_MARKER); // End marker
+    frame.push(profile.frame_marker); // End marker
 
     frame
 }
 
 
-/// Send a framed POST command over serial
-pub fn send_command(
-    port: &mut Box<dyn serialport::SerialPort>,
-    cmd_type: &str,
-    json_value: &serde_json::Value,
-) -> anyhow::Result<()> {
-    send_request(port, "POST", cmd_type, json_value)
+/// Reverse of `escape_data`
+/// 0x5B 0x01 -> 0x5A
+/// 0x5B 0x02 -> 0x5B
+fn unescape_data(profile: &ProtocolProfile, data: &[u8]) -> ProtocolResult<Vec<u8>> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut iter = data.iter();
+    while let Some(&b) = iter.next() {
+        if b != profile.escape_marker {
+            result.push(b);
+            continue;
+        }
+        let Some(&code) = iter.next() else {
+            return Err(ProtocolError::MalformedFrame("truncated escape sequence at end of payload".to_string()));
+        };
+        match profile.escapes.iter().find(|&&(_, c)| c == code) {
+            Some(&(byte, _)) => result.push(byte),
+            None => {
+                return Err(ProtocolError::MalformedFrame(format!(
+                    "invalid escape sequence 0x{:02x} 0x{:02x}",
+                    profile.escape_marker, code
+                )));
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Everything that can go wrong talking to the device. Callers branch on the kind,
+/// e.g. reconnect on `PortClosed` but show `Nack` to the user.
+#[derive(Debug, thiserror::Error)]
+pub enum ProtocolError {
+    #[error("{cmd_type} frame is {len} bytes, more than the u16 length field allows")]
+    FrameTooLarge { cmd_type: String, len: usize },
+
+    #[error("CRC mismatch: frame has 0x{received:02x}, calculated 0x{calculated:02x}")]
+    CrcMismatch { received: u8, calculated: u8 },
+
+    /// Framing is broken (markers, escapes), the frame is dropped
+    #[error("Malformed frame: {0}")]
+    MalformedFrame(String),
+
+    /// Frame was fine but the message inside wasn't
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
+
+    #[error("No reply to {cmd_type} (msgId={msg_id}) within {timeout:?}")]
+    Timeout { cmd_type: String, msg_id: i64, timeout: Duration },
+
+    /// The port or socket went away, reconnecting is the only way forward
+    #[error("Port closed: {0}")]
+    PortClosed(io::Error),
+
+    /// The device answered but refused the command
+    #[error("Device rejected {cmd_type} (code {code}): {message}")]
+    Nack { cmd_type: String, code: i64, message: String },
+
+    #[error(transparent)]
+    Io(io::Error),
+
+    #[error("Invalid JSON body")]
+    Json(#[from] serde_json::Error),
+}
+
+pub type ProtocolResult<T> = Result<T, ProtocolError>;
+
+impl From<io::Error> for ProtocolError {
+    fn from(e: io::Error) -> Self {
+        if crate::serial::is_closed_port(&e) {
+            ProtocolError::PortClosed(e)
+        } else {
+            ProtocolError::Io(e)
+        }
+    }
+}
+
+impl From<fmt::Error> for ProtocolError {
+    fn from(_: fmt::Error) -> Self {
+        ProtocolError::InvalidMessage("failed to format message headers".to_string())
+    }
+}
+
+impl From<std::str::Utf8Error> for ProtocolError {
+    fn from(e: std::str::Utf8Error) -> Self {
+        ProtocolError::InvalidMessage(format!("headers are not UTF-8: {}", e))
+    }
+}
+
+/// Result of looking for a frame at the start of a buffer
+#[derive(Debug)]
+pub enum FrameParse {
+    /// Unescaped message and the number of bytes the frame occupied
+    Complete(Vec<u8>, usize),
+    /// Need more bytes
+    Incomplete,
+}
+
+/// Frame parser (DataConvert.analy)
+/// `data` must start at a start marker (0x5A by default). The length, CRC and end marker are
+/// not escaped, so the length field is the only reliable way to find the end.
+pub fn parse_frame(data: &[u8]) -> ProtocolResult<FrameParse> {
+    parse_frame_with(&crate::protocol_profile::active(), data)
+}
+
+fn parse_frame_with(profile: &ProtocolProfile, data: &[u8]) -> ProtocolResult<FrameParse> {
+    if data.is_empty() {
+        return Ok(FrameParse::Incomplete);
+    }
+    if data[0] != profile.frame_marker {
+        return Err(ProtocolError::MalformedFrame(format!(
+            "frame does not start with 0x{:02x} (got 0x{:02x})",
+            profile.frame_marker, data[0]
+        )));
+    }
+    if data.len() < 3 {
+        return Ok(FrameParse::Incomplete);
+    }
+
+    let length = u16::from_be_bytes([data[1], data[2]]) as usize;
+    let total = length + 5;
+    if data.len() < total {
+        return Ok(FrameParse::Incomplete);
+    }
+
+    let escaped = &data[3..3 + length];
+    let crc = data[3 + length];
+    let end = data[4 + length];
+    if end != profile.frame_marker {
+        return Err(ProtocolError::MalformedFrame(format!("missing end marker (got 0x{:02x})", end)));
+    }
+    let expected = calc_crc(escaped);
+    if crc != expected {
+        return Err(ProtocolError::CrcMismatch { received: crc, calculated: expected });
+    }
+
+    Ok(FrameParse::Complete(unescape_data(profile, escaped)?, total))
+}
+
+/// Undo `ContentEncoding=deflate`, dropping the message if the body doesn't inflate
+fn inflate(mut message: ResponseMessage) -> Option<ResponseMessage> {
+    if message.headers.get("ContentEncoding").map(String::as_str) != Some(crate::compression::ENCODING) {
+        return Some(message);
+    }
+    match crate::compression::decompress(&message.body) {
+        Ok(body) => {
+            message.headers.remove("ContentEncoding");
+            message.headers.insert("ContentLength".to_string(), body.len().to_string());
+            message.body = body;
+            Some(message)
+        }
+        Err(e) => {
+            log::warn!("Dropping {} {} with a corrupt compressed body: {}", message.method, message.cmd_type, e);
+            None
+        }
+    }
+}
+
+/// A complete frame pulled out of the byte stream
+#[derive(Debug, Clone)]
+pub struct AssembledFrame {
+    /// The frame as it was on the wire, markers included
+    pub raw: Vec<u8>,
+    /// Unescaped message, ready for `ResponseMessage::parse`
+    pub message: Vec<u8>,
+}
+
+/// Turns reads of any size into frames. Partial data is kept between reads, so
+/// a frame split across reads, or several frames in one read, come out whole.
+#[derive(Debug, Default)]
+pub struct FrameAssembler {
+    buffer: Vec<u8>,
+    /// Skips since the last `take_resyncs`
+    resyncs: u64,
+    discarded: u64,
+    /// Fragmented messages still missing pieces, by (cmdType, SeqNumber)
+    partial: HashMap<(String, i64), PartialMessage>,
+}
+
+#[derive(Debug)]
+struct PartialMessage {
+    /// First fragment seen, its headers are reused for the whole message
+    head: ResponseMessage,
+    body: Vec<u8>,
+    /// Byte ranges that have arrived, sorted and merged, so a fragment sent
+    /// twice isn't counted twice
+    received: Vec<(usize, usize)>,
+}
+
+impl PartialMessage {
+    fn new(head: ResponseMessage, total: usize) -> Self {
+        Self { head, body: vec![0; total], received: Vec::new() }
+    }
+
+    fn add(&mut self, start: usize, data: &[u8]) {
+        let end = start + data.len();
+        self.body[start..end].copy_from_slice(data);
+        self.received.push((start, end));
+        self.received.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(self.received.len());
+        for &(start, end) in &self.received {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.received = merged;
+    }
+
+    fn complete(&self) -> bool {
+        self.received == [(0, self.body.len())]
+    }
+}
+
+impl FrameAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Forget any partial frame, e.g. after reopening the port
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.partial.clear();
+    }
+
+    /// Run a parsed message through fragment reassembly. A message that isn't a
+    /// fragment comes straight back, fragments are held until the last one arrives.
+    /// Compressed bodies are inflated once the message is whole.
+    pub fn reassemble(&mut self, message: ResponseMessage) -> Option<ResponseMessage> {
+        let message = self.join_fragments(message)?;
+        inflate(message)
+    }
+
+    fn join_fragments(&mut self, message: ResponseMessage) -> Option<ResponseMessage> {
+        let Some((start, end, total)) = fragment_range(&message) else {
+            return Some(message);
+        };
+        if start > end || end > total || end - start != message.body.len() {
+            log::warn!("Dropping {} fragment with inconsistent range {}-{}/{}", message.cmd_type, start, end, total);
+            return None;
+        }
+        // The whole message is allocated up front, don't let one bad header ask for gigabytes
+        if total as u64 > crate::compression::MAX_INFLATED {
+            log::warn!("Dropping {} fragment of a {} byte message, over the size limit", message.cmd_type, total);
+            return None;
+        }
+
+        let key = (message.cmd_type.clone(), message.seq_number);
+        if !self.partial.contains_key(&key) && self.partial.len() >= MAX_PARTIAL_MESSAGES {
+            log::warn!("Too many incomplete fragmented messages, dropping them");
+            self.partial.clear();
+        }
+        let partial = self.partial.entry(key.clone()).or_insert_with(|| PartialMessage::new(message.clone(), total));
+        if partial.body.len() != total {
+            log::warn!("{} fragments disagree on the total size, restarting", message.cmd_type);
+            *partial = PartialMessage::new(message.clone(), total);
+        }
+        partial.add(start, &message.body);
+        log::debug!("{} fragment {}-{}/{}", message.cmd_type, start, end, total);
+        if !partial.complete() {
+            return None;
+        }
+
+        let PartialMessage { mut head, body, .. } = self.partial.remove(&key)?;
+        head.headers.remove("ContentRange");
+        head.headers.insert("ContentLength".to_string(), body.len().to_string());
+        head.content_range = -1;
+        head.counter = -1;
+        head.body = body;
+        Some(head)
+    }
+
+    /// Next frame in the buffer. `Some(Err)` is a frame that had to be dropped
+    /// (the assembler has already moved past it), `None` means more bytes are needed.
+    pub fn next_frame(&mut self) -> Option<ProtocolResult<AssembledFrame>> {
+        let profile = crate::protocol_profile::active();
+        let marker = profile.frame_marker;
+        loop {
+            // Drop noise in front of the first start marker
+            let start = self.buffer.iter().position(|&b| b == marker).unwrap_or(self.buffer.len());
+            self.skip(start);
+            if self.buffer.is_empty() {
+                return None;
+            }
+
+            // The escaped payload never contains the marker. Seeing one inside the announced
+            // length means this wasn't a start marker (e.g. the end of a frame we joined
+            // halfway, or a corrupted length), so resync on the next marker right away
+            // instead of waiting for up to 64 KiB that will never make a valid frame.
+            if self.buffer.len() > 3 {
+                let length = u16::from_be_bytes([self.buffer[1], self.buffer[2]]) as usize;
+                let payload = &self.buffer[3..self.buffer.len().min(3 + length)];
+                if payload.contains(&marker) {
+                    self.skip(1);
+                    continue;
+                }
+            }
+
+            return match parse_frame_with(&profile, &self.buffer) {
+                Ok(FrameParse::Complete(message, used)) => {
+                    let raw = self.buffer.drain(..used).collect();
+                    Some(Ok(AssembledFrame { raw, message }))
+                }
+                Ok(FrameParse::Incomplete) => None,
+                Err(e) => {
+                    self.skip(1);
+                    Some(Err(e))
+                }
+            };
+        }
+    }
+
+    /// Resync events and bytes thrown away since the last call
+    pub fn take_resyncs(&mut self) -> (u64, u64) {
+        (std::mem::take(&mut self.resyncs), std::mem::take(&mut self.discarded))
+    }
+
+    fn skip(&mut self, count: usize) {
+        if count > 0 {
+            log::debug!("Resync: skipped {} byte(s)", count);
+            self.buffer.drain(..count);
+            self.resyncs += 1;
+            self.discarded += count as u64;
+        }
+    }
+}
+
+/// `ContentRange=<start>-<end>/<total>` marks a fragment of a larger message.
+/// File chunks use the same header without `/<total>` and aren't fragments.
+fn fragment_range(message: &ResponseMessage) -> Option<(usize, usize, usize)> {
+    let (range, total) = message.headers.get("ContentRange")?.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    Some((start.parse().ok()?, end.parse().ok()?, total.parse().ok()?))
+}
+
+/// Message received from the device (getSerDataByBytes)
+#[derive(Debug, Clone)]
+pub struct ResponseMessage {
+    pub method: String,
+    pub cmd_type: String,
+    pub version: String,
+    pub seq_number: i64,
+    pub ack_number: i64,
+    pub content_type: String,
+    pub file_name: String,
+    pub file_size: i64,
+    pub content_range: i64,
+    pub counter: i64,
+    pub date: i64,
+    pub msg_id: i64,
+    /// Every header as sent, including ones without a typed field above
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl ResponseMessage {
+    /// Parse an unescaped message:
+    /// METHOD cmdType version\r\n
+    /// Key=Value\r\n
+    /// ...\r\n
+    /// \r\n
+    /// body
+    pub fn parse(message: &[u8]) -> ProtocolResult<Self> {
+        let header_end = message
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| ProtocolError::InvalidMessage("no header terminator".to_string()))?;
+
+        let head = std::str::from_utf8(&message[..header_end])?;
+        let mut lines = head.split(CRLF);
+
+        let request_line = lines.next().unwrap_or_default();
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().ok_or_else(|| ProtocolError::InvalidMessage("empty request line".to_string()))?.to_string();
+        let cmd_type = parts.next().ok_or_else(|| ProtocolError::InvalidMessage(format!("request line has no cmdType: {}", request_line)))?.to_string();
+        let version = parts.next().unwrap_or("1").to_string();
+
+        let headers: HashMap<String, String> = lines
+            .filter_map(|line| line.split_once('='))
+            .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+            .collect();
+        let number = |key: &str| headers.get(key).and_then(|v| v.parse().ok()).unwrap_or(-1);
+
+        let mut body = message[header_end + 4..].to_vec();
+        if let Some(len) = headers.get("ContentLength").and_then(|v| v.parse::<usize>().ok()) {
+            body.truncate(len);
+        }
+
+        Ok(Self {
+            seq_number: number("SeqNumber"),
+            ack_number: number("AckNumber"),
+            file_size: number("FileSize"),
+            content_range: number("ContentRange"),
+            counter: number("Counter"),
+            date: number("Date"),
+            msg_id: number("msgId"),
+            content_type: headers.get("ContentType").cloned().unwrap_or_default(),
+            file_name: headers.get("FileName").cloned().unwrap_or_default(),
+            method,
+            cmd_type,
+            version,
+            headers,
+            body,
+        })
+    }
+
+    pub fn body_str(&self) -> std::borrow::Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+
+    pub fn json(&self) -> ProtocolResult<serde_json::Value> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+
+    /// A JSON reply with a nonzero `code` is the device refusing the command
+    pub fn nack(&self) -> Option<ProtocolError> {
+        let body = serde_json::from_slice::<serde_json::Value>(&self.body).ok()?;
+        let code = body.get("code")?.as_i64()?;
+        if code == 0 {
+            return None;
+        }
+        Some(ProtocolError::Nack {
+            cmd_type: self.cmd_type.clone(),
+            code,
+            message: body.get("msg").and_then(|m| m.as_str()).unwrap_or_default().to_string(),
+        })
+    }
+}
+
+/// Read until one complete frame arrives or `timeout` passes. Returns None on timeout.
+/// Bytes read past the end of the frame stay in `assembler` for the next call.
+pub fn read_response(
+    port: &mut dyn Transport,
+    assembler: &mut FrameAssembler,
+    timeout: Duration,
+) -> ProtocolResult<Option<ResponseMessage>> {
+    let deadline = Instant::now() + timeout;
+    let mut chunk = [0u8; 1024];
+
+    // Short reads so we notice the deadline instead of blocking for the port's full timeout
+    let port_timeout = port.read_timeout();
+    port.set_read_timeout(Duration::from_millis(50))?;
+    let result = (|| {
+        loop {
+            // Frames left over from an earlier read come first
+            while let Some(frame) = assembler.next_frame() {
+                let frame = match frame {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        if matches!(e, ProtocolError::CrcMismatch { .. }) {
+                            crate::link_stats::stats().record_crc_error();
+                        } else {
+                            crate::link_stats::stats().record_malformed_frame();
+                        }
+                        log::warn!("Dropping bad frame: {:#}", e);
+                        continue;
+                    }
+                };
+
+                crate::diagnostics::record_frame("RX", &frame.raw);
+                crate::sniffer::record_frame(crate::sniffer::Direction::Rx, &frame.raw);
+                crate::link_stats::stats().record_received(frame.raw.len());
+                let response = match ResponseMessage::parse(&frame.message) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        log::warn!("Dropping unparseable message: {:#}", e);
+                        continue;
+                    }
+                };
+                let Some(response) = assembler.reassemble(response) else {
+                    continue;
+                };
+                log::info!(
+                    "Received {} {} ({} bytes, Seq={}, Ack={})",
+                    response.method,
+                    response.cmd_type,
+                    response.body.len(),
+                    response.seq_number,
+                    response.ack_number
+                );
+                return Ok(Some(response));
+            }
+
+            let (resyncs, discarded) = assembler.take_resyncs();
+            if resyncs > 0 {
+                crate::link_stats::stats().record_resyncs(resyncs, discarded);
+            }
+
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            match port.read(&mut chunk) {
+                Ok(0) => {}
+                Ok(n) => {
+                    crate::sniffer::record_raw(crate::sniffer::Direction::Rx, &chunk[..n]);
+                    assembler.push(&chunk[..n]);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    })();
+
+    port.set_read_timeout(port_timeout)?;
+    result
+}
+
+// ============================================================================
+// Typed commands
+// Payload field names follow what SerialMsgReceiverHandler reads
+// ============================================================================
+
+/// `waterBlockScreenId` body
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenConfigPayload {
+    pub id: String,
+    pub screen_mode: String,
+    pub play_mode: String,
+    pub ratio: String,
+    pub media: Vec<String>,
+    pub settings: ScreenSettings,
+    pub sysinfo_display: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScreenSettings {
+    pub color: String,
+    pub align: String,
+    pub filter: FilterSettings,
+    pub badges: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FilterSettings {
+    /// Always null so far, the Windows app never sets a filter preset
+    pub value: Option<String>,
+    pub opacity: u8,
+}
+
+/// `mediaDelete` body, removes every media file not listed
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaDeletePayload {
+    pub exclude: Vec<String>,
+}
+
+/// `transport` body announcing a serial upload
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransportPayload {
+    pub file_name: String,
+    pub file_size: u64,
+    pub md5: String,
+}
+
+/// `transported` body closing a serial upload
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransportedPayload {
+    pub file_name: String,
+    pub md5: String,
+}
+
+/// `config` body. Only the keys that are set get sent. The keys were read off
+/// the APK's dispatch table and haven't been seen working on a cooler, so
+/// nothing sends them unless `ScreenConfig::experimental_commands` is on.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeviceConfigPayload {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screen: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u8>,
+}
+
+/// Everything we know how to send, so payload field names are checked at compile time
+#[derive(Debug)]
+pub enum DeviceCommand {
+    ScreenConfig(ScreenConfigPayload),
+    MediaDelete(MediaDeletePayload),
+    SysInfo(Box<crate::sysinfo::SysInfo>),
+    Transport(TransportPayload),
+    Transported(TransportedPayload),
+    /// Panel backlight, 0-100. Experimental, see `DeviceConfigPayload`.
+    Brightness(u8),
+    /// Experimental, see `DeviceConfigPayload`
+    ScreenPower(bool),
+    /// Ask the device for its current state, see `get_command`
+    Get(Query),
 }
 
-/// Send a framed STATE command over serial (used for sysinfo updates)
-pub fn send_state_command(
-    port: &mut Box<dyn serialport::SerialPort>,
-    cmd_type: &str,
-    json_value: &serde_json::Value,
-) -> anyhow::Result<()> {
-    send_request(port, "STATE", cmd_type, json_value)
+/// Device state that can be read back with a GET request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Query {
+    ScreenConfig,
 }
 
-/// Internal: send a framed request with given method (POST/STATE)
-fn send_request(
-    port: &mut Box<dyn serialport::SerialPort>,
-    method: &str,
-    cmd_type: &str,
-    json_value: &serde_json::Value,
-) -> anyhow::Result<()> {
+impl Query {
+    pub fn cmd_type(&self) -> &'static str {
+        match self {
+            Query::ScreenConfig => "waterBlockScreenId",
+        }
+    }
+}
+
+/// GET request for a piece of device state. Send it with a msgId
+/// (`AioCoolerController::request`) so the reply can be picked out.
+pub fn get_command(query: Query) -> DeviceCommand {
+    DeviceCommand::Get(query)
+}
+
+impl DeviceCommand {
+    pub fn method(&self) -> &'static str {
+        match self {
+            DeviceCommand::SysInfo(_) => "STATE",
+            DeviceCommand::Get(_) => "GET",
+            _ => "POST",
+        }
+    }
+
+    pub fn cmd_type(&self) -> &'static str {
+        match self {
+            DeviceCommand::ScreenConfig(_) => "waterBlockScreenId",
+            DeviceCommand::MediaDelete(_) => "mediaDelete",
+            DeviceCommand::SysInfo(_) => "all",
+            DeviceCommand::Transport(_) => "transport",
+            DeviceCommand::Transported(_) => "transported",
+            DeviceCommand::Brightness(_) | DeviceCommand::ScreenPower(_) => "config",
+            DeviceCommand::Get(query) => query.cmd_type(),
+        }
+    }
+
+    pub fn payload(&self) -> ProtocolResult<serde_json::Value> {
+        let value = match self {
+            DeviceCommand::ScreenConfig(p) => serde_json::to_value(p)?,
+            DeviceCommand::MediaDelete(p) => serde_json::to_value(p)?,
+            DeviceCommand::SysInfo(p) => serde_json::to_value(p)?,
+            DeviceCommand::Transport(p) => serde_json::to_value(p)?,
+            DeviceCommand::Transported(p) => serde_json::to_value(p)?,
+            DeviceCommand::Brightness(level) => serde_json::to_value(DeviceConfigPayload {
+                brightness: Some((*level).min(100)),
+                ..Default::default()
+            })?,
+            DeviceCommand::ScreenPower(on) => serde_json::to_value(DeviceConfigPayload {
+                screen: Some(if *on { "on" } else { "off" }),
+                ..Default::default()
+            })?,
+            DeviceCommand::Get(_) => serde_json::json!({}),
+        };
+        Ok(value)
+    }
+
+    /// Frame this command, ready for `write_frame`
+    pub fn build(&self) -> ProtocolResult<OutgoingFrame> {
+        build_request(self.method(), self.cmd_type(), &self.payload()?)
+    }
+
+    /// Frame this command tagged with a msgId so the device reply can be matched to it
+    pub fn build_with_msg_id(&self, msg_id: i64) -> ProtocolResult<OutgoingFrame> {
+        let body = serde_json::to_string(&self.payload()?)?;
+        let mut msg = CommandMessageWithMethod::new(self.method(), self.cmd_type(), &body);
+        msg.msg_id = msg_id;
+        build_message(&msg)
+    }
+}
+
+/// Send a typed command over serial
+pub fn send_device_command(
+    port: &mut dyn Transport,
+    command: &DeviceCommand,
+) -> ProtocolResult<()> {
+    write_frame(port, &command.build()?)
+}
+
+/// A framed request ready to go out, with the SeqNumber it was stamped with
+#[derive(Debug, Clone)]
+pub struct OutgoingFrame {
+    pub method: String,
+    pub cmd_type: String,
+    pub seq_number: i64,
+    pub msg_id: i64,
+    pub bytes: Vec<u8>,
+    /// The same message uncompressed, sent instead if the device rejects this one
+    pub fallback: Option<Box<OutgoingFrame>>,
+}
+
+/// Build a framed request with given method (POST/STATE)
+pub fn build_request(method: &str, cmd_type: &str, json_value: &serde_json::Value) -> ProtocolResult<OutgoingFrame> {
     let body = serde_json::to_string(json_value)?;
-    let msg = CommandMessageWithMethod::new(method, cmd_type, &body);
-    let frame = build_frame(&msg.to_bytes()?);
+    build_message(&CommandMessageWithMethod::new(method, cmd_type, &body))
+}
+
+/// Frame an arbitrary message, e.g. a binary file chunk. Messages too large for
+/// one frame go out as several fragments in the same `OutgoingFrame`. With
+/// compression on, a large body is deflated and the plain frame kept as fallback.
+pub fn build_message(msg: &CommandMessageWithMethod) -> ProtocolResult<OutgoingFrame> {
+    if msg.content_encoding.is_none()
+        && let Some(compressed) = crate::compression::compress(msg.body)
+    {
+        let packed = CommandMessageWithMethod {
+            body: &compressed,
+            content_encoding: Some(crate::compression::ENCODING),
+            content_range: msg.content_range.clone(),
+            ..*msg
+        };
+        let mut frame = frame_message(&packed)?;
+        frame.fallback = Some(Box::new(frame_message(msg)?));
+        return Ok(frame);
+    }
+    frame_message(msg)
+}
+
+fn frame_message(msg: &CommandMessageWithMethod) -> ProtocolResult<OutgoingFrame> {
+    let profile = crate::protocol_profile::active();
+    let payload = msg.to_bytes()?;
+    let mut bytes = build_frame(&profile, &payload);
+    // Length field is a u16, so an oversized payload would silently wrap
+    if bytes.len() - 5 > u16::MAX as usize {
+        // A file chunk already has its own ContentRange, it has to be sent smaller instead
+        if msg.content_range.is_some() || !profile.supports_fragments() {
+            return Err(ProtocolError::FrameTooLarge { cmd_type: msg.cmd_type.to_string(), len: bytes.len() });
+        }
+        bytes = build_fragments(&profile, msg)?;
+    }
+    Ok(OutgoingFrame {
+        method: msg.method.to_string(),
+        cmd_type: msg.cmd_type.to_string(),
+        seq_number: msg.seq_number,
+        msg_id: msg.msg_id,
+        bytes,
+        fallback: None,
+    })
+}
+
+/// Frame message bytes exactly as given, for hand-built messages. The request
+/// line and SeqNumber are picked out for logging when they parse.
+pub fn build_raw(message: &[u8]) -> ProtocolResult<OutgoingFrame> {
+    let bytes = build_frame(&crate::protocol_profile::active(), message);
+    if bytes.len() - 5 > u16::MAX as usize {
+        return Err(ProtocolError::FrameTooLarge { cmd_type: "raw".to_string(), len: bytes.len() });
+    }
+    let parsed = ResponseMessage::parse(message).ok();
+    Ok(OutgoingFrame {
+        method: parsed.as_ref().map_or("RAW".to_string(), |m| m.method.clone()),
+        cmd_type: parsed.as_ref().map_or("raw".to_string(), |m| m.cmd_type.clone()),
+        seq_number: parsed.as_ref().map_or(-1, |m| m.seq_number),
+        msg_id: parsed.as_ref().map_or(-1, |m| m.msg_id),
+        bytes,
+        fallback: None,
+    })
+}
+
+/// Split the body across frames. Each fragment repeats the request line and
+/// headers (same SeqNumber and msgId), with `ContentRange=<start>-<end>/<total>`
+/// and `Counter=<index>`, and the frames are concatenated.
+fn build_fragments(profile: &ProtocolProfile, msg: &CommandMessageWithMethod) -> ProtocolResult<Vec<u8>> {
+
+    let total = msg.body.len();
+    let mut bytes = Vec::with_capacity(total + total / 4);
+    let mut count = 0;
+    for (index, chunk) in msg.body.chunks(MAX_FRAGMENT_BODY).enumerate() {
+        let start = index * MAX_FRAGMENT_BODY;
+        let mut part = msg.clone();
+        part.body = chunk;
+        part.content_range = Some(format!("{}-{}/{}", start, start + chunk.len(), total));
+        part.counter = index as i64;
+
+        let frame = build_frame(profile, &part.to_bytes()?);
+        if frame.len() - 5 > u16::MAX as usize {
+            return Err(ProtocolError::FrameTooLarge { cmd_type: msg.cmd_type.to_string(), len: frame.len() });
+        }
+        bytes.extend_from_slice(&frame);
+        count += 1;
+    }
+    log::debug!("{} body of {} bytes split into {} fragments", msg.cmd_type, total, count);
+    Ok(bytes)
+}
+
+/// The individual frames in a built buffer (more than one for a fragmented message)
+fn frames_in(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = bytes;
+    std::iter::from_fn(move || {
+        if rest.len() < 5 {
+            return None;
+        }
+        let total = (u16::from_be_bytes([rest[1], rest[2]]) as usize + 5).min(rest.len());
+        let (frame, tail) = rest.split_at(total);
+        rest = tail;
+        Some(frame)
+    })
+}
+
+/// Answer a device-initiated request with `method` (see `CommandMessageWithMethod::reply_to`)
+pub fn build_reply(method: &str, request: &ResponseMessage, body: &serde_json::Value) -> ProtocolResult<OutgoingFrame> {
+    let body = body.to_string();
+    let mut msg = CommandMessageWithMethod::reply_to(request, &body);
+    msg.method = method;
+    build_message(&msg)
+}
 
-    log::info!("Sending {} {} ({} bytes)", method, cmd_type, body.len());
+/// Write an already built frame (first send or retransmission)
+pub fn write_frame(port: &mut dyn Transport, frame: &OutgoingFrame) -> ProtocolResult<()> {
+    let bytes = &frame.bytes;
+    log::info!("Sending {} {} (Seq={}, {} bytes)", frame.method, frame.cmd_type, frame.seq_number, bytes.len());
     log::debug!(
         "Frame hex: {}...{}",
-        hex_string(&frame[..30.min(frame.len())]),
-        hex_string(&frame[frame.len().saturating_sub(10)..])
+        hex_string(&bytes[..30.min(bytes.len())]),
+        hex_string(&bytes[bytes.len().saturating_sub(10)..])
     );
 
-    port.write_all(&frame)?;
+    for part in frames_in(bytes) {
+        crate::diagnostics::record_frame("TX", part);
+        crate::sniffer::record_frame(crate::sniffer::Direction::Tx, part);
+    }
+    crate::link_stats::stats().record_sent(&frame.cmd_type, bytes.len());
+    port.write_all(bytes)?;
     port.flush()?;
     Ok(())
 }
@@ -329,3 +1166,143 @@ fn send_request(
 fn hex_string(data: &[u8]) -> String {
     data.iter().map(|b| format!("{:02x}", b)).collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(method: &str, cmd_type: &str, body: &str) -> Vec<u8> {
+        build_message(&CommandMessageWithMethod::new(method, cmd_type, body)).unwrap().bytes
+    }
+
+    /// A fragment of a `total` byte message, `body` being its `start..` part
+    fn fragment(seq_number: i64, start: usize, total: usize, body: &[u8]) -> ResponseMessage {
+        let mut msg = CommandMessageWithMethod::new("POST", "all", "");
+        msg.seq_number = seq_number;
+        msg.body = body;
+        msg.content_range = Some(format!("{}-{}/{}", start, start + body.len(), total));
+        ResponseMessage::parse(&msg.to_bytes().unwrap()).unwrap()
+    }
+
+    fn messages(assembler: &mut FrameAssembler) -> Vec<ResponseMessage> {
+        let mut messages = Vec::new();
+        while let Some(frame) = assembler.next_frame() {
+            let Ok(frame) = frame else { continue };
+            if let Some(message) = assembler.reassemble(ResponseMessage::parse(&frame.message).unwrap()) {
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let sent = build_request("POST", "waterBlockScreenId", &serde_json::json!({ "media": ["a.mp4"] })).unwrap();
+        let FrameParse::Complete(message, used) = parse_frame(&sent.bytes).unwrap() else {
+            panic!("frame incomplete");
+        };
+        assert_eq!(used, sent.bytes.len());
+        let parsed = ResponseMessage::parse(&message).unwrap();
+        assert_eq!(parsed.method, "POST");
+        assert_eq!(parsed.cmd_type, "waterBlockScreenId");
+        assert_eq!(parsed.seq_number, sent.seq_number);
+        assert_eq!(parsed.json().unwrap()["media"][0], "a.mp4");
+    }
+
+    #[test]
+    fn escapes_marker_bytes() {
+        let profile = ProtocolProfile::default();
+        let data = [0x5a, 0x01, 0x5b, 0x02, 0x5a];
+        let escaped = escape_data(&profile, &data);
+        assert_eq!(escaped, [0x5b, 0x01, 0x01, 0x5b, 0x02, 0x02, 0x5b, 0x01]);
+        assert_eq!(unescape_data(&profile, &escaped).unwrap(), data);
+        assert!(unescape_data(&profile, &[0x01, 0x5b]).is_err());
+        assert!(unescape_data(&profile, &[0x5b, 0x7f]).is_err());
+
+        let framed = build_frame(&profile, &data);
+        assert!(!framed[3..framed.len() - 2].contains(&0x5a));
+        let FrameParse::Complete(message, _) = parse_frame_with(&profile, &framed).unwrap() else {
+            panic!("frame incomplete");
+        };
+        assert_eq!(message, data);
+    }
+
+    #[test]
+    fn rejects_bad_crc() {
+        let mut bytes = frame("POST", "conn", "{}");
+        let crc = bytes.len() - 2;
+        bytes[crc] = bytes[crc].wrapping_add(1);
+        assert!(matches!(parse_frame(&bytes), Err(ProtocolError::CrcMismatch { .. })));
+    }
+
+    #[test]
+    fn assembler_resyncs_after_noise() {
+        let first = frame("POST", "conn", "{\"a\":1}");
+        let second = frame("GET", "all", "");
+        let mut assembler = FrameAssembler::new();
+        assembler.push(&[0x00, 0x13, 0x37]);
+        assembler.push(&first[..4]);
+        assert!(assembler.next_frame().is_none());
+        assembler.push(&first[4..]);
+        // A marker with a length that runs into the next frame's marker
+        let mut received = messages(&mut assembler);
+        assembler.push(&[0x5a, 0xff, 0xff, 0x42]);
+        for byte in &second {
+            assembler.push(&[*byte]);
+            received.extend(messages(&mut assembler));
+        }
+
+        assert_eq!(received.iter().map(|m| m.cmd_type.as_str()).collect::<Vec<_>>(), ["conn", "all"]);
+        let (resyncs, discarded) = assembler.take_resyncs();
+        assert!(resyncs >= 2);
+        assert_eq!(discarded, 3 + 4);
+    }
+
+    #[test]
+    fn reassembles_fragments_in_any_order() {
+        let body = "x".repeat(3 * MAX_FRAGMENT_BODY + 17);
+        let sent = frame("POST", "all", &body);
+        let mut frames: Vec<&[u8]> = frames_in(&sent).collect();
+        assert_eq!(frames.len(), 4);
+        frames.reverse();
+
+        let mut assembler = FrameAssembler::new();
+        for frame in frames {
+            assembler.push(frame);
+        }
+        let received = messages(&mut assembler);
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].body, body.as_bytes());
+        assert!(!received[0].headers.contains_key("ContentRange"));
+    }
+
+    #[test]
+    fn repeated_fragment_does_not_complete() {
+        let mut assembler = FrameAssembler::new();
+        assert!(assembler.reassemble(fragment(1, 0, 8, b"abcd")).is_none());
+        assert!(assembler.reassemble(fragment(1, 0, 8, b"abcd")).is_none());
+        let message = assembler.reassemble(fragment(1, 4, 8, b"efgh")).unwrap();
+        assert_eq!(message.body, b"abcdefgh");
+
+        // Overlapping pieces cover the message once together
+        assert!(assembler.reassemble(fragment(2, 0, 8, b"abcdef")).is_none());
+        assert_eq!(assembler.reassemble(fragment(2, 2, 8, b"cdefgh")).unwrap().body, b"abcdefgh");
+    }
+
+    #[test]
+    fn drops_inconsistent_fragments() {
+        let mut assembler = FrameAssembler::new();
+        let mut inverted = fragment(3, 0, 20, b"");
+        inverted.headers.insert("ContentRange".to_string(), "10-5/20".to_string());
+        assert!(assembler.reassemble(inverted).is_none());
+
+        let mut huge = fragment(4, 0, 8, b"abcd");
+        huge.headers.insert("ContentRange".to_string(), format!("0-4/{}", u64::MAX / 2));
+        assert!(assembler.reassemble(huge).is_none());
+
+        let mut short = fragment(5, 0, 8, b"abcd");
+        short.headers.insert("ContentRange".to_string(), "0-6/8".to_string());
+        assert!(assembler.reassemble(short).is_none());
+        assert!(assembler.partial.is_empty());
+    }
+}
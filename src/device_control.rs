@@ -0,0 +1,167 @@
+// Looking after the cooler over ADB: force-stop and relaunch the vendor's
+// display app, or reboot the Android module when that isn't enough, and set
+// its clock from the host's after a power loss reset it
+
+use std::time::Duration;
+use anyhow::{Context, Result};
+
+use crate::adb::{shell_quote, AdbClient, AdbConfig};
+
+/// Package name fragments of the vendor's apps
+pub const VENDOR_PACKAGES: [&str; 2] = ["baiyi", "tryx"];
+/// Time the relaunched app gets before checking it runs
+const START_GRACE: Duration = Duration::from_secs(2);
+
+#[derive(Default)]
+pub struct DeviceControl {
+    /// Reboot was clicked, waiting for the confirmation
+    pub confirm_reboot: bool,
+    pub worker: Option<std::thread::JoinHandle<()>>,
+}
+
+impl DeviceControl {
+    pub fn running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|w| !w.is_finished())
+    }
+}
+
+/// Installed packages that belong to the vendor
+pub fn vendor_packages(client: &mut AdbClient) -> Result<Vec<String>> {
+    Ok(client
+        .shell("pm list packages")?
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("package:"))
+        .filter(|package| VENDOR_PACKAGES.iter().any(|vendor| package.contains(vendor)))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Force-stop every vendor app, then launch the one with a launcher activity.
+/// Returns the package that was started.
+pub fn restart_display_app(adb: &AdbConfig) -> Result<String> {
+    let mut client = AdbClient::connect(adb)?;
+    let packages = vendor_packages(&mut client)?;
+    anyhow::ensure!(!packages.is_empty(), "No {} app is installed on the device", VENDOR_PACKAGES.join("/"));
+
+    for package in &packages {
+        log::info!("Stopping {}", package);
+        client.shell(&format!("am force-stop {}", shell_quote(package)))?;
+    }
+
+    for package in &packages {
+        // monkey starts the launcher activity without knowing its name
+        let output = client.shell(&format!("monkey -p {} -c android.intent.category.LAUNCHER 1", shell_quote(package)))?;
+        if output.contains("No activities found") {
+            continue;
+        }
+        log::info!("Started {}", package);
+        std::thread::sleep(START_GRACE);
+        let pid = client.shell(&format!("pidof {}", shell_quote(package)))?;
+        anyhow::ensure!(!pid.trim().is_empty(), "{} did not stay running after the restart", package);
+        return Ok(package.clone());
+    }
+    // Services without an activity come back on their own (or at the next boot)
+    anyhow::bail!("None of {} has a launcher activity, reboot the cooler instead", packages.join(", "))
+}
+
+pub fn reboot(adb: &AdbConfig) -> Result<()> {
+    AdbClient::connect(adb)?.reboot().context("Failed to reboot the device")
+}
+
+#[derive(Debug, Clone)]
+pub struct ClockSync {
+    /// Device clock minus host clock before the sync, in seconds
+    pub offset_before: i64,
+    /// Zone set on the device, None when the host's isn't known
+    pub timezone: Option<String>,
+}
+
+impl ClockSync {
+    pub fn describe(&self) -> String {
+        let drift = match self.offset_before {
+            0 => "was already right".to_string(),
+            offset if offset < 0 => format!("was {} behind", format_offset(-offset)),
+            offset => format!("was {} ahead", format_offset(offset)),
+        };
+        match &self.timezone {
+            Some(timezone) => format!("Device clock {}, set to this PC's time in {}", drift, timezone),
+            None => format!("Device clock {}, set to this PC's time (timezone unknown)", drift),
+        }
+    }
+}
+
+/// How commands get root, setting the clock needs it
+#[derive(Debug, Clone, Copy)]
+enum Root {
+    /// adbd itself runs as root
+    Adbd,
+    /// `su 0 cmd` of userdebug builds
+    Su0,
+    /// `su -c cmd` of Magisk and friends
+    SuC,
+}
+
+impl Root {
+    fn wrap(self, command: &str) -> String {
+        match self {
+            Root::Adbd => command.to_string(),
+            Root::Su0 => format!("su 0 sh -c {}", shell_quote(command)),
+            Root::SuC => format!("su -c {}", shell_quote(command)),
+        }
+    }
+}
+
+/// Set the device's clock and timezone to the host's, and its RTC where there
+/// is one so the time survives the next power loss
+pub fn sync_clock(adb: &AdbConfig) -> Result<ClockSync> {
+    let mut client = AdbClient::connect(adb)?;
+    let root = [Root::Adbd, Root::Su0, Root::SuC]
+        .into_iter()
+        .find(|root| client.shell(&root.wrap("id -u")).is_ok_and(|uid| uid.trim() == "0"))
+        .context("Setting the clock needs root, and neither adbd nor su runs as root on this device")?;
+    let offset_before = clock_offset(&mut client)?;
+
+    let timezone = host_timezone();
+    if let Some(timezone) = &timezone {
+        client.shell(&root.wrap(&format!("setprop persist.sys.timezone {}", shell_quote(timezone))))?;
+    }
+    let now = chrono::Utc::now();
+    // toybox takes MMDDhhmmCCYY.ss, the older toolbox date -s YYYYMMDD.hhmmss
+    let script = format!(
+        "date -u {} >/dev/null 2>&1 || TZ=UTC date -s {} >/dev/null; hwclock -w -u 2>/dev/null; true",
+        now.format("%m%d%H%M%Y.%S"),
+        now.format("%Y%m%d.%H%M%S")
+    );
+    client.shell(&root.wrap(&script))?;
+
+    let offset_after = clock_offset(&mut client)?;
+    anyhow::ensure!(offset_after.abs() <= 2, "The device clock is still {} s off after setting it", offset_after);
+    Ok(ClockSync { offset_before, timezone })
+}
+
+fn clock_offset(client: &mut AdbClient) -> Result<i64> {
+    let output = client.shell("date +%s")?;
+    let device: i64 = output.trim().parse().with_context(|| format!("Unexpected date output: {}", output.trim()))?;
+    Ok(device - chrono::Utc::now().timestamp())
+}
+
+/// `Europe/Berlin` from $TZ or the /etc/localtime link
+fn host_timezone() -> Option<String> {
+    let zone = |path: &str| match path.split_once("zoneinfo/") {
+        Some((_, zone)) => Some(zone.to_string()),
+        None => (!path.starts_with('/') && path.contains('/')).then(|| path.to_string()),
+    };
+    std::env::var("TZ")
+        .ok()
+        .and_then(|tz| zone(tz.trim_start_matches(':')))
+        .or_else(|| zone(&std::fs::read_link("/etc/localtime").ok()?.to_string_lossy()))
+}
+
+fn format_offset(secs: i64) -> String {
+    match secs {
+        s if s < 120 => format!("{} s", s),
+        s if s < 2 * 3600 => format!("{} min", s / 60),
+        s if s < 2 * 86400 => format!("{} h {} min", s / 3600, s / 60 % 60),
+        s => format!("{} days", s / 86400),
+    }
+}
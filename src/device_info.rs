@@ -0,0 +1,122 @@
+// Device info: what the Android side reports over ADB (model, Android version,
+// display size, display app versions) next to what the serial firmware said in
+// its `conn` handshake, in one place and copyable for bug reports
+
+use anyhow::Result;
+
+use crate::adb::{AdbClient, AdbConfig};
+use crate::apk::ApkInfo;
+
+/// getprop keys shown, with their labels
+const PROPERTIES: [(&str, &str); 7] = [
+    ("Manufacturer", "ro.product.manufacturer"),
+    ("Model", "ro.product.model"),
+    ("Android", "ro.build.version.release"),
+    ("SDK", "ro.build.version.sdk"),
+    ("Build", "ro.build.display.id"),
+    ("Serial", "ro.serialno"),
+    ("Kernel", "ro.kernel.version"),
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct AndroidInfo {
+    /// Label and value, in `PROPERTIES` order, missing ones left out
+    pub properties: Vec<(&'static str, String)>,
+    /// Physical size from `wm size`, the override if one is set
+    pub resolution: Option<[u32; 2]>,
+    pub density: Option<u32>,
+    pub apps: Vec<ApkInfo>,
+}
+
+#[derive(Default)]
+pub struct DeviceInfo {
+    pub android: Option<AndroidInfo>,
+    pub error: Option<String>,
+    pub queried_at: Option<chrono::DateTime<chrono::Local>>,
+    pub worker: Option<std::thread::JoinHandle<()>>,
+}
+
+impl DeviceInfo {
+    pub fn running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|w| !w.is_finished())
+    }
+
+    pub fn set_android(&mut self, result: Result<Box<AndroidInfo>, String>) {
+        match result {
+            Ok(info) => {
+                self.android = Some(*info);
+                self.error = None;
+                self.queried_at = Some(chrono::Local::now());
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Plain text of everything known, for pasting into an issue
+    pub fn report(&self, firmware: Option<&str>, handshake: Option<&serde_json::Value>) -> String {
+        let mut lines = vec![format!("tryx_panorama_linux {}", env!("CARGO_PKG_VERSION"))];
+        match &self.android {
+            Some(android) => {
+                lines.extend(android.properties.iter().map(|(label, value)| format!("{}: {}", label, value)));
+                if let Some([width, height]) = android.resolution {
+                    lines.push(format!("Display: {}x{}", width, height));
+                }
+                if let Some(density) = android.density {
+                    lines.push(format!("Density: {} dpi", density));
+                }
+                lines.extend(android.apps.iter().map(|app| format!("App: {} {}", app.package, app.version())));
+            }
+            None => lines.push("Android: not queried".to_string()),
+        }
+        lines.push(format!("Firmware: {}", firmware.unwrap_or("unknown")));
+        if let Some(handshake) = handshake {
+            lines.push(format!("Handshake: {}", handshake));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Everything in one connection: getprop, wm and the vendor's packages
+pub fn query(adb: &AdbConfig) -> Result<AndroidInfo> {
+    let mut client = AdbClient::connect(adb)?;
+    let props = parse_getprop(&client.shell("getprop")?);
+    let properties = PROPERTIES
+        .iter()
+        .filter_map(|(label, key)| props.iter().find(|(k, _)| k == key).map(|(_, value)| (*label, value.clone())))
+        .filter(|(_, value)| !value.is_empty())
+        .collect();
+
+    let resolution = parse_wm(&client.shell("wm size")?).and_then(|size| {
+        let (width, height) = size.split_once('x')?;
+        Some([width.trim().parse().ok()?, height.trim().parse().ok()?])
+    });
+    let density = parse_wm(&client.shell("wm density")?).and_then(|density| density.trim().parse().ok());
+
+    let mut apps = Vec::new();
+    for package in crate::device_control::vendor_packages(&mut client)? {
+        if let Some(app) = crate::apk::installed_version(&mut client, &package)? {
+            apps.push(app);
+        }
+    }
+    Ok(AndroidInfo { properties, resolution, density, apps })
+}
+
+/// `[key]: [value]` lines
+fn parse_getprop(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once("]: [")?;
+            Some((key.strip_prefix('[')?.to_string(), value.strip_suffix(']')?.to_string()))
+        })
+        .collect()
+}
+
+/// `Physical size: 1080x1920` / `Override size: …`, the override wins
+fn parse_wm(output: &str) -> Option<String> {
+    let value = |prefix: &str| output.lines().find_map(|line| line.trim().strip_prefix(prefix).map(|v| v.trim().to_string()));
+    value("Override size:")
+        .or_else(|| value("Override density:"))
+        .or_else(|| value("Physical size:"))
+        .or_else(|| value("Physical density:"))
+}
@@ -0,0 +1,114 @@
+// ============================================================================
+// Crash Diagnostics
+// Keeps a little recent history around so a panic can leave a useful report
+// ============================================================================
+
+use std::{collections::VecDeque, fmt::Write as _, path::PathBuf, sync::Mutex};
+
+const MAX_LOG_LINES: usize = 200;
+const MAX_FRAMES: usize = 32;
+/// Frames longer than this are truncated in the report
+const MAX_FRAME_HEX_BYTES: usize = 256;
+
+static LOG_LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static FRAMES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static CONNECTION_STATE: Mutex<String> = Mutex::new(String::new());
+
+fn push_bounded(buffer: &Mutex<VecDeque<String>>, line: String, max: usize) {
+    // Never panic from in here, the hook reads these while unwinding
+    if let Ok(mut buffer) = buffer.lock() {
+        if buffer.len() >= max {
+            buffer.pop_front();
+        }
+        buffer.push_back(line);
+    }
+}
+
+pub fn record_log(line: &str) {
+    let ts = chrono::Local::now().format("%H:%M:%S%.3f");
+    push_bounded(&LOG_LINES, format!("{} {}", ts, line), MAX_LOG_LINES);
+}
+
+pub fn record_frame(direction: &str, frame: &[u8]) {
+    let ts = chrono::Local::now().format("%H:%M:%S%.3f");
+    let shown = &frame[..frame.len().min(MAX_FRAME_HEX_BYTES)];
+    let hex: String = shown.iter().map(|b| format!("{:02x}", b)).collect();
+    let ellipsis = if frame.len() > shown.len() { "..." } else { "" };
+    push_bounded(
+        &FRAMES,
+        format!("{} {} {} bytes: {}{}", ts, direction, frame.len(), hex, ellipsis),
+        MAX_FRAMES,
+    );
+}
+
+pub fn record_state(state: &str) {
+    if let Ok(mut current) = CONNECTION_STATE.lock() {
+        *current = state.to_string();
+    }
+}
+
+fn crash_dir() -> PathBuf {
+    crate::paths::state_dir().join("crashes")
+}
+
+/// Marker pointing at the newest report, removed once the user has seen it
+fn marker_path() -> PathBuf {
+    crash_dir().join("last_crash")
+}
+
+fn build_report(info: &std::panic::PanicHookInfo<'_>) -> String {
+    let mut report = String::new();
+    let _ = writeln!(report, "Tryx Panorama crash report");
+    let _ = writeln!(report, "Version: {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(report, "Time: {}", chrono::Local::now().to_rfc3339());
+    let _ = writeln!(report, "Thread: {}", std::thread::current().name().unwrap_or("<unnamed>"));
+    let _ = writeln!(report, "Panic: {}", info);
+    let _ = writeln!(report, "\nBacktrace:\n{}", std::backtrace::Backtrace::force_capture());
+
+    let state = CONNECTION_STATE.lock().map(|s| s.clone()).unwrap_or_default();
+    let _ = writeln!(report, "\nConnection state: {}", if state.is_empty() { "Disconnected" } else { &state });
+
+    let _ = writeln!(report, "\nRecent frames:");
+    if let Ok(frames) = FRAMES.lock() {
+        for frame in frames.iter() {
+            let _ = writeln!(report, "  {}", frame);
+        }
+    }
+
+    let _ = writeln!(report, "\nRecent log:");
+    if let Ok(lines) = LOG_LINES.lock() {
+        for line in lines.iter() {
+            let _ = writeln!(report, "  {}", line);
+        }
+    }
+    report
+}
+
+fn write_report(report: &str) -> std::io::Result<PathBuf> {
+    let dir = crash_dir();
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("crash-{}.txt", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+    std::fs::write(&path, report)?;
+    std::fs::write(marker_path(), path.to_string_lossy().as_bytes())?;
+    Ok(path)
+}
+
+/// Write a crash report on panic, then fall through to the default hook
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        match write_report(&build_report(info)) {
+            Ok(path) => eprintln!("Crash report written to {}", path.display()),
+            Err(e) => eprintln!("Failed to write crash report: {}", e),
+        }
+        default_hook(info);
+    }));
+}
+
+/// Report left behind by the previous run, if it crashed
+pub fn take_last_crash() -> Option<PathBuf> {
+    let marker = marker_path();
+    let path = PathBuf::from(std::fs::read_to_string(&marker).ok()?.trim());
+    let _ = std::fs::remove_file(&marker);
+    path.exists().then_some(path)
+}
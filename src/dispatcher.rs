@@ -0,0 +1,71 @@
+// ============================================================================
+// Request/response correlation
+// Requests get a real msgId, the device echoes it back in its reply
+// ============================================================================
+
+use std::{collections::HashMap, time::Instant};
+
+use crate::data::ResponseMessage;
+
+#[derive(Debug)]
+struct PendingRequest {
+    cmd_type: String,
+    sent_at: Instant,
+}
+
+#[derive(Debug)]
+pub struct Dispatcher {
+    next_msg_id: i64,
+    pending: HashMap<i64, PendingRequest>,
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        // -1 means "no msgId" on the wire, so start at 1
+        Self { next_msg_id: 1, pending: HashMap::new() }
+    }
+}
+
+impl Dispatcher {
+    /// Reserve a msgId for a request that is about to go out
+    pub fn register(&mut self, cmd_type: &str) -> i64 {
+        let msg_id = self.next_msg_id;
+        self.next_msg_id = if msg_id >= i32::MAX as i64 { 1 } else { msg_id + 1 };
+        self.pending.insert(msg_id, PendingRequest { cmd_type: cmd_type.to_string(), sent_at: Instant::now() });
+        msg_id
+    }
+
+    /// Match an inbound message against pending requests.
+    /// Returns the msgId it answers, if any.
+    pub fn resolve(&mut self, response: &ResponseMessage) -> Option<i64> {
+        if response.msg_id < 0 {
+            return None;
+        }
+        match self.pending.remove(&response.msg_id) {
+            Some(request) => {
+                crate::link_stats::stats().record_rtt(&request.cmd_type, request.sent_at.elapsed());
+                log::debug!(
+                    "Reply to {} (msgId={}) after {:?}",
+                    request.cmd_type,
+                    response.msg_id,
+                    request.sent_at.elapsed()
+                );
+                Some(response.msg_id)
+            }
+            None => {
+                log::debug!("Unsolicited or late reply {} msgId={}", response.cmd_type, response.msg_id);
+                None
+            }
+        }
+    }
+
+    /// Wait for `msg_id` again after the request was re-sent under the same id
+    pub fn rearm(&mut self, msg_id: i64, cmd_type: &str) {
+        self.pending.insert(msg_id, PendingRequest { cmd_type: cmd_type.to_string(), sent_at: Instant::now() });
+    }
+
+    /// Stop waiting for a request, e.g. after it timed out
+    pub fn forget(&mut self, msg_id: i64) {
+        self.pending.remove(&msg_id);
+    }
+}
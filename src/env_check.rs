@@ -0,0 +1,310 @@
+// ============================================================================
+// Setup checks
+// Everything that has to be in place before the cooler can be driven: access
+// to the serial port, the ADB interface and its usbfs node, no adb server
+// holding it, and the cooler trusting our key. A failed check says how to fix
+// it instead of surfacing whatever error the first transfer would hit.
+// Run on first launch, from Device Settings, or with `check-setup`.
+// ============================================================================
+
+use std::{ffi::CStr, os::unix::fs::MetadataExt, path::{Path, PathBuf}};
+
+use crate::adb::{AdbClient, AdbConfig};
+use crate::adb_usb::Relation;
+use crate::screen_setup::TransferMethod;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warning,
+    Failed,
+}
+
+impl Status {
+    pub fn icon(self) -> &'static str {
+        match self {
+            Status::Ok => "✅",
+            Status::Warning => "⚠",
+            Status::Failed => "❌",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+    /// What to do about it, often a command to paste into a terminal
+    pub fix: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self { name, status: Status::Ok, detail: detail.into(), fix: None }
+    }
+
+    fn warning(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, status: Status::Warning, detail: detail.into(), fix: Some(fix.into()) }
+    }
+
+    fn failed(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self { name, status: Status::Failed, detail: detail.into(), fix: Some(fix.into()) }
+    }
+
+    /// Status for terminal output
+    fn tag(&self) -> &'static str {
+        match self.status {
+            Status::Ok => "[ ok ]",
+            Status::Warning => "[warn]",
+            Status::Failed => "[FAIL]",
+        }
+    }
+}
+
+/// Results shown in the GUI
+#[derive(Default)]
+pub struct SetupCheck {
+    pub results: Vec<Check>,
+    /// Results are on screen until dismissed
+    pub shown: bool,
+    pub worker: Option<std::thread::JoinHandle<()>>,
+}
+
+impl SetupCheck {
+    pub fn running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|w| !w.is_finished())
+    }
+
+    pub fn set_results(&mut self, results: Vec<Check>) {
+        if all_passed(&results) {
+            mark_passed();
+        }
+        self.results = results;
+        self.shown = true;
+    }
+}
+
+pub fn all_passed(results: &[Check]) -> bool {
+    results.iter().all(|check| check.status != Status::Failed)
+}
+
+fn marker_path() -> PathBuf {
+    crate::paths::state_dir().join("setup-checked")
+}
+
+/// No check has passed on this machine yet, so run them on launch
+pub fn first_run() -> bool {
+    !marker_path().exists()
+}
+
+fn mark_passed() {
+    let path = marker_path();
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Err(e) = std::fs::write(&path, chrono::Local::now().to_rfc3339()) {
+        log::warn!("Failed to write {}: {}", path.display(), e);
+    }
+}
+
+/// Check the serial port, then for ADB uploads the interface, permissions and
+/// authorization. The last step connects, so the cooler may show its prompt.
+pub fn run(serial_device: &str, transfer_method: TransferMethod, adb: &AdbConfig) -> Vec<Check> {
+    let mut results = vec![serial_port(serial_device)];
+    if transfer_method == TransferMethod::Serial {
+        results.push(Check::ok("ADB", "Not used, uploads go over serial"));
+        return results;
+    }
+
+    let before = results.len();
+    if adb.address.trim().is_empty() {
+        results.extend(usb_interface(adb, serial_device));
+        if let Some(check) = adb_server() {
+            results.push(check);
+        }
+    }
+    results.push(adb_key());
+    if results[before..].iter().all(|check| check.status != Status::Failed) {
+        results.push(authorization(adb));
+    }
+    results
+}
+
+/// `check-setup`: print the checks for the default port and the saved ADB
+/// device, exit status 1 if any failed
+pub fn run_cli() -> i32 {
+    let results = run("/dev/ttyACM0", TransferMethod::default(), &AdbConfig::load());
+    for check in &results {
+        println!("{} {}: {}", check.tag(), check.name, check.detail);
+        if let Some(fix) = &check.fix {
+            for line in fix.lines() {
+                println!("      {}", line);
+            }
+        }
+    }
+    if all_passed(&results) {
+        mark_passed();
+        0
+    } else {
+        1
+    }
+}
+
+fn serial_port(device: &str) -> Check {
+    const NAME: &str = "Serial port";
+    let path = Path::new(device);
+    if !path.exists() {
+        let found: Vec<String> = crate::serial::list_tty_devices().into_iter().collect();
+        let fix = if found.is_empty() {
+            "Plug in the cooler; its serial port shows up as /dev/ttyACM*".to_string()
+        } else {
+            format!("Pick one of {} under Device Settings → Serial Device", found.join(", "))
+        };
+        return Check::failed(NAME, format!("{} does not exist", device), fix);
+    }
+
+    if !accessible(path) {
+        let group = path.metadata().ok().and_then(|metadata| group_name(metadata.gid())).unwrap_or_else(|| "dialout".to_string());
+        let fix = if in_group_after_login(&group) {
+            format!("You were added to the {} group, log out and back in (or reboot) for it to take effect", group)
+        } else {
+            format!("sudo usermod -aG {} $USER\nthen log out and back in", group)
+        };
+        return Check::failed(NAME, format!("No read/write access to {} (owned by group {})", device, group), fix);
+    }
+
+    let holders = crate::serial::find_port_holders(device);
+    if !holders.is_empty() {
+        let names: Vec<String> = holders.iter().map(|h| format!("{} ({})", h.name, h.pid)).collect();
+        return Check::warning(
+            NAME,
+            format!("{} is open in {}", device, names.join(", ")),
+            "Close that program before sending, the port is opened exclusively",
+        );
+    }
+    Check::ok(NAME, format!("{} is readable and writable", device))
+}
+
+fn usb_interface(adb: &AdbConfig, serial_device: &str) -> Vec<Check> {
+    const NAME: &str = "ADB device";
+    let device = match adb.usb_device() {
+        Ok(device) => device,
+        Err(e) if crate::adb_usb::devices().is_empty() => {
+            return vec![Check::failed(
+                NAME,
+                format!("{:#}", e),
+                "Enable USB debugging in the cooler's developer options, or set Upload via → Serial",
+            )];
+        }
+        Err(e) => return vec![Check::failed(NAME, format!("{:#}", e), "Pick the cooler under Device Settings → ADB device")],
+    };
+
+    let mut results = vec![match device.relation_to(serial_device) {
+        Relation::Unrelated => Check::warning(
+            NAME,
+            format!("{} is not the USB device behind {}, it may be a phone", device.describe(), serial_device),
+            "Pick the cooler under Device Settings → ADB device",
+        ),
+        Relation::SameHub => Check::ok(NAME, format!("{} (same USB hub as {})", device.describe(), serial_device)),
+        Relation::SameDevice => Check::ok(NAME, format!("{} (same USB device as {})", device.describe(), serial_device)),
+        Relation::Unknown => Check::ok(NAME, device.describe()),
+    }];
+    if accessible(&device.node) {
+        results.push(Check::ok("USB permissions", format!("{} is readable and writable", device.node.display())));
+    } else {
+        let vendor = std::fs::read_to_string(device.sysfs.join("idVendor")).map(|id| id.trim().to_string()).unwrap_or_default();
+        results.push(Check::failed(
+            "USB permissions",
+            format!("No read/write access to {}", device.node.display()),
+            format!(
+                "echo 'SUBSYSTEM==\"usb\", ATTR{{idVendor}}==\"{}\", MODE=\"0660\", TAG+=\"uaccess\"' | sudo tee /etc/udev/rules.d/51-tryx-adb.rules\n\
+                 sudo udevadm control --reload && sudo udevadm trigger\nthen replug the cooler",
+                vendor
+            ),
+        ));
+    }
+    results
+}
+
+/// A running adb server claims every ADB interface it sees
+fn adb_server() -> Option<Check> {
+    let pids: Vec<String> = std::fs::read_dir("/proc")
+        .ok()?
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|&pid| crate::serial::process_name(pid) == "adb")
+        .map(|pid| pid.to_string())
+        .collect();
+    if pids.is_empty() {
+        return None;
+    }
+    Some(Check::failed(
+        "adb server",
+        format!("An adb server is running (pid {}) and holds the USB interface", pids.join(", ")),
+        "adb kill-server",
+    ))
+}
+
+fn adb_key() -> Check {
+    const NAME: &str = "ADB key";
+    let adb = crate::adb_auth::adb_key_path();
+    let own = crate::adb_auth::own_key_path();
+    if let Some(path) = [&adb, &own].into_iter().find(|path| path.exists()) {
+        Check::ok(NAME, path.display().to_string())
+    } else {
+        Check::warning(
+            NAME,
+            format!("Neither {} nor {} exists yet", adb.display(), own.display()),
+            "Ours is created on the first connection; the cooler then asks to allow it",
+        )
+    }
+}
+
+fn authorization(adb: &AdbConfig) -> Check {
+    const NAME: &str = "ADB authorization";
+    match AdbClient::connect(adb) {
+        Ok(client) => Check::ok(NAME, format!("Connected: {}", client.banner)),
+        Err(e) => {
+            let message = format!("{:#}", e);
+            let fix = if message.contains("rejected") || message.contains("timed out") {
+                "Unlock the cooler's screen and accept \"Allow USB debugging?\" (tick \"Always allow from this computer\"), then check again"
+            } else {
+                "Replug the cooler and check again"
+            };
+            Check::failed(NAME, message, fix)
+        }
+    }
+}
+
+fn accessible(path: &Path) -> bool {
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_encoded_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), libc::R_OK | libc::W_OK) == 0 }
+}
+
+fn group_name(gid: u32) -> Option<String> {
+    let group = unsafe { libc::getgrgid(gid) };
+    if group.is_null() {
+        return None;
+    }
+    Some(unsafe { CStr::from_ptr((*group).gr_name) }.to_string_lossy().into_owned())
+}
+
+/// The user is listed in `group` in /etc/group but this session doesn't have it yet
+fn in_group_after_login(group: &str) -> bool {
+    let user = unsafe { libc::getpwuid(libc::getuid()) };
+    if user.is_null() {
+        return false;
+    }
+    let user = unsafe { CStr::from_ptr((*user).pw_name) }.to_string_lossy().into_owned();
+    std::fs::read_to_string("/etc/group")
+        .map(|groups| {
+            groups.lines().any(|line| {
+                let fields: Vec<&str> = line.split(':').collect();
+                fields.first() == Some(&group) && fields.get(3).is_some_and(|members| members.split(',').any(|m| m == user))
+            })
+        })
+        .unwrap_or(false)
+}
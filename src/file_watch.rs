@@ -0,0 +1,61 @@
+// Change notifications for a single file with inotify. The parent directory
+// is watched rather than the file, so editors that save by writing a new file
+// and renaming it over the old one are noticed too.
+
+use std::{
+    ffi::CString,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    thread,
+    time::Duration,
+};
+use anyhow::{Context, Result};
+use crossbeam::channel::{self, Receiver};
+
+/// How often the watcher thread checks for events
+const POLL: Duration = Duration::from_millis(250);
+
+/// A message on the returned channel each time `path` is written or replaced
+pub fn watch(path: &Path) -> Result<Receiver<()>> {
+    let dir = path.parent().context("No parent directory to watch")?;
+    let name = path.file_name().context("No file name to watch")?.to_owned();
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let dir_c = CString::new(dir.as_os_str().as_bytes())?;
+
+    let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+    anyhow::ensure!(fd >= 0, "inotify_init1: {}", std::io::Error::last_os_error());
+    let wd = unsafe { libc::inotify_add_watch(fd, dir_c.as_ptr(), libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO) };
+    if wd < 0 {
+        let error = std::io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        anyhow::bail!("Watching {}: {}", dir.display(), error);
+    }
+
+    let (tx, rx) = channel::unbounded();
+    thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        loop {
+            let read = unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut libc::c_void, buffer.len()) };
+            if read <= 0 {
+                thread::sleep(POLL);
+                continue;
+            }
+            // inotify_event headers, each followed by `len` bytes of NUL padded name
+            let mut changed = false;
+            let mut offset = 0;
+            let header = std::mem::size_of::<libc::inotify_event>();
+            while offset + header <= read as usize {
+                let event = unsafe { std::ptr::read_unaligned(buffer.as_ptr().add(offset) as *const libc::inotify_event) };
+                let bytes = &buffer[offset + header..offset + header + event.len as usize];
+                let event_name = bytes.split(|&b| b == 0).next().unwrap_or_default();
+                changed |= event_name == name.as_bytes();
+                offset += header + event.len as usize;
+            }
+            if changed && tx.send(()).is_err() {
+                break;
+            }
+        }
+        unsafe { libc::close(fd) };
+    });
+    Ok(rx)
+}
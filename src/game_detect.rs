@@ -0,0 +1,158 @@
+// Game detection: while a game runs, the heartbeat shows a gaming profile and
+// goes back to what it showed before once the game has exited. A game is a
+// Steam launch (the `reaper SteamLaunch AppId=…` wrapper Steam starts games
+// in), a Windows program under Proton or Wine, a gamescope session, a game
+// MangoHud is logging, or a process the user named.
+
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::sysinfo::SysInfo;
+
+/// Scanning every process is cheap but not free, once every few beats is plenty
+const SCAN_INTERVAL: Duration = Duration::from_secs(5);
+/// A game unseen for less than this still counts, so a launcher handing over
+/// to the game, or a crash-and-relaunch, doesn't switch back and forth
+const GRACE: Duration = Duration::from_secs(15);
+
+/// Wine's own processes, running alongside any Windows program
+const WINE_SERVICES: &[&str] = &[
+    "services.exe",
+    "winedevice.exe",
+    "plugplay.exe",
+    "svchost.exe",
+    "explorer.exe",
+    "rpcss.exe",
+    "tabtip.exe",
+    "conhost.exe",
+    "start.exe",
+    "wineboot.exe",
+    "winemenubuilder.exe",
+    "rundll32.exe",
+    "steam.exe",
+    "steamwebhelper.exe",
+    "steamerrorreporter.exe",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameDetectConfig {
+    pub enabled: bool,
+    /// Saved profile to show while a game runs
+    pub profile: String,
+    pub steam: bool,
+    /// Windows programs under Proton or Wine
+    pub wine: bool,
+    /// Off by default, handhelds run everything in a gamescope session
+    pub gamescope: bool,
+    /// A game MangoHud is logging, needs the MangoHud logs folder set under sensors
+    pub mangohud: bool,
+    /// Process names (as in `/proc/PID/comm`) that count as games
+    pub processes: Vec<String>,
+}
+
+impl Default for GameDetectConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            profile: String::new(),
+            steam: true,
+            wine: true,
+            gamescope: false,
+            mangohud: true,
+            processes: Vec::new(),
+        }
+    }
+}
+
+pub struct GameDetector {
+    config: GameDetectConfig,
+    last_scan: Option<Instant>,
+    /// The game running and when it was last seen
+    game: Option<(String, Instant)>,
+}
+
+impl GameDetector {
+    pub fn new(config: GameDetectConfig) -> Self {
+        Self { config, last_scan: None, game: None }
+    }
+
+    /// Feed the latest sample, returns the profile to show while a game runs
+    pub fn update(&mut self, info: &SysInfo, now: Instant) -> Option<&str> {
+        if !self.config.enabled || self.config.profile.is_empty() {
+            return None;
+        }
+        let logging = info.game.as_ref().filter(|_| self.config.mangohud).map(|game| game.name.clone());
+        let found = match logging {
+            Some(name) => Some(name),
+            None if self.last_scan.is_none_or(|at| now.duration_since(at) >= SCAN_INTERVAL) => {
+                self.last_scan = Some(now);
+                running_game(&self.config)
+            }
+            // Between scans nothing changes
+            None => return self.profile(),
+        };
+
+        match found {
+            Some(name) => {
+                if self.game.is_none() {
+                    log::info!("Game detected: {}, switching to profile {}", name, self.config.profile);
+                }
+                self.game = Some((name, now));
+            }
+            None => {
+                if let Some((name, seen)) = &self.game
+                    && now.duration_since(*seen) >= GRACE
+                {
+                    log::info!("{} has exited, switching back", name);
+                    self.game = None;
+                }
+            }
+        }
+        self.profile()
+    }
+
+    fn profile(&self) -> Option<&str> {
+        self.game.as_ref().map(|_| self.config.profile.as_str())
+    }
+}
+
+/// The first game found among the running processes
+fn running_game(config: &GameDetectConfig) -> Option<String> {
+    for pid in crate::top_process::pids() {
+        let comm = crate::serial::process_name(pid);
+        if config.processes.iter().any(|name| name.trim() == comm) {
+            return Some(comm);
+        }
+        if config.gamescope && comm.starts_with("gamescope") {
+            return Some(comm);
+        }
+        if !config.steam && !config.wine {
+            continue;
+        }
+        let Ok(cmdline) = fs::read(format!("/proc/{}/cmdline", pid)) else {
+            continue;
+        };
+        let args: Vec<String> = cmdline.split(|byte| *byte == 0).map(|arg| String::from_utf8_lossy(arg).into_owned()).collect();
+        if config.steam
+            && comm == "reaper"
+            && args.iter().any(|arg| arg == "SteamLaunch")
+            && let Some(app) = args.iter().find_map(|arg| arg.strip_prefix("AppId="))
+            // AppId=0 is a non-Steam shortcut, the Windows program is found below
+            && app != "0"
+        {
+            return Some(format!("Steam app {}", app));
+        }
+        if config.wine
+            && let Some(program) = args.first().map(|arg| arg.rsplit(['/', '\\']).next().unwrap_or(arg).to_ascii_lowercase())
+            && program.ends_with(".exe")
+            && !WINE_SERVICES.contains(&program.as_str())
+        {
+            return Some(program);
+        }
+    }
+    None
+}
@@ -0,0 +1,54 @@
+// Sensor history: the sampler appends every sample here and the dashboard
+// plots the last `history_minutes` of it. Values are kept raw (°C); the
+// dashboard converts them to the chosen units when drawing.
+
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::sysinfo::SysInfo;
+
+/// The plotted subset of one sample
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub at: Instant,
+    pub cpu_temperature: u8,
+    pub gpu_temperature: u8,
+    pub cpu_load: u8,
+    /// None when the driver doesn't report it
+    pub gpu_load: Option<u8>,
+    pub memory_load: u8,
+    /// Name and RPM of each fan sent
+    pub fans: Vec<(String, u32)>,
+}
+
+static HISTORY: Mutex<VecDeque<Sample>> = Mutex::new(VecDeque::new());
+
+/// Append `info` and drop what's older than `keep`
+pub fn record(info: &SysInfo, keep: Duration) {
+    let now = Instant::now();
+    let mut history = HISTORY.lock().unwrap();
+    history.push_back(Sample {
+        at: now,
+        cpu_temperature: info.cpu.temperature,
+        gpu_temperature: info.gpu.temperature,
+        cpu_load: info.cpu.load,
+        gpu_load: info.gpu.load_known.then_some(info.gpu.load),
+        memory_load: info.memory.load,
+        fans: info.fans.iter().map(|fan| (fan.name.clone(), fan.value)).collect(),
+    });
+    while history.front().is_some_and(|sample| now.duration_since(sample.at) > keep) {
+        history.pop_front();
+    }
+}
+
+/// Everything kept, oldest first
+pub fn samples() -> Vec<Sample> {
+    HISTORY.lock().unwrap().iter().cloned().collect()
+}
+
+pub fn clear() {
+    HISTORY.lock().unwrap().clear();
+}
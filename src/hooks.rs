@@ -0,0 +1,114 @@
+// Shell hooks around a transfer: commands from config.toml that run before the
+// image goes to the cooler and after it's on screen or failed, so lighting
+// changes or notifications can follow the display. They run where the
+// transfer does, in the window, `send` or the daemon.
+
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+use serde::{Deserialize, Serialize};
+
+/// The transfer waits this long for the before hook, then kills it
+const BEFORE_TIMEOUT: Duration = Duration::from_secs(30);
+/// The after hook runs on its own thread and is only logged once it's done
+const AFTER_TIMEOUT: Duration = Duration::from_secs(300);
+const POLL: Duration = Duration::from_millis(50);
+
+/// Run with `sh -c`. Kept in config.toml rather than the screen config, so
+/// profiles and the daemon's IPC requests never carry commands.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Before the transfer starts, it goes ahead whatever this returns
+    pub before: String,
+    /// Once the transfer is done or has failed
+    pub after: String,
+}
+
+/// What the hooks are told about the transfer, as `TRYX_*` variables
+pub struct Transfer<'a> {
+    pub image: &'a Path,
+    /// The profile being switched to, if the transfer is one
+    pub profile: Option<&'a str>,
+    pub device: &'a str,
+}
+
+impl HooksConfig {
+    pub fn before(&self, transfer: &Transfer) {
+        if self.before.trim().is_empty() {
+            return;
+        }
+        let command = self.command(&self.before, "before", transfer);
+        run("before", command, BEFORE_TIMEOUT, true);
+    }
+
+    /// `result` is the media's name on the device or why the transfer failed
+    pub fn after(&self, transfer: &Transfer, result: &anyhow::Result<String>) {
+        if self.after.trim().is_empty() {
+            return;
+        }
+        let mut command = self.command(&self.after, "after", transfer);
+        match result {
+            Ok(media) => command.env("TRYX_RESULT", "ok").env("TRYX_MEDIA", media),
+            Err(e) => command.env("TRYX_RESULT", "error").env("TRYX_ERROR", format!("{:#}", e)),
+        };
+        thread::spawn(move || run("after", command, AFTER_TIMEOUT, false));
+    }
+
+    fn command(&self, script: &str, stage: &str, transfer: &Transfer) -> Command {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(script)
+            .env("TRYX_HOOK", stage)
+            .env("TRYX_IMAGE", transfer.image)
+            .env("TRYX_PROFILE", transfer.profile.unwrap_or_default())
+            .env("TRYX_DEVICE", transfer.device)
+            .stdin(Stdio::null());
+        command
+    }
+}
+
+/// Output goes wherever ours does: the terminal, or the journal for the daemon.
+/// A `blocking` hook holds up the transfer, so it keeps the watchdog fed.
+fn run(stage: &str, mut command: Command, timeout: Duration, blocking: bool) {
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            log::warn!("Couldn't run the {} hook: {}", stage, e);
+            return;
+        }
+    };
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => {
+                log::info!("Ran the {} hook in {:?}", stage, started.elapsed());
+                return;
+            }
+            Ok(Some(status)) => {
+                log::warn!("The {} hook failed ({})", stage, status);
+                return;
+            }
+            Ok(None) if started.elapsed() >= timeout => {
+                log::warn!("The {} hook is still running after {:?}, killing it", stage, timeout);
+                let _ = child.kill();
+                let _ = child.wait();
+                return;
+            }
+            Ok(None) => {
+                if blocking {
+                    crate::systemd::watchdog();
+                }
+                thread::sleep(POLL);
+            }
+            Err(e) => {
+                log::warn!("Waiting for the {} hook failed: {}", stage, e);
+                return;
+            }
+        }
+    }
+}
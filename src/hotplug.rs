@@ -0,0 +1,118 @@
+// Hotplug: the kernel's uevents (what udev itself listens to) for new serial
+// nodes, so the cooler is picked up the moment it enumerates rather than on
+// the next poll. Its USB vendor:product id finds it under whatever ttyACM
+// number it gets this time. Falls back to polling /dev without netlink.
+
+use std::{os::fd::{AsRawFd, FromRawFd, OwnedFd}, thread};
+use serde::{Deserialize, Serialize};
+
+/// What to do when the cooler shows up
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotplugConfig {
+    /// Start streaming sysinfo without waiting for a button press
+    pub auto_start: bool,
+    /// Re-send the last applied config too, otherwise the device keeps its own screen
+    pub reapply: bool,
+    /// `vvvv:pppp` of the cooler's USB device, learned on the first successful
+    /// apply. Empty only matches the configured port.
+    pub usb_id: String,
+}
+
+impl Default for HotplugConfig {
+    fn default() -> Self {
+        Self { auto_start: true, reapply: true, usb_id: String::new() }
+    }
+}
+
+impl HotplugConfig {
+    /// Whether `device` is the cooler configured as `port`
+    pub fn matches(&self, device: &str, port: &str) -> bool {
+        if self.usb_id.is_empty() {
+            device == port
+        } else {
+            usb_id(device).is_some_and(|id| id == self.usb_id)
+        }
+    }
+}
+
+/// `vvvv:pppp` of the USB device behind the tty `device`, None for a pty or non-USB port
+pub fn usb_id(device: &str) -> Option<String> {
+    let usb = crate::adb_usb::tty_usb_device(device)?;
+    let read = |name: &str| std::fs::read_to_string(usb.join(name)).ok().map(|id| id.trim().to_string());
+    Some(format!("{}:{}", read("idVendor")?, read("idProduct")?))
+}
+
+/// Call `on_appeared` with the node of every serial port that's added
+pub fn watch(on_appeared: impl Fn(String) + Send + 'static) {
+    let socket = match uevent_socket() {
+        Ok(socket) => socket,
+        Err(e) => {
+            log::warn!("No uevents ({}), polling /dev for the cooler instead", e);
+            crate::serial::watch_devices(on_appeared);
+            return;
+        }
+    };
+    thread::spawn(move || {
+        let mut buffer = vec![0u8; 8192];
+        loop {
+            let read = unsafe { libc::recv(socket.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0) };
+            if read < 0 {
+                let error = std::io::Error::last_os_error();
+                if error.kind() == std::io::ErrorKind::Interrupted {
+                    continue;
+                }
+                // ENOBUFS after a burst of events, nothing to do but carry on
+                log::debug!("uevent recv: {}", error);
+                thread::sleep(std::time::Duration::from_millis(100));
+                continue;
+            }
+            if let Some(device) = added_tty(&buffer[..read as usize]) {
+                log::info!("Serial device appeared: {}", device);
+                on_appeared(device);
+            }
+        }
+    });
+}
+
+/// NETLINK_KOBJECT_UEVENT, subscribed to the kernel's broadcast group
+fn uevent_socket() -> std::io::Result<OwnedFd> {
+    let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, libc::NETLINK_KOBJECT_UEVENT) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+    let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    address.nl_groups = 1;
+    let bound = unsafe {
+        libc::bind(
+            fd,
+            &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if bound < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(socket)
+}
+
+/// `add@/devices/…/tty/ttyACM0\0ACTION=add\0SUBSYSTEM=tty\0DEVNAME=ttyACM0\0…`
+fn added_tty(message: &[u8]) -> Option<String> {
+    let mut action = None;
+    let mut subsystem = None;
+    let mut name = None;
+    for field in message.split(|&b| b == 0).skip(1) {
+        let field = std::str::from_utf8(field).ok()?;
+        match field.split_once('=') {
+            Some(("ACTION", value)) => action = Some(value),
+            Some(("SUBSYSTEM", value)) => subsystem = Some(value),
+            Some(("DEVNAME", value)) => name = Some(value),
+            _ => {}
+        }
+    }
+    let name = name?.trim_start_matches("/dev/");
+    (action? == "add" && subsystem? == "tty" && (name.starts_with("ttyACM") || name.starts_with("ttyUSB")))
+        .then(|| format!("/dev/{}", name))
+}
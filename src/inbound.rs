@@ -0,0 +1,69 @@
+// ============================================================================
+// Inbound commands
+// Requests the device starts on its own (anything that isn't an ACK or a reply
+// to one of ours), routed by method and cmdType to a handler
+// ============================================================================
+
+use std::collections::HashMap;
+use anyhow::Result;
+
+use crate::data::{OutgoingFrame, ResponseMessage};
+use crate::screen_setup::AioCoolerController;
+use crate::transport::Transport;
+
+/// Returns the reply frame (see `build_reply`), or None when the device expects no answer
+pub type Handler<T> = fn(&AioCoolerController<T>, &ResponseMessage) -> Result<Option<OutgoingFrame>>;
+
+/// A device-initiated message is one that neither ACKs nor answers anything we sent
+pub fn is_inbound(message: &ResponseMessage, answered: Option<i64>) -> bool {
+    message.ack_number < 0 && answered.is_none()
+}
+
+pub enum Routed {
+    /// A handler ran, with its reply if it has one
+    Handled(Option<OutgoingFrame>),
+    Unhandled,
+}
+
+pub struct InboundRouter<T: Transport> {
+    handlers: HashMap<(String, String), Handler<T>>,
+}
+
+impl<T: Transport> Default for InboundRouter<T> {
+    fn default() -> Self {
+        Self { handlers: HashMap::new() }
+    }
+}
+
+impl<T: Transport> InboundRouter<T> {
+    /// Register a handler, `method` may be "*" to match any method
+    pub fn on(mut self, method: &str, cmd_type: &str, handler: Handler<T>) -> Self {
+        self.handlers.insert((method.to_string(), cmd_type.to_string()), handler);
+        self
+    }
+
+    pub fn route(&self, controller: &AioCoolerController<T>, message: &ResponseMessage) -> Result<Routed> {
+        let handler = self
+            .handlers
+            .get(&(message.method.clone(), message.cmd_type.clone()))
+            .or_else(|| self.handlers.get(&("*".to_string(), message.cmd_type.clone())));
+        match handler {
+            Some(handler) => Ok(Routed::Handled(handler(controller, message)?)),
+            None => {
+                // Full dump so unknown commands can be mapped from the logs
+                let mut headers: Vec<_> = message.headers.iter().collect();
+                headers.sort();
+                log::warn!(
+                    "Unhandled device command {} {} (Seq={}, msgId={}) headers={:?} body={}",
+                    message.method,
+                    message.cmd_type,
+                    message.seq_number,
+                    message.msg_id,
+                    headers,
+                    message.body_str()
+                );
+                Ok(Routed::Unhandled)
+            }
+        }
+    }
+}
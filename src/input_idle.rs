@@ -0,0 +1,152 @@
+// Idle blanking: after some minutes without keyboard or mouse input the
+// heartbeat turns the panel off, or dims it to an idle image such as a clock,
+// and wakes it on the next input, sparing the OLED and the room at night. Idle
+// time comes from GNOME's IdleMonitor, xprintidle on X11, or logind's IdleHint.
+
+use std::{
+    process::Command,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::poll::{Poll, Reader};
+use crate::screen_setup::ScreenConfig;
+
+/// How often idle time is read, and so how long waking up can take
+const POLL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputIdleConfig {
+    pub enabled: bool,
+    /// Minutes without input before the panel goes idle
+    pub minutes: u32,
+    /// Media already on the device to show while idle, empty turns the panel off
+    pub media: String,
+    /// Panel brightness while the idle image shows, 0-100. Turning the panel
+    /// off and dimming take the experimental screen commands.
+    pub dim_brightness: u8,
+    /// Panel brightness to go back to on input, 0-100
+    pub brightness: u8,
+}
+
+impl Default for InputIdleConfig {
+    fn default() -> Self {
+        Self { enabled: false, minutes: 10, media: String::new(), dim_brightness: 10, brightness: 100 }
+    }
+}
+
+/// Whether there has been no input for the configured minutes
+pub fn idle(config: &ScreenConfig) -> bool {
+    let idle = &config.input_idle;
+    let possible = !idle.media.is_empty() || config.experimental_commands;
+    idle.enabled && possible && idle_for() >= Duration::from_secs(idle.minutes.max(1) as u64 * 60)
+}
+
+/// In milliseconds. Unreadable counts as active, so a hiccup wakes the panel rather than blanking it.
+static IDLE_MS: Poll = Poll::new();
+
+/// Time since the last input, read in the background from the first call on
+pub fn idle_for() -> Duration {
+    Duration::from_millis(IDLE_MS.get("idle time", POLL, watch))
+}
+
+enum Source {
+    /// org.gnome.Mutter.IdleMonitor on the session bus, X11 and Wayland alike
+    Mutter,
+    /// X11 only, under Wayland it just sees input to X clients
+    Xprintidle,
+    /// IdleHint of our graphical session. The desktop sets it after its own idle
+    /// delay; GNOME does, elsewhere e.g. `swayidle idlehint 60`.
+    Logind(String),
+}
+
+impl Source {
+    fn detect() -> Option<Self> {
+        if mutter().is_some() {
+            return Some(Source::Mutter);
+        }
+        let wayland = std::env::var("XDG_SESSION_TYPE").is_ok_and(|kind| kind == "wayland");
+        if !wayland && std::env::var_os("DISPLAY").is_some() && xprintidle().is_some() {
+            return Some(Source::Xprintidle);
+        }
+        graphical_session().map(Source::Logind)
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Source::Mutter => "GNOME's IdleMonitor".to_string(),
+            Source::Xprintidle => "xprintidle".to_string(),
+            Source::Logind(session) => format!("logind's IdleHint on session {}", session),
+        }
+    }
+
+    fn idle(&self) -> Option<Duration> {
+        match self {
+            Source::Mutter => mutter(),
+            Source::Xprintidle => xprintidle(),
+            Source::Logind(session) => logind(session),
+        }
+    }
+}
+
+fn watch() -> Option<Reader> {
+    let Some(source) = Source::detect() else {
+        log::warn!("Not watching for input: no GNOME IdleMonitor, xprintidle or logind session");
+        return None;
+    };
+    log::info!("Watching for input through {}", source.describe());
+    Some(Box::new(move || source.idle().map(|idle| idle.as_millis() as u64)))
+}
+
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// `(uint64 12345,)`, in milliseconds
+fn mutter() -> Option<Duration> {
+    let reply = output(
+        "gdbus",
+        &[
+            "call",
+            "--session",
+            "--dest",
+            "org.gnome.Mutter.IdleMonitor",
+            "--object-path",
+            "/org/gnome/Mutter/IdleMonitor/Core",
+            "--method",
+            "org.gnome.Mutter.IdleMonitor.GetIdletime",
+        ],
+    )?;
+    let ms = reply.split_once("uint64 ")?.1.trim_end_matches([',', ')']).parse().ok()?;
+    Some(Duration::from_millis(ms))
+}
+
+fn xprintidle() -> Option<Duration> {
+    output("xprintidle", &[])?.parse().ok().map(Duration::from_millis)
+}
+
+/// The session on a seat, falling back to the first one
+fn graphical_session() -> Option<String> {
+    let uid = unsafe { libc::getuid() }.to_string();
+    output("loginctl", &["show-user", &uid, "-p", "Display", "--value"])
+        .filter(|session| !session.is_empty())
+        .or_else(|| {
+            output("loginctl", &["show-user", &uid, "-p", "Sessions", "--value"])?
+                .split_whitespace()
+                .next()
+                .map(str::to_string)
+        })
+}
+
+/// Zero until the hint is set, then the time since IdleSinceHint (µs since the epoch)
+fn logind(session: &str) -> Option<Duration> {
+    let properties = output("loginctl", &["show-session", session, "-p", "IdleHint", "-p", "IdleSinceHint"])?;
+    let property = |name: &str| properties.lines().find_map(|line| line.strip_prefix(name)?.strip_prefix('='));
+    if property("IdleHint")? != "yes" {
+        return Some(Duration::ZERO);
+    }
+    let since = UNIX_EPOCH + Duration::from_micros(property("IdleSinceHint")?.parse().ok()?);
+    Some(SystemTime::now().duration_since(since).unwrap_or_default())
+}
@@ -0,0 +1,187 @@
+// ============================================================================
+// Single instance guard
+// Two processes writing to the same serial port interleave their frames, so
+// one window and one daemon may run per user. Each holds a listening socket in
+// the abstract namespace, which the kernel drops with the process: there is no
+// lock file to go stale. A second window asks the first to come to the front
+// (or, with `--take-over`, to quit); a daemon asks the window to release the
+// port and then drives it itself, the window carrying on as its client.
+// ============================================================================
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    os::{
+        fd::AsRawFd,
+        linux::net::SocketAddrExt,
+        unix::net::{SocketAddr, UnixListener, UnixStream},
+    },
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use anyhow::{Context, Result};
+use crossbeam::channel::{self, Receiver, Sender};
+
+/// How long the holder may take to act on a request
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long `--take-over` waits for the old window to close
+const TAKE_OVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Gui,
+    Daemon,
+}
+
+impl Role {
+    fn name(self) -> &'static str {
+        match self {
+            Role::Gui => "gui",
+            Role::Daemon => "daemon",
+        }
+    }
+
+    /// Abstract socket names are shared by all users, so the uid is part of it
+    fn address(self) -> io::Result<SocketAddr> {
+        let uid = unsafe { libc::getuid() };
+        SocketAddr::from_abstract_name(format!("tryx-panorama-{}-{}", self.name(), uid))
+    }
+}
+
+/// What another instance can ask of the one holding the lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Bring the window to the front
+    Focus,
+    /// Stop using the serial port, the daemon drives it from now on
+    Release,
+    /// Release the port and exit without the exit action
+    Quit,
+}
+
+impl Command {
+    fn as_str(self) -> &'static str {
+        match self {
+            Command::Focus => "focus",
+            Command::Release => "release",
+            Command::Quit => "quit",
+        }
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        [Command::Focus, Command::Release, Command::Quit].into_iter().find(|command| command.as_str() == text)
+    }
+}
+
+type Waker = Arc<Mutex<Option<Box<dyn Fn() + Send>>>>;
+
+/// Held for the life of the process. Requests arrive on `commands` with a
+/// sender to signal once they've been carried out.
+pub struct Lock {
+    pub commands: Receiver<(Command, Sender<()>)>,
+    waker: Waker,
+}
+
+impl Lock {
+    /// Claim `role`. Fails with `AddrInUse` while another process holds it.
+    pub fn acquire(role: Role) -> io::Result<Self> {
+        let listener = UnixListener::bind_addr(&role.address()?)?;
+        let (tx, rx) = channel::unbounded();
+        let waker: Waker = Default::default();
+        let wake = waker.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let tx = tx.clone();
+                let wake = wake.clone();
+                thread::spawn(move || {
+                    if let Err(e) = serve(&stream, &tx, &wake) {
+                        log::warn!("Instance request failed: {:#}", e);
+                    }
+                });
+            }
+        });
+        Ok(Self { commands: rx, waker })
+    }
+
+    /// Called when a request arrives, so an idle GUI gets a frame to handle it
+    pub fn on_command(&self, wake: impl Fn() + Send + 'static) {
+        *self.waker.lock().unwrap() = Some(Box::new(wake));
+    }
+}
+
+fn serve(stream: &UnixStream, tx: &Sender<(Command, Sender<()>)>, wake: &Waker) -> Result<()> {
+    // Anyone can connect to an abstract socket
+    anyhow::ensure!(peer_uid(stream) == Some(unsafe { libc::getuid() }), "Ignoring a request from another user");
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    let command = Command::parse(line.trim()).with_context(|| format!("Unknown request {:?}", line.trim()))?;
+    let (done_tx, done) = channel::bounded(1);
+    tx.send((command, done_tx))?;
+    if let Some(wake) = wake.lock().unwrap().as_ref() {
+        wake();
+    }
+    done.recv_timeout(REPLY_TIMEOUT).context("Timed out")?;
+    let mut writer = stream;
+    writer.write_all(b"ok\n")?;
+    Ok(())
+}
+
+/// SO_PEERCRED: the user the connecting process runs as
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    (result == 0).then_some(cred.uid)
+}
+
+/// Ask the process holding `role` to carry out `command`, waiting until it
+/// has. False when nothing holds it.
+pub fn ask(role: Role, command: Command) -> Result<bool> {
+    let stream = match UnixStream::connect_addr(&role.address()?) {
+        Ok(stream) => stream,
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    stream.set_read_timeout(Some(REPLY_TIMEOUT + Duration::from_secs(1)))?;
+    (&stream).write_all(format!("{}\n", command.as_str()).as_bytes())?;
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply).context("No reply")?;
+    anyhow::ensure!(reply.trim() == "ok", "The other instance didn't confirm");
+    Ok(true)
+}
+
+/// The lock for a new window. With another window open, bring that one up
+/// and return None, or with `take_over` close it and take its place.
+pub fn claim_gui(take_over: bool) -> Result<Option<Lock>> {
+    match Lock::acquire(Role::Gui) {
+        Ok(lock) => return Ok(Some(lock)),
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {}
+        Err(e) => return Err(e).context("Failed to claim the instance lock"),
+    }
+    if !take_over {
+        ask(Role::Gui, Command::Focus)?;
+        eprintln!("Already running, switched to the open window (--take-over replaces it)");
+        return Ok(None);
+    }
+
+    eprintln!("Asking the open window to close");
+    ask(Role::Gui, Command::Quit)?;
+    let deadline = Instant::now() + TAKE_OVER_TIMEOUT;
+    loop {
+        match Lock::acquire(Role::Gui) {
+            Ok(lock) => return Ok(Some(lock)),
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse && Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(100));
+            }
+            Err(e) => return Err(e).context("The open window didn't close"),
+        }
+    }
+}
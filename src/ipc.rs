@@ -0,0 +1,219 @@
+// ============================================================================
+// Daemon IPC
+// While the daemon holds the serial port, the GUI and the command line talk to
+// it over a Unix socket instead of opening the port themselves. One request
+// per connection, one JSON object per line: the client writes a `Request`, the
+// daemon answers with `Event`s (log lines, progress, the applied config) and
+// ends with `Done`. The socket is only accessible to our user.
+// ============================================================================
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+use anyhow::{Context, Result};
+use crossbeam::channel::Sender;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppMessage;
+use crate::link_stats::SessionStatus;
+use crate::message_bus::MessageSender;
+use crate::screen_setup::{LastApplied, ScreenConfig};
+
+/// How often a connection forwards what its job reported
+const FORWARD_INTERVAL: Duration = Duration::from_millis(50);
+/// How long the GUI trusts its last look at the socket
+const PROBE_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Request {
+    /// Upload `image` (a path the daemon can read) and show it with `config`,
+    /// switching to `profile` if it's named
+    Send {
+        image: PathBuf,
+        config: Box<ScreenConfig>,
+        #[serde(default)]
+        profile: Option<String>,
+    },
+    /// Show the media already on screen with a new `config`
+    Apply { config: Box<ScreenConfig> },
+    /// The serial session's link statistics, answered right away with `Status`
+    Status,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Event {
+    Log(String),
+    Progress(f32, String),
+    Applied(Box<LastApplied>),
+    Status(Box<SessionStatus>),
+    /// The request finished: what to report, or why it failed
+    Done(Result<String, String>),
+}
+
+/// A request handed to the daemon's loop. It reports on `events` like a GUI
+/// worker does and must end with `Success` or `Error`.
+pub struct Job {
+    pub request: Request,
+    pub events: MessageSender,
+}
+
+pub fn socket_path() -> PathBuf {
+    crate::paths::runtime_dir().join("daemon.sock")
+}
+
+/// A daemon is listening, so the serial port is its to use
+pub fn daemon_running() -> bool {
+    UnixStream::connect(socket_path()).is_ok()
+}
+
+/// `daemon_running` for the GUI, which asks every frame
+#[derive(Default)]
+pub struct DaemonProbe {
+    running: bool,
+    checked: Option<Instant>,
+}
+
+impl DaemonProbe {
+    pub fn running(&mut self) -> bool {
+        if self.checked.is_none_or(|at| at.elapsed() >= PROBE_INTERVAL) {
+            self.running = daemon_running();
+            self.checked = Some(Instant::now());
+        }
+        self.running
+    }
+}
+
+/// Listen for clients on a thread. Each request goes to `jobs`, then `wake`
+/// interrupts the heartbeat so the daemon picks it up.
+pub fn serve(jobs: Sender<Job>, wake: impl Fn() + Send + Sync + 'static) -> Result<()> {
+    let path = socket_path();
+    anyhow::ensure!(!daemon_running(), "Another daemon is listening on {}", path.display());
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    // Left behind by a daemon that didn't get to clean up
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).with_context(|| format!("Failed to listen on {}", path.display()))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    log::info!("Listening on {}", path.display());
+
+    let wake = Arc::new(wake);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::warn!("IPC accept failed: {}", e);
+                    continue;
+                }
+            };
+            let jobs = jobs.clone();
+            let wake = wake.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle(&stream, &jobs, wake.as_ref()) {
+                    log::warn!("IPC client: {:#}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+pub fn remove_socket() {
+    let _ = std::fs::remove_file(socket_path());
+}
+
+fn handle(stream: &UnixStream, jobs: &Sender<Job>, wake: &(impl Fn() + ?Sized)) -> Result<()> {
+    let mut line = String::new();
+    // `daemon_running` connects and hangs up without a request
+    if BufReader::new(stream).read_line(&mut line)? == 0 {
+        return Ok(());
+    }
+    let mut writer = stream;
+    let request: Request = match serde_json::from_str(&line) {
+        Ok(request) => request,
+        Err(e) => return write_event(&mut writer, &Event::Done(Err(format!("Bad request: {}", e)))),
+    };
+    // The statistics are this process's, the daemon's loop needn't be woken
+    if let Request::Status = request {
+        return write_event(&mut writer, &Event::Status(Box::new(crate::link_stats::stats().status())));
+    }
+
+    let (tx, rx) = crate::message_bus::channel();
+    jobs.send(Job { request, events: tx }).map_err(|_| anyhow::anyhow!("The daemon is stopping"))?;
+    wake();
+    loop {
+        let batch = rx.drain(usize::MAX);
+        for line in batch.logs {
+            write_event(&mut writer, &Event::Log(line))?;
+        }
+        if let Some((progress, status)) = batch.progress {
+            write_event(&mut writer, &Event::Progress(progress, status))?;
+        }
+        for event in batch.events {
+            let event = match event {
+                AppMessage::Applied(last) => Event::Applied(last),
+                AppMessage::Success(message) => Event::Done(Ok(message)),
+                AppMessage::Error(message) | AppMessage::PortBusy(message) => Event::Done(Err(message)),
+                _ => continue,
+            };
+            let done = matches!(event, Event::Done(_));
+            write_event(&mut writer, &event)?;
+            if done {
+                return Ok(());
+            }
+        }
+        thread::sleep(FORWARD_INTERVAL);
+    }
+}
+
+fn write_event(writer: &mut impl Write, event: &Event) -> Result<()> {
+    let mut line = serde_json::to_vec(event)?;
+    line.push(b'\n');
+    writer.write_all(&line).context("Client went away")
+}
+
+/// Hand `request` to the daemon, passing its log lines, progress and applied
+/// config on to `tx` as they arrive. Returns the daemon's closing message.
+pub fn request(request: &Request, tx: &MessageSender) -> Result<String> {
+    let stream = UnixStream::connect(socket_path()).context("The daemon isn't running")?;
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    (&stream).write_all(&line)?;
+
+    for line in BufReader::new(&stream).lines() {
+        let event: Event = serde_json::from_str(&line?).context("Unexpected reply from the daemon")?;
+        let _ = match event {
+            Event::Log(line) => tx.send(AppMessage::Log(line)),
+            Event::Progress(progress, status) => tx.send(AppMessage::Progress(progress, status)),
+            Event::Applied(last) => tx.send(AppMessage::Applied(last)),
+            Event::Status(_) => continue,
+            Event::Done(result) => return result.map_err(anyhow::Error::msg),
+        };
+    }
+    anyhow::bail!("The daemon closed the connection before finishing")
+}
+
+/// The daemon's serial session statistics
+pub fn status() -> Result<SessionStatus> {
+    let stream = UnixStream::connect(socket_path()).context("The daemon isn't running")?;
+    let mut line = serde_json::to_vec(&Request::Status)?;
+    line.push(b'\n');
+    (&stream).write_all(&line)?;
+
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply)?;
+    match serde_json::from_str(&reply).context("Unexpected reply from the daemon")? {
+        Event::Status(status) => Ok(*status),
+        Event::Done(Err(e)) => Err(anyhow::Error::msg(e)),
+        _ => anyhow::bail!("Unexpected reply from the daemon"),
+    }
+}
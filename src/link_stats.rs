@@ -0,0 +1,227 @@
+// Serial link statistics for the current session, to help diagnose flaky USB links
+
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, atomic::{AtomicU64, Ordering}},
+    time::{Duration, Instant},
+};
+use serde::{Deserialize, Serialize};
+
+pub struct LinkStats {
+    frames_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    frames_received: AtomicU64,
+    bytes_received: AtomicU64,
+    crc_errors: AtomicU64,
+    /// Frames dropped for other reasons (missing end marker, bad escape)
+    malformed_frames: AtomicU64,
+    /// Times the parser had to skip bytes to find the next start marker
+    resyncs: AtomicU64,
+    discarded_bytes: AtomicU64,
+    retransmissions: AtomicU64,
+    rtt_total_us: AtomicU64,
+    rtt_samples: AtomicU64,
+    session_started: Mutex<Option<Instant>>,
+    /// Per cmdType breakdown
+    commands: Mutex<BTreeMap<String, CommandStats>>,
+}
+
+/// Point-in-time copy, also what `status --json` prints for the daemon's session
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LinkStatsSnapshot {
+    pub frames_sent: u64,
+    pub bytes_sent: u64,
+    pub frames_received: u64,
+    pub bytes_received: u64,
+    pub crc_errors: u64,
+    pub malformed_frames: u64,
+    pub resyncs: u64,
+    pub discarded_bytes: u64,
+    pub retransmissions: u64,
+    pub avg_rtt_ms: Option<f64>,
+    pub session_secs: f64,
+}
+
+/// Counters for one cmdType
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommandStats {
+    /// Frames written, retransmissions included
+    pub sent: u64,
+    pub bytes: u64,
+    /// ACKs or replies received
+    pub answered: u64,
+    pub retries: u64,
+    /// Gave up after all retransmits
+    pub failed: u64,
+    pub rtt_total_ms: f64,
+    pub rtt_min_ms: Option<f64>,
+    pub rtt_max_ms: Option<f64>,
+}
+
+impl CommandStats {
+    pub fn avg_rtt_ms(&self) -> Option<f64> {
+        (self.answered > 0).then(|| self.rtt_total_ms / self.answered as f64)
+    }
+}
+
+/// Per-command latency and throughput for the session, for tuning delays and pacing
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProtocolStats {
+    pub commands: BTreeMap<String, CommandStats>,
+    pub tx_bytes_per_sec: f64,
+    pub rx_bytes_per_sec: f64,
+}
+
+static STATS: LinkStats = LinkStats {
+    frames_sent: AtomicU64::new(0),
+    bytes_sent: AtomicU64::new(0),
+    frames_received: AtomicU64::new(0),
+    bytes_received: AtomicU64::new(0),
+    crc_errors: AtomicU64::new(0),
+    malformed_frames: AtomicU64::new(0),
+    resyncs: AtomicU64::new(0),
+    discarded_bytes: AtomicU64::new(0),
+    retransmissions: AtomicU64::new(0),
+    rtt_total_us: AtomicU64::new(0),
+    rtt_samples: AtomicU64::new(0),
+    session_started: Mutex::new(None),
+    commands: Mutex::new(BTreeMap::new()),
+};
+
+pub fn stats() -> &'static LinkStats {
+    &STATS
+}
+
+impl LinkStats {
+    /// Zero everything, called whenever a new serial session opens
+    pub fn start_session(&self) {
+        for counter in [
+            &self.frames_sent,
+            &self.bytes_sent,
+            &self.frames_received,
+            &self.bytes_received,
+            &self.crc_errors,
+            &self.malformed_frames,
+            &self.resyncs,
+            &self.discarded_bytes,
+            &self.retransmissions,
+            &self.rtt_total_us,
+            &self.rtt_samples,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        *self.session_started.lock().unwrap() = Some(Instant::now());
+        self.commands.lock().unwrap().clear();
+    }
+
+    fn command(&self, cmd_type: &str, update: impl FnOnce(&mut CommandStats)) {
+        update(self.commands.lock().unwrap().entry(cmd_type.to_string()).or_default());
+    }
+
+    pub fn record_sent(&self, cmd_type: &str, bytes: usize) {
+        self.frames_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.command(cmd_type, |c| {
+            c.sent += 1;
+            c.bytes += bytes as u64;
+        });
+    }
+
+    pub fn record_received(&self, bytes: usize) {
+        self.frames_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_crc_error(&self) {
+        self.crc_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_malformed_frame(&self) {
+        self.malformed_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_resyncs(&self, events: u64, bytes: u64) {
+        self.resyncs.fetch_add(events, Ordering::Relaxed);
+        self.discarded_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_retransmission(&self, cmd_type: &str) {
+        self.retransmissions.fetch_add(1, Ordering::Relaxed);
+        self.command(cmd_type, |c| c.retries += 1);
+    }
+
+    pub fn record_failure(&self, cmd_type: &str) {
+        self.command(cmd_type, |c| c.failed += 1);
+    }
+
+    /// Time from sending a command to its ACK or reply
+    pub fn record_rtt(&self, cmd_type: &str, rtt: Duration) {
+        self.rtt_total_us.fetch_add(rtt.as_micros() as u64, Ordering::Relaxed);
+        self.rtt_samples.fetch_add(1, Ordering::Relaxed);
+        let ms = rtt.as_secs_f64() * 1000.0;
+        self.command(cmd_type, |c| {
+            c.answered += 1;
+            c.rtt_total_ms += ms;
+            c.rtt_min_ms = Some(c.rtt_min_ms.map_or(ms, |min| min.min(ms)));
+            c.rtt_max_ms = Some(c.rtt_max_ms.map_or(ms, |max| max.max(ms)));
+        });
+    }
+
+    pub fn snapshot(&self) -> LinkStatsSnapshot {
+        let samples = self.rtt_samples.load(Ordering::Relaxed);
+        let avg_rtt_ms = (samples > 0)
+            .then(|| self.rtt_total_us.load(Ordering::Relaxed) as f64 / samples as f64 / 1000.0);
+        let session_secs = self
+            .session_started
+            .lock()
+            .unwrap()
+            .map(|start| start.elapsed().as_secs_f64())
+            .unwrap_or(0.0);
+
+        LinkStatsSnapshot {
+            frames_sent: self.frames_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            frames_received: self.frames_received.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            crc_errors: self.crc_errors.load(Ordering::Relaxed),
+            malformed_frames: self.malformed_frames.load(Ordering::Relaxed),
+            resyncs: self.resyncs.load(Ordering::Relaxed),
+            discarded_bytes: self.discarded_bytes.load(Ordering::Relaxed),
+            retransmissions: self.retransmissions.load(Ordering::Relaxed),
+            avg_rtt_ms,
+            session_secs,
+        }
+    }
+
+    pub fn protocol(&self) -> ProtocolStats {
+        let link = self.snapshot();
+        let per_sec = |bytes: u64| if link.session_secs > 0.0 { bytes as f64 / link.session_secs } else { 0.0 };
+        ProtocolStats {
+            commands: self.commands.lock().unwrap().clone(),
+            tx_bytes_per_sec: per_sec(link.bytes_sent),
+            rx_bytes_per_sec: per_sec(link.bytes_received),
+        }
+    }
+}
+
+/// Both views of the session, as the daemon reports them over IPC
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionStatus {
+    pub link: LinkStatsSnapshot,
+    pub protocol: ProtocolStats,
+}
+
+impl LinkStats {
+    pub fn status(&self) -> SessionStatus {
+        SessionStatus { link: self.snapshot(), protocol: self.protocol() }
+    }
+}
+
+impl LinkStatsSnapshot {
+    /// Share of inbound frames that arrived damaged, None before any arrived
+    pub fn corruption_rate(&self) -> Option<f64> {
+        let bad = self.crc_errors + self.malformed_frames;
+        let total = self.frames_received + bad;
+        (total > 0).then(|| bad as f64 / total as f64)
+    }
+}
@@ -0,0 +1,186 @@
+// Persistent log in `~/.local/state/tryx-panorama/logs`, next to whatever
+// shows logs live (the GUI panel, the daemon's stderr), so a failure in the
+// middle of the night can still be read the next morning. One file per process
+// kind, rotated by size, older files kept as `daemon.log.1`, `.2`, ...
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+};
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogFileConfig {
+    /// Lines above this level only go to the live log
+    pub level: LogLevel,
+    /// Start a new file once the current one reaches this size
+    pub max_size_kb: u32,
+    /// Rotated files kept besides the current one
+    pub keep: u32,
+}
+
+impl Default for LogFileConfig {
+    fn default() -> Self {
+        Self { level: LogLevel::Info, max_size_kb: 1024, keep: 3 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum LogLevel {
+    /// No log file
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 6] = [LogLevel::Off, LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug, LogLevel::Trace];
+
+    pub fn filter(self) -> LevelFilter {
+        match self {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LogLevel::Off => "Off",
+            LogLevel::Error => "Errors",
+            LogLevel::Warn => "Warnings",
+            LogLevel::Info => "Info",
+            LogLevel::Debug => "Debug",
+            LogLevel::Trace => "Trace",
+        }
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    config: LogFileConfig,
+}
+
+impl LogFile {
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.written + line.len() as u64 > self.config.max_size_kb as u64 * 1024 && self.written > 0 {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    /// `name.log` becomes `name.log.1`, `.1` becomes `.2` and so on, the oldest is dropped
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let numbered = |n: u32| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        let _ = std::fs::remove_file(numbered(self.config.keep));
+        for n in (1..self.config.keep).rev() {
+            let _ = std::fs::rename(numbered(n), numbered(n + 1));
+        }
+        if self.config.keep > 0 {
+            std::fs::rename(&self.path, numbered(1))?;
+        }
+        self.file = OpenOptions::new().create(true).write(true).truncate(true).open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+static FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+/// What the live log shows, set once by `install`
+static CONSOLE_LEVEL: OnceLock<LevelFilter> = OnceLock::new();
+
+pub fn dir() -> PathBuf {
+    crate::paths::state_dir().join("logs")
+}
+
+/// Install `console` as the logger for lines up to `console_level`, with the
+/// log file alongside once `open` has been called
+pub fn install(console: impl log::Log + 'static, console_level: LevelFilter) -> Result<(), log::SetLoggerError> {
+    log::set_logger(Box::leak(Box::new(Tee { console, console_level })))?;
+    let _ = CONSOLE_LEVEL.set(console_level);
+    log::set_max_level(console_level);
+    Ok(())
+}
+
+/// Log to `dir()/<name>.log` with `config`. Called again with a changed
+/// config, the same file carries on with the new level and limits.
+pub fn open(name: &str, config: &LogFileConfig) {
+    let path = dir().join(format!("{}.log", name));
+    let mut current = FILE.lock().unwrap();
+    match current.as_mut() {
+        Some(file) if file.path == path => file.config = config.clone(),
+        _ if config.level == LogLevel::Off => {}
+        _ => {
+            let opened = std::fs::create_dir_all(dir())
+                .and_then(|()| OpenOptions::new().create(true).append(true).open(&path));
+            match opened {
+                Ok(file) => {
+                    let written = file.metadata().map(|m| m.len()).unwrap_or(0);
+                    *current = Some(LogFile { path, file, written, config: config.clone() });
+                }
+                // Can't go through `log` while holding the lock
+                Err(e) => eprintln!("Failed to open {}: {}", path.display(), e),
+            }
+        }
+    }
+    let console = CONSOLE_LEVEL.get().copied().unwrap_or(LevelFilter::Off);
+    log::set_max_level(console.max(config.level.filter()));
+}
+
+/// Hands each record to the live logger and the file, each with its own level
+struct Tee<L> {
+    console: L,
+    console_level: LevelFilter,
+}
+
+impl<L: log::Log> log::Log for Tee<L> {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.level() <= self.console_level {
+            self.console.log(record);
+        }
+        let mut current = FILE.lock().unwrap();
+        let Some(file) = current.as_mut() else {
+            return;
+        };
+        if record.level() > file.config.level.filter() {
+            return;
+        }
+        let line = format!(
+            "{} {:<5} [{}] {}\n",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if let Err(e) = file.write_line(&line) {
+            eprintln!("Log file {} stopped: {}", file.path.display(), e);
+            *current = None;
+        }
+    }
+
+    fn flush(&self) {
+        self.console.flush();
+        if let Some(file) = FILE.lock().unwrap().as_mut() {
+            let _ = file.file.flush();
+        }
+    }
+}
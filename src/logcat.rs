@@ -0,0 +1,193 @@
+// ============================================================================
+// Logcat viewer
+// Streams `adb logcat` from the cooler and keeps the lines of the vendor's
+// display app, so a rejected config or media file can be traced without a
+// second terminal. The app is recognized by its pids (picked up again from
+// ActivityManager when it restarts) and by tags naming the vendor.
+// ============================================================================
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+};
+use anyhow::Result;
+
+use crate::adb::{shell_quote, AdbClient, AdbConfig};
+use crate::device_control::VENDOR_PACKAGES;
+
+/// Lines kept in memory, the oldest are dropped beyond this
+const MAX_LINES: usize = 5000;
+/// Lines of history printed before following
+const BACKLOG: usize = 200;
+/// Priorities from lowest to highest, with their names
+pub const LEVELS: [(char, &str); 6] =
+    [('V', "Verbose"), ('D', "Debug"), ('I', "Info"), ('W', "Warning"), ('E', "Error"), ('F', "Fatal")];
+
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    /// `HH:MM:SS.mmm` on the device's clock
+    pub time: String,
+    pub pid: u32,
+    pub level: char,
+    pub tag: String,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum LogcatEvent {
+    /// Processes of the display app running when the stream started
+    Pids(Vec<u32>),
+    Lines(Vec<LogLine>),
+    /// The stream ended, Ok when stopped
+    Stopped(Result<(), String>),
+}
+
+pub struct LogcatViewer {
+    pub lines: VecDeque<LogLine>,
+    /// Text searched in tag and message, case-insensitive
+    pub filter: String,
+    pub min_level: char,
+    /// Only lines of the display app
+    pub app_only: bool,
+    pids: HashSet<u32>,
+    pub error: Option<String>,
+    pub cancel: Arc<AtomicBool>,
+    pub worker: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Default for LogcatViewer {
+    fn default() -> Self {
+        Self {
+            lines: VecDeque::new(),
+            filter: String::new(),
+            min_level: 'V',
+            app_only: true,
+            pids: HashSet::new(),
+            error: None,
+            cancel: Default::default(),
+            worker: None,
+        }
+    }
+}
+
+impl LogcatViewer {
+    pub fn running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|w| !w.is_finished())
+    }
+
+    /// Forget the previous stream before starting a new one
+    pub fn begin(&mut self) {
+        self.lines.clear();
+        self.pids.clear();
+        self.error = None;
+        self.cancel.store(false, Ordering::Relaxed);
+    }
+
+    pub fn stop(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn handle(&mut self, event: LogcatEvent) {
+        match event {
+            LogcatEvent::Pids(pids) => self.pids.extend(pids),
+            LogcatEvent::Lines(lines) => {
+                for line in lines {
+                    if let Some(pid) = started_vendor_pid(&line) {
+                        self.pids.insert(pid);
+                    }
+                    if self.lines.len() >= MAX_LINES {
+                        self.lines.pop_front();
+                    }
+                    self.lines.push_back(line);
+                }
+            }
+            LogcatEvent::Stopped(result) => {
+                if let Err(e) = result {
+                    log::error!("logcat: {}", e);
+                    self.error = Some(e);
+                }
+            }
+        }
+    }
+
+    /// Lines passing the app, level and text filters
+    pub fn visible(&self) -> Vec<&LogLine> {
+        let min_rank = rank(self.min_level);
+        let filter = self.filter.trim().to_lowercase();
+        self.lines
+            .iter()
+            .filter(|line| !self.app_only || self.pids.contains(&line.pid) || is_vendor(&line.tag))
+            .filter(|line| rank(line.level) >= min_rank)
+            .filter(|line| {
+                filter.is_empty() || line.tag.to_lowercase().contains(&filter) || line.message.to_lowercase().contains(&filter)
+            })
+            .collect()
+    }
+}
+
+/// Look up the display app's processes, then follow logcat until `cancel` is
+/// set. Lines are handed over a packet's worth at a time.
+pub fn stream(adb: &AdbConfig, cancel: &AtomicBool, mut on_event: impl FnMut(LogcatEvent)) -> Result<()> {
+    let mut client = AdbClient::connect(adb)?;
+    let mut pids = Vec::new();
+    for package in crate::device_control::vendor_packages(&mut client)? {
+        let output = client.shell(&format!("pidof {}", shell_quote(&package)))?;
+        pids.extend(output.split_whitespace().filter_map(|pid| pid.parse::<u32>().ok()));
+    }
+    on_event(LogcatEvent::Pids(pids));
+
+    let mut partial = Vec::new();
+    client.shell_streaming(&format!("logcat -v threadtime -T {}", BACKLOG), cancel, |chunk| {
+        partial.extend_from_slice(chunk);
+        let Some(end) = partial.iter().rposition(|&b| b == b'\n') else {
+            return;
+        };
+        let complete: Vec<u8> = partial.drain(..=end).collect();
+        let lines: Vec<LogLine> = String::from_utf8_lossy(&complete).lines().filter_map(parse_line).collect();
+        if !lines.is_empty() {
+            on_event(LogcatEvent::Lines(lines));
+        }
+    })?;
+    Ok(())
+}
+
+/// `10-18 12:34:56.789  1234  1250 I Tag     : message`
+fn parse_line(line: &str) -> Option<LogLine> {
+    let mut rest = line.trim_end_matches('\r');
+    let mut field = || {
+        let trimmed = rest.trim_start();
+        let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+        rest = &trimmed[end..];
+        Some(&trimmed[..end]).filter(|field| !field.is_empty())
+    };
+    let _date = field()?;
+    let time = field()?.to_string();
+    let pid = field()?.parse().ok()?;
+    let _tid = field()?;
+    let level = field()?;
+    let mut chars = level.chars();
+    let (Some(level), None) = (chars.next(), chars.next()) else {
+        return None;
+    };
+    let rest = rest.trim_start();
+    let (tag, message) = rest.split_once(": ").unwrap_or(("", rest));
+    Some(LogLine { time, pid, level, tag: tag.trim().to_string(), message: message.to_string() })
+}
+
+/// `Start proc 1234:com.vendor.app/u0a55 for activity ...` from ActivityManager
+fn started_vendor_pid(line: &LogLine) -> Option<u32> {
+    if line.tag != "ActivityManager" {
+        return None;
+    }
+    let (pid, process) = line.message.strip_prefix("Start proc ")?.split_once(':')?;
+    is_vendor(process).then(|| pid.parse().ok()).flatten()
+}
+
+fn is_vendor(name: &str) -> bool {
+    let name = name.to_lowercase();
+    VENDOR_PACKAGES.iter().any(|vendor| name.contains(vendor))
+}
+
+fn rank(level: char) -> usize {
+    LEVELS.iter().position(|(c, _)| *c == level).unwrap_or(0)
+}
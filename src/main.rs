@@ -1,5 +1,8 @@
 use std::{path::PathBuf, process::Command, sync::mpsc::{self, Receiver, Sender}, thread, time::{Duration, SystemTime, UNIX_EPOCH}};
-use crate::screen_setup::{AioCoolerController, ScreenConfig};
+use crate::screen_setup::{AioCoolerController, ConnectionState, ScreenConfig, ShutdownAction, TransferMethod};
+use crate::serial::{FlowControl, Parity, SerialSettings};
+use crate::console::ConsoleMode;
+use crate::media_browser::{format_size, Thumbnail};
 use serde::{Deserialize, Serialize};
 use eframe::egui::{self, Color32};
 use anyhow::{Context, Result};
@@ -7,16 +10,102 @@ use std::io::{Read, Write};
 use egui_logger::logger_ui;
 
 mod screen_setup;
+mod adb;
+mod adb_auth;
+mod adb_usb;
+mod media_browser;
+mod screen_preview;
+mod env_check;
+mod adb_shell;
+mod logcat;
+mod apk;
+mod device_info;
+mod device_control;
 mod data;
 mod app_state;
 mod sysinfo;
+mod mangohud;
+mod custom_sensor;
+mod top_process;
+mod power_supply;
+mod smoothing;
+mod sysinfo_dump;
+mod units;
+mod history;
+mod plot;
+mod ping;
+mod daemon;
+mod cli;
+mod systemd;
+mod ipc;
+mod instance;
+mod file_watch;
+mod hotplug;
+mod settings;
+mod log_file;
+mod profiles;
+mod mqtt;
+mod broadcast;
+mod websocket;
+mod autostart;
+mod session_lock;
+mod input_idle;
+mod poll;
+mod hooks;
+mod scripting;
+mod profile_rules;
+mod game_detect;
+mod serial;
+mod paths;
+mod media_cache;
+mod diagnostics;
+mod message_bus;
+mod timing;
+mod screensaver;
+mod alerts;
+mod verify;
+mod capabilities;
+mod link_stats;
+mod reliability;
+mod dispatcher;
+mod inbound;
+mod sniffer;
+mod replay;
+mod serial_writer;
+mod simulator;
+mod sequence;
+mod compression;
+mod console;
+mod protocol_profile;
+mod self_test;
+mod transport;
 
 impl eframe::App for app_state::AioCoolerApp {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.save_settings(true);
+        self.shutdown();
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.process_messages();
+        self.handle_instance_commands(ctx);
+        self.save_settings(false);
+
+        if self.preview.due() {
+            self.start_screen_capture();
+        }
+        for uri in self.preview.stale.drain(..) {
+            ctx.forget_image(&uri);
+        }
 
-        if self.is_processing {
+        if self.is_busy() || self.media.running() || self.preview.running() || self.setup_check.running() || self.shell.running() || self.logcat.running() || self.app_update.running() || self.device_info.running() || self.device_control.running() || self.message_receiver.has_pending() {
             ctx.request_repaint();
+        } else if self.heartbeat_running() {
+            // Pick up heartbeat logs and state changes without spinning
+            ctx.request_repaint_after(Duration::from_millis(500));
+        }
+        if self.preview.auto_refresh {
+            ctx.request_repaint_after(self.preview.interval());
         }
 
         egui::TopBottomPanel::top("header").show(ctx, |ui| {
@@ -32,11 +121,11 @@ impl eframe::App for app_state::AioCoolerApp {
             ui.add_space(4.0);
             ui.horizontal(|ui| {
                 ui.label(&self.status_message);
-                if self.is_processing {
+                if self.is_busy() {
                     ui.spinner();
                 }
             });
-            if self.is_processing || self.progress > 0.0 {
+            if self.is_busy() || self.progress > 0.0 {
                 ui.add(egui::ProgressBar::new(self.progress).show_percentage());
             }
             ui.add_space(4.0);
@@ -59,6 +148,150 @@ impl eframe::App for app_state::AioCoolerApp {
 
         egui::CentralPanel::default().show(ctx, |ui| {
             egui::ScrollArea::vertical().show(ui, |ui| {
+                if let Some(report) = self.crash_report.clone() {
+                    ui.group(|ui| {
+                        ui.colored_label(
+                            Color32::from_rgb(255, 55, 102),
+                            "The previous session crashed. Please attach this report to your bug report:",
+                        );
+                        ui.horizontal(|ui| {
+                            ui.monospace(report.display().to_string());
+                            if ui.button("📋 Copy path").clicked() {
+                                ui.ctx().copy_text(report.display().to_string());
+                            }
+                            if ui.button("Dismiss").clicked() {
+                                self.crash_report = None;
+                            }
+                        });
+                    });
+                    ui.add_space(10.0);
+                }
+
+                if self.setup_check.shown {
+                    ui.group(|ui| {
+                        let passed = env_check::all_passed(&self.setup_check.results);
+                        ui.horizontal(|ui| {
+                            ui.heading("🩺 Setup Check");
+                            if !passed {
+                                ui.colored_label(Color32::from_rgb(255, 55, 102), "some checks failed, see the fixes below");
+                            }
+                        });
+                        ui.separator();
+                        for check in &self.setup_check.results {
+                            ui.horizontal(|ui| {
+                                ui.label(check.status.icon());
+                                ui.strong(check.name);
+                                ui.label(&check.detail);
+                            });
+                            if let Some(fix) = &check.fix {
+                                ui.horizontal(|ui| {
+                                    ui.add_space(24.0);
+                                    ui.monospace(fix);
+                                    if ui.small_button("📋").on_hover_text("Copy").clicked() {
+                                        ui.ctx().copy_text(fix.clone());
+                                    }
+                                });
+                            }
+                        }
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(!self.setup_check.running(), egui::Button::new("🔄 Check again")).clicked() {
+                                self.start_setup_check();
+                            }
+                            if ui.button("Dismiss").clicked() {
+                                self.setup_check.shown = false;
+                            }
+                        });
+                    });
+                    ui.add_space(10.0);
+                }
+
+                let dashboard = egui::CollapsingHeader::new("📈 Dashboard").id_salt("dashboard").show(ui, |ui| {
+                    let sensors = &mut self.screen_config.sensors;
+                    ui.horizontal(|ui| {
+                        ui.label("Keep:");
+                        if ui.add(egui::DragValue::new(&mut sensors.history_minutes).range(1..=120).suffix(" min")).changed() {
+                            sysinfo::configure(sensors);
+                        }
+                        if ui.button("🗑 Clear").clicked() {
+                            history::clear();
+                        }
+                    });
+
+                    let samples = history::samples();
+                    let now = std::time::Instant::now();
+                    let span = sensors.history_minutes.max(1) as f64 * 60.0;
+                    // A stopped sampler leaves a gap, which shouldn't be drawn as a straight line
+                    let gap = (sensors.sample_interval_ms as f64 / 1000.0 * 3.0).max(3.0);
+                    let series = |name: &str, color: Color32, value: &dyn Fn(&history::Sample) -> Option<f64>| {
+                        let mut points = Vec::new();
+                        let mut previous: Option<std::time::Instant> = None;
+                        for sample in &samples {
+                            let seconds_ago = now.duration_since(sample.at).as_secs_f64();
+                            if previous.is_some_and(|at| sample.at.duration_since(at).as_secs_f64() > gap) {
+                                points.push([seconds_ago, f64::NAN]);
+                            }
+                            previous = Some(sample.at);
+                            points.push([seconds_ago, value(sample).unwrap_or(f64::NAN)]);
+                        }
+                        plot::Series { name: name.to_string(), color, points }
+                    };
+                    let has_data = |series: &plot::Series| series.points.iter().any(|point| !point[1].is_nan());
+
+                    if samples.is_empty() {
+                        ui.weak("Collecting samples…");
+                        return;
+                    }
+                    let temperature = sensors.units.temperature;
+                    // 0 means the sensor is missing
+                    let degrees = |celsius: u8| (celsius > 0).then(|| temperature.convert(celsius) as f64);
+                    let temps: Vec<plot::Series> = [
+                        series("CPU", plot::PALETTE[0], &|sample| degrees(sample.cpu_temperature)),
+                        series("GPU", plot::PALETTE[1], &|sample| degrees(sample.gpu_temperature)),
+                    ]
+                    .into_iter()
+                    .filter(has_data)
+                    .collect();
+                    plot::show(ui, "Temperature", temperature.label(), &temps, span, None);
+
+                    ui.add_space(6.0);
+                    let loads: Vec<plot::Series> = [
+                        series("CPU", plot::PALETTE[0], &|sample| Some(sample.cpu_load as f64)),
+                        series("GPU", plot::PALETTE[1], &|sample| sample.gpu_load.map(f64::from)),
+                        series("Memory", plot::PALETTE[2], &|sample| Some(sample.memory_load as f64)),
+                    ]
+                    .into_iter()
+                    .filter(has_data)
+                    .collect();
+                    plot::show(ui, "Load", "%", &loads, span, Some((0.0, 100.0)));
+
+                    let mut fan_names: Vec<&str> = Vec::new();
+                    for sample in &samples {
+                        for (name, _) in &sample.fans {
+                            if !fan_names.contains(&name.as_str()) {
+                                fan_names.push(name);
+                            }
+                        }
+                    }
+                    if !fan_names.is_empty() {
+                        ui.add_space(6.0);
+                        let fans: Vec<plot::Series> = fan_names
+                            .iter()
+                            .enumerate()
+                            .map(|(i, name)| {
+                                series(name, plot::PALETTE[i % plot::PALETTE.len()], &|sample| {
+                                    sample.fans.iter().find(|(fan, _)| fan == name).map(|(_, rpm)| *rpm as f64)
+                                })
+                            })
+                            .collect();
+                        plot::show(ui, "Fans", " RPM", &fans, span, None);
+                    }
+                });
+                if dashboard.body_returned.is_some() {
+                    sysinfo::keep_sampling();
+                    ui.ctx().request_repaint_after(Duration::from_millis(self.screen_config.sensors.sample_interval_ms.max(250)));
+                }
+                ui.add_space(10.0);
+
                 ui.group(|ui| {
                     ui.heading("⚙️ Device Settings");
                     ui.separator();
@@ -66,7 +299,419 @@ impl eframe::App for app_state::AioCoolerApp {
                     ui.horizontal(|ui| {
                         ui.label("Serial Device:");
                         ui.text_edit_singleline(&mut self.serial_device);
+                        let checking = self.setup_check.running();
+                        if ui.add_enabled(!checking, egui::Button::new(if checking { "🩺 Checking..." } else { "🩺 Check setup" }))
+                            .on_hover_text("Check serial port and ADB access, with fixes for whatever is missing")
+                            .clicked()
+                        {
+                            self.start_setup_check();
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Protocol profile:");
+                        let profiles = self.profile_store.all();
+                        let current = self.profile_store.for_device(&self.serial_device);
+                        let mut selected = current.name.clone();
+                        egui::ComboBox::from_id_salt("protocol_profile")
+                            .selected_text(&selected)
+                            .show_ui(ui, |ui| {
+                                for profile in &profiles {
+                                    ui.selectable_value(&mut selected, profile.name.clone(), &profile.name);
+                                }
+                            })
+                            .response
+                            .on_hover_text(format!(
+                                "Frame marker 0x{:02x}, escape 0x{:02x}, {} headers{}",
+                                current.frame_marker,
+                                current.escape_marker,
+                                current.headers.len(),
+                                current.baud_rate.map(|b| format!(", {} baud", b)).unwrap_or_default()
+                            ));
+                        if selected != current.name {
+                            self.select_protocol_profile(&selected);
+                        }
+                        if ui.button("Reload").on_hover_text("Re-read custom profiles from protocol_profiles.json").clicked() {
+                            self.profile_store = protocol_profile::ProfileStore::load();
+                        }
+                    });
+
+                    let adb_before = self.screen_config.adb.clone();
+                    ui.horizontal(|ui| {
+                        ui.label("ADB device:");
+                        let adb = &mut self.screen_config.adb;
+                        let selected_text = if adb.serial.is_empty() { "Auto (the cooler)".to_string() } else { adb.serial.clone() };
+                        egui::ComboBox::from_id_salt("adb_device")
+                            .selected_text(selected_text)
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut adb.serial, String::new(), "Auto (the cooler)");
+                                for device in adb_usb::devices() {
+                                    let label = format!("{}{}", device.describe(), if device.has_serial_port() { " — cooler" } else { "" });
+                                    ui.selectable_value(&mut adb.serial, device.serial.clone(), label);
+                                }
+                            })
+                            .response
+                            .on_hover_text("With several Android devices plugged in, Auto picks the one that also has a serial port");
+                        ui.add_enabled(adb.address.trim().is_empty(), egui::Label::new("or over TCP:"));
+                        ui.add(egui::TextEdit::singleline(&mut adb.address).hint_text("host:port").desired_width(140.0))
+                            .on_hover_text("adbd after `adb tcpip 5555`, leave empty for USB");
+                    });
+                    egui::CollapsingHeader::new("ADB enable command")
+                        .id_salt("adb_enable")
+                        .default_open(false)
+                        .show(ui, |ui| {
+                            ui.weak("For units that boot with ADB off: sent over serial when no ADB device is found before a push");
+                            let adb = &mut self.screen_config.adb;
+                            egui::Grid::new("adb_enable_grid")
+                                .num_columns(2)
+                                .spacing([20.0, 8.0])
+                                .show(ui, |ui| {
+                                    ui.label("cmdType:");
+                                    ui.add(egui::TextEdit::singleline(&mut adb.enable_cmd_type).hint_text("empty = never send"));
+                                    ui.end_row();
+
+                                    ui.label("Body:");
+                                    ui.add(egui::TextEdit::singleline(&mut adb.enable_body).code_editor());
+                                    ui.end_row();
+
+                                    ui.label("Wait for ADB:");
+                                    ui.add(egui::DragValue::new(&mut adb.enable_wait_secs).range(1..=120).suffix(" s"));
+                                    ui.end_row();
+                                });
+                        });
+                    if self.screen_config.adb != adb_before {
+                        self.save_adb_config();
+                    }
+
+                    egui::CollapsingHeader::new("Line settings")
+                        .id_salt("serial_settings")
+                        .default_open(false)
+                        .show(ui, |ui| {
+                            let serial = &mut self.screen_config.serial;
+                            egui::Grid::new("serial_settings_grid")
+                                .num_columns(2)
+                                .spacing([20.0, 8.0])
+                                .show(ui, |ui| {
+                                    ui.label("Baud rate:");
+                                    egui::ComboBox::from_id_salt("baud_rate")
+                                        .selected_text(serial.baud_rate.to_string())
+                                        .show_ui(ui, |ui| {
+                                            for &baud in SerialSettings::BAUD_RATES {
+                                                ui.selectable_value(&mut serial.baud_rate, baud, baud.to_string());
+                                            }
+                                        });
+                                    ui.end_row();
+
+                                    ui.label("Data bits:");
+                                    ui.add(egui::Slider::new(&mut serial.data_bits, 5..=8));
+                                    ui.end_row();
+
+                                    ui.label("Parity:");
+                                    ui.horizontal(|ui| {
+                                        ui.selectable_value(&mut serial.parity, Parity::None, "None");
+                                        ui.selectable_value(&mut serial.parity, Parity::Odd, "Odd");
+                                        ui.selectable_value(&mut serial.parity, Parity::Even, "Even");
+                                    });
+                                    ui.end_row();
+
+                                    ui.label("Stop bits:");
+                                    ui.horizontal(|ui| {
+                                        ui.selectable_value(&mut serial.stop_bits, 1, "1");
+                                        ui.selectable_value(&mut serial.stop_bits, 2, "2");
+                                    });
+                                    ui.end_row();
+
+                                    ui.label("Flow control:");
+                                    ui.horizontal(|ui| {
+                                        ui.selectable_value(&mut serial.flow_control, FlowControl::None, "None");
+                                        ui.selectable_value(&mut serial.flow_control, FlowControl::Software, "XON/XOFF");
+                                        ui.selectable_value(&mut serial.flow_control, FlowControl::Hardware, "RTS/CTS");
+                                    });
+                                    ui.end_row();
+
+                                    ui.label("Timeout:");
+                                    ui.add(egui::Slider::new(&mut serial.timeout_ms, 100..=10_000).suffix(" ms"))
+                                        .on_hover_text("Applies to both reads and writes");
+                                    ui.end_row();
+                                });
+                            if ui.button("Reset to 115200 8N1").clicked() {
+                                *serial = SerialSettings::default();
+                            }
+                        });
+
+                    egui::CollapsingHeader::new("Retries")
+                        .id_salt("retry_policy")
+                        .default_open(false)
+                        .show(ui, |ui| {
+                            ui.weak("Opening the port, ADB and writes are retried with growing delays. Raise these on slow USB hubs.");
+                            let retry = &mut self.screen_config.retry;
+                            egui::Grid::new("retry_policy_grid")
+                                .num_columns(2)
+                                .spacing([20.0, 8.0])
+                                .show(ui, |ui| {
+                                    ui.label("Attempts:");
+                                    ui.add(egui::Slider::new(&mut retry.attempts, 1..=10));
+                                    ui.end_row();
+
+                                    ui.label("First delay:");
+                                    ui.add(egui::Slider::new(&mut retry.base_delay_ms, 50..=5000).suffix(" ms"))
+                                        .on_hover_text("Doubled for every retry after the first");
+                                    ui.end_row();
+
+                                    ui.label("Max delay:");
+                                    ui.add(egui::Slider::new(&mut retry.max_delay_ms, 250..=30_000).suffix(" ms"));
+                                    ui.end_row();
+                                });
+                        });
+
+                    egui::CollapsingHeader::new("Log File")
+                        .id_salt("log_file")
+                        .default_open(false)
+                        .show(ui, |ui| {
+                            ui.weak(format!("Kept in {} so problems can be looked into after the fact.", log_file::dir().display()));
+                            let log = &mut self.screen_config.log_file;
+                            let before = log.clone();
+                            egui::Grid::new("log_file_grid")
+                                .num_columns(2)
+                                .spacing([20.0, 8.0])
+                                .show(ui, |ui| {
+                                    ui.label("Level:");
+                                    egui::ComboBox::from_id_salt("log_file_level")
+                                        .selected_text(log.level.label())
+                                        .show_ui(ui, |ui| {
+                                            for level in log_file::LogLevel::ALL {
+                                                ui.selectable_value(&mut log.level, level, level.label());
+                                            }
+                                        });
+                                    ui.end_row();
+
+                                    ui.label("Rotate at:");
+                                    ui.add(egui::Slider::new(&mut log.max_size_kb, 64..=65_536).logarithmic(true).suffix(" KiB"));
+                                    ui.end_row();
+
+                                    ui.label("Old files kept:");
+                                    ui.add(egui::Slider::new(&mut log.keep, 0..=20));
+                                    ui.end_row();
+                                });
+                            if *log != before {
+                                log_file::open("gui", log);
+                            }
+                        });
+
+                    egui::CollapsingHeader::new("MQTT")
+                        .id_salt("mqtt")
+                        .default_open(false)
+                        .show(ui, |ui| {
+                            ui.weak("The daemon publishes state and sysinfo under the topic and takes commands on <topic>/set/image, set/profile and set/screen.");
+                            let mqtt = &mut self.mqtt;
+                            ui.checkbox(&mut mqtt.enabled, "Connect to a broker");
+                            ui.add_enabled_ui(mqtt.enabled, |ui| {
+                                egui::Grid::new("mqtt_grid")
+                                    .num_columns(2)
+                                    .spacing([20.0, 8.0])
+                                    .show(ui, |ui| {
+                                        ui.label("Broker:");
+                                        ui.horizontal(|ui| {
+                                            ui.add(egui::TextEdit::singleline(&mut mqtt.host).desired_width(160.0));
+                                            ui.add(egui::DragValue::new(&mut mqtt.port).prefix("port "));
+                                        });
+                                        ui.end_row();
+
+                                        ui.label("User:");
+                                        ui.add(egui::TextEdit::singleline(&mut mqtt.username).hint_text("anonymous").desired_width(160.0));
+                                        ui.end_row();
+
+                                        ui.label("Password:");
+                                        ui.add(egui::TextEdit::singleline(&mut mqtt.password).password(true).desired_width(160.0));
+                                        ui.end_row();
+
+                                        ui.label("Client id:");
+                                        ui.add(egui::TextEdit::singleline(&mut mqtt.client_id).desired_width(160.0));
+                                        ui.end_row();
+
+                                        ui.label("Topic:");
+                                        ui.add(egui::TextEdit::singleline(&mut mqtt.topic).desired_width(160.0));
+                                        ui.end_row();
+
+                                        ui.label("Sysinfo every:");
+                                        ui.add(egui::Slider::new(&mut mqtt.publish_interval_secs, 1..=300).suffix(" s"));
+                                        ui.end_row();
+                                    });
+                            });
+                            if self.mqtt.enabled && !self.daemon.running() {
+                                ui.weak("Only the daemon connects, start it to use MQTT.");
+                            }
+                        });
+
+                    egui::CollapsingHeader::new("WebSocket")
+                        .id_salt("websocket")
+                        .default_open(false)
+                        .show(ui, |ui| {
+                            ui.weak("The daemon streams connection state, transfer progress and sysinfo as JSON, for a web dashboard or an OBS browser source.");
+                            let websocket = &mut self.websocket;
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut websocket.enabled, "Listen on");
+                                ui.add_enabled(websocket.enabled, egui::TextEdit::singleline(&mut websocket.address).desired_width(160.0))
+                                    .on_hover_text("host:port, 0.0.0.0 lets other machines connect");
+                            });
+                            if websocket.enabled {
+                                ui.horizontal(|ui| {
+                                    let url = format!("ws://{}/", websocket.address);
+                                    ui.monospace(&url);
+                                    if ui.small_button("📋").on_hover_text("Copy").clicked() {
+                                        ui.ctx().copy_text(url);
+                                    }
+                                });
+                                ui.label("Pages on this machine can connect. Also allow pages from:");
+                                let mut remove = None;
+                                for (i, origin) in websocket.allowed_origins.iter_mut().enumerate() {
+                                    ui.horizontal(|ui| {
+                                        ui.add(egui::TextEdit::singleline(origin).hint_text("e.g. https://dash.example.com").desired_width(220.0));
+                                        if ui.small_button("🗑").clicked() {
+                                            remove = Some(i);
+                                        }
+                                    });
+                                }
+                                if let Some(i) = remove {
+                                    websocket.allowed_origins.remove(i);
+                                }
+                                if ui.button("➕ Add origin").clicked() {
+                                    websocket.allowed_origins.push(String::new());
+                                }
+                                if !self.daemon.running() {
+                                    ui.weak("Only the daemon serves it, start it to connect.");
+                                }
+                            }
+                        });
+
+                    egui::CollapsingHeader::new("Transfer Hooks")
+                        .id_salt("hooks")
+                        .default_open(false)
+                        .show(ui, |ui| {
+                            ui.weak("Shell commands run before and after each transfer or profile switch, with TRYX_IMAGE, TRYX_PROFILE, TRYX_DEVICE, and TRYX_RESULT, TRYX_MEDIA or TRYX_ERROR afterwards.");
+                            egui::Grid::new("hooks_grid").num_columns(2).spacing([20.0, 8.0]).show(ui, |ui| {
+                                ui.label("Before:");
+                                ui.add(egui::TextEdit::singleline(&mut self.hooks.before).hint_text("e.g. openrgb -p transfer").desired_width(280.0));
+                                ui.end_row();
+
+                                ui.label("After:");
+                                ui.add(egui::TextEdit::singleline(&mut self.hooks.after).hint_text("e.g. notify-send \"Cooler: $TRYX_RESULT\"").desired_width(280.0));
+                                ui.end_row();
+                            });
+                        });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Firmware:");
+                        match &self.capability_store.last_version {
+                            Some(version) => ui.label(version),
+                            None => ui.weak("unknown (no handshake seen yet)"),
+                        };
+                    });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Connection:");
+                        let color = match &self.connection_state {
+                            ConnectionState::Disconnected => Color32::GRAY,
+                            ConnectionState::SerialOpen | ConnectionState::Handshaked | ConnectionState::Reconnecting => {
+                                Color32::from_rgb(94, 215, 221)
+                            }
+                            ConnectionState::Streaming => Color32::from_rgb(80, 200, 120),
+                            ConnectionState::Error(_) => Color32::from_rgb(255, 55, 102),
+                        };
+                        let label = ui.colored_label(color, format!("● {}", self.connection_state.label()));
+                        if let ConnectionState::Error(e) = &self.connection_state {
+                            label.on_hover_text(e);
+                        }
+                        if ui
+                            .add_enabled(self.connection_state.can_stop(), egui::Button::new("⏹ Stop"))
+                            .clicked()
+                        {
+                            self.stop();
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        let hotplug = &mut self.screen_config.hotplug;
+                        ui.checkbox(&mut hotplug.auto_start, "Start when the cooler is plugged in")
+                            .on_hover_text("Stream sysinfo as soon as the cooler appears, after boot or a replug");
+                        ui.add_enabled(hotplug.auto_start, egui::Checkbox::new(&mut hotplug.reapply, "and re-apply last config"));
+                    });
+
+                    ui.horizontal(|ui| {
+                        let mut window = self.autostart.window;
+                        if ui
+                            .checkbox(&mut window, "Open minimized at login")
+                            .on_hover_text(format!("Writes {}", autostart::entry_path().display()))
+                            .changed()
+                        {
+                            self.set_autostart_window(window);
+                        }
+                        if let Some(mut service) = self.autostart.service
+                            && ui
+                                .checkbox(&mut service, "Run the daemon at login")
+                                .on_hover_text(format!("Enables the {} systemd user unit", systemd::UNIT_NAME))
+                                .changed()
+                        {
+                            self.set_autostart_service(service);
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        let usb_id = &mut self.screen_config.hotplug.usb_id;
+                        if usb_id.is_empty() {
+                            ui.weak("Cooler USB id: learned on the first transfer, until then only this port is watched");
+                        } else {
+                            ui.label(format!("Cooler USB id: {}", usb_id))
+                                .on_hover_text("Recognised on whichever ttyACM/ttyUSB node it appears as");
+                            if ui.small_button("Forget").clicked() {
+                                usb_id.clear();
+                            }
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        let can_reapply = self.last_applied.is_some() && self.can_start_session();
+                        let hover = match &self.last_applied {
+                            Some(last) => format!("Last applied: {}", last.media),
+                            None => "Nothing applied yet".to_string(),
+                        };
+                        if ui
+                            .add_enabled(can_reapply, egui::Button::new("↺ Re-apply now"))
+                            .on_hover_text(hover)
+                            .clicked()
+                        {
+                            self.reapply_last();
+                        }
+                    });
+
+                    ui.horizontal(|ui| {
+                        let heartbeat = &mut self.screen_config.heartbeat;
+                        ui.checkbox(&mut heartbeat.enabled, "Keep display alive after applying")
+                            .on_hover_text("Keep sending sysinfo until stopped, otherwise the display reverts to its default screen");
+                        ui.add_enabled(
+                            heartbeat.enabled,
+                            egui::Slider::new(&mut heartbeat.interval_ms, 250..=10_000).suffix(" ms").text("interval"),
+                        );
                     });
+                    ui.checkbox(&mut self.screen_config.heartbeat.auto_reconnect, "Reconnect automatically if the device drops off")
+                        .on_hover_text("Wait for the cooler to re-enumerate (USB reset, suspend) and resume the heartbeat");
+
+                    if let Some(conflict) = self.port_conflict.clone() {
+                        ui.add_space(4.0);
+                        ui.colored_label(Color32::from_rgb(255, 55, 102), format!("⚠ {}", conflict));
+                        ui.horizontal(|ui| {
+                            let retry = ui.add_enabled(
+                                self.can_transfer(),
+                                egui::Button::new("🔄 Retry"),
+                            );
+                            if retry.clicked() {
+                                self.start_transfer();
+                            }
+                            if ui.button("Dismiss").clicked() {
+                                self.port_conflict = None;
+                            }
+                        });
+                    }
                 });
 
                 ui.add_space(10.0);
@@ -77,10 +722,12 @@ impl eframe::App for app_state::AioCoolerApp {
 
                     ui.horizontal(|ui| {
                         if ui.button("Browse...").clicked() {
-                            if let Some(path) = rfd::FileDialog::new()
-                                .add_filter("Images", &["png", "jpg", "jpeg", "gif", "bmp"])
-                                .pick_file()
-                            {
+                            let mut dialog = rfd::FileDialog::new()
+                                .add_filter("Images", &["png", "jpg", "jpeg", "gif", "bmp"]);
+                            if self.capabilities.video {
+                                dialog = dialog.add_filter("Videos", &["mp4", "webm", "mkv"]);
+                            }
+                            if let Some(path) = dialog.pick_file() {
                                 self.selected_image = Some(path);
                             }
                         }
@@ -91,6 +738,193 @@ impl eframe::App for app_state::AioCoolerApp {
                             ui.label("No image selected");
                         }
                     });
+
+                    ui.horizontal(|ui| {
+                        ui.label("Profile:");
+                        let mut load = None;
+                        egui::ComboBox::from_id_salt("profile_load")
+                            .selected_text("Load...")
+                            .show_ui(ui, |ui| {
+                                let names = profiles::list();
+                                if names.is_empty() {
+                                    ui.weak("No profiles saved yet");
+                                }
+                                for name in names {
+                                    if ui.selectable_label(false, &name).clicked() {
+                                        load = Some(name);
+                                    }
+                                }
+                            });
+                        if let Some(name) = load {
+                            self.load_profile(&name);
+                        }
+                        ui.add(egui::TextEdit::singleline(&mut self.profile_name).hint_text("name").desired_width(120.0));
+                        let can_save = self.selected_image.is_some() && !self.profile_name.trim().is_empty();
+                        if ui
+                            .add_enabled(can_save, egui::Button::new("💾 Save"))
+                            .on_hover_text("The selected image with the current screen configuration")
+                            .clicked()
+                        {
+                            self.save_profile();
+                        }
+                        if ui
+                            .add_enabled(!self.profile_name.trim().is_empty(), egui::Button::new("🗑"))
+                            .on_hover_text("Delete the profile with this name")
+                            .clicked()
+                        {
+                            self.delete_profile();
+                        }
+                    });
+                });
+
+                ui.add_space(10.0);
+
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.heading("📁 Media on Device");
+                        if ui.add_enabled(!self.media.running(), egui::Button::new("🔄 Refresh")).clicked() {
+                            self.start_media_refresh();
+                        }
+                        if self.media.running() {
+                            ui.spinner();
+                        }
+                    });
+                    ui.checkbox(&mut self.screen_config.keep_old_media, "Keep old media when transferring")
+                        .on_hover_text("Otherwise every transfer deletes all other files in pcMedia");
+                    ui.separator();
+
+                    if let Some(error) = &self.media.error {
+                        ui.colored_label(Color32::from_rgb(220, 80, 80), format!("⚠ {}", error));
+                    }
+                    match self.media.listed_at {
+                        None => {
+                            ui.label("Not listed yet, hit Refresh (uses ADB)");
+                        }
+                        Some(listed_at) if self.media.files.is_empty() => {
+                            ui.label(format!("{} is empty (listed {})", screen_setup::MEDIA_DIR, listed_at.format("%H:%M:%S")));
+                        }
+                        Some(listed_at) => {
+                            ui.label(format!(
+                                "{} files, {} (listed {})",
+                                self.media.files.len(),
+                                format_size(self.media.total_size()),
+                                listed_at.format("%H:%M:%S")
+                            ));
+                            let on_screen = self.last_applied.as_ref().map(|last| last.media.clone());
+
+                            ui.horizontal(|ui| {
+                                if ui.small_button("Select all").clicked() {
+                                    self.media.selected = self.media.files.iter().map(|file| file.name.clone()).collect();
+                                }
+                                if ui.small_button("Select none").clicked() {
+                                    self.media.selected.clear();
+                                }
+                                let selected = self.media.selected.len();
+                                if ui
+                                    .add_enabled(selected > 0 && !self.media.running(), egui::Button::new(format!("⬇ Download selected ({})", selected)))
+                                    .on_hover_text("Copy the files to a folder on this computer")
+                                    .clicked()
+                                    && let Some(dir) = rfd::FileDialog::new().set_title("Download to").pick_folder()
+                                {
+                                    self.download_selected_media(dir);
+                                }
+                                let count = self.deletable_selection().len();
+                                if self.media.confirm_delete {
+                                    ui.colored_label(Color32::from_rgb(255, 165, 0), format!("Delete {} file(s) from the device?", count));
+                                    if ui.button("Delete").clicked() {
+                                        self.delete_selected_media();
+                                    }
+                                    if ui.button("Cancel").clicked() {
+                                        self.media.confirm_delete = false;
+                                    }
+                                } else if ui
+                                    .add_enabled(count > 0 && !self.media.running(), egui::Button::new(format!("🗑 Delete selected ({})", count)))
+                                    .clicked()
+                                {
+                                    self.media.confirm_delete = true;
+                                }
+                            });
+
+                            egui::ScrollArea::vertical().id_salt("media_browser").max_height(300.0).show(ui, |ui| {
+                                egui::Grid::new("media_browser_grid")
+                                    .num_columns(5)
+                                    .spacing([20.0, 8.0])
+                                    .striped(true)
+                                    .show(ui, |ui| {
+                                        for file in &self.media.files {
+                                            let mut selected = self.media.selected.contains(&file.name);
+                                            let mut checkbox = ui.add(egui::Checkbox::without_text(&mut selected));
+                                            if on_screen.as_ref() == Some(&file.name) {
+                                                checkbox = checkbox.on_hover_text("Shown on the display, it can be downloaded but not deleted");
+                                            }
+                                            if checkbox.changed() {
+                                                if selected {
+                                                    self.media.selected.insert(file.name.clone());
+                                                } else {
+                                                    self.media.selected.remove(&file.name);
+                                                }
+                                            }
+                                            match &file.thumbnail {
+                                                Thumbnail::Loaded(bytes) => {
+                                                    ui.add(egui::Image::from_bytes(file.uri(), bytes.clone()).max_size(egui::vec2(96.0, 48.0)));
+                                                }
+                                                Thumbnail::Pending => {
+                                                    ui.spinner();
+                                                }
+                                                Thumbnail::Unavailable(reason) => {
+                                                    ui.label("—").on_hover_text(reason);
+                                                }
+                                            }
+                                            if on_screen.as_ref() == Some(&file.name) {
+                                                ui.strong(format!("▶ {}", file.name)).on_hover_text("Shown on the display");
+                                            } else {
+                                                ui.monospace(&file.name);
+                                            }
+                                            ui.label(format_size(file.size));
+                                            ui.label(file.modified.map_or("—".to_string(), |time| time.format("%Y-%m-%d %H:%M").to_string()));
+                                            ui.end_row();
+                                        }
+                                    });
+                            });
+                        }
+                    }
+                });
+
+                ui.add_space(10.0);
+
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.heading("🖥 Device Screen");
+                        if ui.add_enabled(!self.preview.running(), egui::Button::new("📷 Preview device screen")).clicked() {
+                            self.start_screen_capture();
+                        }
+                        if self.preview.running() {
+                            ui.spinner();
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.preview.auto_refresh, "Auto-refresh every");
+                        ui.add(egui::DragValue::new(&mut self.preview.interval_secs).range(1..=600).suffix(" s"));
+                    });
+                    ui.separator();
+
+                    if let Some(error) = &self.preview.error {
+                        ui.colored_label(Color32::from_rgb(220, 80, 80), format!("⚠ {}", error));
+                    }
+                    match &self.preview.capture {
+                        Some(capture) => {
+                            let resolution = capture.size.map_or(String::new(), |[w, h]| format!("{}×{}, ", w, h));
+                            ui.label(format!("{}captured {}", resolution, capture.taken_at.format("%H:%M:%S")));
+                            ui.add(
+                                egui::Image::from_bytes(capture.uri.clone(), capture.png.clone())
+                                    .max_width(ui.available_width())
+                                    .max_height(240.0),
+                            );
+                        }
+                        None => {
+                            ui.label("No capture yet (uses ADB screencap)");
+                        }
+                    }
                 });
 
                 ui.add_space(10.0);
@@ -134,11 +968,14 @@ impl eframe::App for app_state::AioCoolerApp {
                                         "Loop".to_string(),
                                         "Loop",
                                     );
-                                    ui.selectable_value(
-                                        &mut self.screen_config.play_mode,
-                                        "Slideshow".to_string(),
-                                        "Slideshow",
-                                    );
+                                    ui.add_enabled_ui(self.capabilities.slideshow, |ui| {
+                                        ui.selectable_value(
+                                            &mut self.screen_config.play_mode,
+                                            "Slideshow".to_string(),
+                                            "Slideshow",
+                                        )
+                                        .on_disabled_hover_text(self.unsupported_hint("Slideshow"));
+                                    });
                                 });
                             ui.end_row();
 
@@ -196,7 +1033,55 @@ impl eframe::App for app_state::AioCoolerApp {
                             ui.end_row();
 
                             ui.label("Filter Opacity:");
-                            ui.add(egui::Slider::new(&mut self.screen_config.filter_opacity, 0..=100).suffix("%"));
+                            ui.add_enabled(
+                                self.capabilities.filters,
+                                egui::Slider::new(&mut self.screen_config.filter_opacity, 0..=100).suffix("%"),
+                            )
+                            .on_disabled_hover_text(self.unsupported_hint("Filters"));
+                            ui.end_row();
+
+                            ui.label("Upload via:");
+                            egui::ComboBox::from_id_salt("transfer_method")
+                                .selected_text(self.screen_config.transfer_method.label())
+                                .show_ui(ui, |ui| {
+                                    for method in [TransferMethod::Adb, TransferMethod::Serial] {
+                                        ui.selectable_value(&mut self.screen_config.transfer_method, method, method.label());
+                                    }
+                                });
+                            ui.end_row();
+
+                            ui.label("On Exit:");
+                            ui.horizontal(|ui| {
+                                egui::ComboBox::from_id_salt("shutdown_action")
+                                    .selected_text(self.screen_config.shutdown_action.label())
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(
+                                            &mut self.screen_config.shutdown_action,
+                                            ShutdownAction::LeaveAsIs,
+                                            ShutdownAction::LeaveAsIs.label(),
+                                        );
+                                        if self.screen_config.experimental_commands {
+                                            ui.selectable_value(
+                                                &mut self.screen_config.shutdown_action,
+                                                ShutdownAction::ScreenOff,
+                                                ShutdownAction::ScreenOff.label(),
+                                            );
+                                        }
+                                        if ui
+                                            .selectable_label(
+                                                matches!(self.screen_config.shutdown_action, ShutdownAction::ParkedImage(_)),
+                                                "Parked image",
+                                            )
+                                            .clicked()
+                                            && !matches!(self.screen_config.shutdown_action, ShutdownAction::ParkedImage(_))
+                                        {
+                                            self.screen_config.shutdown_action = ShutdownAction::ParkedImage(String::new());
+                                        }
+                                    });
+                                if let ShutdownAction::ParkedImage(file_name) = &mut self.screen_config.shutdown_action {
+                                    ui.add(egui::TextEdit::singleline(file_name).hint_text("remote file name"));
+                                }
+                            });
                             ui.end_row();
                         });
                 });
@@ -215,7 +1100,10 @@ impl eframe::App for app_state::AioCoolerApp {
                     ui.horizontal_wrapped(|ui| {
                         for badge in badges {
                             let mut enabled = self.screen_config.badges.contains(&badge.to_string());
-                            if ui.checkbox(&mut enabled, badge).changed() {
+                            let checkbox = ui
+                                .add_enabled(self.capabilities.badges, egui::Checkbox::new(&mut enabled, badge))
+                                .on_disabled_hover_text(self.unsupported_hint("Badges"));
+                            if checkbox.changed() {
                                 if enabled {
                                     self.screen_config.badges.push(badge.to_string());
                                 } else {
@@ -238,11 +1126,24 @@ impl eframe::App for app_state::AioCoolerApp {
                         "GPU Usage",
                         "RAM Usage",
                         "Fan Speed",
+                        "Coolant Temperature",
+                        "Pump Speed",
+                        "Ping",
                     ];
                     ui.horizontal_wrapped(|ui| {
                         for info in sysinfo_options {
                             let mut enabled = self.screen_config.sysinfo_display.contains(&info.to_string());
-                            if ui.checkbox(&mut enabled, info).changed() {
+                            let checkbox = ui
+                                .add_enabled(self.capabilities.sysinfo_overlay, egui::Checkbox::new(&mut enabled, info))
+                                .on_disabled_hover_text(self.unsupported_hint("System info overlays"));
+                            let checkbox = if matches!(info, "Coolant Temperature" | "Pump Speed") {
+                                checkbox.on_hover_text("Sent in the payload's extra `liquid` object, which the Windows app never sends; stock firmware may leave it blank")
+                            } else if info == "Ping" {
+                                checkbox.on_hover_text("Sent as `network.ping` once a ping host is set under Sensors; stock firmware may leave it blank")
+                            } else {
+                                checkbox
+                            };
+                            if checkbox.changed() {
                                 if enabled {
                                     self.screen_config.sysinfo_display.push(info.to_string());
                                 } else {
@@ -253,6 +1154,1252 @@ impl eframe::App for app_state::AioCoolerApp {
                     });
                 });
 
+                ui.add_space(10.0);
+
+                ui.group(|ui| {
+                    ui.heading("💤 Screen Saver");
+                    ui.separator();
+
+                    let saver = &mut self.screen_config.screensaver;
+                    ui.checkbox(&mut saver.enabled, "Switch to a minimal screen when the system is idle");
+                    ui.add_enabled_ui(saver.enabled, |ui| {
+                        egui::Grid::new("screensaver_grid")
+                            .num_columns(2)
+                            .spacing([20.0, 8.0])
+                            .show(ui, |ui| {
+                                ui.label("Idle after:");
+                                ui.add(egui::Slider::new(&mut saver.idle_minutes, 1..=120).suffix(" min"));
+                                ui.end_row();
+
+                                ui.label("CPU below:");
+                                ui.add(egui::Slider::new(&mut saver.cpu_threshold, 1..=100).suffix("%"));
+                                ui.end_row();
+
+                                ui.label("GPU below:");
+                                ui.add(egui::Slider::new(&mut saver.gpu_threshold, 1..=100).suffix("%"))
+                                    .on_hover_text("GPU load comes from amdgpu or nvidia-smi. Without either the saver stays off.");
+                                ui.end_row();
+
+                                ui.label("Idle sysinfo interval:");
+                                ui.add(egui::Slider::new(&mut saver.idle_interval_secs, 1..=60).suffix(" s"));
+                                ui.end_row();
+
+                                ui.label("Saver image:");
+                                ui.add(egui::TextEdit::singleline(&mut saver.media).hint_text("remote file name (optional)"));
+                                ui.end_row();
+                            });
+                    });
+                });
+
+                ui.add_space(10.0);
+
+                ui.group(|ui| {
+                    ui.heading("🔒 Lock Screen");
+                    ui.separator();
+
+                    let experimental = self.screen_config.experimental_commands;
+                    let lock = &mut self.screen_config.lock_screen;
+                    ui.checkbox(&mut lock.enabled, "Switch screens while the desktop session is locked");
+                    ui.add_enabled_ui(lock.enabled, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Lock image:");
+                            let hint = if experimental { "remote file name, empty turns the panel off" } else { "remote file name" };
+                            ui.add(egui::TextEdit::singleline(&mut lock.media).hint_text(hint));
+                        });
+                    });
+                });
+
+                ui.add_space(10.0);
+
+                ui.group(|ui| {
+                    ui.heading("🌙 Idle Blanking");
+                    ui.separator();
+
+                    let experimental = self.screen_config.experimental_commands;
+                    let idle = &mut self.screen_config.input_idle;
+                    ui.checkbox(&mut idle.enabled, "Turn the panel off or dim it while there's no keyboard or mouse input");
+                    ui.add_enabled_ui(idle.enabled, |ui| {
+                        egui::Grid::new("input_idle_grid")
+                            .num_columns(2)
+                            .spacing([20.0, 8.0])
+                            .show(ui, |ui| {
+                                ui.label("After:");
+                                ui.add(egui::Slider::new(&mut idle.minutes, 1..=120).suffix(" min"));
+                                ui.end_row();
+
+                                ui.label("Idle image:");
+                                let hint = if experimental { "remote file name, empty turns the panel off" } else { "remote file name" };
+                                ui.add(egui::TextEdit::singleline(&mut idle.media).hint_text(hint));
+                                ui.end_row();
+
+                                // Brightness is one of the experimental commands
+                                if experimental {
+                                    ui.add_enabled_ui(!idle.media.is_empty(), |ui| ui.label("Dimmed to:"));
+                                    ui.add_enabled(!idle.media.is_empty(), egui::Slider::new(&mut idle.dim_brightness, 0..=100).suffix("%"));
+                                    ui.end_row();
+
+                                    ui.add_enabled_ui(!idle.media.is_empty(), |ui| ui.label("Back to:"))
+                                        .response
+                                        .on_hover_text("The panel's brightness once there's input again");
+                                    ui.add_enabled(!idle.media.is_empty(), egui::Slider::new(&mut idle.brightness, 0..=100).suffix("%"));
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                });
+
+                ui.add_space(10.0);
+
+                ui.group(|ui| {
+                    ui.heading("🚨 Alerts");
+                    ui.separator();
+
+                    let alerts = &mut self.screen_config.alerts;
+                    ui.checkbox(&mut alerts.enabled, "Switch to a warning screen when a reading crosses its threshold");
+                    ui.add_enabled_ui(alerts.enabled, |ui| {
+                        let mut remove = None;
+                        egui::Grid::new("alert_rules_grid")
+                            .num_columns(3)
+                            .spacing([20.0, 8.0])
+                            .show(ui, |ui| {
+                                for (i, rule) in alerts.rules.iter_mut().enumerate() {
+                                    egui::ComboBox::from_id_salt(("alert_metric", i))
+                                        .selected_text(rule.metric.label())
+                                        .show_ui(ui, |ui| {
+                                            for metric in alerts::AlertMetric::ALL {
+                                                ui.selectable_value(&mut rule.metric, metric, metric.label());
+                                            }
+                                        });
+                                    ui.add(egui::DragValue::new(&mut rule.above).range(0.0..=200.0).prefix("above ").suffix(rule.metric.unit()));
+                                    if ui.small_button("🗑").clicked() {
+                                        remove = Some(i);
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                        if let Some(i) = remove {
+                            alerts.rules.remove(i);
+                        }
+                        if ui.button("➕ Add rule").clicked() {
+                            alerts.rules.push(alerts::AlertRule { metric: alerts::AlertMetric::CpuTemperature, above: 90.0 });
+                        }
+
+                        ui.add_space(6.0);
+                        egui::Grid::new("alerts_grid")
+                            .num_columns(2)
+                            .spacing([20.0, 8.0])
+                            .show(ui, |ui| {
+                                ui.label("Warning color:");
+                                ui.text_edit_singleline(&mut alerts.color);
+                                ui.end_row();
+
+                                ui.label("Warning image:");
+                                ui.add(egui::TextEdit::singleline(&mut alerts.media).hint_text("remote file name (optional)"));
+                                ui.end_row();
+
+                                ui.label("Flash:");
+                                ui.checkbox(&mut alerts.flash, "Alternate with black every second");
+                                ui.end_row();
+
+                                ui.label("Recover below threshold by:");
+                                ui.add(egui::DragValue::new(&mut alerts.hysteresis).range(0.0..=50.0).speed(0.5));
+                                ui.end_row();
+                            });
+                    });
+                });
+
+                ui.add_space(10.0);
+
+                ui.group(|ui| {
+                    ui.heading("🔥 Profile Rules");
+                    ui.separator();
+
+                    let rules = &mut self.screen_config.profile_rules;
+                    ui.checkbox(&mut rules.enabled, "Show a profile while a reading is over its threshold");
+                    ui.weak("The first rule that holds wins, with none the applied screen is shown. Profiles need to have been used once so the device has their image, a warning below says which haven't.");
+                    ui.add_enabled_ui(rules.enabled, |ui| {
+                        let mut remove = None;
+                        egui::Grid::new("profile_rules_grid")
+                            .num_columns(4)
+                            .spacing([20.0, 8.0])
+                            .show(ui, |ui| {
+                                for (i, rule) in rules.rules.iter_mut().enumerate() {
+                                    egui::ComboBox::from_id_salt(("profile_rule_metric", i))
+                                        .selected_text(rule.metric.label())
+                                        .show_ui(ui, |ui| {
+                                            for metric in alerts::AlertMetric::ALL {
+                                                ui.selectable_value(&mut rule.metric, metric, metric.label());
+                                            }
+                                        });
+                                    ui.add(egui::DragValue::new(&mut rule.above).range(0.0..=200.0).prefix("above ").suffix(rule.metric.unit()));
+                                    egui::ComboBox::from_id_salt(("profile_rule_profile", i))
+                                        .selected_text(if rule.profile.is_empty() { "profile..." } else { rule.profile.as_str() })
+                                        .show_ui(ui, |ui| {
+                                            for name in profiles::list() {
+                                                ui.selectable_value(&mut rule.profile, name.clone(), name);
+                                            }
+                                        });
+                                    if ui.small_button("🗑").clicked() {
+                                        remove = Some(i);
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                        if let Some(i) = remove {
+                            rules.rules.remove(i);
+                        }
+                        for rule in &rules.rules {
+                            if let Some(problem) = self.profile_problems.get(&rule.profile) {
+                                ui.colored_label(Color32::from_rgb(220, 80, 80), format!("⚠ {}", problem));
+                            }
+                        }
+                        if ui.button("➕ Add rule").clicked() {
+                            rules.rules.push(profile_rules::ProfileRule {
+                                metric: alerts::AlertMetric::GpuTemperature,
+                                above: 75.0,
+                                profile: String::new(),
+                            });
+                        }
+
+                        ui.add_space(6.0);
+                        egui::Grid::new("profile_rules_settings_grid")
+                            .num_columns(2)
+                            .spacing([20.0, 8.0])
+                            .show(ui, |ui| {
+                                ui.label("Let go below threshold by:");
+                                ui.add(egui::DragValue::new(&mut rules.hysteresis).range(0.0..=50.0).speed(0.5));
+                                ui.end_row();
+
+                                ui.label("Switch at most every:");
+                                ui.add(egui::Slider::new(&mut rules.hold_secs, 0..=300).suffix(" s"));
+                                ui.end_row();
+                            });
+                    });
+                });
+
+                ui.add_space(10.0);
+
+                ui.group(|ui| {
+                    ui.heading("🎮 Games");
+                    ui.separator();
+
+                    let games = &mut self.screen_config.game_detect;
+                    ui.checkbox(&mut games.enabled, "Show a profile while a game is running");
+                    ui.add_enabled_ui(games.enabled, |ui| {
+                        egui::Grid::new("game_detect_grid")
+                            .num_columns(2)
+                            .spacing([20.0, 8.0])
+                            .show(ui, |ui| {
+                                ui.label("Gaming profile:");
+                                ui.vertical(|ui| {
+                                    egui::ComboBox::from_id_salt("game_profile")
+                                        .selected_text(if games.profile.is_empty() { "profile..." } else { games.profile.as_str() })
+                                        .show_ui(ui, |ui| {
+                                            for name in profiles::list() {
+                                                ui.selectable_value(&mut games.profile, name.clone(), name);
+                                            }
+                                        });
+                                    if let Some(problem) = self.profile_problems.get(&games.profile) {
+                                        ui.colored_label(Color32::from_rgb(220, 80, 80), format!("⚠ {}", problem));
+                                    }
+                                });
+                                ui.end_row();
+
+                                ui.label("Detect:");
+                                ui.vertical(|ui| {
+                                    ui.checkbox(&mut games.steam, "Steam games");
+                                    ui.checkbox(&mut games.wine, "Windows programs under Proton or Wine");
+                                    ui.checkbox(&mut games.gamescope, "A gamescope session")
+                                        .on_hover_text("Leave off where the desktop itself runs in gamescope, as on handhelds");
+                                    ui.checkbox(&mut games.mangohud, "A game MangoHud is logging")
+                                        .on_hover_text("Needs 🌡 Sensors → MangoHud logs");
+                                });
+                                ui.end_row();
+
+                                ui.label("Also these processes:");
+                                ui.vertical(|ui| {
+                                    let mut remove = None;
+                                    for (i, name) in games.processes.iter_mut().enumerate() {
+                                        ui.horizontal(|ui| {
+                                            ui.add(egui::TextEdit::singleline(name).hint_text("name as in ps, e.g. factorio").desired_width(180.0));
+                                            if ui.small_button("🗑").clicked() {
+                                                remove = Some(i);
+                                            }
+                                        });
+                                    }
+                                    if let Some(i) = remove {
+                                        games.processes.remove(i);
+                                    }
+                                    if ui.button("➕ Add process").clicked() {
+                                        games.processes.push(String::new());
+                                    }
+                                });
+                                ui.end_row();
+                            });
+                    });
+                });
+
+                ui.add_space(10.0);
+
+                ui.group(|ui| {
+                    ui.heading("🌡 Sensors");
+                    ui.separator();
+
+                    let sensors = &mut self.screen_config.sensors;
+                    let before = sensors.clone();
+                    let readings = sysinfo::readings();
+                    let temperature = sensors.units.temperature;
+                    egui::Grid::new("sensors_grid")
+                        .num_columns(2)
+                        .spacing([20.0, 8.0])
+                        .show(ui, |ui| {
+                            ui.label("Backend:");
+                            egui::ComboBox::from_id_salt("sensor_backend")
+                                .selected_text(sensors.backend.label())
+                                .show_ui(ui, |ui| {
+                                    for backend in sysinfo::Backend::ALL {
+                                        ui.selectable_value(&mut sensors.backend, backend, backend.label());
+                                    }
+                                })
+                                .response
+                                .on_hover_text("Source of CPU load and clock, memory, disk usage and speed, and network rates; temperatures, fans and voltages always come from hwmon");
+                            ui.end_row();
+
+                            ui.label("Sample every:");
+                            ui.add(egui::Slider::new(&mut sensors.sample_interval_ms, 250..=10_000).suffix(" ms"))
+                                .on_hover_text("Sensors are read in the background at this interval; heartbeats send the latest sample");
+                            ui.end_row();
+
+                            ui.label("Units:");
+                            ui.horizontal(|ui| {
+                                let units = &mut sensors.units;
+                                egui::ComboBox::from_id_salt("units_temperature")
+                                    .selected_text(units.temperature.label())
+                                    .show_ui(ui, |ui| {
+                                        for unit in units::Temperature::ALL {
+                                            ui.selectable_value(&mut units.temperature, unit, unit.label());
+                                        }
+                                    });
+                                egui::ComboBox::from_id_salt("units_memory")
+                                    .selected_text(units.memory.label())
+                                    .show_ui(ui, |ui| {
+                                        for unit in units::Memory::ALL {
+                                            ui.selectable_value(&mut units.memory, unit, unit.label());
+                                        }
+                                    });
+                                egui::ComboBox::from_id_salt("units_rate")
+                                    .selected_text(units.rate.label())
+                                    .show_ui(ui, |ui| {
+                                        for unit in units::Rate::ALL {
+                                            ui.selectable_value(&mut units.rate, unit, unit.label());
+                                        }
+                                    });
+                            })
+                            .response
+                            .on_hover_text("Temperatures, memory and network rates are converted before they're sent; whole numbers only, so large units lose detail");
+                            ui.end_row();
+
+                            ui.label("Disk:");
+                            egui::ComboBox::from_id_salt("sensor_disk")
+                                .selected_text(if sensors.disk_device.is_empty() { "Auto (root filesystem)" } else { sensors.disk_device.as_str() })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut sensors.disk_device, String::new(), "Auto (root filesystem)");
+                                    for device in sysinfo::block_devices() {
+                                        ui.selectable_value(&mut sensors.disk_device, device.clone(), device);
+                                    }
+                                })
+                                .response
+                                .on_hover_text("Read/write speed and activity come from this disk's /proc/diskstats counters");
+                            ui.end_row();
+
+                            ui.label("Disk temperature:");
+                            egui::ComboBox::from_id_salt("sensor_disk_temp")
+                                .selected_text(if sensors.disk_temp_device.is_empty() { "Same as Disk" } else { sensors.disk_temp_device.as_str() })
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut sensors.disk_temp_device, String::new(), "Same as Disk");
+                                    for (device, temp) in &readings.disk_temps {
+                                        ui.selectable_value(&mut sensors.disk_temp_device, device.clone(), format!("{} ({})", device, temperature.show(*temp)));
+                                    }
+                                })
+                                .response
+                                .on_hover_text("Drives with an nvme or drivetemp hwmon sensor. SATA drives need the drivetemp module loaded.");
+                            ui.end_row();
+
+                            ui.label("");
+                            ui.checkbox(&mut sensors.smartctl, "Fall back to smartctl")
+                                .on_hover_text("For drives without a hwmon sensor, read once a minute. smartctl usually needs root.");
+                            ui.end_row();
+
+                            let temps = &readings.temps;
+                            for (label, role, key) in [
+                                ("CPU temperature:", sysinfo::TempRole::Cpu, &mut sensors.cpu_sensor),
+                                ("GPU temperature:", sysinfo::TempRole::Gpu, &mut sensors.gpu_sensor),
+                                ("Motherboard:", sysinfo::TempRole::Board, &mut sensors.board_sensor),
+                                ("Chipset (PCH):", sysinfo::TempRole::Pch, &mut sensors.pch_sensor),
+                                ("Coolant:", sysinfo::TempRole::Coolant, &mut sensors.coolant_sensor),
+                            ] {
+                                ui.label(label);
+                                let reading = |sensor: &sysinfo::TempSensor| format!("{} ({})", sensor.name(), temperature.show(sensor.celsius));
+                                let auto = match sysinfo::auto_sensor(role, temps) {
+                                    Some(sensor) => format!("Auto: {}", reading(sensor)),
+                                    None if role == sysinfo::TempRole::Gpu => "Auto (nvidia-smi)".to_string(),
+                                    None => "Auto: none found".to_string(),
+                                };
+                                let selected = if key.is_empty() {
+                                    auto.clone()
+                                } else {
+                                    temps.iter().find(|sensor| sensor.key == *key).map_or(format!("{} (missing)", key), reading)
+                                };
+                                egui::ComboBox::from_id_salt(label)
+                                    .selected_text(selected)
+                                    .show_ui(ui, |ui| {
+                                        ui.selectable_value(key, String::new(), auto);
+                                        for sensor in temps {
+                                            ui.selectable_value(key, sensor.key.clone(), format!("{} ({})", sensor.name(), temperature.show(sensor.celsius)));
+                                        }
+                                    });
+                                ui.end_row();
+                            }
+
+                            ui.label("Pump:");
+                            let rpm = |fan: &sysinfo::FanSensor| format!("{} ({} RPM)", fan.name, fan.rpm);
+                            let auto = sysinfo::auto_pump(&readings.fans).map_or("Auto: none found".to_string(), |fan| format!("Auto: {}", rpm(fan)));
+                            let selected = if sensors.pump_sensor.is_empty() {
+                                auto.clone()
+                            } else {
+                                readings.fans.iter().find(|fan| fan.key == sensors.pump_sensor).map_or(format!("{} (missing)", sensors.pump_sensor), rpm)
+                            };
+                            egui::ComboBox::from_id_salt("sensor_pump")
+                                .selected_text(selected)
+                                .show_ui(ui, |ui| {
+                                    ui.selectable_value(&mut sensors.pump_sensor, String::new(), auto);
+                                    for fan in &readings.fans {
+                                        ui.selectable_value(&mut sensors.pump_sensor, fan.key.clone(), rpm(fan));
+                                    }
+                                });
+                            ui.end_row();
+
+                            ui.label("");
+                            ui.checkbox(&mut sensors.liquidctl, "Fall back to liquidctl")
+                                .on_hover_text("For AIOs without a kernel driver, read `liquidctl status` every 5 s. Needs liquidctl installed and access to the cooler's USB device.");
+                            ui.end_row();
+
+                            ui.label("Host:");
+                            ui.horizontal(|ui| {
+                                let host = sysinfo::host_info();
+                                let (days, hours, minutes) = (host.uptime / 86_400, host.uptime / 3600 % 24, host.uptime / 60 % 60);
+                                ui.label(format!("{} · {} · {} · up {}d {}h {}m", host.hostname, host.distro, host.kernel, days, hours, minutes));
+                                ui.checkbox(&mut sensors.send_host, "Send")
+                                    .on_hover_text("Add a `host` object with these to the payload, for telling machines apart on the display");
+                            });
+                            ui.end_row();
+
+                            ui.label("");
+                            ui.checkbox(&mut sensors.top_process, "Send the busiest process")
+                                .on_hover_text("Scan /proc each sample for the process using the most CPU, and GPU on drivers with per-client fdinfo (amdgpu, i915)");
+                            ui.end_row();
+
+                            ui.label("UPS (NUT):");
+                            ui.add(egui::TextEdit::singleline(&mut sensors.nut_ups).hint_text("myups@localhost").desired_width(220.0))
+                                .on_hover_text("Read with upsc every 5 s; empty reports the laptop battery, if any");
+                            ui.end_row();
+
+                            ui.label("Ping:");
+                            ui.add(egui::TextEdit::singleline(&mut sensors.ping_host).hint_text("1.1.1.1 or a game server").desired_width(220.0))
+                                .on_hover_text("Round trip time with the system ping every 2 s, sent as network.ping; empty leaves it out");
+                            ui.end_row();
+
+                            ui.label("MangoHud logs:");
+                            ui.horizontal(|ui| {
+                                ui.add(egui::TextEdit::singleline(&mut sensors.mangohud_dir).hint_text("off").desired_width(220.0))
+                                    .on_hover_text("MangoHud's output_folder; the newest log being written gives the game's FPS and frametime");
+                                if ui.button("📁").clicked()
+                                    && let Some(dir) = rfd::FileDialog::new().set_title("MangoHud output_folder").pick_folder()
+                                {
+                                    sensors.mangohud_dir = dir.display().to_string();
+                                }
+                            });
+                            ui.end_row();
+
+                            ui.label("Fans:");
+                            ui.vertical(|ui| {
+                                let fans = &readings.fans;
+                                if fans.is_empty() {
+                                    ui.weak("No fan sensors found, the board's Super I/O driver may not be loaded");
+                                } else {
+                                    ui.weak(if sensors.fans.is_empty() { "None ticked: every fan that spins is sent" } else { "Only the ticked fans are sent" });
+                                }
+                                for fan in fans {
+                                    let mut included = sensors.fans.contains(&fan.key);
+                                    let label = if fan.name == fan.key { format!("{} — {} RPM", fan.name, fan.rpm) } else { format!("{} ({}) — {} RPM", fan.name, fan.key, fan.rpm) };
+                                    if ui.checkbox(&mut included, label).changed() {
+                                        if included {
+                                            sensors.fans.push(fan.key.clone());
+                                        } else {
+                                            sensors.fans.retain(|key| *key != fan.key);
+                                        }
+                                    }
+                                }
+                            });
+                            ui.end_row();
+                        });
+
+                    // A half-typed command must never run, so commands take effect when their field loses focus
+                    let mut editing = false;
+                    let mut committed = false;
+                    ui.add_space(6.0);
+                    egui::CollapsingHeader::new(format!("Custom sensors ({})", sensors.custom.len())).id_salt("custom_sensors").show(ui, |ui| {
+                        ui.weak("Shell commands run with sh -c; the first number printed is the value");
+                        let mut remove = None;
+                        egui::Grid::new("custom_sensors_grid")
+                            .num_columns(6)
+                            .spacing([20.0, 8.0])
+                            .show(ui, |ui| {
+                                for (i, sensor) in sensors.custom.iter_mut().enumerate() {
+                                    ui.add(egui::TextEdit::singleline(&mut sensor.name).desired_width(100.0));
+                                    let command = ui.add(egui::TextEdit::singleline(&mut sensor.command).hint_text("curl -s http://router/temp").desired_width(240.0));
+                                    editing |= command.has_focus();
+                                    committed |= command.lost_focus();
+                                    egui::ComboBox::from_id_salt(("custom_sensor_field", i))
+                                        .selected_text(sensor.field.label())
+                                        .show_ui(ui, |ui| {
+                                            for field in custom_sensor::Field::ALL {
+                                                ui.selectable_value(&mut sensor.field, field, field.label());
+                                            }
+                                        });
+                                    ui.add(egui::DragValue::new(&mut sensor.interval_secs).range(1..=3600).suffix(" s"));
+                                    let reading = custom_sensor::reading(&sensor.command);
+                                    match (reading.value, &reading.error) {
+                                        (_, Some(error)) => ui.colored_label(Color32::from_rgb(220, 80, 80), "error").on_hover_text(error),
+                                        (Some(value), None) => ui.label(format!("{}", value)),
+                                        (None, None) => ui.weak("—"),
+                                    };
+                                    if ui.small_button("🗑").clicked() {
+                                        remove = Some(i);
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                        if let Some(i) = remove {
+                            sensors.custom.remove(i);
+                        }
+                        if ui.button("➕ Add custom sensor").clicked() {
+                            sensors.custom.push(Default::default());
+                        }
+                    });
+                    egui::CollapsingHeader::new("Script").id_salt("sensor_script").show(ui, |ui| {
+                        ui.weak("A rhai script run over each sample: it can change `info`, keep values in `state`, and set `media` or `profile` to switch the screen");
+                        ui.horizontal(|ui| {
+                            let path = ui.add(egui::TextEdit::singleline(&mut sensors.script).hint_text("off").desired_width(320.0));
+                            editing |= path.has_focus();
+                            committed |= path.lost_focus();
+                            if ui.button("📁").clicked()
+                                && let Some(file) = rfd::FileDialog::new().set_title("Sysinfo script").add_filter("rhai", &["rhai"]).pick_file()
+                            {
+                                sensors.script = file.display().to_string();
+                            }
+                        });
+                        if let Some(choice) = scripting::choice() {
+                            ui.label(format!("Script picked {}", choice.describe()));
+                        }
+                    });
+                    egui::CollapsingHeader::new("Smoothing").id_salt("sensor_smoothing").show(ui, |ui| {
+                        ui.weak("Averages each value over recent samples before it's sent");
+                        egui::Grid::new("smoothing_grid")
+                            .num_columns(3)
+                            .spacing([20.0, 8.0])
+                            .show(ui, |ui| {
+                                for metric in smoothing::Metric::ALL {
+                                    let mut setting = sensors.smoothing.get(&metric).copied().unwrap_or_default();
+                                    ui.label(metric.label());
+                                    egui::ComboBox::from_id_salt(("smoothing_method", metric))
+                                        .selected_text(setting.method.label())
+                                        .show_ui(ui, |ui| {
+                                            for method in smoothing::Method::ALL {
+                                                ui.selectable_value(&mut setting.method, method, method.label());
+                                            }
+                                        });
+                                    ui.add_enabled(
+                                        setting.method != smoothing::Method::Off,
+                                        egui::DragValue::new(&mut setting.window).range(2..=60).suffix(" samples"),
+                                    );
+                                    ui.end_row();
+                                    if setting.method == smoothing::Method::Off {
+                                        sensors.smoothing.remove(&metric);
+                                    } else {
+                                        sensors.smoothing.insert(metric, setting);
+                                    }
+                                }
+                            });
+                    });
+                    if (*sensors != before && !editing) || committed {
+                        sysinfo::configure(sensors);
+                    }
+
+                    if let Some(info) = &self.last_sysinfo {
+                        ui.add_space(6.0);
+                        egui::CollapsingHeader::new("Last sent").id_salt("sensors_last_sent").show(ui, |ui| {
+                            // Already in these units, as sent
+                            let units = sensors.units;
+                            let degrees = units.temperature.label();
+                            let cpu = &info.cpu;
+                            ui.label(format!(
+                                "CPU {}% at {} MHz, {}{}{}",
+                                cpu.load,
+                                cpu.speedAverage,
+                                cpu.temperature,
+                                degrees,
+                                cpu.hottest_core().map_or(String::new(), |(label, temp)| format!(", hottest {} {}{}", label, temp, degrees))
+                            ));
+                            ui.label(format!(
+                                "Memory {} / {} {}, network ↑{} ↓{} {}{}",
+                                info.memory.used,
+                                info.memory.total,
+                                units.memory.label(),
+                                info.network.upload,
+                                info.network.download,
+                                units.rate.label(),
+                                info.network.ping.map_or(String::new(), |ms| format!(", ping {} ms", ms))
+                            ));
+                            ui.horizontal_wrapped(|ui| {
+                                for (i, usage) in cpu.core_usage.iter().enumerate() {
+                                    ui.add(egui::ProgressBar::new(*usage as f32 / 100.0).desired_width(48.0).text(format!("{} {}%", i, usage)));
+                                }
+                            });
+                            if !cpu.core_temperatures.is_empty() {
+                                let temps: Vec<String> = cpu.core_temperatures.iter().map(|(label, temp)| format!("{} {}{}", label, temp, degrees)).collect();
+                                ui.weak(temps.join("  "));
+                            }
+                            if let Some(liquid) = &info.liquid {
+                                ui.label(format!("Coolant {}{}, pump {} RPM", liquid.temperature, degrees, liquid.pump));
+                            }
+                            if let Some(top) = &info.top {
+                                for (kind, usage) in [("CPU", &top.cpu), ("GPU", &top.gpu)] {
+                                    if let Some(usage) = usage {
+                                        ui.label(format!("Top {}: {} ({}) {}%", kind, usage.name, usage.pid, usage.usage));
+                                    }
+                                }
+                            }
+                            if let Some(power) = &info.power {
+                                let state = if !power.ac { "on battery" } else if power.charging { "charging" } else { "on mains" };
+                                let runtime = power.runtime.map_or(String::new(), |secs| format!(", {} min left", secs / 60));
+                                ui.label(format!("Power ({}): {}%, {}{}", power.source, power.charge, state, runtime));
+                            }
+                            if let Some(game) = &info.game {
+                                ui.label(format!("{}: {} FPS, {:.1} ms", game.name, game.fps, game.frametime));
+                            }
+                        });
+                    }
+                });
+
+                ui.add_space(10.0);
+
+                egui::CollapsingHeader::new("ℹ Device Info")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            if ui
+                                .add_enabled(!self.device_info.running(), egui::Button::new("🔄 Query device"))
+                                .on_hover_text("getprop, wm size/density and the display app versions over ADB")
+                                .clicked()
+                            {
+                                self.start_device_info();
+                            }
+                            if self.device_info.running() {
+                                ui.spinner();
+                            }
+                            if ui.button("📋 Copy for bug report").clicked() {
+                                let report = self.device_info.report(self.capability_store.last_version.as_deref(), self.handshake.as_ref());
+                                ui.ctx().copy_text(report);
+                            }
+                        });
+                        if let Some(error) = &self.device_info.error {
+                            ui.colored_label(Color32::from_rgb(220, 80, 80), error);
+                        }
+
+                        egui::Grid::new("device_info_grid")
+                            .num_columns(2)
+                            .spacing([20.0, 8.0])
+                            .striped(true)
+                            .show(ui, |ui| {
+                                match &self.device_info.android {
+                                    Some(android) => {
+                                        for (label, value) in &android.properties {
+                                            ui.label(format!("{}:", label));
+                                            ui.monospace(value);
+                                            ui.end_row();
+                                        }
+                                        if let Some([width, height]) = android.resolution {
+                                            ui.label("Display:");
+                                            ui.monospace(format!("{}x{}{}", width, height, android.density.map_or(String::new(), |dpi| format!(" @ {} dpi", dpi))));
+                                            ui.end_row();
+                                        }
+                                        for app in &android.apps {
+                                            ui.label("App:");
+                                            ui.monospace(format!("{} {}", app.package, app.version()));
+                                            ui.end_row();
+                                        }
+                                    }
+                                    None => {
+                                        ui.label("Android:");
+                                        ui.weak("not queried yet");
+                                        ui.end_row();
+                                    }
+                                }
+
+                                ui.label("Firmware:");
+                                ui.monospace(self.capability_store.last_version.as_deref().unwrap_or("unknown (no handshake seen yet)"));
+                                ui.end_row();
+                            });
+                        if let Some(handshake) = &self.handshake {
+                            egui::CollapsingHeader::new("Handshake").id_salt("device_info_handshake").show(ui, |ui| {
+                                ui.monospace(serde_json::to_string_pretty(handshake).unwrap_or_default());
+                            });
+                        }
+                    });
+
+                ui.add_space(10.0);
+
+                egui::CollapsingHeader::new("🎛 Device Control")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.checkbox(&mut self.screen_config.experimental_commands, "⚠ Experimental screen commands")
+                            .on_hover_text("Screen power and brightness over serial. Their keys come from the APK's dispatch table and haven't been confirmed on a cooler.");
+                        if self.screen_config.experimental_commands {
+                            ui.horizontal(|ui| {
+                                ui.label("Screen:");
+                                let idle = self.heartbeat_running() || !self.worker_running();
+                                if ui.add_enabled(idle, egui::Button::new("☀ On")).clicked() {
+                                    self.set_screen_power(true);
+                                }
+                                if ui
+                                    .add_enabled(idle, egui::Button::new("🌑 Off"))
+                                    .on_hover_text("Blanks the panel, the cooler keeps running. Sent over serial.")
+                                    .clicked()
+                                {
+                                    self.set_screen_power(false);
+                                }
+                            });
+                        }
+                        ui.horizontal(|ui| {
+                            let idle = !self.device_control.running();
+                            if self.device_control.confirm_reboot {
+                                ui.colored_label(Color32::from_rgb(255, 165, 0), "Reboot the cooler's Android module?");
+                                if ui.button("Reboot").clicked() {
+                                    self.reboot_device();
+                                }
+                                if ui.button("Cancel").clicked() {
+                                    self.device_control.confirm_reboot = false;
+                                }
+                            } else if ui
+                                .add_enabled(idle, egui::Button::new("⏻ Reboot cooler"))
+                                .on_hover_text("Through adb's reboot service. The display comes back with the last applied image once the device reconnects")
+                                .clicked()
+                            {
+                                self.device_control.confirm_reboot = true;
+                            }
+                            if !idle {
+                                ui.spinner();
+                            }
+                        });
+                    });
+
+                ui.add_space(10.0);
+
+                egui::CollapsingHeader::new("🛠 Maintenance")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        ui.label("When the display hangs");
+                        ui.horizontal(|ui| {
+                            let idle = !self.device_control.running();
+                            if ui
+                                .add_enabled(idle, egui::Button::new("🔄 Restart display app"))
+                                .on_hover_text("am force-stop the vendor's apps, then launch the display app again")
+                                .clicked()
+                            {
+                                self.restart_display_app();
+                            }
+                            if !idle {
+                                ui.spinner();
+                            }
+                        });
+
+                        ui.add_space(6.0);
+                        ui.label("Clock");
+                        if ui
+                            .add_enabled(!self.device_control.running(), egui::Button::new("🕒 Sync clock with this PC"))
+                            .on_hover_text("Sets the cooler's time and timezone over ADB, needs a rooted adbd or su on the device")
+                            .clicked()
+                        {
+                            self.sync_device_clock();
+                        }
+
+                        ui.add_space(6.0);
+                        ui.label("Display app update");
+                        ui.horizontal(|ui| {
+                            let running = self.app_update.running();
+                            if ui
+                                .add_enabled(!running, egui::Button::new("📦 Pick APK..."))
+                                .on_hover_text("An update for the cooler's display service, checked against the installed version")
+                                .clicked()
+                                && let Some(path) = rfd::FileDialog::new().add_filter("Android package", &["apk"]).pick_file()
+                            {
+                                self.inspect_apk(path);
+                            }
+                            if running {
+                                ui.spinner();
+                            }
+                        });
+                        if let Some(error) = &self.app_update.error {
+                            ui.colored_label(Color32::from_rgb(220, 80, 80), error);
+                        }
+                        if let Some(plan) = self.app_update.plan.clone() {
+                            egui::Grid::new("app_update_grid")
+                                .num_columns(2)
+                                .spacing([20.0, 8.0])
+                                .show(ui, |ui| {
+                                    ui.label("File:");
+                                    ui.monospace(plan.path.display().to_string());
+                                    ui.end_row();
+
+                                    ui.label("Package:");
+                                    ui.monospace(&plan.apk.package);
+                                    ui.end_row();
+
+                                    ui.label("Installed:");
+                                    ui.label(plan.installed.as_ref().map_or("not installed".to_string(), |installed| installed.version()));
+                                    ui.end_row();
+
+                                    ui.label("New:");
+                                    ui.label(plan.apk.version());
+                                    ui.end_row();
+                                });
+                            if plan.installed.is_none() {
+                                ui.colored_label(Color32::from_rgb(255, 165, 0), "This package isn't on the cooler, check it's the display app");
+                            } else if plan.is_reinstall() {
+                                ui.weak("Same version as installed, it will be reinstalled");
+                            } else if plan.is_downgrade() {
+                                ui.checkbox(&mut self.app_update.allow_downgrade, "Allow installing an older version")
+                                    .on_hover_text("pm install -d, keeps the app's data");
+                            }
+                            let ready = !self.app_update.running() && (!plan.is_downgrade() || self.app_update.allow_downgrade);
+                            if ui.add_enabled(ready, egui::Button::new("⬆ Install on device")).clicked() {
+                                self.install_apk();
+                            }
+                        }
+                    });
+
+                ui.add_space(10.0);
+
+                egui::CollapsingHeader::new("🔧 Debug")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        let stats = link_stats::stats().snapshot();
+                        ui.label("Serial link (current session)");
+                        egui::Grid::new("link_stats_grid")
+                            .num_columns(2)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.label("Frames sent / received:");
+                                ui.monospace(format!("{} / {}", stats.frames_sent, stats.frames_received));
+                                ui.end_row();
+
+                                ui.label("Bytes sent / received:");
+                                ui.monospace(format!("{} / {}", stats.bytes_sent, stats.bytes_received));
+                                ui.end_row();
+
+                                ui.label("CRC errors:");
+                                ui.monospace(stats.crc_errors.to_string());
+                                ui.end_row();
+
+                                ui.label("Malformed frames:");
+                                ui.monospace(stats.malformed_frames.to_string());
+                                ui.end_row();
+
+                                ui.label("Resyncs:");
+                                ui.monospace(format!("{} ({} bytes skipped)", stats.resyncs, stats.discarded_bytes));
+                                ui.end_row();
+
+                                ui.label("Retransmissions:");
+                                ui.monospace(stats.retransmissions.to_string());
+                                ui.end_row();
+
+                                ui.label("Avg round-trip:");
+                                ui.monospace(match stats.avg_rtt_ms {
+                                    Some(ms) => format!("{:.1} ms", ms),
+                                    None => "—".to_string(),
+                                });
+                                ui.end_row();
+
+                                ui.label("Session length:");
+                                ui.monospace(format!("{:.0} s", stats.session_secs));
+                                ui.end_row();
+                            });
+
+                        if let Some(rate) = stats.corruption_rate()
+                            && rate > 0.01
+                        {
+                            ui.colored_label(
+                                Color32::from_rgb(255, 165, 0),
+                                format!(
+                                    "⚠ {:.1}% of received frames were corrupt, try another cable or USB port (avoid hubs)",
+                                    rate * 100.0
+                                ),
+                            );
+                        }
+
+                        let protocol = link_stats::stats().protocol();
+                        egui::CollapsingHeader::new("Per-command latency")
+                            .default_open(false)
+                            .show(ui, |ui| {
+                                ui.monospace(format!(
+                                    "Throughput: {:.0} B/s out, {:.0} B/s in",
+                                    protocol.tx_bytes_per_sec, protocol.rx_bytes_per_sec
+                                ));
+                                let ms = |value: Option<f64>| value.map_or("—".to_string(), |ms| format!("{:.1}", ms));
+                                egui::Grid::new("protocol_stats_grid")
+                                    .num_columns(7)
+                                    .striped(true)
+                                    .show(ui, |ui| {
+                                        for heading in ["Command", "Sent", "Bytes", "Answered", "Retries", "Failed", "RTT avg / min / max (ms)"] {
+                                            ui.strong(heading);
+                                        }
+                                        ui.end_row();
+
+                                        for (cmd_type, command) in &protocol.commands {
+                                            ui.monospace(cmd_type);
+                                            ui.monospace(command.sent.to_string());
+                                            ui.monospace(command.bytes.to_string());
+                                            ui.monospace(command.answered.to_string());
+                                            ui.monospace(command.retries.to_string());
+                                            ui.monospace(command.failed.to_string());
+                                            ui.monospace(format!(
+                                                "{} / {} / {}",
+                                                ms(command.avg_rtt_ms()),
+                                                ms(command.rtt_min_ms),
+                                                ms(command.rtt_max_ms)
+                                            ));
+                                            ui.end_row();
+                                        }
+                                    });
+                            });
+
+                        ui.add_space(6.0);
+                        ui.label("Retransmission and pacing");
+                        let reliability = &mut self.screen_config.reliability;
+                        let writer = &mut self.screen_config.writer;
+                        let compression = &mut self.screen_config.compression;
+                        egui::Grid::new("reliability_grid")
+                            .num_columns(2)
+                            .spacing([20.0, 8.0])
+                            .show(ui, |ui| {
+                                ui.label("ACK timeout:");
+                                ui.add(egui::Slider::new(&mut reliability.ack_timeout_ms, 100..=5000).suffix(" ms"));
+                                ui.end_row();
+
+                                ui.label("Max retransmits:");
+                                ui.add(egui::Slider::new(&mut reliability.max_retransmits, 0..=10));
+                                ui.end_row();
+
+                                ui.label("Min command gap:");
+                                ui.add(egui::Slider::new(&mut writer.min_gap_ms, 0..=500).suffix(" ms"));
+                                ui.end_row();
+
+                                ui.label("Compress bodies:");
+                                ui.checkbox(&mut compression.enabled, "zlib")
+                                    .on_hover_text("Experimental. Falls back to plain bodies for the session if the device rejects one.");
+                                ui.end_row();
+
+                                ui.label("Compress from:");
+                                ui.add_enabled(
+                                    compression.enabled,
+                                    egui::Slider::new(&mut compression.min_size, 256..=65_536).logarithmic(true).suffix(" bytes"),
+                                );
+                                ui.end_row();
+                            });
+
+                        ui.add_space(6.0);
+                        ui.label("Protocol sniffer");
+                        match sniffer::status() {
+                            Some((path, records)) => {
+                                ui.horizontal(|ui| {
+                                    if ui.button("⏹ Stop capture").clicked() {
+                                        sniffer::stop();
+                                    }
+                                    ui.weak(format!("{} records", records));
+                                });
+                                ui.horizontal(|ui| {
+                                    ui.monospace(path.display().to_string());
+                                    if ui.button("📋").on_hover_text("Copy path").clicked() {
+                                        ui.ctx().copy_text(path.display().to_string());
+                                    }
+                                });
+                            }
+                            None => {
+                                if ui
+                                    .button("⏺ Start capture")
+                                    .on_hover_text("Record every byte sent and received to a .jsonl file")
+                                    .clicked()
+                                    && let Err(e) = sniffer::start()
+                                {
+                                    log::error!("Failed to start capture: {:#}", e);
+                                }
+                            }
+                        }
+
+                        ui.horizontal(|ui| {
+                            if ui
+                                .add_enabled(!self.is_busy(), egui::Button::new("▶ Replay capture..."))
+                                .on_hover_text("Re-send the TX frames of a capture with their original timing")
+                                .clicked()
+                                && let Some(path) = rfd::FileDialog::new()
+                                    .add_filter("Captures", &["jsonl", "txt", "hex"])
+                                    .set_directory(paths::state_dir().join("captures"))
+                                    .pick_file()
+                            {
+                                self.start_replay(path);
+                            }
+                            ui.add(egui::Slider::new(&mut self.replay_speed, 0.25..=4.0).suffix("×").text("speed"));
+                        });
+
+                        ui.add_space(6.0);
+                        ui.label("Device simulator");
+                        ui.horizontal(|ui| match &self.simulator {
+                            Some(simulator) => {
+                                let path = simulator.path().display().to_string();
+                                if ui.add_enabled(!self.is_busy(), egui::Button::new("⏹ Stop simulator")).clicked() {
+                                    self.stop_simulator();
+                                }
+                                ui.monospace(path);
+                            }
+                            None => {
+                                if ui
+                                    .add_enabled(!self.is_busy(), egui::Button::new("🖥 Start simulator"))
+                                    .on_hover_text("Fake cooler on a pseudo-terminal, the serial device is switched to it")
+                                    .clicked()
+                                {
+                                    self.start_simulator();
+                                }
+                            }
+                        });
+
+                        ui.add_space(6.0);
+                        egui::CollapsingHeader::new("Protocol console")
+                            .default_open(false)
+                            .show(ui, |ui| {
+                                let console = &mut self.console;
+                                ui.horizontal(|ui| {
+                                    ui.selectable_value(&mut console.mode, ConsoleMode::Json, "cmdType + JSON");
+                                    ui.selectable_value(&mut console.mode, ConsoleMode::Hex, "Raw hex");
+                                });
+                                match console.mode {
+                                    ConsoleMode::Json => {
+                                        egui::Grid::new("console_grid")
+                                            .num_columns(2)
+                                            .spacing([20.0, 8.0])
+                                            .show(ui, |ui| {
+                                                ui.label("Method:");
+                                                ui.horizontal(|ui| {
+                                                    for method in ["POST", "GET", "STATE"] {
+                                                        ui.selectable_value(&mut console.method, method.to_string(), method);
+                                                    }
+                                                });
+                                                ui.end_row();
+
+                                                ui.label("cmdType:");
+                                                ui.text_edit_singleline(&mut console.cmd_type);
+                                                ui.end_row();
+                                            });
+                                        ui.add(
+                                            egui::TextEdit::multiline(&mut console.body)
+                                                .code_editor()
+                                                .desired_rows(4)
+                                                .desired_width(f32::INFINITY),
+                                        );
+                                    }
+                                    ConsoleMode::Hex => {
+                                        ui.add(
+                                            egui::TextEdit::multiline(&mut console.hex)
+                                                .code_editor()
+                                                .hint_text("POST foo 1\\r\\n... as hex, e.g. 50 4f 53 54 20 ...")
+                                                .desired_rows(4)
+                                                .desired_width(f32::INFINITY),
+                                        );
+                                        ui.checkbox(&mut console.framed, "Already framed (send verbatim)")
+                                            .on_hover_text("Otherwise the bytes are escaped and wrapped in a frame");
+                                    }
+                                }
+                                ui.horizontal(|ui| {
+                                    ui.add(egui::Slider::new(&mut console.listen_ms, 200..=10_000).suffix(" ms").text("listen"));
+                                    if !console.history.is_empty() && ui.button("Clear").clicked() {
+                                        console.history.clear();
+                                    }
+                                });
+                                if ui
+                                    .add_enabled(!self.worker_running(), egui::Button::new("📤 Send"))
+                                    .on_hover_text("Opens the port, so it can't run alongside a transfer or heartbeat")
+                                    .clicked()
+                                {
+                                    self.start_console_send();
+                                }
+
+                                for (i, exchange) in self.console.history.iter().enumerate() {
+                                    let title = match &exchange.error {
+                                        Some(_) => format!("{} — failed", exchange.request),
+                                        None => format!("{} — {} repl{}", exchange.request, exchange.replies.len(), if exchange.replies.len() == 1 { "y" } else { "ies" }),
+                                    };
+                                    egui::CollapsingHeader::new(title)
+                                        .id_salt(("console_exchange", i, exchange.sent.len()))
+                                        .default_open(i == 0)
+                                        .show(ui, |ui| {
+                                            ui.weak("Sent:");
+                                            ui.monospace(console::hex_dump(&exchange.sent));
+                                            if let Some(error) = &exchange.error {
+                                                ui.colored_label(Color32::from_rgb(220, 80, 80), error);
+                                            }
+                                            for reply in &exchange.replies {
+                                                ui.separator();
+                                                ui.monospace(console::describe(reply));
+                                            }
+                                        });
+                                }
+                            });
+
+                        ui.add_space(6.0);
+                        egui::CollapsingHeader::new("ADB shell")
+                            .default_open(false)
+                            .show(ui, |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.weak("Runs each command with the cooler's sh, without stdin: interactive programs don't work");
+                                    if !self.shell.entries.is_empty() && ui.button("Clear").clicked() {
+                                        self.shell.entries.clear();
+                                    }
+                                });
+                                egui::ScrollArea::vertical()
+                                    .id_salt("adb_shell_output")
+                                    .max_height(300.0)
+                                    .stick_to_bottom(true)
+                                    .show(ui, |ui| {
+                                        for entry in &self.shell.entries {
+                                            ui.monospace(format!("{} $ {}", entry.cwd, entry.command));
+                                            if !entry.output.is_empty() {
+                                                ui.add(egui::Label::new(egui::RichText::new(&entry.output).monospace().weak()).selectable(true));
+                                            }
+                                            match &entry.status {
+                                                Some(Ok(0)) | None => {}
+                                                Some(Ok(code)) => {
+                                                    ui.colored_label(Color32::from_rgb(220, 160, 60), format!("exit status {}", code));
+                                                }
+                                                Some(Err(e)) => {
+                                                    ui.colored_label(Color32::from_rgb(220, 80, 80), e);
+                                                }
+                                            }
+                                        }
+                                    });
+                                ui.horizontal(|ui| {
+                                    ui.monospace(format!("{} $", self.shell.cwd));
+                                    let running = self.shell.running();
+                                    let response = ui.add_enabled(
+                                        !running,
+                                        egui::TextEdit::singleline(&mut self.shell.input)
+                                            .code_editor()
+                                            .hint_text("ls -l /sdcard")
+                                            .desired_width(f32::INFINITY),
+                                    );
+                                    if response.has_focus() {
+                                        if ui.input(|i| i.key_pressed(egui::Key::ArrowUp)) {
+                                            self.shell.browse_history(true);
+                                        } else if ui.input(|i| i.key_pressed(egui::Key::ArrowDown)) {
+                                            self.shell.browse_history(false);
+                                        }
+                                    }
+                                    if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                                        self.start_shell_command();
+                                        response.request_focus();
+                                    }
+                                });
+                                if self.shell.running() {
+                                    ui.horizontal(|ui| {
+                                        ui.spinner();
+                                        if ui.button("⏹ Stop").clicked() {
+                                            self.shell.cancel.store(true, std::sync::atomic::Ordering::Relaxed);
+                                        }
+                                    });
+                                }
+                            });
+
+                        ui.add_space(6.0);
+                        egui::CollapsingHeader::new("Logcat")
+                            .default_open(false)
+                            .show(ui, |ui| {
+                                ui.horizontal(|ui| {
+                                    if self.logcat.running() {
+                                        ui.spinner();
+                                        if ui.button("⏹ Stop").clicked() {
+                                            self.logcat.stop();
+                                        }
+                                    } else if ui.button("▶ Start").clicked() {
+                                        self.start_logcat();
+                                    }
+                                    if !self.logcat.lines.is_empty() && ui.button("Clear").clicked() {
+                                        self.logcat.lines.clear();
+                                    }
+                                    ui.checkbox(&mut self.logcat.app_only, "Display app only");
+                                    egui::ComboBox::from_id_salt("logcat_level")
+                                        .selected_text(
+                                            logcat::LEVELS.iter().find(|(c, _)| *c == self.logcat.min_level).map_or("Verbose", |(_, name)| name),
+                                        )
+                                        .show_ui(ui, |ui| {
+                                            for (level, name) in logcat::LEVELS {
+                                                ui.selectable_value(&mut self.logcat.min_level, level, name);
+                                            }
+                                        });
+                                    ui.add(egui::TextEdit::singleline(&mut self.logcat.filter).hint_text("Filter").desired_width(160.0));
+                                });
+                                if let Some(error) = &self.logcat.error {
+                                    ui.colored_label(Color32::from_rgb(220, 80, 80), error);
+                                }
+                                let visible = self.logcat.visible();
+                                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                                egui::ScrollArea::both()
+                                    .id_salt("logcat_output")
+                                    .max_height(300.0)
+                                    .stick_to_bottom(true)
+                                    .show_rows(ui, row_height, visible.len(), |ui, rows| {
+                                        for line in &visible[rows] {
+                                            let text = format!("{} {:>5} {} {}: {}", line.time, line.pid, line.level, line.tag, line.message);
+                                            let text = egui::RichText::new(text).monospace();
+                                            let text = match line.level {
+                                                'E' | 'F' => text.color(Color32::from_rgb(220, 80, 80)),
+                                                'W' => text.color(Color32::from_rgb(220, 160, 60)),
+                                                'I' => text,
+                                                _ => text.weak(),
+                                            };
+                                            ui.add(egui::Label::new(text).selectable(true).extend());
+                                        }
+                                    });
+                                ui.weak(format!("{} of {} lines", visible.len(), self.logcat.lines.len()));
+                            });
+                    });
+
+                if let Some(report) = &self.last_report {
+                    ui.add_space(10.0);
+                    egui::CollapsingHeader::new(format!("⏱ Last transfer ({:.2}s)", report.total().as_secs_f32()))
+                        .default_open(false)
+                        .show(ui, |ui| {
+                            egui::Grid::new("transfer_timings_grid")
+                                .num_columns(2)
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    for stage in &report.stages {
+                                        ui.label(&stage.name);
+                                        ui.monospace(format!("{:.1} ms", stage.elapsed.as_secs_f64() * 1000.0));
+                                        ui.end_row();
+                                    }
+                                });
+                        });
+                }
+
                 ui.add_space(20.0);
 
                 // Transfer Button
@@ -260,12 +2407,39 @@ impl eframe::App for app_state::AioCoolerApp {
                     let button = egui::Button::new("🚀 Transfer Image to Cooler")
                         .min_size(egui::vec2(200.0, 40.0));
 
-                    let enabled = !self.is_processing && self.selected_image.is_some();
+                    let enabled = self.can_transfer();
 
                     if ui.add_enabled(enabled, button).clicked() {
                         self.start_transfer();
                     }
+
+                    if self.daemon.running() {
+                        if ui
+                            .add_enabled(!self.is_busy(), egui::Button::new("🔁 Apply Config").min_size(egui::vec2(120.0, 40.0)))
+                            .on_hover_text("Show the current screen configuration with the media the daemon already shows")
+                            .clicked()
+                        {
+                            self.apply_via_daemon();
+                        }
+                        ui.label("🔌 The daemon holds the port, transfers go through it");
+                    }
                 });
+
+                match &self.config_mismatches {
+                    Some(mismatches) if mismatches.is_empty() => {
+                        ui.colored_label(Color32::from_rgb(80, 200, 120), "✔ Device config verified");
+                    }
+                    Some(mismatches) => {
+                        ui.colored_label(
+                            Color32::from_rgb(255, 55, 102),
+                            "⚠ The device ignored some settings:",
+                        );
+                        for mismatch in mismatches {
+                            ui.label(format!("  • {}", mismatch));
+                        }
+                    }
+                    None => {}
+                }
             });
         });
     }
@@ -276,8 +2450,21 @@ impl eframe::App for app_state::AioCoolerApp {
 // ============================================================================
 
 fn main() -> eframe::Result {
-    
-    egui_logger::builder().max_level(log::LevelFilter::Info).init().unwrap();
+    let mut cli = <cli::Cli as clap::Parser>::parse();
+    if let Some(command) = cli.take_command() {
+        std::process::exit(cli::run(command));
+    }
+    let instance = match instance::claim_gui(cli.take_over) {
+        Ok(Some(lock)) => Some(lock),
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            eprintln!("{:#}; starting without the single instance guard", e);
+            None
+        }
+    };
+
+    log_file::install(egui_logger::builder().build(), log::LevelFilter::Info).unwrap();
+    diagnostics::install_panic_hook();
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -289,9 +2476,24 @@ fn main() -> eframe::Result {
     eframe::run_native(
         "Tryx Panorama Display Controller",
         options,
-        Box::new(|cc| {
+        Box::new(move |cc| {
             egui_extras::install_image_loaders(&cc.egui_ctx);
-            Ok(Box::new(app_state::AioCoolerApp::default()))
+            let mut app = app_state::AioCoolerApp::default();
+            log_file::open("gui", &app.screen_config.log_file);
+            if let Some(lock) = &instance {
+                let ctx = cc.egui_ctx.clone();
+                lock.on_command(move || ctx.request_repaint());
+            }
+            app.instance = instance;
+            app.start_device_watcher(cc.egui_ctx.clone());
+            if cli.start_minimized {
+                cc.egui_ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true));
+                app.start_if_present();
+            }
+            if env_check::first_run() {
+                app.start_setup_check();
+            }
+            Ok(Box::new(app))
         }),
     )
 }
@@ -0,0 +1,69 @@
+// MangoHud frame logs: FPS and frametime of the running game, read from the
+// newest CSV MangoHud is writing into its `output_folder`
+
+use std::{
+    fs::{self, File},
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::sysinfo::GameInfo;
+
+/// A log not written to for this long belongs to a game that has exited
+const STALE_AFTER: Duration = Duration::from_secs(3);
+/// Bytes read from the start for the column names and from the end for the last row
+const CHUNK: u64 = 8192;
+
+/// The game currently logging to `dir`, None when nothing is
+pub fn read(dir: &Path) -> Option<GameInfo> {
+    let path = newest_log(dir)?;
+    let mut file = File::open(&path).ok()?;
+    let len = file.metadata().ok()?.len();
+
+    // System info lines, a `---FRAME METRICS---` line, then `fps,frametime,cpu_load,…`
+    let head = read_at(&mut file, 0, CHUNK.min(len))?;
+    let columns: Vec<&str> = head.lines().find(|line| line.starts_with("fps,"))?.split(',').map(str::trim).collect();
+
+    let tail = read_at(&mut file, len.saturating_sub(CHUNK), len.min(CHUNK))?;
+    // The last line may be half written
+    let complete = &tail[..tail.rfind('\n')?];
+    let row: Vec<&str> = complete.lines().last()?.split(',').collect();
+    let value = |name: &str| columns.iter().position(|column| *column == name).and_then(|i| row.get(i)?.trim().parse::<f64>().ok());
+
+    let fps = value("fps")?;
+    let frametime = value("frametime").unwrap_or(if fps > 0.0 { 1000.0 / fps } else { 0.0 });
+    Some(GameInfo { name: game_name(&path), fps: fps.round() as u32, frametime: frametime as f32 })
+}
+
+/// The most recently written frame log, if it's still being written
+fn newest_log(dir: &Path) -> Option<PathBuf> {
+    let (modified, path) = fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            name.ends_with(".csv") && !name.ends_with("_summary.csv")
+        })
+        .filter_map(|path| Some((path.metadata().ok()?.modified().ok()?, path)))
+        .max_by_key(|(modified, _)| *modified)?;
+    let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+    (age < STALE_AFTER).then_some(path)
+}
+
+fn read_at(file: &mut File, offset: u64, len: u64) -> Option<String> {
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    let mut bytes = Vec::with_capacity(len as usize);
+    file.take(len).read_to_end(&mut bytes).ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// `<program>_<YYYY-MM-DD>_<HH-MM-SS>.csv` → `<program>`
+fn game_name(path: &Path) -> String {
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+    match stem.rsplitn(3, '_').nth(2) {
+        Some(program) if !program.is_empty() => program.to_string(),
+        _ => stem.to_string(),
+    }
+}
@@ -0,0 +1,234 @@
+// On-device media browser: what is actually stored in /sdcard/pcMedia, with
+// size, date and a thumbnail pulled over ADB, and removing or downloading files
+// picked from it
+
+use std::{collections::BTreeSet, io::Write, path::{Path, PathBuf}, sync::Arc};
+use anyhow::{Context, Result};
+
+use crate::adb::{shell_quote, AdbClient, AdbConfig};
+use crate::screen_setup::{media_path, MEDIA_DIR};
+
+/// Larger files get no thumbnail, pulling them would take longer than it's worth
+const MAX_THUMBNAIL_SIZE: u64 = 8 * 1024 * 1024;
+/// Files per `rm`, keeps the command line short
+const DELETE_BATCH: usize = 50;
+/// What the image loaders can show
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "bmp"];
+
+#[derive(Debug, Clone)]
+pub enum Thumbnail {
+    Pending,
+    /// Raw file bytes, decoded by the image loaders
+    Loaded(Arc<[u8]>),
+    /// Why there is none (video, too large, pull failed)
+    Unavailable(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct MediaFile {
+    pub name: String,
+    pub size: u64,
+    pub modified: Option<chrono::DateTime<chrono::Local>>,
+    pub thumbnail: Thumbnail,
+}
+
+impl MediaFile {
+    fn is_image(&self) -> bool {
+        let name = self.name.to_ascii_lowercase();
+        IMAGE_EXTENSIONS.iter().any(|ext| name.ends_with(&format!(".{}", ext)))
+    }
+
+    /// Cache key for the image loaders
+    pub fn uri(&self) -> String {
+        format!("bytes://pcMedia/{}", self.name)
+    }
+}
+
+#[derive(Default)]
+pub struct MediaBrowser {
+    /// Newest first
+    pub files: Vec<MediaFile>,
+    pub error: Option<String>,
+    pub listed_at: Option<chrono::DateTime<chrono::Local>>,
+    /// Names ticked for deletion
+    pub selected: BTreeSet<String>,
+    /// Delete was clicked, waiting for the confirmation
+    pub confirm_delete: bool,
+    /// Deletes, lists the directory, then pulls thumbnails one by one
+    pub worker: Option<std::thread::JoinHandle<()>>,
+}
+
+impl MediaBrowser {
+    pub fn running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|w| !w.is_finished())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+
+    /// Thumbnails already pulled, so a re-list doesn't fetch them again
+    pub fn loaded_thumbnails(&self) -> Vec<String> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.thumbnail, Thumbnail::Loaded(_)))
+            .map(|file| file.name.clone())
+            .collect()
+    }
+
+    pub fn set_listing(&mut self, result: Result<Vec<MediaFile>, String>) {
+        match result {
+            Ok(mut files) => {
+                for file in &mut files {
+                    if let Some(old) = self.files.iter().find(|old| old.name == file.name && matches!(old.thumbnail, Thumbnail::Loaded(_))) {
+                        file.thumbnail = old.thumbnail.clone();
+                    }
+                }
+                self.selected.retain(|name| files.iter().any(|file| &file.name == name));
+                self.files = files;
+                self.error = None;
+                self.listed_at = Some(chrono::Local::now());
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    pub fn set_thumbnail(&mut self, name: &str, thumbnail: Thumbnail) {
+        if let Some(file) = self.files.iter_mut().find(|file| file.name == name) {
+            file.thumbnail = thumbnail;
+        }
+    }
+}
+
+/// Regular files in the media directory, newest first
+pub fn list(adb: &AdbConfig) -> Result<Vec<MediaFile>> {
+    let mut client = AdbClient::connect(adb)?;
+    let mut files: Vec<MediaFile> = client
+        .list(MEDIA_DIR)?
+        .into_iter()
+        .filter(|entry| entry.is_file())
+        .map(|entry| {
+            let mut file = MediaFile {
+                name: entry.name,
+                size: entry.size,
+                modified: chrono::DateTime::from_timestamp(entry.mtime as i64, 0).map(|time| time.with_timezone(&chrono::Local)),
+                thumbnail: Thumbnail::Pending,
+            };
+            if !file.is_image() {
+                file.thumbnail = Thumbnail::Unavailable("not an image".to_string());
+            } else if file.size > MAX_THUMBNAIL_SIZE {
+                file.thumbnail = Thumbnail::Unavailable("too large to preview".to_string());
+            }
+            file
+        })
+        .collect();
+    files.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.name.cmp(&b.name)));
+    Ok(files)
+}
+
+/// `rm` the named files from the media directory. Whatever `rm` prints is
+/// logged; compare a fresh listing to see what is really gone.
+pub fn delete(adb: &AdbConfig, names: &[String]) -> Result<()> {
+    let mut client = AdbClient::connect(adb)?;
+    for batch in names.chunks(DELETE_BATCH) {
+        let paths: Vec<String> = batch.iter().map(|name| shell_quote(&media_path(name))).collect();
+        let output = client.shell(&format!("rm -f -- {}", paths.join(" ")))?;
+        if !output.trim().is_empty() {
+            log::warn!("rm: {}", output.trim());
+        }
+    }
+    Ok(())
+}
+
+/// Pull the named files into `dir`, never overwriting: a taken name gets a
+/// ` (1)`, ` (2)`… suffix. `progress` gets the file index, its name and the
+/// fraction of it done. Stops at the first failure, what was pulled stays.
+pub fn download(adb: &AdbConfig, names: &[String], dir: &Path, mut progress: impl FnMut(usize, &str, f32)) -> Result<Vec<PathBuf>> {
+    let mut client = AdbClient::connect(adb)?;
+    let mut saved = Vec::new();
+    for (i, name) in names.iter().enumerate() {
+        let remote = media_path(name);
+        let size = client.stat(&remote)?.with_context(|| format!("{} is no longer on the device", name))?.size;
+        let target = free_path(dir, name);
+        // Pull next to the target so a failed pull never leaves a truncated file under the real name
+        let partial = target.with_file_name(format!(".{}.part", name));
+        let result = (|| -> Result<()> {
+            let file = std::fs::File::create(&partial).with_context(|| format!("Failed to create {}", partial.display()))?;
+            let mut out = ProgressWriter {
+                inner: std::io::BufWriter::new(file),
+                written: 0,
+                on_write: |written: u64| progress(i, name, written as f32 / size.max(1) as f32),
+            };
+            client.pull(&remote, &mut out)?;
+            out.inner.flush()?;
+            std::fs::rename(&partial, &target).with_context(|| format!("Failed to move the download to {}", target.display()))
+        })();
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&partial);
+            return Err(e.context(format!("Failed to download {}", name)));
+        }
+        log::info!("Downloaded {} to {}", name, target.display());
+        saved.push(target);
+    }
+    Ok(saved)
+}
+
+/// `dir/name`, or with a counter before the extension if that exists
+fn free_path(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    if !path.exists() {
+        return path;
+    }
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (name, String::new()),
+    };
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Counts what goes through for a progress callback
+struct ProgressWriter<W, F> {
+    inner: W,
+    written: u64,
+    on_write: F,
+}
+
+impl<W: Write, F: FnMut(u64)> Write for ProgressWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        (self.on_write)(self.written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Pull `file` for its thumbnail, reconnecting so a push can get in between two pulls
+pub fn thumbnail(adb: &AdbConfig, file: &MediaFile) -> Thumbnail {
+    let mut bytes = Vec::with_capacity(file.size as usize);
+    match AdbClient::connect(adb).and_then(|mut client| client.pull(&media_path(&file.name), &mut bytes)) {
+        Ok(_) => Thumbnail::Loaded(bytes.into()),
+        Err(e) => {
+            log::warn!("No thumbnail for {}: {:#}", file.name, e);
+            Thumbnail::Unavailable(format!("{:#}", e))
+        }
+    }
+}
+
+/// "1.4 MiB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", size, UNITS[unit]) }
+}
@@ -0,0 +1,91 @@
+// Tracks which local files (by MD5) already live on the device under which name,
+// so re-selecting an image only needs a config command instead of a full push
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+use serde::{Deserialize, Serialize};
+use anyhow::{Context, Result};
+
+/// An MD5 already computed, valid while size and mtime stay the same
+struct KnownHash {
+    size: u64,
+    modified: Option<SystemTime>,
+    md5: String,
+}
+
+static HASHES: Mutex<Option<HashMap<PathBuf, KnownHash>>> = Mutex::new(None);
+
+/// MD5 of a local file, only read again once it changed. For checks that run
+/// often, such as the heartbeat's profile switches.
+pub fn file_md5(path: &Path) -> Result<String> {
+    let meta = std::fs::metadata(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let (size, modified) = (meta.len(), meta.modified().ok());
+    if let Some(known) = HASHES.lock().unwrap().as_ref().and_then(|hashes| hashes.get(path))
+        && known.size == size
+        && known.modified == modified
+    {
+        return Ok(known.md5.clone());
+    }
+    let md5 = crate::AioCoolerController::calculate_md5(&path.to_path_buf())?;
+    let known = KnownHash { size, modified, md5: md5.clone() };
+    HASHES.lock().unwrap().get_or_insert_with(HashMap::new).insert(path.to_path_buf(), known);
+    Ok(md5)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedMedia {
+    pub remote_name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MediaCache {
+    entries: HashMap<String, CachedMedia>,
+}
+
+impl MediaCache {
+    fn path() -> PathBuf {
+        crate::paths::state_dir().join("media_cache.json")
+    }
+
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn get(&self, md5: &str) -> Option<&CachedMedia> {
+        self.entries.get(md5)
+    }
+
+    pub fn insert(&mut self, md5: &str, remote_name: &str, size: u64) {
+        self.entries.insert(
+            md5.to_string(),
+            CachedMedia { remote_name: remote_name.to_string(), size },
+        );
+    }
+
+    pub fn remove(&mut self, md5: &str) {
+        self.entries.remove(md5);
+    }
+
+    /// `mediaDelete` wipes everything except the excluded files
+    pub fn retain_only(&mut self, remote_names: &[&str]) {
+        self.entries
+            .retain(|_, media| remote_names.contains(&media.remote_name.as_str()));
+    }
+}
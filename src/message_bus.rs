@@ -0,0 +1,129 @@
+// ============================================================================
+// Worker → GUI message channel
+// Progress and sysinfo samples are coalesced to the latest value, log lines go
+// into a bounded ring, everything else travels over a bounded channel that
+// applies backpressure.
+// ============================================================================
+
+use std::{collections::VecDeque, sync::{Arc, Mutex}};
+use crossbeam::channel::{self, SendError};
+
+use crate::app_state::AppMessage;
+
+/// Discrete events (state changes, results) waiting for the UI
+const EVENT_CAPACITY: usize = 64;
+/// Log lines kept between two UI frames, older ones are dropped
+const LOG_CAPACITY: usize = 256;
+
+#[derive(Default)]
+struct Coalesced {
+    progress: Option<(f32, String)>,
+    sysinfo: Option<Box<crate::sysinfo::SysInfo>>,
+    logs: VecDeque<String>,
+    dropped_logs: usize,
+}
+
+#[derive(Clone)]
+pub struct MessageSender {
+    events: channel::Sender<AppMessage>,
+    shared: Arc<Mutex<Coalesced>>,
+    /// Also gets progress and results, for watchers of the daemon
+    broadcast: Option<crate::broadcast::Broadcast>,
+}
+
+pub struct MessageReceiver {
+    events: channel::Receiver<AppMessage>,
+    shared: Arc<Mutex<Coalesced>>,
+}
+
+/// Everything that arrived since the last frame
+#[derive(Default)]
+pub struct MessageBatch {
+    pub progress: Option<(f32, String)>,
+    pub sysinfo: Option<Box<crate::sysinfo::SysInfo>>,
+    pub logs: VecDeque<String>,
+    pub dropped_logs: usize,
+    pub events: Vec<AppMessage>,
+}
+
+pub fn channel() -> (MessageSender, MessageReceiver) {
+    let (tx, rx) = channel::bounded(EVENT_CAPACITY);
+    let shared = Arc::new(Mutex::new(Coalesced::default()));
+    (
+        MessageSender { events: tx, shared: shared.clone(), broadcast: None },
+        MessageReceiver { events: rx, shared },
+    )
+}
+
+impl MessageSender {
+    /// Copy progress, results and applied media to `broadcast`. State and
+    /// sysinfo are left to the controller, which has its own.
+    pub fn with_broadcast(mut self, broadcast: crate::broadcast::Broadcast) -> Self {
+        self.broadcast = Some(broadcast);
+        self
+    }
+
+    /// Blocks only when the event queue is full and the UI hasn't caught up
+    pub fn send(&self, msg: AppMessage) -> Result<(), SendError<AppMessage>> {
+        if let Some(broadcast) = &self.broadcast {
+            use crate::broadcast::Status;
+            let status = match &msg {
+                AppMessage::Progress(progress, status) => Some(Status::Progress { progress: *progress, status: status.clone() }),
+                AppMessage::Success(message) => Some(Status::Done { ok: true, message: message.clone() }),
+                AppMessage::Error(message) | AppMessage::PortBusy(message) => Some(Status::Done { ok: false, message: message.clone() }),
+                AppMessage::Applied(last) => Some(Status::Applied { media: last.media.clone() }),
+                _ => None,
+            };
+            if let Some(status) = status {
+                broadcast.send(status);
+            }
+        }
+        match msg {
+            AppMessage::Progress(progress, status) => {
+                self.shared.lock().unwrap().progress = Some((progress, status));
+                Ok(())
+            }
+            AppMessage::SysInfo(info) => {
+                self.shared.lock().unwrap().sysinfo = Some(info);
+                Ok(())
+            }
+            AppMessage::Log(line) => {
+                crate::diagnostics::record_log(&line);
+                let mut shared = self.shared.lock().unwrap();
+                if shared.logs.len() >= LOG_CAPACITY {
+                    shared.logs.pop_front();
+                    shared.dropped_logs += 1;
+                }
+                shared.logs.push_back(line);
+                Ok(())
+            }
+            msg @ (AppMessage::Success(_) | AppMessage::Error(_) | AppMessage::PortBusy(_)) => {
+                // A stale progress value must not overwrite the final result
+                self.shared.lock().unwrap().progress = None;
+                self.events.send(msg)
+            }
+            other => self.events.send(other),
+        }
+    }
+}
+
+impl MessageReceiver {
+    /// Take what's pending without blocking. At most `max_events` discrete
+    /// events are returned so a flood can't stall a frame.
+    pub fn drain(&self, max_events: usize) -> MessageBatch {
+        let mut batch = MessageBatch::default();
+        {
+            let mut shared = self.shared.lock().unwrap();
+            batch.progress = shared.progress.take();
+            batch.sysinfo = shared.sysinfo.take();
+            batch.logs = std::mem::take(&mut shared.logs);
+            batch.dropped_logs = std::mem::take(&mut shared.dropped_logs);
+        }
+        batch.events.extend(self.events.try_iter().take(max_events));
+        batch
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.events.is_empty()
+    }
+}
@@ -0,0 +1,453 @@
+// ============================================================================
+// MQTT bridge
+// The daemon publishes the cooler's state and the sysinfo it sends, and takes
+// commands, so home automation can watch and drive the display. A small MQTT
+// 3.1.1 client over plain TCP, QoS 0 only, which is all this needs.
+//
+//   <topic>/status        online / offline (retained, offline is the will)
+//   <topic>/state         {"connection": ..., "media": ...} (retained)
+//   <topic>/sysinfo       the payload as sent to the device, as JSON
+//   <topic>/result        {"command": ..., "ok": ..., "message": ...}
+//   <topic>/set/image     path on this machine, or an http(s) URL to fetch
+//   <topic>/set/profile   name of a saved profile
+//   <topic>/set/screen    on / off
+//
+// Images and profiles go through the daemon's IPC socket like any other
+// client; screen power is queued for the running heartbeat. The settings live
+// in config.toml and a change there reconnects.
+// ============================================================================
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+use anyhow::{Context, Result};
+use crossbeam::channel::{self, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+
+use crate::broadcast::{Broadcast, Status};
+use crate::data::DeviceCommand;
+use crate::screen_setup::{LastApplied, ScreenConfig};
+
+/// Sent in CONNECT, the broker drops us after 1.5 times this without a packet
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a read waits, which also paces the bridge loop
+const POLL: Duration = Duration::from_millis(250);
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+/// For fetching `set/image` URLs
+const DOWNLOAD_TIMEOUT_SECS: u32 = 120;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xC0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    pub enabled: bool,
+    pub host: String,
+    pub port: u16,
+    /// Empty for brokers that allow anonymous clients
+    pub username: String,
+    pub password: String,
+    pub client_id: String,
+    /// Prefix of every topic
+    pub topic: String,
+    /// Least time between two sysinfo messages
+    pub publish_interval_secs: u32,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 1883,
+            username: String::new(),
+            password: String::new(),
+            client_id: "tryx-panorama".to_string(),
+            topic: "tryx-panorama".to_string(),
+            publish_interval_secs: 5,
+        }
+    }
+}
+
+/// Run the bridge on a thread for as long as the process lives, publishing
+/// what comes through `broadcast`. Screen power commands go to `commands`.
+pub fn spawn(broadcast: Broadcast, commands: Sender<DeviceCommand>) {
+    thread::spawn(move || {
+        let path = crate::settings::Settings::path();
+        let changes = crate::file_watch::watch(&path)
+            .inspect_err(|e| log::warn!("Not watching {}, MQTT settings apply on restart: {:#}", path.display(), e))
+            .ok();
+        loop {
+            let config = crate::settings::Settings::load().mqtt;
+            if !config.enabled {
+                wait(changes.as_ref(), None);
+                continue;
+            }
+            match run(&config, &broadcast, &commands, changes.as_ref()) {
+                Ok(()) => log::info!("MQTT settings changed, reconnecting"),
+                Err(e) => {
+                    log::warn!("MQTT {}:{}: {:#}, retrying in {:?}", config.host, config.port, e, RECONNECT_DELAY);
+                    wait(changes.as_ref(), Some(RECONNECT_DELAY));
+                }
+            }
+        }
+    });
+}
+
+/// Until the settings file changes or `timeout` passes
+fn wait(changes: Option<&Receiver<()>>, timeout: Option<Duration>) {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    while deadline.is_none_or(|deadline| Instant::now() < deadline) {
+        match changes {
+            Some(changes) if changes.recv_timeout(POLL).is_ok() => return,
+            Some(_) => {}
+            None => thread::sleep(POLL),
+        }
+    }
+}
+
+/// One connection, until it fails or the MQTT settings change
+fn run(
+    config: &MqttConfig,
+    broadcast: &Broadcast,
+    commands: &Sender<DeviceCommand>,
+    changes: Option<&Receiver<()>>,
+) -> Result<()> {
+    let topic = |leaf: &str| format!("{}/{}", config.topic, leaf);
+    let mut client = Client::connect(config, &topic("status"))?;
+    client.subscribe(&topic("set/+"))?;
+    client.publish(&topic("status"), b"online", true)?;
+    log::info!("MQTT connected to {}:{} as {}", config.host, config.port, config.topic);
+
+    let (results_tx, results) = channel::unbounded();
+    let updates = broadcast.subscribe();
+    let mut state = Status::connection(&Default::default());
+    client.publish(&topic("state"), state_json(&state).as_bytes(), true)?;
+    let mut sysinfo = None;
+    let mut published: Option<Instant> = None;
+    let interval = Duration::from_secs(config.publish_interval_secs.max(1) as u64);
+
+    loop {
+        for (name, payload, retained) in client.poll()? {
+            let Some(leaf) = name.strip_prefix(&topic("set/")) else {
+                continue;
+            };
+            // Left on the broker from some earlier time, not meant for now
+            if retained {
+                log::warn!("Ignoring retained MQTT command {}", name);
+                continue;
+            }
+            handle_command(leaf, String::from_utf8_lossy(&payload).trim(), commands, &results_tx);
+        }
+
+        for update in updates.try_iter() {
+            match update {
+                Status::Connection { .. } => {
+                    state = update;
+                    client.publish(&topic("state"), state_json(&state).as_bytes(), true)?;
+                }
+                // With the new media name
+                Status::Applied { .. } => client.publish(&topic("state"), state_json(&state).as_bytes(), true)?,
+                Status::Sysinfo { sysinfo: info } => sysinfo = Some(info),
+                _ => {}
+            }
+        }
+        if published.is_none_or(|at| at.elapsed() >= interval)
+            && let Some(info) = sysinfo.take()
+        {
+            client.publish(&topic("sysinfo"), &serde_json::to_vec(&info)?, false)?;
+            published = Some(Instant::now());
+        }
+        for result in results.try_iter() {
+            client.publish(&topic("result"), result.to_string().as_bytes(), false)?;
+        }
+
+        // Publishing at QoS 0 gets no reply, the ping's is what shows the broker is there
+        if client.last_ping.elapsed() >= KEEP_ALIVE / 2 {
+            client.write_packet(PINGREQ, &[])?;
+            client.last_ping = Instant::now();
+        }
+        anyhow::ensure!(client.last_received.elapsed() < KEEP_ALIVE * 3 / 2, "The broker stopped answering");
+        if changes.is_some_and(|changes| changes.try_iter().count() > 0)
+            && crate::settings::Settings::load().mqtt != *config
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// `state` is the latest `Status::Connection`
+fn state_json(state: &Status) -> String {
+    let mut json = serde_json::json!({ "media": LastApplied::load().map(|last| last.media) });
+    if let Status::Connection { state, error } = state {
+        json["connection"] = state.clone().into();
+        if let Some(error) = error {
+            json["error"] = error.clone().into();
+        }
+    }
+    json.to_string()
+}
+
+/// Carry out `set/<leaf>`. Transfers run on a thread of their own and report
+/// on `results` when done.
+fn handle_command(leaf: &str, payload: &str, commands: &Sender<DeviceCommand>, results: &Sender<serde_json::Value>) {
+    log::info!("MQTT command set/{}: {}", leaf, payload);
+    let result = match leaf {
+        "screen" => screen_power(payload, commands),
+        "image" | "profile" => {
+            let leaf = leaf.to_string();
+            let payload = payload.to_string();
+            let results = results.clone();
+            thread::spawn(move || {
+                let result = if leaf == "image" { send_image(&payload) } else { send_profile(&payload) };
+                let _ = results.send(outcome(&leaf, result));
+            });
+            return;
+        }
+        _ => Err(anyhow::anyhow!("Unknown command set/{}", leaf)),
+    };
+    let _ = results.send(outcome(leaf, result));
+}
+
+fn outcome(leaf: &str, result: Result<String>) -> serde_json::Value {
+    let (ok, message) = match result {
+        Ok(message) => (true, message),
+        Err(e) => {
+            log::warn!("MQTT set/{}: {:#}", leaf, e);
+            (false, format!("{:#}", e))
+        }
+    };
+    serde_json::json!({ "command": leaf, "ok": ok, "message": message })
+}
+
+fn screen_power(payload: &str, commands: &Sender<DeviceCommand>) -> Result<String> {
+    anyhow::ensure!(
+        crate::cli::saved_config().experimental_commands,
+        "Screen power is experimental, turn it on under Device Control first"
+    );
+    let on = match payload.to_ascii_lowercase().as_str() {
+        "on" | "1" | "true" => true,
+        "off" | "0" | "false" => false,
+        _ => anyhow::bail!("Expected on or off, got {:?}", payload),
+    };
+    commands.send(DeviceCommand::ScreenPower(on))?;
+    Ok(format!("Screen {}", if on { "on" } else { "off" }))
+}
+
+fn send_image(source: &str) -> Result<String> {
+    let image = if source.starts_with("http://") || source.starts_with("https://") {
+        fetch(source)?
+    } else {
+        PathBuf::from(source)
+    };
+    anyhow::ensure!(image.is_file(), "{} is not a file", image.display());
+    send(image, crate::cli::saved_config(), None)
+}
+
+fn send_profile(name: &str) -> Result<String> {
+    let profile = crate::profiles::load(name)?;
+    let config = ScreenConfig { adb: crate::adb::AdbConfig::load(), ..profile.config };
+    send(profile.image, config, Some(name.to_string())).map(|message| format!("{} ({})", message, name))
+}
+
+fn send(image: PathBuf, config: ScreenConfig, profile: Option<String>) -> Result<String> {
+    // Only the closing message matters here, the rest is dropped with the receiver
+    let (tx, _rx) = crate::message_bus::channel();
+    crate::ipc::request(&crate::ipc::Request::Send { image, config: Box::new(config), profile }, &tx)
+}
+
+/// Download `url` with curl into the state directory, keeping its file name
+/// so the device knows the media type
+fn fetch(url: &str) -> Result<PathBuf> {
+    let dir = crate::paths::state_dir().join("downloads");
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let name: String = url
+        .split(['?', '#'])
+        .next()
+        .and_then(|url| url.rsplit('/').next())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        .collect();
+    let path = dir.join(if name.trim_start_matches('.').is_empty() { "download".to_string() } else { name });
+    let status = std::process::Command::new("curl")
+        .args(["-fsSL", "--max-time", &DOWNLOAD_TIMEOUT_SECS.to_string(), "-o"])
+        .arg(&path)
+        .arg(url)
+        .status()
+        .context("Failed to run curl")?;
+    anyhow::ensure!(status.success(), "Downloading {} failed ({})", url, status);
+    Ok(path)
+}
+
+struct Client {
+    stream: TcpStream,
+    /// Bytes read that don't make up a whole packet yet
+    buffer: Vec<u8>,
+    last_ping: Instant,
+    last_received: Instant,
+    next_id: u16,
+}
+
+impl Client {
+    /// Open a clean session with `will_topic` set to `offline` should we vanish
+    fn connect(config: &MqttConfig, will_topic: &str) -> Result<Self> {
+        let address = (config.host.as_str(), config.port)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve {}", config.host))?
+            .next()
+            .with_context(|| format!("No address for {}", config.host))?;
+        let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        stream.set_read_timeout(Some(POLL))?;
+        stream.set_nodelay(true)?;
+        let mut client = Self { stream, buffer: Vec::new(), last_ping: Instant::now(), last_received: Instant::now(), next_id: 1 };
+
+        // Clean session, will with QoS 0 and retain
+        let mut flags = 0x02 | 0x04 | 0x20;
+        if !config.username.is_empty() {
+            flags |= 0x80;
+            if !config.password.is_empty() {
+                flags |= 0x40;
+            }
+        }
+        let mut body = Vec::new();
+        push_str(&mut body, "MQTT");
+        body.push(4); // 3.1.1
+        body.push(flags);
+        body.extend((KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+        push_str(&mut body, &config.client_id);
+        push_str(&mut body, will_topic);
+        push_str(&mut body, "offline");
+        if !config.username.is_empty() {
+            push_str(&mut body, &config.username);
+            if !config.password.is_empty() {
+                push_str(&mut body, &config.password);
+            }
+        }
+        client.write_packet(CONNECT, &body)?;
+
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        while Instant::now() < deadline {
+            client.read()?;
+            let Some((header, body)) = take_packet(&mut client.buffer) else {
+                continue;
+            };
+            anyhow::ensure!(header == CONNACK && body.len() == 2, "Expected CONNACK, got packet type {:#04x}", header);
+            return match body[1] {
+                0 => Ok(client),
+                1 => anyhow::bail!("The broker doesn't speak MQTT 3.1.1"),
+                2 => anyhow::bail!("Client id {:?} rejected", config.client_id),
+                3 => anyhow::bail!("Broker unavailable"),
+                4 => anyhow::bail!("Bad user name or password"),
+                5 => anyhow::bail!("Not authorized"),
+                code => anyhow::bail!("Connection refused ({})", code),
+            };
+        }
+        anyhow::bail!("No CONNACK within {:?}", CONNECT_TIMEOUT)
+    }
+
+    fn subscribe(&mut self, filter: &str) -> Result<()> {
+        let mut body = self.next_id.to_be_bytes().to_vec();
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        push_str(&mut body, filter);
+        body.push(0);
+        self.write_packet(SUBSCRIBE, &body)
+    }
+
+    fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<()> {
+        let mut body = Vec::with_capacity(topic.len() + payload.len() + 2);
+        push_str(&mut body, topic);
+        body.extend_from_slice(payload);
+        self.write_packet(PUBLISH | retain as u8, &body)
+    }
+
+    fn write_packet(&mut self, header: u8, body: &[u8]) -> Result<()> {
+        let mut packet = vec![header];
+        let mut length = body.len();
+        loop {
+            let mut byte = (length % 128) as u8;
+            length /= 128;
+            if length > 0 {
+                byte |= 0x80;
+            }
+            packet.push(byte);
+            if length == 0 {
+                break;
+            }
+        }
+        packet.extend_from_slice(body);
+        self.stream.write_all(&packet).context("Write to the broker failed")
+    }
+
+    /// Whatever arrives within `POLL`, false if nothing did
+    fn read(&mut self) -> Result<bool> {
+        let mut chunk = [0u8; 4096];
+        match self.stream.read(&mut chunk) {
+            Ok(0) => anyhow::bail!("The broker closed the connection"),
+            Ok(read) => {
+                self.buffer.extend_from_slice(&chunk[..read]);
+                self.last_received = Instant::now();
+                Ok(true)
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => Ok(false),
+            Err(e) => Err(e).context("Read from the broker failed"),
+        }
+    }
+
+    /// Messages on subscribed topics as (topic, payload, retained). Acks and
+    /// ping replies only count as signs of life.
+    fn poll(&mut self) -> Result<Vec<(String, Vec<u8>, bool)>> {
+        self.read()?;
+        let mut messages = Vec::new();
+        while let Some((header, body)) = take_packet(&mut self.buffer) {
+            if header & 0xF0 == PUBLISH
+                && let Some(message) = parse_publish(header, &body)
+            {
+                messages.push(message);
+            }
+        }
+        Ok(messages)
+    }
+}
+
+/// UTF-8 string with its u16 length in front
+fn push_str(out: &mut Vec<u8>, text: &str) {
+    out.extend((text.len() as u16).to_be_bytes());
+    out.extend_from_slice(text.as_bytes());
+}
+
+/// Split the first whole packet off `buffer`: fixed header byte and body
+fn take_packet(buffer: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+    let mut length = 0;
+    for (i, &byte) in buffer.iter().enumerate().skip(1).take(4) {
+        length |= ((byte & 0x7F) as usize) << (7 * (i - 1));
+        if byte & 0x80 == 0 {
+            let end = i + 1 + length;
+            if buffer.len() < end {
+                return None;
+            }
+            let header = buffer[0];
+            let body = buffer[i + 1..end].to_vec();
+            buffer.drain(..end);
+            return Some((header, body));
+        }
+    }
+    None
+}
+
+fn parse_publish(header: u8, body: &[u8]) -> Option<(String, Vec<u8>, bool)> {
+    let length = u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize;
+    let topic = std::str::from_utf8(body.get(2..2 + length)?).ok()?.to_string();
+    // QoS 1 and 2 carry a packet id, we subscribe at 0 so it's only skipped
+    let start = if (header >> 1) & 0x03 > 0 { 4 + length } else { 2 + length };
+    Some((topic, body.get(start..)?.to_vec(), header & 0x01 != 0))
+}
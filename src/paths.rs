@@ -0,0 +1,60 @@
+// XDG base directories for files we keep between runs
+
+use std::path::PathBuf;
+
+const APP_DIR: &str = "tryx-panorama";
+
+fn home_dir() -> PathBuf {
+    std::env::var_os("HOME").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .unwrap_or_else(|| home_dir().join(fallback))
+        .join(APP_DIR)
+}
+
+/// `$XDG_CONFIG_HOME/tryx-panorama` (defaults to `~/.config/tryx-panorama`)
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_STATE_HOME/tryx-panorama` (defaults to `~/.local/state/tryx-panorama`)
+pub fn state_dir() -> PathBuf {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// `$XDG_RUNTIME_DIR/tryx-panorama` for sockets, the state directory when unset
+pub fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from).filter(|p| p.is_absolute()) {
+        Some(dir) => dir.join(APP_DIR),
+        None => state_dir(),
+    }
+}
+
+/// `$ANDROID_USER_HOME`, or `~/.android` where the adb tool keeps its key
+pub fn android_dir() -> PathBuf {
+    std::env::var_os("ANDROID_USER_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .unwrap_or_else(|| home_dir().join(".android"))
+}
+
+fn config_home() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .unwrap_or_else(|| home_dir().join(".config"))
+}
+
+/// `$XDG_CONFIG_HOME/systemd/user` (defaults to `~/.config/systemd/user`), where user units go
+pub fn systemd_user_dir() -> PathBuf {
+    config_home().join("systemd/user")
+}
+
+/// `$XDG_CONFIG_HOME/autostart`, desktop entries the session starts at login
+pub fn autostart_dir() -> PathBuf {
+    config_home().join("autostart")
+}
@@ -0,0 +1,90 @@
+// Network latency: round trip to a configured host with the system `ping`
+// (setuid or with CAP_NET_RAW on every distro, where our own ICMP socket
+// would need privileges). Runs on its own thread so a lost packet never delays
+// a sample; samples take the latest round trip.
+
+use std::{
+    process::{Command, Stdio},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Between two pings of the same host
+const INTERVAL: Duration = Duration::from_secs(2);
+/// Wait for a reply, in seconds, passed to `ping -W`
+const REPLY_TIMEOUT_SECS: u32 = 2;
+
+#[derive(Debug, Clone, Default)]
+struct Reading {
+    host: String,
+    /// Milliseconds, None after a lost packet or an error
+    latency: Option<u32>,
+    error: Option<String>,
+    at: Option<Instant>,
+    running: bool,
+}
+
+static READING: Mutex<Option<Reading>> = Mutex::new(None);
+
+/// Latest round trip to `host` in ms, starting a ping when one is due. None
+/// when no host is set, before the first reply, or while the host doesn't answer.
+pub fn latency(host: &str) -> Option<u32> {
+    let host = host.trim();
+    if host.is_empty() {
+        return None;
+    }
+    let mut reading = READING.lock().unwrap();
+    let reading = reading.get_or_insert_with(Default::default);
+    if reading.host != host {
+        *reading = Reading { host: host.to_string(), ..Default::default() };
+    }
+    if !reading.running && reading.at.is_none_or(|at| at.elapsed() >= INTERVAL) {
+        reading.running = true;
+        let host = host.to_string();
+        std::thread::spawn(move || {
+            let result = ping(&host);
+            let mut reading = READING.lock().unwrap();
+            let Some(reading) = reading.as_mut().filter(|reading| reading.host == host) else {
+                return;
+            };
+            reading.running = false;
+            reading.at = Some(Instant::now());
+            match result {
+                Ok(latency) => {
+                    reading.latency = Some(latency);
+                    reading.error = None;
+                }
+                Err(e) => {
+                    if reading.error.as_ref() != Some(&e) {
+                        log::warn!("ping {}: {}", host, e);
+                    }
+                    reading.latency = None;
+                    reading.error = Some(e);
+                }
+            }
+        });
+    }
+    reading.latency
+}
+
+/// `ping -c 1`: `64 bytes from 1.1.1.1: icmp_seq=1 ttl=57 time=12.3 ms`
+fn ping(host: &str) -> Result<u32, String> {
+    let output = Command::new("ping")
+        .args(["-c", "1", "-n", "-W", &REPLY_TIMEOUT_SECS.to_string(), host])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to start: {}", e))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let detail = stderr.trim();
+        return Err(if detail.is_empty() { "no reply".to_string() } else { detail.to_string() });
+    }
+    let ms: f64 = stdout
+        .lines()
+        .find_map(|line| line.split_whitespace().find_map(|word| word.strip_prefix("time=")))
+        .and_then(|time| time.trim_end_matches("ms").parse().ok())
+        .ok_or_else(|| format!("No round trip time in output: {:?}", stdout.trim()))?;
+    // Rounded up, so a LAN host under a millisecond reads 1 rather than 0
+    Ok(ms.ceil() as u32)
+}
@@ -0,0 +1,79 @@
+// Line charts for the dashboard, drawn with egui_plot: time on x (seconds
+// before now, now at the right edge), the unit on y, latest values above the
+// chart and a readout of the nearest point under the pointer. Dragging or
+// scrolling pans, Ctrl+scroll zooms, a double click goes back to the data.
+
+use eframe::egui::{self, Color32};
+use egui_plot::{GridMark, Line, Plot, PlotPoints};
+
+/// Colors of successive series
+pub const PALETTE: [Color32; 6] = [
+    Color32::from_rgb(94, 215, 221),
+    Color32::from_rgb(255, 140, 60),
+    Color32::from_rgb(140, 220, 90),
+    Color32::from_rgb(220, 110, 220),
+    Color32::from_rgb(240, 210, 70),
+    Color32::from_rgb(120, 150, 255),
+];
+
+const HEIGHT: f32 = 140.0;
+
+pub struct Series {
+    pub name: String,
+    pub color: Color32,
+    /// `[seconds ago, value]`, oldest first. NaN breaks the line.
+    pub points: Vec<[f64; 2]>,
+}
+
+/// Draw `series` over the last `span` seconds. `range` fixes the y axis (loads
+/// are 0–100); None fits it to the data.
+pub fn show(ui: &mut egui::Ui, title: &str, unit: &str, series: &[Series], span: f64, range: Option<(f64, f64)>) {
+    ui.horizontal_wrapped(|ui| {
+        ui.strong(title);
+        for series in series {
+            let latest = series.points.iter().rev().map(|point| point[1]).find(|value| !value.is_nan());
+            let text = match latest {
+                Some(value) => format!("{} {:.0}{}", series.name, value, unit),
+                None => series.name.clone(),
+            };
+            ui.colored_label(series.color, text);
+        }
+    });
+
+    let mut plot = Plot::new(title)
+        .height(HEIGHT)
+        .include_x(-span)
+        .include_x(0.0)
+        .x_axis_label("time ago")
+        .y_axis_label(unit.trim())
+        .x_axis_formatter(|mark: GridMark, _| clock(-mark.value))
+        .label_formatter(|name, point| {
+            if name.is_empty() {
+                return String::new();
+            }
+            format!("{} {:.0}{}\n{} ago", name, point.y, unit, clock(-point.x))
+        });
+    if let Some((low, high)) = range {
+        plot = plot.include_y(low).include_y(high);
+    }
+    plot.show(ui, |plot_ui| {
+        for series in series {
+            // One line per run of readings, so a gap stays a gap
+            let runs = series.points.split(|&[seconds_ago, value]| value.is_nan() || seconds_ago > span);
+            for run in runs.filter(|run| !run.is_empty()) {
+                let points: PlotPoints = run.iter().map(|&[seconds_ago, value]| [-seconds_ago, value]).collect();
+                plot_ui.line(Line::new(series.name.as_str(), points).color(series.color));
+            }
+        }
+    });
+}
+
+/// `m:ss`, or whole seconds under a minute
+fn clock(seconds: f64) -> String {
+    let seconds = seconds.round().max(0.0) as u64;
+    if seconds < 60 {
+        format!("{} s", seconds)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,55 @@
+// Desktop state we only learn by asking, such as idle time or the session
+// lock: a thread reads it at a fixed interval from the first time it's wanted,
+// and the heartbeat picks up the latest reading without waiting on a command.
+
+use std::{
+    sync::{
+        Once,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+/// One reading, None when it couldn't be taken
+pub type Reader = Box<dyn FnMut() -> Option<u64> + Send>;
+
+pub struct Poll {
+    started: Once,
+    value: AtomicU64,
+}
+
+impl Poll {
+    pub const fn new() -> Self {
+        Self { started: Once::new(), value: AtomicU64::new(0) }
+    }
+
+    /// Latest reading of `what`, 0 until there is one or while reading fails.
+    /// The first call runs `start` on a new thread, which picks how to read and
+    /// returns None (having said why) when there's no way.
+    pub fn get(&'static self, what: &'static str, interval: Duration, start: fn() -> Option<Reader>) -> u64 {
+        self.started.call_once(|| {
+            thread::spawn(move || {
+                if let Some(reader) = start() {
+                    self.run(what, interval, reader);
+                }
+            });
+        });
+        self.value.load(Ordering::Relaxed)
+    }
+
+    fn run(&self, what: &str, interval: Duration, mut read: Reader) {
+        let mut failing = false;
+        loop {
+            let value = read();
+            if value.is_none() != failing {
+                failing = value.is_none();
+                if failing {
+                    log::warn!("Couldn't read {}", what);
+                }
+            }
+            self.value.store(value.unwrap_or(0), Ordering::Relaxed);
+            thread::sleep(interval);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+// Power supply: laptop batteries and AC from /sys/class/power_supply, UPSes
+// through NUT's `upsc`. Charge, whether we're on mains, and how long the
+// battery would last.
+
+use std::{
+    fs,
+    path::Path,
+    process::Command,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::sysinfo::PowerInfo;
+
+/// upsc asks the NUT server over the network, so it's cached between samples
+const NUT_INTERVAL: Duration = Duration::from_secs(5);
+
+static NUT: Mutex<Option<(Instant, String, Option<PowerInfo>)>> = Mutex::new(None);
+
+/// The UPS named `ups` (`name@host`) when set, else the system battery. None
+/// on a desktop without either.
+pub fn read(ups: &str) -> Option<PowerInfo> {
+    let ups = ups.trim();
+    if ups.is_empty() {
+        return battery(Path::new("/sys/class/power_supply"));
+    }
+    let mut cache = NUT.lock().unwrap();
+    match &*cache {
+        Some((at, cached, info)) if cached == ups && at.elapsed() < NUT_INTERVAL => info.clone(),
+        _ => {
+            let info = upsc(ups);
+            *cache = Some((Instant::now(), ups.to_string(), info.clone()));
+            info
+        }
+    }
+}
+
+/// System batteries (not a mouse's, those have `scope` = Device) combined, and
+/// whether any mains supply is online
+fn battery(dir: &Path) -> Option<PowerInfo> {
+    let read = |path: &Path, name: &str| fs::read_to_string(path.join(name)).ok().map(|value| value.trim().to_string());
+    let number = |path: &Path, name: &str| read(path, name).and_then(|value| value.parse::<f64>().ok());
+
+    let mut ac = false;
+    let mut batteries = Vec::new();
+    let mut entries: Vec<_> = fs::read_dir(dir).ok()?.flatten().map(|entry| entry.path()).collect();
+    entries.sort();
+    for path in entries {
+        match read(&path, "type").as_deref() {
+            Some("Mains") | Some("USB") => ac |= number(&path, "online") == Some(1.0),
+            Some("Battery") | Some("UPS") if read(&path, "scope").as_deref() != Some("Device") => batteries.push(path),
+            _ => {}
+        }
+    }
+    if batteries.is_empty() {
+        return None;
+    }
+
+    // energy_* in µWh with power_now in µW, or charge_* in µAh with current_now in µA
+    let (mut now, mut full, mut rate) = (0.0, 0.0, 0.0);
+    let mut capacities = Vec::new();
+    let mut charging = false;
+    for path in &batteries {
+        let (now_key, full_key, rate_key) =
+            if path.join("energy_now").exists() { ("energy_now", "energy_full", "power_now") } else { ("charge_now", "charge_full", "current_now") };
+        now += number(path, now_key).unwrap_or(0.0);
+        full += number(path, full_key).unwrap_or(0.0);
+        rate += number(path, rate_key).unwrap_or(0.0).abs();
+        capacities.extend(number(path, "capacity"));
+        charging |= read(path, "status").as_deref() == Some("Charging");
+    }
+    let charge = if full > 0.0 {
+        now / full * 100.0
+    } else if !capacities.is_empty() {
+        capacities.iter().sum::<f64>() / capacities.len() as f64
+    } else {
+        0.0
+    };
+    let on_battery = !ac && !charging;
+    Some(PowerInfo {
+        source: "battery".to_string(),
+        ac: !on_battery,
+        charging,
+        charge: charge.round().clamp(0.0, 100.0) as u8,
+        runtime: (on_battery && rate > 0.0 && now > 0.0).then(|| (now / rate * 3600.0) as u32),
+    })
+}
+
+/// `upsc name@host`: `battery.charge: 87`, `battery.runtime: 1260`, `ups.status: OB DISCHRG`
+fn upsc(ups: &str) -> Option<PowerInfo> {
+    let output = match Command::new("upsc").arg(ups).output() {
+        Ok(output) => output,
+        Err(e) => {
+            log::warn!("upsc {}: {}", ups, e);
+            return None;
+        }
+    };
+    if !output.status.success() {
+        log::warn!("upsc {}: {}", ups, String::from_utf8_lossy(&output.stderr).trim());
+        return None;
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let value = |key: &str| text.lines().find_map(|line| line.strip_prefix(key)?.strip_prefix(':').map(|value| value.trim().to_string()));
+    let status = value("ups.status").unwrap_or_default();
+    let flags: Vec<&str> = status.split_whitespace().collect();
+    Some(PowerInfo {
+        source: ups.to_string(),
+        ac: !flags.contains(&"OB"),
+        charging: flags.contains(&"CHRG"),
+        charge: value("battery.charge").and_then(|charge| charge.parse::<f64>().ok()).map_or(0, |charge| charge.round().clamp(0.0, 100.0) as u8),
+        runtime: value("battery.runtime").and_then(|runtime| runtime.parse::<f64>().ok()).map(|secs| secs as u32),
+    })
+}
@@ -0,0 +1,77 @@
+// Profiles bound to readings: "GPU over 75°C shows the inferno profile",
+// checked against every sample the heartbeat sends. Rules are tried in order
+// and the first one over its threshold wins; with none, the applied screen
+// comes back. A rule only lets go below its threshold minus the hysteresis, and
+// the screen changes at most once per hold time, so it doesn't flap.
+
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::AlertMetric;
+use crate::sysinfo::SysInfo;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileRulesConfig {
+    pub enabled: bool,
+    pub rules: Vec<ProfileRule>,
+    /// How far below its threshold a reading must drop before its rule lets go
+    pub hysteresis: f64,
+    /// Shortest time between two switches
+    pub hold_secs: u32,
+}
+
+impl Default for ProfileRulesConfig {
+    fn default() -> Self {
+        Self { enabled: false, rules: Vec::new(), hysteresis: 3.0, hold_secs: 10 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileRule {
+    pub metric: AlertMetric,
+    pub above: f64,
+    /// Saved profile to show while the rule holds
+    pub profile: String,
+}
+
+pub struct ProfileRules {
+    config: ProfileRulesConfig,
+    /// Index of the rule being shown
+    active: Option<usize>,
+    switched: Option<Instant>,
+}
+
+impl ProfileRules {
+    pub fn new(config: ProfileRulesConfig) -> Self {
+        Self { config, active: None, switched: None }
+    }
+
+    /// Feed the latest sample, returns the profile to show, None for the applied screen
+    pub fn update(&mut self, info: &SysInfo, now: Instant) -> Option<&str> {
+        if !self.config.enabled {
+            return None;
+        }
+        let matching = self.config.rules.iter().enumerate().position(|(i, rule)| {
+            let threshold = if self.active == Some(i) { rule.above - self.config.hysteresis } else { rule.above };
+            match rule.metric.value(info) {
+                Some(value) => value > threshold,
+                // A sensor dropping out for a sample doesn't end its rule
+                None => self.active == Some(i),
+            }
+        });
+        let hold = Duration::from_secs(self.config.hold_secs as u64);
+        if matching != self.active && self.switched.is_none_or(|at| now.duration_since(at) >= hold) {
+            match matching {
+                Some(i) => {
+                    let rule = &self.config.rules[i];
+                    log::info!("{} over {}{}, switching to profile {}", rule.metric.label(), rule.above, rule.metric.unit(), rule.profile);
+                }
+                None => log::info!("No profile rule holds, back to the applied screen"),
+            }
+            self.active = matching;
+            self.switched = Some(now);
+        }
+        self.active.map(|i| self.config.rules[i].profile.as_str())
+    }
+}
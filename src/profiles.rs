@@ -0,0 +1,60 @@
+// Named profiles: an image and the screen config to show it with, one TOML
+// file each in `~/.config/tryx-panorama/profiles`. Switching to a profile is a
+// transfer of its image, which skips the push when the device still has it.
+
+use std::path::PathBuf;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::screen_setup::ScreenConfig;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    /// Local file, pushed again if the device no longer has it
+    pub image: PathBuf,
+    #[serde(default)]
+    pub config: ScreenConfig,
+}
+
+pub fn dir() -> PathBuf {
+    crate::paths::config_dir().join("profiles")
+}
+
+fn path(name: &str) -> Result<PathBuf> {
+    anyhow::ensure!(
+        !name.is_empty() && !name.starts_with('.') && !name.contains('/'),
+        "Invalid profile name {:?}",
+        name
+    );
+    Ok(dir().join(format!("{}.toml", name)))
+}
+
+/// Names of the saved profiles, sorted
+pub fn list() -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir()) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".toml").map(str::to_string))
+        .collect();
+    names.sort();
+    names
+}
+
+pub fn load(name: &str) -> Result<Profile> {
+    let path = path(name)?;
+    let content = std::fs::read_to_string(&path).with_context(|| format!("No profile {:?}", name))?;
+    toml::from_str(&content).with_context(|| format!("Invalid profile {}", path.display()))
+}
+
+pub fn save(name: &str, profile: &Profile) -> Result<()> {
+    let path = path(name)?;
+    std::fs::create_dir_all(dir())?;
+    std::fs::write(&path, toml::to_string_pretty(profile)?).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+pub fn delete(name: &str) -> Result<()> {
+    let path = path(name)?;
+    std::fs::remove_file(&path).with_context(|| format!("Failed to delete {}", path.display()))
+}
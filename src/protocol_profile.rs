@@ -0,0 +1,183 @@
+// ============================================================================
+// Protocol profiles
+// Framing details that differ between firmware revisions: markers, escape
+// table, the headers sent with every message and the line speed. Picked per
+// serial device, so another hardware revision is a config entry, not a patch.
+// ============================================================================
+
+use std::{collections::HashMap, path::PathBuf, sync::{Arc, RwLock}};
+use serde::{Deserialize, Serialize};
+use anyhow::{Context, Result};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProtocolProfile {
+    pub name: String,
+    /// Start and end of every frame, never appears inside the escaped payload
+    pub frame_marker: u8,
+    pub escape_marker: u8,
+    /// `[byte, code]` pairs: `byte` goes on the wire as `escape_marker, code`.
+    /// Must cover both markers.
+    pub escapes: Vec<(u8, u8)>,
+    /// Headers written after the request line, in this order. Names the message
+    /// has no field for are sent as `-1`.
+    pub headers: Vec<String>,
+    /// Overrides the baud rate from the line settings
+    pub baud_rate: Option<u32>,
+}
+
+/// Panorama firmware as shipped with the Android service this was reversed from
+impl Default for ProtocolProfile {
+    fn default() -> Self {
+        Self {
+            name: "tryx-panorama-v1".to_string(),
+            frame_marker: 0x5A,
+            escape_marker: 0x5B,
+            escapes: vec![(0x5A, 0x01), (0x5B, 0x02)],
+            headers: [
+                "SeqNumber",
+                "AckNumber",
+                "ContentLength",
+                "ContentType",
+                "FileName",
+                "FileSize",
+                "ContentRange",
+                "Counter",
+                "Date",
+                "msgId",
+            ]
+            .map(str::to_string)
+            .to_vec(),
+            baud_rate: None,
+        }
+    }
+}
+
+impl ProtocolProfile {
+    pub fn builtin() -> Vec<Self> {
+        vec![Self::default()]
+    }
+
+    /// Catch tables that can't round-trip before they garble traffic
+    pub fn validate(&self) -> Result<()> {
+        anyhow::ensure!(self.frame_marker != self.escape_marker, "{}: frame and escape marker are the same", self.name);
+        for marker in [self.frame_marker, self.escape_marker] {
+            anyhow::ensure!(
+                self.escapes.iter().any(|&(byte, _)| byte == marker),
+                "{}: no escape for 0x{:02x}",
+                self.name,
+                marker
+            );
+        }
+        for (i, &(byte, code)) in self.escapes.iter().enumerate() {
+            anyhow::ensure!(code != self.frame_marker, "{}: escape code for 0x{:02x} is the frame marker", self.name, byte);
+            anyhow::ensure!(
+                self.escapes[..i].iter().all(|&(b, c)| b != byte && c != code),
+                "{}: duplicate escape for 0x{:02x} / code 0x{:02x}",
+                self.name,
+                byte,
+                code
+            );
+        }
+        Ok(())
+    }
+
+    /// Fragmentation needs somewhere to put the byte range
+    pub fn supports_fragments(&self) -> bool {
+        self.headers.iter().any(|h| h == "ContentRange")
+    }
+
+    /// Line settings with this profile's baud rate applied
+    pub fn line_settings(&self, serial: &crate::serial::SerialSettings) -> crate::serial::SerialSettings {
+        let mut serial = serial.clone();
+        if let Some(baud) = self.baud_rate {
+            serial.baud_rate = baud;
+        }
+        serial
+    }
+}
+
+static ACTIVE: RwLock<Option<Arc<ProtocolProfile>>> = RwLock::new(None);
+
+/// Profile the framing code uses, the default until a session picks another
+pub fn active() -> Arc<ProtocolProfile> {
+    if let Some(profile) = ACTIVE.read().unwrap().as_ref() {
+        return profile.clone();
+    }
+    ACTIVE.write().unwrap().get_or_insert_with(Default::default).clone()
+}
+
+pub fn set_active(profile: ProtocolProfile) {
+    let mut active = ACTIVE.write().unwrap();
+    if active.as_deref() != Some(&profile) {
+        log::info!("Using protocol profile {}", profile.name);
+        *active = Some(Arc::new(profile));
+    }
+}
+
+/// Custom profiles and which one each device uses, remembered across runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileStore {
+    /// User-defined profiles, on top of the built-in ones
+    pub custom: Vec<ProtocolProfile>,
+    /// Serial device → profile name
+    pub by_device: HashMap<String, String>,
+}
+
+impl ProfileStore {
+    fn path() -> PathBuf {
+        crate::paths::state_dir().join("protocol_profiles.json")
+    }
+
+    pub fn load() -> Self {
+        std::fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Built-in profiles first, then valid custom ones (a custom profile can replace a built-in by name)
+    pub fn all(&self) -> Vec<ProtocolProfile> {
+        let mut profiles = ProtocolProfile::builtin();
+        for profile in &self.custom {
+            if let Err(e) = profile.validate() {
+                log::warn!("Ignoring protocol profile: {:#}", e);
+                continue;
+            }
+            match profiles.iter_mut().find(|p| p.name == profile.name) {
+                Some(existing) => *existing = profile.clone(),
+                None => profiles.push(profile.clone()),
+            }
+        }
+        profiles
+    }
+
+    /// Profile assigned to `device`, the default if none is or it no longer exists
+    pub fn for_device(&self, device: &str) -> ProtocolProfile {
+        let Some(name) = self.by_device.get(device) else {
+            return ProtocolProfile::default();
+        };
+        self.all().into_iter().find(|p| &p.name == name).unwrap_or_else(|| {
+            log::warn!("Protocol profile {} for {} not found, using the default", name, device);
+            ProtocolProfile::default()
+        })
+    }
+
+    pub fn assign(&mut self, device: &str, name: &str) {
+        if name == ProtocolProfile::default().name {
+            self.by_device.remove(device);
+        } else {
+            self.by_device.insert(device.to_string(), name.to_string());
+        }
+    }
+}
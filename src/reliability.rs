@@ -0,0 +1,154 @@
+// ============================================================================
+// ACK tracking and retransmission
+// Outstanding commands are matched against AckNumber in device replies and
+// resent when no ACK shows up in time. Operations that fail outright (opening
+// the port, adb, writes) are retried with backoff by `RetryPolicy`.
+// ============================================================================
+
+use std::{collections::HashMap, time::{Duration, Instant}};
+use serde::{Deserialize, Serialize};
+
+use crate::data::OutgoingFrame;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReliabilityConfig {
+    /// How long to wait for an ACK before resending
+    pub ack_timeout_ms: u64,
+    /// Resends before giving up on a command
+    pub max_retransmits: u32,
+}
+
+impl Default for ReliabilityConfig {
+    fn default() -> Self {
+        Self { ack_timeout_ms: 1000, max_retransmits: 3 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Tries in total, 1 means fail on the first error
+    pub attempts: u32,
+    /// Wait before the first retry, doubled for each one after
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { attempts: 3, base_delay_ms: 250, max_delay_ms: 4000 }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `retry` (1 for the first retry)
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 1u64 << retry.saturating_sub(1).min(20);
+        Duration::from_millis(self.base_delay_ms.saturating_mul(factor).min(self.max_delay_ms))
+    }
+
+    /// Run `op` until it succeeds, fails with an error `retryable` rejects, or
+    /// the attempts run out. The last error is returned.
+    pub fn run<T>(
+        &self,
+        what: &str,
+        retryable: impl Fn(&anyhow::Error) -> bool,
+        mut op: impl FnMut() -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let attempts = self.attempts.max(1);
+        let mut attempt = 1;
+        loop {
+            match op() {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < attempts && retryable(&e) => {
+                    let delay = self.delay(attempt);
+                    log::warn!("{} failed (attempt {}/{}), retrying in {:?}: {:#}", what, attempt, attempts, delay, e);
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PendingCommand {
+    frame: OutgoingFrame,
+    first_sent: Instant,
+    last_sent: Instant,
+    retransmits: u32,
+}
+
+/// What the caller should do after a timeout check
+#[derive(Debug, Default)]
+pub struct RetransmitPlan {
+    pub resend: Vec<OutgoingFrame>,
+    /// Commands that ran out of retries
+    pub gave_up: Vec<OutgoingFrame>,
+}
+
+#[derive(Debug, Default)]
+pub struct AckTracker {
+    config: ReliabilityConfig,
+    pending: HashMap<i64, PendingCommand>,
+}
+
+impl AckTracker {
+    pub fn new(config: ReliabilityConfig) -> Self {
+        Self { config, pending: HashMap::new() }
+    }
+
+    pub fn track(&mut self, frame: OutgoingFrame) {
+        let now = Instant::now();
+        self.pending.insert(
+            frame.seq_number,
+            PendingCommand { frame, first_sent: now, last_sent: now, retransmits: 0 },
+        );
+    }
+
+    /// Match an AckNumber from the device, returns the acknowledged command and its round-trip time
+    pub fn acknowledge(&mut self, ack_number: i64) -> Option<(OutgoingFrame, Duration)> {
+        let pending = self.pending.remove(&ack_number)?;
+        let rtt = pending.last_sent.elapsed();
+        crate::link_stats::stats().record_rtt(&pending.frame.cmd_type, rtt);
+        log::debug!(
+            "ACK for {} Seq={} after {:?} ({} retransmits)",
+            pending.frame.cmd_type,
+            ack_number,
+            pending.first_sent.elapsed(),
+            pending.retransmits
+        );
+        Some((pending.frame, rtt))
+    }
+
+    /// Commands whose ACK is overdue. Resent ones get their timer restarted.
+    pub fn check_timeouts(&mut self, now: Instant) -> RetransmitPlan {
+        let timeout = Duration::from_millis(self.config.ack_timeout_ms);
+        let mut plan = RetransmitPlan::default();
+        let mut expired = Vec::new();
+
+        for (seq, pending) in self.pending.iter_mut() {
+            if now.duration_since(pending.last_sent) < timeout {
+                continue;
+            }
+            if pending.retransmits >= self.config.max_retransmits {
+                expired.push(*seq);
+                continue;
+            }
+            pending.retransmits += 1;
+            pending.last_sent = now;
+            crate::link_stats::stats().record_retransmission(&pending.frame.cmd_type);
+            plan.resend.push(pending.frame.clone());
+        }
+
+        for seq in expired {
+            if let Some(pending) = self.pending.remove(&seq) {
+                crate::link_stats::stats().record_failure(&pending.frame.cmd_type);
+                plan.gave_up.push(pending.frame);
+            }
+        }
+        plan
+    }
+}
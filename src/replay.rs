@@ -0,0 +1,99 @@
+// ============================================================================
+// Session Replay
+// Loads the TX frames of a capture so they can be re-sent with their original timing
+// ============================================================================
+
+use std::{path::Path, time::Duration};
+use anyhow::{Context, Result};
+
+use crate::data::OutgoingFrame;
+use crate::sniffer::{CaptureRecord, Direction, RecordKind};
+
+/// One frame to send, `at` is relative to the start of the replay
+#[derive(Debug, Clone)]
+pub struct ReplayFrame {
+    pub at: Duration,
+    pub frame: OutgoingFrame,
+}
+
+/// Read a capture. Accepts the sniffer's `.jsonl` files, or plain text with one
+/// frame per line as `<offset_ms> <hex>` or just `<hex>` (e.g. converted from usbmon).
+pub fn load(path: &Path) -> Result<Vec<ReplayFrame>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let mut frames = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = if line.starts_with('{') {
+            from_record(line)
+        } else {
+            from_hex_line(line).map(Some)
+        };
+        if let Some(frame) = parsed.with_context(|| format!("{}:{}", path.display(), number + 1))? {
+            frames.push(frame);
+        }
+    }
+
+    anyhow::ensure!(!frames.is_empty(), "{} contains no TX frames", path.display());
+
+    // Replay relative to the first frame, not to when the capture started
+    let first = frames[0].at;
+    for frame in &mut frames {
+        frame.at = frame.at.saturating_sub(first);
+    }
+    Ok(frames)
+}
+
+fn from_record(line: &str) -> Result<Option<ReplayFrame>> {
+    let record: CaptureRecord = serde_json::from_str(line)?;
+    if record.direction != Direction::Tx || record.kind != RecordKind::Frame {
+        return Ok(None);
+    }
+    let bytes = decode_hex(&record.hex)?;
+    let (method, cmd_type, seq_number, msg_id) = match &record.decoded {
+        Some(decoded) => {
+            let header = |key: &str| decoded.headers.get(key).and_then(|v| v.parse().ok()).unwrap_or(-1);
+            (decoded.method.clone(), decoded.cmd_type.clone(), header("SeqNumber"), header("msgId"))
+        }
+        None => ("?".to_string(), "?".to_string(), -1, -1),
+    };
+    Ok(Some(ReplayFrame {
+        at: Duration::from_secs_f64(record.offset_ms.max(0.0) / 1000.0),
+        frame: OutgoingFrame { method, cmd_type, seq_number, msg_id, bytes, fallback: None },
+    }))
+}
+
+fn from_hex_line(line: &str) -> Result<ReplayFrame> {
+    let (at, hex) = match line.split_once(char::is_whitespace) {
+        Some((ms, hex)) if ms.parse::<f64>().is_ok() => (ms.parse::<f64>()?, hex),
+        _ => (0.0, line),
+    };
+    let bytes = decode_hex(hex)?;
+    Ok(ReplayFrame {
+        at: Duration::from_secs_f64(at.max(0.0) / 1000.0),
+        frame: OutgoingFrame {
+            method: "?".to_string(),
+            cmd_type: "raw".to_string(),
+            seq_number: -1,
+            msg_id: -1,
+            bytes,
+            fallback: None,
+        },
+    })
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = hex.bytes().filter(|b| !b.is_ascii_whitespace() && *b != b':').collect();
+    anyhow::ensure!(digits.len().is_multiple_of(2), "Odd number of hex digits");
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair)?;
+            u8::from_str_radix(pair, 16).with_context(|| format!("Invalid hex byte '{}'", pair))
+        })
+        .collect()
+}
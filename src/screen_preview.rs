@@ -0,0 +1,107 @@
+// Live preview of what the cooler's display shows: `screencap -p` over ADB,
+// shown in the GUI and optionally refreshed on a timer
+
+use std::{sync::Arc, time::{Duration, Instant}};
+use anyhow::Result;
+
+use crate::adb::{AdbClient, AdbConfig};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+pub struct Capture {
+    /// Unique per capture, the image loaders cache by URI
+    pub uri: String,
+    pub png: Arc<[u8]>,
+    pub taken_at: chrono::DateTime<chrono::Local>,
+    /// Width and height from the PNG header
+    pub size: Option<[u32; 2]>,
+}
+
+pub struct ScreenPreview {
+    pub capture: Option<Capture>,
+    pub error: Option<String>,
+    pub auto_refresh: bool,
+    pub interval_secs: u32,
+    pub worker: Option<std::thread::JoinHandle<()>>,
+    /// URIs of replaced captures, for the UI to evict from the image cache
+    pub stale: Vec<String>,
+    last_started: Option<Instant>,
+    captures: u64,
+}
+
+impl Default for ScreenPreview {
+    fn default() -> Self {
+        Self {
+            capture: None,
+            error: None,
+            auto_refresh: false,
+            interval_secs: 5,
+            worker: None,
+            stale: Vec::new(),
+            last_started: None,
+            captures: 0,
+        }
+    }
+}
+
+impl ScreenPreview {
+    pub fn running(&self) -> bool {
+        self.worker.as_ref().is_some_and(|w| !w.is_finished())
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs.max(1) as u64)
+    }
+
+    /// Auto-refresh is on and the last capture started at least an interval ago
+    pub fn due(&self) -> bool {
+        self.auto_refresh && !self.running() && self.last_started.is_none_or(|started| started.elapsed() >= self.interval())
+    }
+
+    pub fn mark_started(&mut self) {
+        self.last_started = Some(Instant::now());
+    }
+
+    pub fn set_capture(&mut self, result: Result<Arc<[u8]>, String>) {
+        match result {
+            Ok(png) => {
+                self.captures += 1;
+                let capture = Capture {
+                    uri: format!("bytes://screencap/{}.png", self.captures),
+                    size: png_size(&png),
+                    png,
+                    taken_at: chrono::Local::now(),
+                };
+                if let Some(old) = self.capture.replace(capture) {
+                    self.stale.push(old.uri);
+                }
+                self.error = None;
+            }
+            Err(e) => {
+                // A failing device shouldn't be polled every few seconds
+                self.auto_refresh = false;
+                self.error = Some(e);
+            }
+        }
+    }
+}
+
+/// PNG of the device's screen. `exec:` rather than `shell:`, a pty would mangle the bytes.
+pub fn capture(adb: &AdbConfig) -> Result<Arc<[u8]>> {
+    let png = AdbClient::connect(adb)?.exec("screencap -p")?;
+    anyhow::ensure!(
+        png.starts_with(PNG_SIGNATURE),
+        "screencap returned no image: {}",
+        String::from_utf8_lossy(&png[..png.len().min(200)]).trim()
+    );
+    Ok(png.into())
+}
+
+fn png_size(png: &[u8]) -> Option<[u32; 2]> {
+    // Signature, IHDR length and type, then width and height
+    let header = png.get(16..24)?;
+    Some([
+        u32::from_be_bytes(header[..4].try_into().ok()?),
+        u32::from_be_bytes(header[4..].try_into().ok()?),
+    ])
+}
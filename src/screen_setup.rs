@@ -1,10 +1,33 @@
-use std::{path::PathBuf, process::Command, thread, time::Duration};
+use std::{path::PathBuf, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}, thread, time::Duration};
 use serde::{Deserialize, Serialize};
 use anyhow::{Context, Result};
 use std::io::Read;
+use crossbeam::channel::Receiver;
 
-use crate::data::{send_command, send_state_command};
+use crate::app_state::AppMessage;
+use crate::message_bus::MessageSender;
+use crate::timing::TransferReport;
+use crate::data::{
+    build_message, build_reply, build_request, get_command, read_response, send_device_command, write_frame, CommandMessageWithMethod, FrameAssembler, OutgoingFrame,
+    DeviceCommand, ProtocolError, FilterSettings, MediaDeletePayload, Query, ResponseMessage, ScreenConfigPayload, ScreenSettings,
+    TransportPayload, TransportedPayload,
+};
+use crate::adb::{AdbClient, AdbConfig};
+use crate::adb_usb::Relation;
+use crate::compression::CompressionConfig;
+use crate::protocol_profile::ProfileStore;
+use crate::reliability::{AckTracker, ReliabilityConfig, RetryPolicy};
+use crate::dispatcher::Dispatcher;
+use crate::inbound::{is_inbound, InboundRouter, Routed};
+use crate::serial_writer::{SerialWriter, WriterConfig};
+use crate::serial::{is_disconnect, is_transient_open_error, is_transient_write_error, DeviceGone, SerialSettings};
+use crate::transport::{SerialTransport, Transport};
 use crate::sysinfo::SysInfo;
+use crate::screensaver::{SaverEvent, ScreenSaver, ScreenSaverConfig};
+use crate::alerts::{AlertConfig, AlertEvent, Alerts};
+use crate::profile_rules::ProfileRules;
+use crate::game_detect::GameDetector;
+use crate::scripting::Choice;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScreenConfig {
@@ -17,6 +40,108 @@ pub struct ScreenConfig {
     pub filter_opacity: u8,
     pub badges: Vec<String>,
     pub sysinfo_display: Vec<String>,
+    #[serde(default)]
+    pub shutdown_action: ShutdownAction,
+    /// Screen power and brightness, whose `config` keys are unconfirmed. Off
+    /// by default; what needs them (screen off on exit, panel off while locked
+    /// or idle, dimming) waits until this is on.
+    #[serde(default)]
+    pub experimental_commands: bool,
+    #[serde(default)]
+    pub screensaver: ScreenSaverConfig,
+    #[serde(default)]
+    pub lock_screen: crate::session_lock::LockScreenConfig,
+    #[serde(default)]
+    pub input_idle: crate::input_idle::InputIdleConfig,
+    #[serde(default)]
+    pub alerts: AlertConfig,
+    #[serde(default)]
+    pub profile_rules: crate::profile_rules::ProfileRulesConfig,
+    #[serde(default)]
+    pub game_detect: crate::game_detect::GameDetectConfig,
+    #[serde(default)]
+    pub reliability: ReliabilityConfig,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    #[serde(default)]
+    pub transfer_method: TransferMethod,
+    #[serde(default)]
+    pub writer: WriterConfig,
+    #[serde(default)]
+    pub heartbeat: HeartbeatConfig,
+    #[serde(default)]
+    pub serial: SerialSettings,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    #[serde(default)]
+    pub adb: AdbConfig,
+    #[serde(default)]
+    pub sensors: crate::sysinfo::SensorConfig,
+    /// Skip the mediaDelete cleanup, old files stay until removed in the media browser
+    #[serde(default)]
+    pub keep_old_media: bool,
+    #[serde(default)]
+    pub hotplug: crate::hotplug::HotplugConfig,
+    #[serde(default)]
+    pub log_file: crate::log_file::LogFileConfig,
+}
+
+/// Sysinfo keepalive after a config has been applied. Without it the display
+/// falls back to its default screen.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HeartbeatConfig {
+    /// Keep the port open and beat until stopped, otherwise send a few updates and close
+    pub enabled: bool,
+    pub interval_ms: u64,
+    /// Wait for the device to re-enumerate after a disconnect and resume
+    pub auto_reconnect: bool,
+    /// Give up reconnecting after this long
+    pub reconnect_timeout_secs: u64,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self { enabled: true, interval_ms: 1000, auto_reconnect: true, reconnect_timeout_secs: 120 }
+    }
+}
+
+/// How media files get onto the device
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum TransferMethod {
+    #[default]
+    Adb,
+    /// transport/transported over the serial port, for when ADB debugging is unavailable
+    Serial,
+}
+
+impl TransferMethod {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TransferMethod::Adb => "ADB push",
+            TransferMethod::Serial => "Serial (slow)",
+        }
+    }
+}
+
+/// What to leave on the display when the app exits
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum ShutdownAction {
+    #[default]
+    LeaveAsIs,
+    ScreenOff,
+    /// Switch to a media file that is already on the device
+    ParkedImage(String),
+}
+
+impl ShutdownAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ShutdownAction::LeaveAsIs => "Leave as-is",
+            ShutdownAction::ScreenOff => "Screen off",
+            ShutdownAction::ParkedImage(_) => "Parked image",
+        }
+    }
 }
 
 impl Default for ScreenConfig {
@@ -31,69 +156,446 @@ impl Default for ScreenConfig {
             filter_opacity: 100,
             badges: vec!["GPU Badge".to_string(), "CPU Badge".to_string()],
             sysinfo_display: vec!["CPU Temperature".to_string(), "GPU Temperature".to_string()],
+            shutdown_action: ShutdownAction::default(),
+            experimental_commands: false,
+            screensaver: ScreenSaverConfig::default(),
+            lock_screen: Default::default(),
+            input_idle: Default::default(),
+            alerts: AlertConfig::default(),
+            profile_rules: Default::default(),
+            game_detect: Default::default(),
+            reliability: ReliabilityConfig::default(),
+            retry: RetryPolicy::default(),
+            transfer_method: TransferMethod::default(),
+            writer: WriterConfig::default(),
+            heartbeat: HeartbeatConfig::default(),
+            serial: SerialSettings::default(),
+            compression: CompressionConfig::default(),
+            adb: AdbConfig::default(),
+            sensors: Default::default(),
+            keep_old_media: false,
+            hotplug: Default::default(),
+            log_file: Default::default(),
         }
     }
 }
 
-pub struct AioCoolerController {
+impl ScreenConfig {
+    /// The same look without badges or sensor readouts, for the lock, idle and
+    /// saver screens
+    pub fn minimal(&self) -> ScreenConfig {
+        let mut minimal = self.clone();
+        minimal.badges.clear();
+        minimal.sysinfo_display.clear();
+        minimal
+    }
+
+    /// `media` with the minimal look, None without media, when the panel is
+    /// turned off instead
+    pub fn minimal_screen(&self, media: &str) -> Option<(ScreenConfig, String)> {
+        (!media.is_empty()).then(|| (self.minimal(), media.to_string()))
+    }
+}
+
+/// The last configuration that made it onto the device, re-sent after a reconnect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastApplied {
+    pub config: ScreenConfig,
+    pub media: String,
+    pub applied_at: i64,
+}
+
+impl LastApplied {
+    pub fn path() -> PathBuf {
+        crate::paths::state_dir().join("last_applied.json")
+    }
+
+    pub fn new(config: &ScreenConfig, media: &str) -> Self {
+        Self {
+            config: config.clone(),
+            media: media.to_string(),
+            applied_at: chrono::Local::now().timestamp_millis(),
+        }
+    }
+
+    pub fn load() -> Option<Self> {
+        let content = std::fs::read_to_string(Self::path()).ok()?;
+        match serde_json::from_str(&content) {
+            Ok(last) => Some(last),
+            Err(e) => {
+                log::warn!("Ignoring unreadable {}: {}", Self::path().display(), e);
+                None
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Device lifecycle as seen by the controller
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ConnectionState {
+    #[default]
+    Disconnected,
+    SerialOpen,
+    /// Device answered (or accepted) the initial sysinfo
+    Handshaked,
+    /// Config applied, keepalives flowing
+    Streaming,
+    /// Device went away mid-session, waiting for it to come back
+    Reconnecting,
+    Error(String),
+}
+
+impl ConnectionState {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ConnectionState::Disconnected => "Disconnected",
+            ConnectionState::SerialOpen => "Serial open",
+            ConnectionState::Handshaked => "Handshaked",
+            ConnectionState::Streaming => "Streaming",
+            ConnectionState::Reconnecting => "Reconnecting",
+            ConnectionState::Error(_) => "Error",
+        }
+    }
+
+    /// Valid edges of the lifecycle. Any state can fail or drop back to Disconnected.
+    pub fn can_transition_to(&self, next: &ConnectionState) -> bool {
+        use ConnectionState::*;
+        matches!(
+            (self, next),
+            (_, Disconnected)
+                | (_, Error(_))
+                | (Disconnected, SerialOpen)
+                | (Error(_), SerialOpen)
+                | (SerialOpen, Handshaked)
+                | (Handshaked, Streaming)
+                | (Streaming, Reconnecting)
+                | (Reconnecting, SerialOpen)
+        )
+    }
+
+    /// A new transfer may only start from an idle state
+    pub fn can_transfer(&self) -> bool {
+        matches!(self, ConnectionState::Disconnected | ConnectionState::Error(_))
+    }
+
+    /// The link is up and can be asked to stop
+    pub fn can_stop(&self) -> bool {
+        matches!(
+            self,
+            ConnectionState::SerialOpen
+                | ConnectionState::Handshaked
+                | ConnectionState::Streaming
+                | ConnectionState::Reconnecting
+        )
+    }
+}
+
+/// Raw bytes per transport frame. Worst case escaping doubles this, which
+/// still has to fit the u16 frame length.
+const SERIAL_CHUNK_SIZE: usize = 4096;
+
+/// How long to wait for the reply to a GET before assuming the firmware ignores it
+const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long to wait for the status of a command that changes device state
+const STATUS_TIMEOUT: Duration = Duration::from_secs(3);
+/// Minimum time between two ADB push progress updates
+const PUSH_PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Drives the device over a `Transport`, the serial port unless told otherwise
+pub struct AioCoolerController<T: Transport = SerialTransport> {
     serial_device: String,
+    cancel: Arc<AtomicBool>,
+    state: Mutex<ConnectionState>,
+    events: Option<MessageSender>,
+    timings: Mutex<TransferReport>,
+    acks: Mutex<AckTracker>,
+    dispatcher: Mutex<Dispatcher>,
+    /// Write side of the port while a session is running
+    writer: Mutex<Option<SerialWriter>>,
+    /// Read side of the port, kept open between applying a config and the heartbeat
+    session: Mutex<Option<T>>,
+    /// Partial frames carried over between reads
+    assembler: Mutex<FrameAssembler>,
+    /// Handlers for requests the device sends on its own
+    inbound: InboundRouter<T>,
+    /// Backoff for opening the port, adb and writes
+    retry: RetryPolicy,
+    /// Where pushes go, USB unless an adbd address is set
+    adb: AdbConfig,
+    /// Commands from the device controls, sent between keepalives
+    commands: Option<Receiver<DeviceCommand>>,
+    /// Configs to switch to during the heartbeat, without reopening the port
+    reload: Option<Receiver<LastApplied>>,
+    /// Gets state changes and sysinfo samples too, for the daemon's watchers
+    broadcast: Option<crate::broadcast::Broadcast>,
 }
 
 impl AioCoolerController {
     pub fn new(serial_device: &str) -> Self {
+        Self::with_transport(serial_device)
+    }
+
+    /// Streamed, videos can be far larger than we'd want in memory
+    pub fn calculate_md5(path: &PathBuf) -> Result<String> {
+        let mut file = std::fs::File::open(path)?;
+        let mut context = md5::Context::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            context.consume(&buffer[..read]);
+            crate::systemd::watchdog();
+        }
+        Ok(format!("{:x}", context.finalize()))
+    }
+
+    pub fn generate_filename(extension: &str) -> String {
+        let now = chrono::Local::now();
+        now.format(&format!("%Y-%m-%d_%H-%M-%S-%3f.{}", extension))
+            .to_string()
+    }
+}
+
+impl<T: Transport + 'static> AioCoolerController<T> {
+    /// Controller over any transport, `serial_device` is what gets passed to `T::open`
+    pub fn with_transport(serial_device: &str) -> Self {
         Self {
             serial_device: serial_device.to_string(),
+            cancel: Arc::new(AtomicBool::new(false)),
+            state: Mutex::new(ConnectionState::Disconnected),
+            events: None,
+            timings: Mutex::new(TransferReport::default()),
+            acks: Mutex::new(AckTracker::default()),
+            dispatcher: Mutex::new(Dispatcher::default()),
+            writer: Mutex::new(None),
+            session: Mutex::new(None),
+            assembler: Mutex::new(FrameAssembler::new()),
+            inbound: InboundRouter::default()
+                .on("*", "conn", Self::on_handshake)
+                .on("*", "all", Self::on_sysinfo_pull),
+            retry: RetryPolicy::default(),
+            adb: AdbConfig::default(),
+            commands: None,
+            reload: None,
+            broadcast: None,
         }
     }
 
-    pub fn adb_push(&self, local_path: &PathBuf, remote_name: &str) -> Result<()> {
-        log::info!("Pushing image to device through ADB");
-        
-        let status = Command::new("adb")
-            .args(["wait-for-device"])
-            .status()
-            .context("Failed to execute adb wait-for-device")?;
+    /// Report state changes over the app message channel
+    pub fn with_events(mut self, events: MessageSender) -> Self {
+        self.events = Some(events);
+        self
+    }
 
-        if !status.success() {
-            anyhow::bail!("ADB wait-for-device failed");
+    /// Copy state changes and sysinfo samples to `broadcast`, for watchers
+    /// besides whoever gets the events
+    pub fn with_broadcast(mut self, broadcast: crate::broadcast::Broadcast) -> Self {
+        self.broadcast = Some(broadcast);
+        self
+    }
+
+    /// Run one step of the serial sequence and record its duration
+    fn timed<R>(&self, name: &str, f: impl FnOnce() -> R) -> R {
+        let start = std::time::Instant::now();
+        let result = f();
+        self.timings.lock().unwrap().record(name, start.elapsed());
+        result
+    }
+
+    /// Stage timings collected since the last call
+    pub fn take_timings(&self) -> TransferReport {
+        std::mem::take(&mut *self.timings.lock().unwrap())
+    }
+
+    fn set_state(&self, next: ConnectionState) {
+        let mut state = self.state.lock().unwrap();
+        if *state == next {
+            return;
+        }
+        if !state.can_transition_to(&next) {
+            log::warn!("Unexpected state transition {} -> {}", state.label(), next.label());
         }
+        log::debug!("Connection state: {} -> {}", state.label(), next.label());
+        *state = next.clone();
+        drop(state);
 
-        let remote_path = format!("/sdcard/pcMedia/{}", remote_name);
-        log::info!("Pushing {} to {}", local_path.display(), remote_path);
+        match &next {
+            ConnectionState::Error(e) => crate::diagnostics::record_state(&format!("Error: {}", e)),
+            other => crate::diagnostics::record_state(other.label()),
+        }
+
+        if let Some(broadcast) = &self.broadcast {
+            broadcast.send(crate::broadcast::Status::connection(&next));
+        }
+        if let Some(events) = &self.events {
+            let _ = events.send(AppMessage::State(next));
+        }
+    }
 
-        let output = Command::new("adb")
-            .args(["push", &local_path.to_string_lossy(), &remote_path])
-            .output()
-            .context("Failed to execute adb push")?;
+    pub fn serial_device(&self) -> &str {
+        &self.serial_device
+    }
+
+    /// Share a flag that aborts the keepalive loop when set (e.g. on app exit)
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = cancel;
+        self
+    }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            anyhow::bail!("ADB push failed: {}", stderr);
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_adb(mut self, adb: AdbConfig) -> Self {
+        self.adb = adb;
+        self
+    }
+
+    /// Take commands to send during the heartbeat from `commands`
+    pub fn with_commands(mut self, commands: Receiver<DeviceCommand>) -> Self {
+        self.commands = Some(commands);
+        self
+    }
+
+    /// Take new configs for the running heartbeat from `reload`, the latest one wins
+    pub fn with_reload(mut self, reload: Receiver<LastApplied>) -> Self {
+        self.reload = Some(reload);
+        self
+    }
+
+    fn reload_pending(&self) -> bool {
+        self.reload.as_ref().is_some_and(|reload| !reload.is_empty())
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// Push over ADB, first switching the cooler to ADB mode over `serial` if
+    /// that's configured and no ADB device is there
+    pub fn adb_push(&self, local_path: &PathBuf, remote_name: &str, serial: &SerialSettings) -> Result<()> {
+        self.ensure_adb_mode(serial)?;
+        self.check_same_cooler()?;
+        self.retry.run("ADB push", |_| !self.is_cancelled(), || self.try_adb_push(local_path, remote_name))
+    }
+
+    /// Make sure the ADB device is the cooler behind the serial port. A picked
+    /// device is trusted with a warning, an automatic pick is refused.
+    fn check_same_cooler(&self) -> Result<()> {
+        if !self.adb.address.trim().is_empty() {
+            return Ok(());
         }
+        let Ok(device) = self.adb.usb_device() else {
+            // Connecting reports that
+            return Ok(());
+        };
+        match device.relation_to(&self.serial_device) {
+            Relation::SameDevice | Relation::Unknown => Ok(()),
+            Relation::SameHub => {
+                log::info!("{} and {} share a USB hub, assuming they are the same cooler", device.describe(), self.serial_device);
+                Ok(())
+            }
+            Relation::Unrelated if !self.adb.serial.is_empty() => {
+                log::warn!("ADB device {} doesn't look like the cooler on {}, pushing anyway as it was picked", device.describe(), self.serial_device);
+                Ok(())
+            }
+            Relation::Unrelated => anyhow::bail!(
+                "The ADB device {} is not the cooler on {}; pick the cooler under Device Settings → ADB device",
+                device.describe(),
+                self.serial_device
+            ),
+        }
+    }
+
+    /// Send the configured enable command when there is no ADB interface on USB,
+    /// then wait for one to appear
+    fn ensure_adb_mode(&self, serial: &SerialSettings) -> Result<()> {
+        let cmd_type = self.adb.enable_cmd_type.trim();
+        if cmd_type.is_empty() || !self.adb.address.trim().is_empty() || !crate::adb_usb::devices().is_empty() {
+            return Ok(());
+        }
+        let body: serde_json::Value = match self.adb.enable_body.trim() {
+            "" => serde_json::json!({}),
+            body => serde_json::from_str(body).context("The ADB enable command body is not valid JSON")?,
+        };
+
+        log::info!("No ADB device on USB, sending {} to switch the cooler to ADB mode", cmd_type);
+        if let Some(events) = &self.events {
+            let _ = events.send(AppMessage::Progress(0.2, "Switching the cooler to ADB mode...".to_string()));
+        }
+        {
+            let mut port = self.open_port(serial)?;
+            let _ = port.discard_buffers();
+            self.write_direct(&mut port, &build_request("POST", cmd_type, &body)?)?;
+            self.drain_responses(&mut port, Duration::from_secs(1))?;
+        }
+
+        let timeout = Duration::from_secs(self.adb.enable_wait_secs);
+        let deadline = std::time::Instant::now() + timeout;
+        while crate::adb_usb::devices().is_empty() {
+            anyhow::ensure!(!self.is_cancelled(), "Cancelled while waiting for ADB");
+            if std::time::Instant::now() >= deadline {
+                anyhow::bail!("No ADB device appeared within {:?} of sending {}", timeout, cmd_type);
+            }
+            thread::sleep(Duration::from_millis(500));
+        }
+        log::info!("ADB device appeared after {}", cmd_type);
+        Ok(())
+    }
+
+    fn try_adb_push(&self, local_path: &PathBuf, remote_name: &str) -> Result<()> {
+        log::info!("Pushing image to device through ADB");
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        log::info!("ADB push output: {}", stdout.trim());
+        let mut adb = AdbClient::connect(&self.adb)?;
+        let remote_path = media_path(remote_name);
+        log::info!("Pushing {} to {}", local_path.display(), remote_path);
+        let started = std::time::Instant::now();
+        let mut last_report: Option<std::time::Instant> = None;
+        adb.push(local_path, &remote_path, |sent, total| {
+            let Some(events) = &self.events else {
+                return;
+            };
+            // A few updates per second is plenty, a video has thousands of chunks
+            if sent < total && last_report.is_some_and(|at| at.elapsed() < PUSH_PROGRESS_INTERVAL) {
+                return;
+            }
+            last_report = Some(std::time::Instant::now());
+            let fraction = sent as f32 / total.max(1) as f32;
+            let rate = sent as f64 / started.elapsed().as_secs_f64().max(0.001);
+            let _ = events.send(AppMessage::Progress(
+                0.2 + 0.3 * fraction,
+                format!(
+                    "Pushing via ADB... {} / {} ({}/s)",
+                    crate::media_browser::format_size(sent),
+                    crate::media_browser::format_size(total),
+                    crate::media_browser::format_size(rate as u64)
+                ),
+            ));
+        })?;
 
         // Verify file exists and has correct size
         let expected_size = std::fs::metadata(local_path)?.len();
-        let size_check = Command::new("adb")
-            .args(["shell", "stat", "-c", "%s", &remote_path])
-            .output()?;
-        
-        if size_check.status.success() {
-            let remote_size: u64 = String::from_utf8_lossy(&size_check.stdout)
-                .trim()
-                .parse()
-                .unwrap_or(0);
-            
-            if remote_size != expected_size {
+        if let Some(remote) = adb.stat(&remote_path)? {
+            if remote.size != expected_size {
                 anyhow::bail!(
                     "File size mismatch: local={}, remote={}",
                     expected_size,
-                    remote_size
+                    remote.size
                 );
             }
-            log::info!("Verified file size: {} bytes", remote_size);
+            log::info!("Verified file size: {} bytes", remote.size);
         }
 
         // Small delay to ensure device has processed the file
@@ -103,101 +605,973 @@ impl AioCoolerController {
         Ok(())
     }
 
-    /// Send screen configuration command with sysinfo to keep connection alive.
-    /// Skip transport/transported commands for nowbecause those expect file data over serial.
+    /// Size of a file in /sdcard/pcMedia, or None if it isn't there
+    pub fn remote_file_size(&self, remote_name: &str) -> Result<Option<u64>> {
+        let mut adb = AdbClient::connect(&self.adb)?;
+        Ok(adb.stat(&media_path(remote_name))?.map(|stat| stat.size))
+    }
+
+    /// Open a session and apply the screen configuration. The port stays open
+    /// in Streaming state afterwards, run `heartbeat` to keep the display alive.
+    /// With `upload` set the file is streamed over serial first (transport/transported),
+    /// otherwise it is expected to be on the device already (ADB push).
     pub fn send_image_commands(
         &self,
         file_name: &str,
-        _file_size: u64,
-        _file_md5: &str,
+        file_size: u64,
+        file_md5: &str,
+        upload: Option<&PathBuf>,
         config: &ScreenConfig,
     ) -> Result<()> {
-        log::info!("Opening serial port: {}", self.serial_device);
+        let result = self.run_image_commands(file_name, file_size, file_md5, upload, config);
+        if let Err(e) = &result {
+            let _ = self.close_session();
+            self.set_state(ConnectionState::Error(format!("{:#}", e)));
+        }
+        result
+    }
 
-        let mut port = serialport::new(&self.serial_device, 115200)
-            .timeout(Duration::from_secs(2))
-            .open()
-            .context("Failed to open serial port")?;
+    /// Open a session and send sysinfo without touching the screen config, for
+    /// a `heartbeat` with no media
+    pub fn start_stream(&self, config: &ScreenConfig) -> Result<()> {
+        let result = (|| -> Result<()> {
+            let mut port = self.open_session(config)?;
+            self.send_sysinfo(&mut port)?;
+            self.set_state(ConnectionState::Handshaked);
+            self.drain_responses(&mut port, Duration::from_millis(200))?;
+            self.set_state(ConnectionState::Streaming);
+            *self.session.lock().unwrap() = Some(port);
+            Ok(())
+        })();
+        if let Err(e) = &result {
+            let _ = self.close_session();
+            self.set_state(ConnectionState::Error(format!("{:#}", e)));
+        }
+        result
+    }
 
-        // Clear buffers
-        thread::sleep(Duration::from_millis(100));
-        let _ = port.clear(serialport::ClearBuffer::All);
+    /// Send sysinfo every `interval_ms` until cancelled (or a handful of times when
+    /// the heartbeat is disabled), then close the session
+    pub fn heartbeat(&self, config: &ScreenConfig, file_name: &str) -> Result<()> {
+        let mut result = self.run_heartbeat(config, file_name);
+        let closed = self.close_session();
+        if result.is_ok() {
+            result = closed;
+        }
+        match &result {
+            Ok(()) => self.set_state(ConnectionState::Disconnected),
+            Err(e) => self.set_state(ConnectionState::Error(format!("{:#}", e))),
+        }
+        result
+    }
+
+    fn run_heartbeat(&self, config: &ScreenConfig, file_name: &str) -> Result<()> {
+        let mut port = self.session.lock().unwrap().take().context("No open session to keep alive")?;
+        let mut config = config.clone();
+        let mut file_name = file_name.to_string();
+        let mut interval = Duration::from_millis(config.heartbeat.interval_ms.max(100));
+        let max_beats = if config.heartbeat.enabled { u64::MAX } else { 5 };
+        log::info!("Heartbeat every {:?}", interval);
+
+        let mut saver = ScreenSaver::new(config.screensaver.clone());
+        let mut alerts = Alerts::new(config.alerts.clone());
+        // Showing the lock screen, the saver and alerts wait until unlocked
+        let mut locked = false;
+        // Likewise while idle without input, until the next input
+        let mut blanked = false;
+        let mut rules = ProfileRules::new(config.profile_rules.clone());
+        let mut games = GameDetector::new(config.game_detect.clone());
+        // What was applied, shown again once no rule, game or script picks anything
+        let mut applied = (config.clone(), file_name.clone());
+        let mut picked = None;
+        let mut beats = 0u64;
+        while beats < max_beats {
+            let beat = (|| -> Result<bool> {
+                // Keep the normal pace while alerting so recovery and flashing aren't delayed
+                let wait = if alerts.active() { interval } else { saver.interval(interval) };
+                self.drain_until_cancelled(&mut port, wait)?;
+                if self.is_cancelled() {
+                    return Ok(false);
+                }
+                // A hung-up tty just reads EOF, so check the node itself too
+                if !T::is_present(&self.serial_device) {
+                    return Err(DeviceGone { device: self.serial_device.clone(), reason: "device node removed".to_string() }.into());
+                }
+                if let Some(next) = self.reload.as_ref().and_then(|reload| reload.try_iter().last())
+                    && (next.media != applied.1 || !same_config(&next.config, &applied.0))
+                {
+                    // Line settings only change when the port is next opened
+                    log::info!("Reloading configuration ({})", next.media);
+                    if std::mem::take(&mut locked) && config.lock_screen.media.is_empty() {
+                        self.send_frame(&mut port, DeviceCommand::ScreenPower(true).build()?)?;
+                    }
+                    if std::mem::take(&mut blanked) {
+                        self.leave_idle_screen(&mut port, &config, None)?;
+                    }
+                    crate::sysinfo::configure(&next.config.sensors);
+                    crate::compression::configure(&next.config.compression);
+                    self.send_reliable(&mut port, &DeviceCommand::ScreenConfig(screen_payload(&next.config, &next.media)))?;
+                    interval = Duration::from_millis(next.config.heartbeat.interval_ms.max(100));
+                    saver = ScreenSaver::new(next.config.screensaver.clone());
+                    alerts = Alerts::new(next.config.alerts.clone());
+                    rules = ProfileRules::new(next.config.profile_rules.clone());
+                    games = GameDetector::new(next.config.game_detect.clone());
+                    applied = (next.config.clone(), next.media.clone());
+                    picked = None;
+                    config = next.config;
+                    file_name = next.media;
+                }
+                let info = self.send_sysinfo(&mut port)?;
+                // Profile rules, then a running game, then the script. Without
+                // applied media the screen is the device's own, not theirs to change.
+                let now = std::time::Instant::now();
+                let game = games.update(&info, now).map(str::to_string);
+                let choice = rules
+                    .update(&info, now)
+                    .map(str::to_string)
+                    .or(game)
+                    .map(Choice::Profile)
+                    .or_else(crate::scripting::choice);
+                if choice != picked && !applied.1.is_empty() {
+                    let (next_config, next_media) = match &choice {
+                        Some(choice) => crate::scripting::resolve(choice, &applied.0)
+                            .inspect_err(|e| {
+                                log::warn!("Can't show {}: {:#}", choice.describe(), e);
+                                if let Some(events) = &self.events {
+                                    let _ = events.send(AppMessage::Error(format!("Can't show {}: {:#}", choice.describe(), e)));
+                                }
+                            })
+                            .unwrap_or_else(|_| applied.clone()),
+                        None => applied.clone(),
+                    };
+                    picked = choice;
+                    if next_media != file_name || !same_config(&next_config, &config) {
+                        log::info!("Switching the screen to {}", next_media);
+                        // The lock screen, saver and alerts bring this back when they end
+                        if !locked && !blanked && !saver.active() && !alerts.active() {
+                            self.send_frame(&mut port, DeviceCommand::ScreenConfig(screen_payload(&next_config, &next_media)).build()?)?;
+                        }
+                        config = next_config;
+                        file_name = next_media;
+                    }
+                }
+                // Without media the screen is the device's own, only sysinfo is ours
+                if !file_name.is_empty() {
+                    let lock = crate::session_lock::enabled(&config) && crate::session_lock::locked();
+                    if lock != locked {
+                        locked = lock;
+                        if locked {
+                            log::info!("Session locked, switching to the lock screen");
+                            // From the idle screen only the panel's power or brightness needs
+                            // undoing, and not even that when both turn it off
+                            if std::mem::take(&mut blanked) && !(config.input_idle.media.is_empty() && config.lock_screen.media.is_empty()) {
+                                self.leave_idle_screen(&mut port, &config, None)?;
+                            }
+                            self.show_lock_screen(&mut port, &config)?;
+                        } else {
+                            log::info!("Session unlocked, restoring the screen");
+                            self.leave_lock_screen(&mut port, &config, &file_name)?;
+                            saver = ScreenSaver::new(config.screensaver.clone());
+                            alerts = Alerts::new(config.alerts.clone());
+                        }
+                    }
+                    let idle = !locked && crate::input_idle::idle(&config);
+                    if idle != blanked {
+                        blanked = idle;
+                        if blanked {
+                            log::info!("No input for {} minutes, {}", config.input_idle.minutes, if config.input_idle.media.is_empty() { "turning the panel off" } else { "dimming to the idle screen" });
+                            self.show_idle_screen(&mut port, &config)?;
+                        } else {
+                            log::info!("Input again, waking the panel");
+                            self.leave_idle_screen(&mut port, &config, Some(&file_name))?;
+                            saver = ScreenSaver::new(config.screensaver.clone());
+                            alerts = Alerts::new(config.alerts.clone());
+                        }
+                    }
+                    if !locked && !blanked {
+                        self.apply_screen_events(&mut port, &mut saver, &mut alerts, &info, &config, &file_name)?;
+                    }
+                }
+                Ok(true)
+            })();
+
+            match beat {
+                Ok(true) => {
+                    beats += 1;
+                    log::debug!("Heartbeat {}", beats);
+                }
+                Ok(false) => {
+                    log::info!("Heartbeat stopped after {} beats", beats);
+                    break;
+                }
+                Err(e) if config.heartbeat.auto_reconnect && is_disconnect(&e) => {
+                    log::warn!("{:#}", e);
+                    // The config is sent again, the next beat locks or blanks again if need be
+                    locked = false;
+                    blanked = false;
+                    match self.reconnect(&config, &file_name)? {
+                        Some(reopened) => port = reopened,
+                        None => return Ok(()),
+                    }
+                }
+                Err(e) => {
+                    *self.session.lock().unwrap() = Some(port);
+                    return Err(e);
+                }
+            }
+        }
+
+        // Put the port back so close_session shuts both halves down together
+        *self.session.lock().unwrap() = Some(port);
+        Ok(())
+    }
+
+    /// Flush queued frames, then close the writer thread and the port
+    fn close_session(&self) -> Result<()> {
+        let writer = self.writer.lock().unwrap().take();
+        let flushed = match writer {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        };
+        self.session.lock().unwrap().take();
+        flushed
+    }
+
+    fn run_image_commands(
+        &self,
+        file_name: &str,
+        file_size: u64,
+        file_md5: &str,
+        upload: Option<&PathBuf>,
+        config: &ScreenConfig,
+    ) -> Result<()> {
+        let mut port = self.timed("serial open", || self.open_session(config))?;
 
         // Send initial sysinfo to establish connection
         log::info!("Sending initial sysinfo...");
-        self.send_sysinfo(&mut port)?;
-        thread::sleep(Duration::from_millis(200));
+        self.timed("sysinfo (initial)", || self.send_sysinfo(&mut port))?;
+        self.set_state(ConnectionState::Handshaked);
+        self.drain_responses(&mut port, Duration::from_millis(200))?;
+
+        if let Some(local_path) = upload {
+            self.timed("serial upload", || {
+                self.serial_upload(&mut port, local_path, file_name, file_size, file_md5)
+            })?;
+        }
 
         // Clean up old media files FIRST to avoid playlist fuckery
-        log::info!("Cleaning up old media files (keeping: {})", file_name);
-        send_command(
-            &mut port,
-            "mediaDelete",
-            &serde_json::json!({
-                "exclude": [file_name]
-            }),
-        )?;
-        thread::sleep(Duration::from_millis(300));
+        if config.keep_old_media {
+            log::info!("Keeping old media files on the device");
+        } else {
+            log::info!("Cleaning up old media files (keeping: {})", file_name);
+            self.timed("mediaDelete", || {
+                self.send_checked(
+                    &mut port,
+                    &DeviceCommand::MediaDelete(MediaDeletePayload { exclude: vec![file_name.to_string()] }),
+                )
+                .context("Device could not remove old media")
+            })?;
+            self.drain_responses(&mut port, Duration::from_millis(300))?;
+        }
 
         // Keepalive
-        self.send_sysinfo(&mut port)?;
-        thread::sleep(Duration::from_millis(200));
+        self.timed("sysinfo (keepalive)", || self.send_sysinfo(&mut port))?;
+        self.drain_responses(&mut port, Duration::from_millis(200))?;
 
         // Send screen config with new file
         log::info!("Sending screen configuration for: {}", file_name);
-        send_command(
-            &mut port,
-            "waterBlockScreenId",
-            &serde_json::json!({
-                "id": config.id,
-                "screenMode": config.screen_mode,
-                "playMode": config.play_mode,
-                "ratio": config.ratio,
-                "media": [file_name],
-                "settings": {
-                    "color": config.color,
-                    "align": config.align,
-                    "filter": {
-                        "value": null,
-                        "opacity": config.filter_opacity
-                    },
-                    "badges": config.badges
-                },
-                "sysinfoDisplay": config.sysinfo_display
+        let command = DeviceCommand::ScreenConfig(screen_payload(config, file_name));
+        let sent = command.payload()?;
+        self.timed("waterBlockScreenId", || {
+            self.send_checked(&mut port, &command).context("Device rejected the screen configuration")
+        })?;
+        self.timed("verify config", || self.verify_screen_config(&mut port, &sent))?;
+
+        self.set_state(ConnectionState::Streaming);
+        *self.session.lock().unwrap() = Some(port);
+
+        log::info!("Screen configuration sent successfully!");
+        Ok(())
+    }
+
+    /// Open the device with the protocol profile assigned to it, retrying while
+    /// it's busy or still enumerating
+    fn open_port(&self, serial: &SerialSettings) -> Result<T> {
+        let profile = ProfileStore::load().for_device(&self.serial_device);
+        let serial = profile.line_settings(serial);
+        crate::protocol_profile::set_active(profile);
+        self.retry.run(
+            &format!("Opening {}", self.serial_device),
+            |e| !self.is_cancelled() && is_transient_open_error(e),
+            || T::open(&self.serial_device, &serial),
+        )
+    }
+
+    /// Write outside the writer thread, retrying writes that time out
+    fn write_direct(&self, port: &mut T, frame: &OutgoingFrame) -> Result<()> {
+        self.retry.run(&format!("Writing {}", frame.cmd_type), is_transient_write_error, || Ok(write_frame(port, frame)?))
+    }
+
+    /// Open the port and start the writer thread for a new session
+    fn open_session(&self, config: &ScreenConfig) -> Result<T> {
+        log::info!("Opening serial port: {} ({})", self.serial_device, config.serial.summary());
+        let mut port = self.open_port(&config.serial)?;
+        self.set_state(ConnectionState::SerialOpen);
+        crate::link_stats::stats().start_session();
+        crate::compression::configure(&config.compression);
+        crate::sysinfo::configure(&config.sensors);
+        *self.acks.lock().unwrap() = AckTracker::new(config.reliability.clone());
+
+        // Clear buffers
+        thread::sleep(Duration::from_millis(100));
+        let _ = port.discard_buffers();
+        self.assembler.lock().unwrap().clear();
+
+        // All writes from here on go through the writer thread
+        let write_half = port.try_clone().context("Failed to clone port for writing")?;
+        *self.writer.lock().unwrap() =
+            Some(SerialWriter::spawn(Box::new(write_half), config.writer.clone(), self.retry.clone()));
+        Ok(port)
+    }
+
+    /// Wait for the device to come back after a disconnect, then reopen it and
+    /// re-send the config (the display resets when the cooler re-enumerates).
+    /// Returns None if cancelled while waiting.
+    fn reconnect(&self, config: &ScreenConfig, file_name: &str) -> Result<Option<T>> {
+        self.set_state(ConnectionState::Reconnecting);
+        let _ = self.close_session();
+
+        let timeout = Duration::from_secs(config.heartbeat.reconnect_timeout_secs);
+        let deadline = std::time::Instant::now() + timeout;
+        log::info!("Waiting up to {:?} for {} to come back", timeout, self.serial_device);
+
+        while !self.is_cancelled() {
+            if std::time::Instant::now() >= deadline {
+                anyhow::bail!("{} did not come back within {:?}", self.serial_device, timeout);
+            }
+            crate::systemd::watchdog();
+            if !T::is_present(&self.serial_device) {
+                thread::sleep(Duration::from_millis(500));
+                continue;
+            }
+
+            // Let the device finish booting its serial service
+            thread::sleep(Duration::from_secs(1));
+            let resumed = (|| -> Result<T> {
+                let mut port = self.open_session(config)?;
+                self.send_sysinfo(&mut port)?;
+                self.set_state(ConnectionState::Handshaked);
+                self.drain_responses(&mut port, Duration::from_millis(200))?;
+                if !file_name.is_empty() {
+                    self.send_reliable(&mut port, &DeviceCommand::ScreenConfig(screen_payload(config, file_name)))?;
+                }
+                self.set_state(ConnectionState::Streaming);
+                Ok(port)
+            })();
+            match resumed {
+                Ok(port) => {
+                    log::info!("Reconnected to {}, heartbeat resumed", self.serial_device);
+                    return Ok(Some(port));
+                }
+                Err(e) => {
+                    log::warn!("Reconnect attempt failed: {:#}", e);
+                    let _ = self.close_session();
+                    self.set_state(ConnectionState::Reconnecting);
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Stream a file over serial the way the Windows app does:
+    /// `transport` opens it on the device, raw chunks follow, `transported` checks the MD5
+    fn serial_upload(
+        &self,
+        port: &mut T,
+        local_path: &PathBuf,
+        remote_name: &str,
+        file_size: u64,
+        file_md5: &str,
+    ) -> Result<()> {
+        let mut file = std::fs::File::open(local_path)
+            .with_context(|| format!("Failed to read {}", local_path.display()))?;
+        log::info!("Uploading {} over serial as {} ({} bytes)", local_path.display(), remote_name, file_size);
+
+        self.send_reliable(
+            port,
+            &DeviceCommand::Transport(TransportPayload {
+                file_name: remote_name.to_string(),
+                file_size,
+                md5: file_md5.to_string(),
             }),
         )?;
+        self.drain_responses(port, Duration::from_millis(300))?;
 
-        // Send several sysinfo updates to keep connection alive and display temps
-        log::info!("Sending sysinfo updates to keep connection alive...");
-        for i in 0..5 {
-            thread::sleep(Duration::from_millis(800));
-            self.send_sysinfo(&mut port)?;
-            log::debug!("Sysinfo update {}/5", i + 1);
+        // Read a chunk at a time rather than holding a whole video
+        let mut chunk = Vec::with_capacity(SERIAL_CHUNK_SIZE);
+        let mut sent = 0u64;
+        for i in 0usize.. {
+            if self.is_cancelled() {
+                anyhow::bail!("Serial upload cancelled");
+            }
+            chunk.clear();
+            (&mut file)
+                .take(SERIAL_CHUNK_SIZE as u64)
+                .read_to_end(&mut chunk)
+                .with_context(|| format!("Failed to read {}", local_path.display()))?;
+            if chunk.is_empty() {
+                break;
+            }
+            let msg = CommandMessageWithMethod::file_chunk("transport", remote_name, file_size, sent, &chunk);
+            self.send_frame(port, build_message(&msg)?)?;
+            sent += chunk.len() as u64;
+            crate::systemd::watchdog();
+
+            if let Some(events) = &self.events {
+                let fraction = sent as f32 / file_size.max(1) as f32;
+                let _ = events.send(AppMessage::Progress(
+                    0.5 + 0.3 * fraction,
+                    format!("Uploading over serial... {}/{} KiB", sent / 1024, file_size / 1024),
+                ));
+            }
+            // Pick up ACKs/errors now and then without stalling the stream
+            if i % 16 == 15 {
+                self.drain_responses(port, Duration::from_millis(20))?;
+            }
         }
 
-        log::info!("Screen configuration sent successfully!");
+        self.send_reliable(
+            port,
+            &DeviceCommand::Transported(TransportedPayload {
+                file_name: remote_name.to_string(),
+                md5: file_md5.to_string(),
+            }),
+        )?;
+        self.drain_responses(port, Duration::from_millis(500))?;
+        log::info!("Serial upload of {} finished", remote_name);
+        Ok(())
+    }
+
+    /// Re-send captured frames with their original spacing, scaled by `speed`.
+    /// Whatever the device answers is handled and logged like in a normal session.
+    pub fn replay(&self, frames: &[crate::replay::ReplayFrame], speed: f32, serial: &SerialSettings) -> Result<()> {
+        let mut port = self.open_port(serial)?;
+        self.set_state(ConnectionState::SerialOpen);
+        crate::link_stats::stats().start_session();
+        let _ = port.discard_buffers();
+        self.assembler.lock().unwrap().clear();
+
+        let result = (|| -> Result<()> {
+            let start = std::time::Instant::now();
+            for (i, replay) in frames.iter().enumerate() {
+                let due = replay.at.div_f32(speed.max(0.01));
+                self.drain_responses(&mut port, due.saturating_sub(start.elapsed()))?;
+                if self.is_cancelled() {
+                    log::info!("Replay cancelled after {}/{} frames", i, frames.len());
+                    return Ok(());
+                }
+                self.write_direct(&mut port, &replay.frame)?;
+                if let Some(events) = &self.events {
+                    let _ = events.send(AppMessage::Progress(
+                        (i + 1) as f32 / frames.len() as f32,
+                        format!("Replaying frame {}/{}", i + 1, frames.len()),
+                    ));
+                }
+            }
+            // Catch the replies to the last frames
+            self.drain_responses(&mut port, Duration::from_millis(500))
+        })();
+
+        match &result {
+            Ok(()) => self.set_state(ConnectionState::Disconnected),
+            Err(e) => self.set_state(ConnectionState::Error(format!("{:#}", e))),
+        }
+        result
+    }
+
+    /// Send one frame and collect everything the device sends back within `listen`
+    pub fn exchange(&self, frame: &OutgoingFrame, listen: Duration, serial: &SerialSettings) -> Result<Vec<ResponseMessage>> {
+        let mut port = self.open_port(serial)?;
+        self.set_state(ConnectionState::SerialOpen);
+        let _ = port.discard_buffers();
+        self.assembler.lock().unwrap().clear();
+
+        let result = (|| -> Result<Vec<ResponseMessage>> {
+            self.write_direct(&mut port, frame)?;
+            let deadline = std::time::Instant::now() + listen;
+            let mut replies = Vec::new();
+            while !self.is_cancelled() {
+                let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                if remaining.is_zero() {
+                    break;
+                }
+                if let Some(response) = read_response(&mut port, &mut self.assembler.lock().unwrap(), remaining)? {
+                    self.handle_response(&mut port, &response)?;
+                    replies.push(response);
+                }
+            }
+            Ok(replies)
+        })();
+
+        self.set_state(ConnectionState::Disconnected);
+        result
+    }
+
+    /// Run the configured exit action and close the port cleanly
+    pub fn shutdown(&self, config: &ScreenConfig) -> Result<()> {
+        if config.shutdown_action == ShutdownAction::LeaveAsIs {
+            return Ok(());
+        }
+        if config.shutdown_action == ShutdownAction::ScreenOff && !config.experimental_commands {
+            log::warn!("Screen off on exit needs the experimental screen commands, leaving the display as is");
+            return Ok(());
+        }
+
+        log::info!("Running shutdown action: {}", config.shutdown_action.label());
+        let mut port = self.open_port(&config.serial)?;
+
+        let command = match &config.shutdown_action {
+            ShutdownAction::LeaveAsIs => return Ok(()),
+            ShutdownAction::ScreenOff => DeviceCommand::ScreenPower(false),
+            ShutdownAction::ParkedImage(file_name) => DeviceCommand::ScreenConfig(screen_payload(config, file_name)),
+        };
+        self.retry.run("Shutdown command", is_transient_write_error, || Ok(send_device_command(&mut port, &command)?))?;
+
+        // Give the device a moment to read the frame before the fd goes away
+        thread::sleep(Duration::from_millis(200));
         Ok(())
     }
 
     /// Send current system info (CPU/GPU temps, etc)
-    fn send_sysinfo(&self, port: &mut Box<dyn serialport::SerialPort>) -> Result<()> {
-        let info = SysInfo::get_sysinfo();
-        let json = serde_json::to_value(&info)?;
-        send_state_command(port, "all", &json)?;
+    fn send_sysinfo(&self, port: &mut T) -> Result<SysInfo> {
+        let info = SysInfo::snapshot();
+        let sent = info.in_units();
+        let frame = DeviceCommand::SysInfo(Box::new(sent.clone())).build()?;
+        match self.writer.lock().unwrap().as_ref() {
+            Some(writer) => writer.send_sysinfo(frame)?,
+            None => self.write_direct(port, &frame)?,
+        }
         log::debug!("Sysinfo: CPU {}°C, GPU {}°C", info.cpu.temperature, info.gpu.temperature);
+        if let Some(broadcast) = &self.broadcast {
+            broadcast.send(crate::broadcast::Status::Sysinfo { sysinfo: Box::new(sent.clone()) });
+        }
+        if let Some(events) = &self.events {
+            let _ = events.send(AppMessage::SysInfo(Box::new(sent)));
+        }
+        // Alerts compare against the raw sample, their thresholds are in °C
+        Ok(info)
+    }
+
+    /// Read and handle whatever the device sends for `duration`.
+    /// Used in place of a plain sleep between commands.
+    fn drain_responses(&self, port: &mut T, duration: Duration) -> Result<()> {
+        let deadline = std::time::Instant::now() + duration;
+        loop {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                return Ok(());
+            }
+            let response = read_response(port, &mut self.assembler.lock().unwrap(), remaining)?;
+            if let Some(response) = &response {
+                self.handle_response(port, response)?;
+            }
+            self.retransmit_overdue(port)?;
+            if response.is_none() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Queue a frame on the writer thread, or write it directly outside a session
+    fn send_frame(&self, port: &mut T, frame: OutgoingFrame) -> Result<()> {
+        match self.writer.lock().unwrap().as_ref() {
+            Some(writer) => writer.send(frame),
+            None => self.write_direct(port, &frame),
+        }
+    }
+
+    /// Send a command that reports a status and wait for it. Either a reply to its
+    /// msgId or an ACK of its SeqNumber counts, a nonzero `code` is a `ProtocolError::Nack`.
+    /// Firmware that stays silent is assumed to have applied the command.
+    fn send_checked(&self, port: &mut T, command: &DeviceCommand) -> Result<()> {
+        let msg_id = self.dispatcher.lock().unwrap().register(command.cmd_type());
+        let mut frame = command.build_with_msg_id(msg_id)?;
+        let mut fallback = frame.fallback.take();
+        let mut seq_number = frame.seq_number;
+        self.send_frame(port, frame.clone())?;
+        self.acks.lock().unwrap().track(frame);
+
+        let deadline = std::time::Instant::now() + STATUS_TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                self.dispatcher.lock().unwrap().forget(msg_id);
+                log::info!("No status for {} within {:?}, assuming it was applied", command.cmd_type(), STATUS_TIMEOUT);
+                return Ok(());
+            }
+            if let Some(response) = read_response(port, &mut self.assembler.lock().unwrap(), remaining)? {
+                let answered = self.handle_response(port, &response)?;
+                if answered == Some(msg_id) || response.ack_number == seq_number {
+                    let Some(nack) = response.nack() else {
+                        self.dispatcher.lock().unwrap().forget(msg_id);
+                        log::debug!("{} status: {}", command.cmd_type(), response.body_str());
+                        return Ok(());
+                    };
+                    let Some(plain) = fallback.take() else {
+                        self.dispatcher.lock().unwrap().forget(msg_id);
+                        return Err(nack.into());
+                    };
+                    seq_number = plain.seq_number;
+                    self.dispatcher.lock().unwrap().rearm(msg_id, command.cmd_type());
+                    self.send_uncompressed(port, *plain)?;
+                }
+            }
+            self.retransmit_overdue(port)?;
+        }
+    }
+
+    /// Send a command and keep it around until the device ACKs its SeqNumber
+    fn send_reliable(&self, port: &mut T, command: &DeviceCommand) -> Result<()> {
+        let frame = command.build()?;
+        self.send_frame(port, frame.clone())?;
+        self.acks.lock().unwrap().track(frame);
         Ok(())
     }
 
-    pub fn calculate_md5(path: &PathBuf) -> Result<String> {
-        let mut file = std::fs::File::open(path)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
-        Ok(format!("{:x}", md5::compute(&buffer)))
+    /// Like `drain_responses`, but returns early once the cancel flag is set
+    /// so stopping a long heartbeat interval doesn't stall the caller
+    fn drain_until_cancelled(&self, port: &mut T, duration: Duration) -> Result<()> {
+        let deadline = std::time::Instant::now() + duration;
+        while !self.is_cancelled() && !self.reload_pending() {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            crate::systemd::watchdog();
+            self.send_queued(port)?;
+            self.drain_responses(port, remaining.min(Duration::from_millis(100)))?;
+        }
+        Ok(())
     }
 
-    pub fn generate_filename(extension: &str) -> String {
-        let now = chrono::Local::now();
-        now.format(&format!("%Y-%m-%d_%H-%M-%S-%3f.{}", extension))
-            .to_string()
+    /// Send what the device controls queued. A rejected command is reported
+    /// without ending the session, a lost port is not.
+    fn send_queued(&self, port: &mut T) -> Result<()> {
+        let Some(commands) = &self.commands else {
+            return Ok(());
+        };
+        while let Ok(command) = commands.try_recv() {
+            match self.send_checked(port, &command) {
+                Ok(()) => log::info!("Sent {} {:?}", command.cmd_type(), command.payload()?),
+                Err(e) if is_disconnect(&e) => return Err(e),
+                Err(e) => {
+                    if let Some(events) = &self.events {
+                        let _ = events.send(AppMessage::Error(format!("{} failed: {:#}", command.cmd_type(), e)));
+                    }
+                }
+            }
+        }
+        Ok(())
     }
+
+    /// Open the port, send one command and wait for its status, outside a session
+    pub fn send_once(&self, command: &DeviceCommand, serial: &SerialSettings) -> Result<()> {
+        let mut port = self.open_port(serial)?;
+        self.set_state(ConnectionState::SerialOpen);
+        let result = self.send_checked(&mut port, command);
+        self.set_state(ConnectionState::Disconnected);
+        result
+    }
+
+    /// Send a command and wait for the reply carrying the same msgId.
+    /// Anything else that arrives meanwhile is handled as usual.
+    pub fn request(
+        &self,
+        port: &mut T,
+        command: &DeviceCommand,
+        timeout: Duration,
+    ) -> Result<ResponseMessage> {
+        let msg_id = self.dispatcher.lock().unwrap().register(command.cmd_type());
+        let mut frame = command.build_with_msg_id(msg_id)?;
+        let mut fallback = frame.fallback.take();
+        self.send_frame(port, frame)?;
+
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                self.dispatcher.lock().unwrap().forget(msg_id);
+                return Err(ProtocolError::Timeout { cmd_type: command.cmd_type().to_string(), msg_id, timeout }.into());
+            }
+            if let Some(response) = read_response(port, &mut self.assembler.lock().unwrap(), remaining)? {
+                let answered = self.handle_response(port, &response)?;
+                if answered == Some(msg_id) {
+                    let Some(nack) = response.nack() else {
+                        return Ok(response);
+                    };
+                    let Some(plain) = fallback.take() else {
+                        return Err(nack.into());
+                    };
+                    // Same msgId, so keep waiting for the reply to the plain one
+                    crate::compression::reject(&plain.cmd_type);
+                    self.dispatcher.lock().unwrap().rearm(msg_id, command.cmd_type());
+                    self.send_frame(port, *plain)?;
+                }
+            }
+            self.retransmit_overdue(port)?;
+        }
+    }
+
+    /// Resend commands whose ACK is overdue, give up on ones out of retries
+    fn retransmit_overdue(&self, port: &mut T) -> Result<()> {
+        let plan = self.acks.lock().unwrap().check_timeouts(std::time::Instant::now());
+        for frame in &plan.resend {
+            log::warn!("No ACK for {} Seq={}, retransmitting", frame.cmd_type, frame.seq_number);
+            self.send_frame(port, frame.clone())?;
+        }
+        for frame in plan.gave_up {
+            match frame.fallback {
+                // Firmware that can't parse a compressed body may just stay silent
+                Some(plain) => self.send_uncompressed(port, *plain)?,
+                None => log::error!("No ACK for {} Seq={} after all retransmits, giving up", frame.cmd_type, frame.seq_number),
+            }
+        }
+        Ok(())
+    }
+
+    /// The device didn't take a compressed message, send the plain one and stop compressing
+    fn send_uncompressed(&self, port: &mut T, plain: OutgoingFrame) -> Result<()> {
+        crate::compression::reject(&plain.cmd_type);
+        self.send_frame(port, plain.clone())?;
+        self.acks.lock().unwrap().track(plain);
+        Ok(())
+    }
+
+    /// Returns the msgId of the pending request this message answers, if any
+    fn handle_response(&self, port: &mut T, response: &ResponseMessage) -> Result<Option<i64>> {
+        log::debug!("{} {} body: {}", response.method, response.cmd_type, response.body_str());
+
+        let acked = match response.ack_number {
+            ack if ack >= 0 => self.acks.lock().unwrap().acknowledge(ack).map(|(frame, _)| frame),
+            _ => None,
+        };
+        let answered = self.dispatcher.lock().unwrap().resolve(response);
+
+        // Requests get their Nack from `request`, fire-and-forget commands only show up here
+        if answered.is_none()
+            && response.ack_number >= 0
+            && let Some(nack) = response.nack()
+        {
+            match acked.and_then(|frame| frame.fallback) {
+                Some(plain) => self.send_uncompressed(port, *plain)?,
+                None => {
+                    log::warn!("{}", nack);
+                    if let Some(events) = &self.events {
+                        let _ = events.send(AppMessage::Log(nack.to_string()));
+                    }
+                }
+            }
+        }
+
+        if is_inbound(response, answered) {
+            match self.inbound.route(self, response) {
+                Ok(Routed::Handled(Some(reply))) => self.send_frame(port, reply)?,
+                Ok(Routed::Handled(None) | Routed::Unhandled) => {}
+                Err(e) => log::warn!("Handling {} {} failed: {:#}", response.method, response.cmd_type, e),
+            }
+        }
+        Ok(answered)
+    }
+
+    /// `conn`: the device announcing itself and what it supports
+    fn on_handshake(&self, message: &ResponseMessage) -> Result<Option<OutgoingFrame>> {
+        let body = message.json().context("Unparseable conn handshake")?;
+        if let Some(events) = &self.events {
+            let _ = events.send(AppMessage::Handshake(body));
+        }
+        Ok(None)
+    }
+
+    /// `all`: the device polling for system state. Answered with the latest
+    /// sample so the display keeps updating even when our own push interval drifts.
+    fn on_sysinfo_pull(&self, message: &ResponseMessage) -> Result<Option<OutgoingFrame>> {
+        let info = SysInfo::snapshot();
+        log::debug!("Device pulled sysinfo: CPU {}°C, GPU {}°C", info.cpu.temperature, info.gpu.temperature);
+        let body = DeviceCommand::SysInfo(Box::new(info.in_units())).payload()?;
+        Ok(Some(build_reply("STATE", message, &body)?))
+    }
+
+    /// Compare what we sent against the config the device reports as active
+    fn verify_screen_config(
+        &self,
+        port: &mut T,
+        sent: &serde_json::Value,
+    ) -> Result<()> {
+        let Some(actual) = self.read_back_screen_config(port)? else {
+            log::info!("Config read-back not available, skipping verification");
+            return Ok(());
+        };
+
+        let mismatches = crate::verify::compare(sent, &actual);
+        if mismatches.is_empty() {
+            log::info!("Device config matches what was sent");
+        }
+        for mismatch in &mismatches {
+            log::warn!("Config mismatch: {}", mismatch);
+        }
+
+        if let Some(events) = &self.events {
+            let _ = events.send(AppMessage::Verification(
+                mismatches.iter().map(|m| m.to_string()).collect(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Active screen config as reported by the device.
+    /// None when the firmware doesn't answer GET requests.
+    fn read_back_screen_config(
+        &self,
+        port: &mut T,
+    ) -> Result<Option<serde_json::Value>> {
+        self.query(port, Query::ScreenConfig)
+    }
+
+    /// GET a piece of device state. A timeout means the firmware doesn't support the query.
+    fn query(&self, port: &mut T, query: Query) -> Result<Option<serde_json::Value>> {
+        match self.request(port, &get_command(query), QUERY_TIMEOUT) {
+            Ok(response) => Ok(Some(response.json()?)),
+            // Either way the firmware doesn't support the query
+            Err(e) if matches!(e.downcast_ref(), Some(ProtocolError::Timeout { .. } | ProtocolError::Nack { .. })) => {
+                log::debug!("{:#}", e);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The panel off, or the lock image without readouts
+    fn show_lock_screen(&self, port: &mut T, config: &ScreenConfig) -> Result<()> {
+        let command = match config.minimal_screen(&config.lock_screen.media) {
+            Some((minimal, media)) => DeviceCommand::ScreenConfig(screen_payload(&minimal, &media)),
+            None => DeviceCommand::ScreenPower(false),
+        };
+        self.send_frame(port, command.build()?)
+    }
+
+    fn leave_lock_screen(&self, port: &mut T, config: &ScreenConfig, file_name: &str) -> Result<()> {
+        if config.lock_screen.media.is_empty() {
+            self.send_frame(port, DeviceCommand::ScreenPower(true).build()?)?;
+        }
+        self.send_frame(port, DeviceCommand::ScreenConfig(screen_payload(config, file_name)).build()?)
+    }
+
+    /// The panel off, or the idle image dimmed
+    fn show_idle_screen(&self, port: &mut T, config: &ScreenConfig) -> Result<()> {
+        match config.minimal_screen(&config.input_idle.media) {
+            Some((minimal, media)) => {
+                self.send_frame(port, DeviceCommand::ScreenConfig(screen_payload(&minimal, &media)).build()?)?;
+                if config.experimental_commands {
+                    self.send_frame(port, DeviceCommand::Brightness(config.input_idle.dim_brightness).build()?)?;
+                }
+                Ok(())
+            }
+            None => self.send_frame(port, DeviceCommand::ScreenPower(false).build()?),
+        }
+    }
+
+    /// Power or brightness back, then `file_name` when given
+    fn leave_idle_screen(&self, port: &mut T, config: &ScreenConfig, file_name: Option<&str>) -> Result<()> {
+        if config.input_idle.media.is_empty() {
+            self.send_frame(port, DeviceCommand::ScreenPower(true).build()?)?;
+        } else if config.experimental_commands {
+            self.send_frame(port, DeviceCommand::Brightness(config.input_idle.brightness).build()?)?;
+        }
+        match file_name {
+            Some(file_name) => self.send_frame(port, DeviceCommand::ScreenConfig(screen_payload(config, file_name)).build()?),
+            None => Ok(()),
+        }
+    }
+
+    /// Swap between the normal, screen saver and alert configs when readings
+    /// cross their thresholds. An alert wins over the saver; once it clears,
+    /// whichever of the two applies then is restored.
+    fn apply_screen_events(
+        &self,
+        port: &mut T,
+        saver: &mut ScreenSaver,
+        alerts: &mut Alerts,
+        info: &SysInfo,
+        config: &ScreenConfig,
+        file_name: &str,
+    ) -> Result<()> {
+        let now = std::time::Instant::now();
+        let saver_event = saver.update(info.cpu.load, info.gpu.load_known.then_some(info.gpu.load), now);
+        match alerts.update(info, now) {
+            Some(AlertEvent::Enter(reasons)) => {
+                log::warn!("Alert: {}", reasons.join(", "));
+                let (warning, media) = alerts.alert_config(config, file_name);
+                self.send_frame(port, DeviceCommand::ScreenConfig(screen_payload(&warning, &media)).build()?)?;
+            }
+            Some(AlertEvent::Flash) => {
+                let (warning, media) = alerts.alert_config(config, file_name);
+                self.send_frame(port, DeviceCommand::ScreenConfig(screen_payload(&warning, &media)).build()?)?;
+            }
+            Some(AlertEvent::Exit) => {
+                log::info!("Readings recovered, clearing alert");
+                if saver.active() {
+                    let (minimal, media) = saver.saver_config(config, file_name);
+                    self.send_frame(port, DeviceCommand::ScreenConfig(screen_payload(&minimal, &media)).build()?)?;
+                } else {
+                    self.send_frame(port, DeviceCommand::ScreenConfig(screen_payload(config, file_name)).build()?)?;
+                }
+            }
+            // The alert screen stays up whatever the saver does underneath
+            None if alerts.active() => {}
+            None => match saver_event {
+                Some(SaverEvent::Enter) => {
+                    log::info!("Load idle, switching to screen saver");
+                    let (minimal, media) = saver.saver_config(config, file_name);
+                    self.send_frame(port, DeviceCommand::ScreenConfig(screen_payload(&minimal, &media)).build()?)?;
+                }
+                Some(SaverEvent::Exit) => {
+                    log::info!("Load is back, restoring dashboard");
+                    self.send_frame(port, DeviceCommand::ScreenConfig(screen_payload(config, file_name)).build()?)?;
+                }
+                None => {}
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Where the device looks for media named in waterBlockScreenId
+pub const MEDIA_DIR: &str = "/sdcard/pcMedia";
+
+pub fn media_path(remote_name: &str) -> String {
+    format!("{}/{}", MEDIA_DIR, remote_name)
+}
+
+/// waterBlockScreenId payload for a single media file
+fn screen_payload(config: &ScreenConfig, file_name: &str) -> ScreenConfigPayload {
+    ScreenConfigPayload {
+        id: config.id.clone(),
+        screen_mode: config.screen_mode.clone(),
+        play_mode: config.play_mode.clone(),
+        ratio: config.ratio.clone(),
+        media: vec![file_name.to_string()],
+        settings: ScreenSettings {
+            color: config.color.clone(),
+            align: config.align.clone(),
+            filter: FilterSettings { value: None, opacity: config.filter_opacity },
+            badges: config.badges.clone(),
+        },
+        sysinfo_display: config.sysinfo_display.clone(),
+    }
+}
+
+/// Same settings, compared the way they're saved
+fn same_config(a: &ScreenConfig, b: &ScreenConfig) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
 }
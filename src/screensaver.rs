@@ -0,0 +1,103 @@
+// Low-power "screen saver": after a stretch of low CPU/GPU load, swap the panel to a
+// minimal, overlay-free screen and slow down the sysinfo stream until load returns
+
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::screen_setup::ScreenConfig;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenSaverConfig {
+    pub enabled: bool,
+    /// Minutes below both thresholds before the saver kicks in
+    pub idle_minutes: u32,
+    pub cpu_threshold: u8,
+    pub gpu_threshold: u8,
+    /// Media already on the device to show while idle, empty keeps the current one dimmed
+    pub media: String,
+    /// Sysinfo interval while the saver is active
+    pub idle_interval_secs: u32,
+}
+
+impl Default for ScreenSaverConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            idle_minutes: 15,
+            cpu_threshold: 10,
+            gpu_threshold: 10,
+            media: String::new(),
+            idle_interval_secs: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SaverEvent {
+    Enter,
+    Exit,
+}
+
+pub struct ScreenSaver {
+    config: ScreenSaverConfig,
+    idle_since: Option<Instant>,
+    active: bool,
+}
+
+impl ScreenSaver {
+    pub fn new(config: ScreenSaverConfig) -> Self {
+        Self { config, idle_since: None, active: false }
+    }
+
+    /// Feed the latest load sample, returns a transition when one happens. A
+    /// GPU load that couldn't be read (None) never counts as idle.
+    pub fn update(&mut self, cpu_load: u8, gpu_load: Option<u8>, now: Instant) -> Option<SaverEvent> {
+        if !self.config.enabled {
+            return None;
+        }
+
+        let gpu_idle = gpu_load.is_some_and(|load| load < self.config.gpu_threshold);
+        let idle = cpu_load < self.config.cpu_threshold && gpu_idle;
+        if !idle {
+            self.idle_since = None;
+            if self.active {
+                self.active = false;
+                return Some(SaverEvent::Exit);
+            }
+            return None;
+        }
+
+        let since = *self.idle_since.get_or_insert(now);
+        let threshold = Duration::from_secs(self.config.idle_minutes as u64 * 60);
+        if !self.active && now.duration_since(since) >= threshold {
+            self.active = true;
+            return Some(SaverEvent::Enter);
+        }
+        None
+    }
+
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    /// Keepalive interval to use right now
+    pub fn interval(&self, normal: Duration) -> Duration {
+        if self.active {
+            Duration::from_secs(self.config.idle_interval_secs.max(1) as u64).max(normal)
+        } else {
+            normal
+        }
+    }
+
+    /// Minimal variant of `config`: no badges or sensor readouts, and the saver
+    /// image instead of the current media when one is set.
+    /// Returns the config and the media file to show.
+    pub fn saver_config(&self, config: &ScreenConfig, current_media: &str) -> (ScreenConfig, String) {
+        let mut minimal = config.minimal();
+        minimal.color = "#000000".to_string();
+
+        let media = if self.config.media.is_empty() { current_media } else { &self.config.media };
+        (minimal, media.to_string())
+    }
+}
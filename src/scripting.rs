@@ -0,0 +1,206 @@
+// ============================================================================
+// Sysinfo scripts
+// A rhai script, named by `SensorConfig::script`, runs after every sample. It
+// sees the sample as `info`, an object map shaped like the payload (°C, MiB and
+// KiB/s, before units are applied), and may change it, e.g. to derive a value
+// or to clamp one. `state` is a map kept between samples, for averages and
+// counters. Setting `profile` or `media` picks what the heartbeat shows instead
+// of the applied media, as long as the device already has it; leaving both
+// empty goes back to the applied one. `print` logs. The file is compiled again
+// when it changes, and a script that fails leaves the sample as it was.
+// ============================================================================
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+use anyhow::{Context, Result};
+use rhai::{AST, Dynamic, Engine, ImmutableString, Map, Scope};
+
+use crate::screen_setup::ScreenConfig;
+use crate::sysinfo::SysInfo;
+
+/// Enough for a few hundred lines of arithmetic, an endless loop stops here
+const MAX_OPERATIONS: u64 = 200_000;
+
+/// What the script asked to show
+#[derive(Debug, Clone, PartialEq)]
+pub enum Choice {
+    /// Media already on the device, with the applied screen config
+    Media(String),
+    /// A saved profile's look and image, when the image was pushed before
+    Profile(String),
+}
+
+impl Choice {
+    pub fn describe(&self) -> String {
+        match self {
+            Choice::Media(media) => format!("media {}", media),
+            Choice::Profile(name) => format!("profile {}", name),
+        }
+    }
+}
+
+struct Runner {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    engine: Engine,
+    /// None after a compile error, until the file changes
+    ast: Option<AST>,
+    /// `info`, `state`, `profile` and `media`; what the script declares is dropped after each run
+    scope: Scope<'static>,
+    /// Logged once, not after every sample
+    last_error: Option<String>,
+}
+
+static RUNNER: Mutex<Option<Runner>> = Mutex::new(None);
+static CHOICE: Mutex<Option<Choice>> = Mutex::new(None);
+
+/// Run the script at `path` over `info`, for the sampler. Empty turns scripting off.
+pub fn apply(path: &str, info: &mut SysInfo) {
+    let mut runner = RUNNER.lock().unwrap();
+    if path.is_empty() {
+        if runner.take().is_some() {
+            log::info!("Sysinfo script turned off");
+            *CHOICE.lock().unwrap() = None;
+        }
+        return;
+    }
+    let path = Path::new(path);
+    let modified = std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let runner = match runner.as_mut() {
+        Some(current) if current.path == path && current.modified == modified => current,
+        _ => {
+            *CHOICE.lock().unwrap() = None;
+            runner.insert(Runner::load(path, modified))
+        }
+    };
+    match runner.run(info) {
+        Ok(choice) => {
+            runner.last_error = None;
+            *CHOICE.lock().unwrap() = choice;
+        }
+        Err(e) => {
+            let message = format!("{:#}", e);
+            if runner.last_error.as_ref() != Some(&message) {
+                log::warn!("Sysinfo script {}: {}", runner.path.display(), message);
+                runner.last_error = Some(message);
+            }
+        }
+    }
+}
+
+/// What the script picked as of the latest sample
+pub fn choice() -> Option<Choice> {
+    CHOICE.lock().unwrap().clone()
+}
+
+impl Runner {
+    fn load(path: &Path, modified: Option<SystemTime>) -> Self {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| log::info!("Script: {}", text));
+        engine.on_debug(|text, _, position| log::debug!("Script {}: {}", position, text));
+
+        let ast = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))
+            .and_then(|source| engine.compile(source).map_err(|e| anyhow::anyhow!("{}", e)))
+            .inspect(|_| log::info!("Loaded sysinfo script {}", path.display()))
+            .inspect_err(|e| log::warn!("Sysinfo script {}: {:#}", path.display(), e))
+            .ok();
+
+        let mut scope = Scope::new();
+        scope.push("info", Dynamic::UNIT);
+        scope.push("state", Map::new());
+        scope.push("profile", String::new());
+        scope.push("media", String::new());
+        Self { path: path.to_path_buf(), modified, engine, ast, scope, last_error: None }
+    }
+
+    fn run(&mut self, info: &mut SysInfo) -> Result<Option<Choice>> {
+        let Some(ast) = &self.ast else {
+            return Ok(None);
+        };
+        let base = self.scope.len();
+        self.scope.set_value("info", rhai::serde::to_dynamic(&*info).map_err(|e| anyhow::anyhow!("{}", e))?);
+        let result = self.engine.run_ast_with_scope(&mut self.scope, ast).map_err(|e| anyhow::anyhow!("{}", e));
+        // Read before the script's own variables go, they may shadow ours
+        let changed = self.scope.get_value::<Dynamic>("info");
+        let profile = self.scope.get_value::<ImmutableString>("profile").unwrap_or_default();
+        let media = self.scope.get_value::<ImmutableString>("media").unwrap_or_default();
+        self.scope.rewind(base);
+        result?;
+
+        // Through JSON, which takes rhai's f64 for the f32 fields
+        let changed: SysInfo = serde_json::to_value(changed.unwrap_or_default())
+            .and_then(serde_json::from_value)
+            .context("`info` no longer fits the payload")?;
+        // Kept out of the payload, so the script never saw them
+        let core_usage = std::mem::take(&mut info.cpu.core_usage);
+        let core_temperatures = std::mem::take(&mut info.cpu.core_temperatures);
+        *info = changed;
+        info.cpu.core_usage = core_usage;
+        info.cpu.core_temperatures = core_temperatures;
+
+        Ok(if !profile.is_empty() {
+            Some(Choice::Profile(profile.to_string()))
+        } else if !media.is_empty() {
+            Some(Choice::Media(media.to_string()))
+        } else {
+            None
+        })
+    }
+}
+
+/// The screen config and media for `choice`, based on `config`. A profile
+/// brings its look, the heartbeat, sensors and the rest stay as applied.
+pub fn resolve(choice: &Choice, config: &ScreenConfig) -> Result<(ScreenConfig, String)> {
+    match choice {
+        Choice::Media(media) => Ok((config.clone(), media.clone())),
+        Choice::Profile(name) => {
+            let profile = crate::profiles::load(name)?;
+            let media = profile_media(name, &profile)?;
+            let look = profile.config;
+            let config = ScreenConfig {
+                id: look.id,
+                screen_mode: look.screen_mode,
+                play_mode: look.play_mode,
+                ratio: look.ratio,
+                color: look.color,
+                align: look.align,
+                filter_opacity: look.filter_opacity,
+                badges: look.badges,
+                sysinfo_display: look.sysinfo_display,
+                ..config.clone()
+            };
+            Ok((config, media))
+        }
+    }
+}
+
+/// The device's name for a profile's image, which only exists once the image
+/// was pushed, e.g. by using the profile
+fn profile_media(name: &str, profile: &crate::profiles::Profile) -> Result<String> {
+    let md5 = crate::media_cache::file_md5(&profile.image)?;
+    crate::media_cache::MediaCache::load()
+        .get(&md5)
+        .map(|media| media.remote_name.clone())
+        .with_context(|| format!("The device doesn't have profile {}'s image yet, use it once first", name))
+}
+
+/// Why profiles the rules or the gaming profile name can't be shown, by name.
+/// Checked when the settings are saved, so the window can say so before a
+/// rule or a game switches.
+pub fn profile_problems(config: &ScreenConfig) -> HashMap<String, String> {
+    let mut problems = HashMap::new();
+    let rules = config.profile_rules.rules.iter().filter(|_| config.profile_rules.enabled).map(|rule| &rule.profile);
+    let game = Some(&config.game_detect.profile).filter(|_| config.game_detect.enabled);
+    for name in rules.chain(game).filter(|name| !name.is_empty()) {
+        if let Err(e) = crate::profiles::load(name).and_then(|profile| profile_media(name, &profile)) {
+            problems.insert(name.clone(), format!("{:#}", e));
+        }
+    }
+    problems
+}
@@ -0,0 +1,354 @@
+// ============================================================================
+// Protocol self-test
+// `self-test [--count N]` round-trips randomized messages through the framing
+// code (escape, frame, fragment, reassemble, parse) and reports every mismatch.
+// With `--simulator` the same messages also go over a pty to the simulated
+// cooler, plus an upload it has to MD5-verify. `--compress` deflates bodies.
+// ============================================================================
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::{Context, Result};
+
+use crate::data::{
+    build_message, read_response, write_frame, CommandMessageWithMethod, ContentType, FrameAssembler, ResponseMessage,
+};
+use crate::serial::SerialSettings;
+use crate::transport::{SerialTransport, Transport};
+
+pub const DEFAULT_COUNT: usize = 5000;
+/// Failures printed in full, the rest are only counted
+const MAX_REPORTED: usize = 20;
+/// Size of the file uploaded to the simulator
+const UPLOAD_SIZE: usize = 256 * 1024;
+const UPLOAD_CHUNK: usize = 16 * 1024;
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Options {
+    pub count: usize,
+    pub seed: u64,
+    pub simulator: bool,
+    pub compress: bool,
+}
+
+/// Seed for a run without `--seed`
+pub fn clock_seed() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+}
+
+/// xorshift64*, plenty for test data and reproducible from the printed seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    fn range(&mut self, low: usize, high: usize) -> usize {
+        low + self.below(high - low)
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+/// A message to send and what it should decode back to
+struct Sample {
+    method: &'static str,
+    cmd_type: String,
+    content_type: ContentType,
+    seq_number: i64,
+    msg_id: i64,
+    body: Vec<u8>,
+}
+
+impl Sample {
+    fn random(rng: &mut Rng) -> Self {
+        const METHODS: [&str; 3] = ["POST", "GET", "STATE"];
+        const LETTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+        // Framing bytes and the header terminator are the interesting ones
+        const SPECIAL: [u8; 4] = [0x5A, 0x5B, b'\r', b'\n'];
+
+        let cmd_type = (0..rng.range(1, 24)).map(|_| LETTERS[rng.below(LETTERS.len())] as char).collect();
+        // Mostly small, some medium, a few big enough to be fragmented
+        let len = match rng.below(100) {
+            0..=89 => rng.below(512),
+            90..=97 => rng.range(512, 20_000),
+            _ => rng.range(60_000, 200_000),
+        };
+
+        let (content_type, body) = if rng.chance(50) {
+            let body = (0..len)
+                .map(|_| if rng.chance(25) { SPECIAL[rng.below(SPECIAL.len())] } else { rng.next() as u8 })
+                .collect();
+            (ContentType::Binary, body)
+        } else {
+            // 'Z' is 0x5A and '[' is 0x5B
+            let text: String = (0..len).map(|_| ['Z', '[', 'a', '\n', 'é'][rng.below(5)]).collect();
+            (ContentType::Json, serde_json::json!({ "value": text }).to_string().into_bytes())
+        };
+
+        Self {
+            method: METHODS[rng.below(METHODS.len())],
+            cmd_type,
+            content_type,
+            seq_number: rng.range(1, i32::MAX as usize) as i64,
+            msg_id: if rng.chance(50) { -1 } else { rng.range(1, 1_000_000) as i64 },
+            body,
+        }
+    }
+
+    fn message(&self) -> CommandMessageWithMethod<'_> {
+        let mut msg = CommandMessageWithMethod::new(self.method, &self.cmd_type, "");
+        msg.content_type = self.content_type;
+        msg.body = &self.body;
+        msg.seq_number = self.seq_number;
+        msg.msg_id = self.msg_id;
+        msg
+    }
+
+    /// Describe the first difference, None when `decoded` matches
+    fn compare(&self, decoded: &ResponseMessage) -> Option<String> {
+        let field = if decoded.method != self.method {
+            format!("method {} != {}", decoded.method, self.method)
+        } else if decoded.cmd_type != self.cmd_type {
+            format!("cmdType {} != {}", decoded.cmd_type, self.cmd_type)
+        } else if decoded.seq_number != self.seq_number {
+            format!("SeqNumber {} != {}", decoded.seq_number, self.seq_number)
+        } else if decoded.msg_id != self.msg_id {
+            format!("msgId {} != {}", decoded.msg_id, self.msg_id)
+        } else if decoded.content_type != self.content_type.as_str() {
+            format!("ContentType {} != {}", decoded.content_type, self.content_type.as_str())
+        } else if decoded.body != self.body {
+            let at = decoded.body.iter().zip(&self.body).position(|(a, b)| a != b).unwrap_or(decoded.body.len().min(self.body.len()));
+            format!("body differs at byte {} ({} vs {} bytes)", at, decoded.body.len(), self.body.len())
+        } else {
+            return None;
+        };
+        Some(format!("{} {} ({} bytes): {}", self.method, self.cmd_type, self.body.len(), field))
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    checked: usize,
+    bytes: usize,
+    failures: Vec<String>,
+}
+
+impl Report {
+    fn fail(&mut self, failure: String) {
+        self.failures.push(failure);
+    }
+
+    fn print(&self, stage: &str, elapsed: Duration) {
+        println!(
+            "{}: {} messages, {} bytes on the wire, {} failed ({:.1?})",
+            stage,
+            self.checked,
+            self.bytes,
+            self.failures.len(),
+            elapsed
+        );
+        for failure in self.failures.iter().take(MAX_REPORTED) {
+            println!("  FAIL {}", failure);
+        }
+        if self.failures.len() > MAX_REPORTED {
+            println!("  ... and {} more", self.failures.len() - MAX_REPORTED);
+        }
+    }
+}
+
+/// Run the test and return the process exit code
+pub fn run(options: &Options) -> i32 {
+    println!("Protocol self-test: {} messages, seed {}", options.count, options.seed);
+    if options.compress {
+        crate::compression::configure(&crate::compression::CompressionConfig {
+            enabled: true,
+            min_size: 256,
+            ..Default::default()
+        });
+    }
+
+    let started = Instant::now();
+    let codec = codec_round_trip(options);
+    codec.print("Codec", started.elapsed());
+    let mut failed = !codec.failures.is_empty();
+
+    if options.simulator {
+        let started = Instant::now();
+        match simulator_round_trip(options) {
+            Ok(report) => {
+                report.print("Simulator", started.elapsed());
+                failed |= !report.failures.is_empty();
+            }
+            Err(e) => {
+                println!("Simulator: could not run: {:#}", e);
+                failed = true;
+            }
+        }
+    }
+
+    println!("{}", if failed { "Self-test FAILED" } else { "Self-test passed" });
+    i32::from(failed)
+}
+
+/// Build each sample, feed it to a `FrameAssembler` in random-sized chunks with
+/// noise in front, and check exactly the original message comes back out
+fn codec_round_trip(options: &Options) -> Report {
+    let mut rng = Rng::new(options.seed);
+    let mut assembler = FrameAssembler::new();
+    let mut report = Report::default();
+
+    for index in 0..options.count {
+        let sample = Sample::random(&mut rng);
+        report.checked += 1;
+
+        let frame = match build_message(&sample.message()) {
+            Ok(frame) => frame,
+            Err(e) => {
+                report.fail(format!("#{} {} {}: build failed: {}", index, sample.method, sample.cmd_type, e));
+                continue;
+            }
+        };
+        report.bytes += frame.bytes.len();
+
+        let mut stream: Vec<u8> = Vec::new();
+        if rng.chance(20) {
+            // Line noise never contains the start marker, or it would be a (bad) frame
+            stream.extend((0..rng.range(1, 16)).map(|_| rng.next() as u8).filter(|&b| b != 0x5A));
+        }
+        stream.extend_from_slice(&frame.bytes);
+
+        let mut decoded = Vec::new();
+        let mut rest = stream.as_slice();
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at(rng.range(1, 4096).min(rest.len()));
+            rest = tail;
+            assembler.push(chunk);
+            while let Some(frame) = assembler.next_frame() {
+                match frame.and_then(|frame| ResponseMessage::parse(&frame.message)) {
+                    Ok(message) => decoded.extend(assembler.reassemble(message)),
+                    Err(e) => report.fail(format!("#{} {} {}: {}", index, sample.method, sample.cmd_type, e)),
+                }
+            }
+        }
+
+        match decoded.as_slice() {
+            [message] => {
+                if let Some(mismatch) = sample.compare(message) {
+                    report.fail(format!("#{} {}", index, mismatch));
+                }
+            }
+            other => report.fail(format!(
+                "#{} {} {}: decoded {} messages instead of 1",
+                index,
+                sample.method,
+                sample.cmd_type,
+                other.len()
+            )),
+        }
+        assembler.clear();
+    }
+    report
+}
+
+/// Send samples to the simulated cooler, which answers unknown commands with a
+/// Nack carrying our SeqNumber and msgId, then upload a file it MD5-checks
+fn simulator_round_trip(options: &Options) -> Result<Report> {
+    let simulator = crate::simulator::Simulator::start()?;
+    let device = simulator.path().to_string_lossy().into_owned();
+    let mut port = SerialTransport::open(&device, &SerialSettings::default())
+        .with_context(|| format!("Failed to open simulator pty {}", device))?;
+    let mut assembler = FrameAssembler::new();
+    let mut rng = Rng::new(options.seed ^ 0x5A5A_5A5A);
+    let mut report = Report::default();
+
+    // Fewer than the codec pass, every one waits for a reply
+    for index in 0..(options.count / 10).max(1) {
+        let mut sample = Sample::random(&mut rng);
+        sample.method = "POST";
+        sample.cmd_type = format!("selfTest{}", sample.cmd_type);
+        sample.msg_id = index as i64 + 1;
+        report.checked += 1;
+
+        let frame = build_message(&sample.message())?;
+        report.bytes += frame.bytes.len();
+        write_frame(&mut port, &frame)?;
+
+        let reply = wait_for_reply(&mut port, &mut assembler, sample.seq_number)?;
+        match reply {
+            Some(reply) if reply.msg_id != sample.msg_id => report.fail(format!(
+                "#{} {}: reply msgId {} != {}",
+                index, sample.cmd_type, reply.msg_id, sample.msg_id
+            )),
+            Some(reply) if reply.cmd_type != sample.cmd_type => report.fail(format!(
+                "#{} {}: reply cmdType {}",
+                index, sample.cmd_type, reply.cmd_type
+            )),
+            Some(_) => {}
+            None => report.fail(format!("#{} {} ({} bytes): no reply", index, sample.cmd_type, sample.body.len())),
+        }
+    }
+
+    report.checked += 1;
+    if let Err(e) = upload(&mut port, &mut assembler, &mut rng, &mut report) {
+        report.fail(format!("upload: {:#}", e));
+    }
+    Ok(report)
+}
+
+/// Next reply ACKing `seq_number`, skipping the handshake and sysinfo polls
+fn wait_for_reply(port: &mut dyn Transport, assembler: &mut FrameAssembler, seq_number: i64) -> Result<Option<ResponseMessage>> {
+    let deadline = Instant::now() + REPLY_TIMEOUT;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        if let Some(message) = read_response(port, assembler, remaining)?
+            && message.ack_number == seq_number
+        {
+            return Ok(Some(message));
+        }
+    }
+}
+
+/// `transport`, binary chunks, then `transported` with the MD5 the simulator must match
+fn upload(port: &mut dyn Transport, assembler: &mut FrameAssembler, rng: &mut Rng, report: &mut Report) -> Result<()> {
+    let data: Vec<u8> = (0..UPLOAD_SIZE).map(|_| rng.next() as u8).collect();
+    let name = "self-test.bin";
+    let md5 = format!("{:x}", md5::compute(&data));
+
+    let start = serde_json::json!({ "fileName": name, "fileSize": data.len() }).to_string();
+    let frame = build_message(&CommandMessageWithMethod::new("POST", "transport", &start))?;
+    write_frame(port, &frame)?;
+    wait_for_reply(port, assembler, frame.seq_number)?.context("No reply to transport")?;
+
+    for (index, chunk) in data.chunks(UPLOAD_CHUNK).enumerate() {
+        let offset = (index * UPLOAD_CHUNK) as u64;
+        let frame = build_message(&CommandMessageWithMethod::file_chunk("transport", name, data.len() as u64, offset, chunk))?;
+        report.bytes += frame.bytes.len();
+        write_frame(port, &frame)?;
+    }
+
+    let done = serde_json::json!({ "fileName": name, "md5": md5 }).to_string();
+    let frame = build_message(&CommandMessageWithMethod::new("POST", "transported", &done))?;
+    write_frame(port, &frame)?;
+    let reply = wait_for_reply(port, assembler, frame.seq_number)?.context("No reply to transported")?;
+    if let Some(nack) = reply.nack() {
+        report.fail(format!("upload of {} bytes: {}", data.len(), nack));
+    }
+    Ok(())
+}
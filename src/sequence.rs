@@ -0,0 +1,71 @@
+// Monotonic SeqNumber source for every outgoing message. The counter survives
+// restarts: blocks of numbers are reserved in the state dir ahead of use, so a
+// crash can skip numbers but never reuse one. Under `cargo test` it stays in
+// memory, so tests don't touch the real state dir.
+
+use std::{path::PathBuf, sync::Mutex, time::{SystemTime, UNIX_EPOCH}};
+
+/// Numbers handed out per write to the state file
+const RESERVE_BLOCK: i64 = 1000;
+/// Kept within an int, the Android side parses it as one
+const MAX_SEQ: i64 = i32::MAX as i64;
+
+struct Sequence {
+    next: i64,
+    /// First number not covered by the persisted reservation
+    reserved_until: i64,
+}
+
+static SEQUENCE: Mutex<Option<Sequence>> = Mutex::new(None);
+
+fn path() -> PathBuf {
+    crate::paths::state_dir().join("seq_number")
+}
+
+/// Where to continue from: the saved reservation, or a time-based seed on first run
+fn load() -> i64 {
+    std::fs::read_to_string(path())
+        .ok()
+        .and_then(|content| content.trim().parse::<i64>().ok())
+        .filter(|seq| (1..=MAX_SEQ).contains(seq))
+        .unwrap_or_else(|| {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+            (now % 100_000) as i64 + 1
+        })
+}
+
+fn persist(reserved_until: i64) {
+    let path = path();
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, reserved_until.to_string()));
+    if let Err(e) = written {
+        // Not fatal, numbers just restart from a seed next run
+        log::warn!("Failed to save sequence number to {}: {}", path.display(), e);
+    }
+}
+
+/// Next SeqNumber, strictly increasing until it wraps back to 1 at `i32::MAX`
+pub fn next() -> i64 {
+    let mut guard = SEQUENCE.lock().unwrap();
+    let sequence = guard.get_or_insert_with(|| {
+        let start = if cfg!(test) { 1 } else { load() };
+        Sequence { next: start, reserved_until: start }
+    });
+
+    let seq = sequence.next;
+    if seq >= sequence.reserved_until {
+        sequence.reserved_until = (seq + RESERVE_BLOCK).min(MAX_SEQ);
+        if !cfg!(test) {
+            persist(sequence.reserved_until);
+        }
+    }
+    if seq >= MAX_SEQ {
+        sequence.next = 1;
+        sequence.reserved_until = 1;
+    } else {
+        sequence.next = seq + 1;
+    }
+    seq
+}
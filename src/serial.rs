@@ -0,0 +1,365 @@
+// ============================================================================
+// Serial Port Access
+// Exclusive open + detection of processes fighting us for the tty
+// ============================================================================
+
+use std::{collections::HashSet, fmt, fs, os::unix::io::AsRawFd, path::Path, thread, time::Duration};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serialport::{SerialPort, TTYPort};
+
+/// Processes known to grab freshly enumerated ttyACM devices
+const KNOWN_INTERFERERS: &[&str] = &["ModemManager", "brltty"];
+
+#[derive(Debug, Clone)]
+pub struct PortHolder {
+    pub pid: u32,
+    pub name: String,
+}
+
+impl fmt::Display for PortHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (pid {})", self.name, self.pid)
+    }
+}
+
+/// Returned when the port is locked or held open by another process
+#[derive(Debug, Clone)]
+pub struct PortBusyError {
+    pub device: String,
+    pub holders: Vec<PortHolder>,
+    /// True when `holders` are known interferers that are merely running,
+    /// because we weren't allowed to inspect their open file descriptors
+    pub suspected: bool,
+}
+
+impl fmt::Display for PortBusyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.holders.is_empty() {
+            return write!(f, "{} is busy (held by an unknown process)", self.device);
+        }
+
+        let names: Vec<String> = self.holders.iter().map(|h| h.to_string()).collect();
+        if self.suspected {
+            write!(f, "{} is busy, possibly held by {}", self.device, names.join(", "))?;
+        } else {
+            write!(f, "{} is held by {}", self.device, names.join(", "))?;
+        }
+
+        if self.holders.iter().any(|h| h.name == "ModemManager") {
+            write!(
+                f,
+                ". Add ENV{{ID_MM_DEVICE_IGNORE}}=\"1\" to your udev rule so ModemManager stops probing the cooler"
+            )?;
+        }
+        if self.holders.iter().any(|h| h.name == "brltty") {
+            write!(f, ". brltty can be removed or its udev rules disabled if you don't use a braille display")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PortBusyError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FlowControl {
+    None,
+    Software,
+    Hardware,
+}
+
+/// Line settings for the cooler's tty. Current firmware runs 115200 8N1 without flow control.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SerialSettings {
+    pub baud_rate: u32,
+    /// 5-8
+    pub data_bits: u8,
+    pub parity: Parity,
+    /// 1 or 2
+    pub stop_bits: u8,
+    pub flow_control: FlowControl,
+    /// Blocking read/write timeout. serialport has a single timeout for both directions.
+    pub timeout_ms: u64,
+}
+
+impl Default for SerialSettings {
+    fn default() -> Self {
+        Self {
+            baud_rate: 115200,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: FlowControl::None,
+            timeout_ms: 2000,
+        }
+    }
+}
+
+impl SerialSettings {
+    pub const BAUD_RATES: &'static [u32] = &[9600, 19200, 38400, 57600, 115200, 230400, 460800, 921600, 1_500_000];
+
+    /// Short form for logs, e.g. "115200 8N1"
+    pub fn summary(&self) -> String {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        let flow = match self.flow_control {
+            FlowControl::None => "",
+            FlowControl::Software => " XON/XOFF",
+            FlowControl::Hardware => " RTS/CTS",
+        };
+        format!("{} {}{}{}{}", self.baud_rate, self.data_bits, parity, self.stop_bits, flow)
+    }
+
+    fn builder(&self, device: &str) -> Result<serialport::SerialPortBuilder> {
+        let data_bits = match self.data_bits {
+            5 => serialport::DataBits::Five,
+            6 => serialport::DataBits::Six,
+            7 => serialport::DataBits::Seven,
+            8 => serialport::DataBits::Eight,
+            other => anyhow::bail!("Unsupported data bits: {}", other),
+        };
+        let stop_bits = match self.stop_bits {
+            1 => serialport::StopBits::One,
+            2 => serialport::StopBits::Two,
+            other => anyhow::bail!("Unsupported stop bits: {}", other),
+        };
+        let parity = match self.parity {
+            Parity::None => serialport::Parity::None,
+            Parity::Odd => serialport::Parity::Odd,
+            Parity::Even => serialport::Parity::Even,
+        };
+        let flow_control = match self.flow_control {
+            FlowControl::None => serialport::FlowControl::None,
+            FlowControl::Software => serialport::FlowControl::Software,
+            FlowControl::Hardware => serialport::FlowControl::Hardware,
+        };
+        Ok(serialport::new(device, self.baud_rate)
+            .data_bits(data_bits)
+            .stop_bits(stop_bits)
+            .parity(parity)
+            .flow_control(flow_control)
+            .timeout(Duration::from_millis(self.timeout_ms)))
+    }
+}
+
+/// The device went away mid-session (unplugged, USB reset, suspend)
+#[derive(Debug, Clone)]
+pub struct DeviceGone {
+    pub device: String,
+    pub reason: String,
+}
+
+impl fmt::Display for DeviceGone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} disconnected: {}", self.device, self.reason)
+    }
+}
+
+impl std::error::Error for DeviceGone {}
+
+/// Whether an error from a session means the tty is gone rather than a protocol problem
+pub fn is_disconnect(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        if cause.is::<DeviceGone>() {
+            return true;
+        }
+        if let Some(protocol) = cause.downcast_ref::<crate::data::ProtocolError>() {
+            return matches!(protocol, crate::data::ProtocolError::PortClosed(_));
+        }
+        if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+            return is_closed_port(io);
+        }
+        if let Some(serial) = cause.downcast_ref::<serialport::Error>() {
+            return serial.kind() == serialport::ErrorKind::NoDevice;
+        }
+        false
+    })
+}
+
+/// I/O errors that mean the port or socket is gone rather than just slow
+pub fn is_closed_port(io: &std::io::Error) -> bool {
+    matches!(io.raw_os_error(), Some(libc::ENODEV | libc::ENXIO | libc::EIO))
+        || matches!(
+            io.kind(),
+            std::io::ErrorKind::BrokenPipe | std::io::ErrorKind::NotConnected | std::io::ErrorKind::NotFound
+        )
+}
+
+/// Whether opening the port is worth another try: busy or not enumerated yet
+/// clears up on its own, a permission problem doesn't
+pub fn is_transient_open_error(e: &anyhow::Error) -> bool {
+    !e.chain().any(|cause| {
+        cause.downcast_ref::<std::io::Error>().is_some_and(|io| io.kind() == std::io::ErrorKind::PermissionDenied)
+            || cause.downcast_ref::<serialport::Error>().is_some_and(|serial| {
+                matches!(serial.kind(), serialport::ErrorKind::Io(std::io::ErrorKind::PermissionDenied))
+            })
+    })
+}
+
+/// A write that timed out or was interrupted, as opposed to the device going away
+pub fn is_transient_write_error(e: &anyhow::Error) -> bool {
+    !is_disconnect(e)
+        && e.chain().any(|cause| {
+            let io = match cause.downcast_ref::<crate::data::ProtocolError>() {
+                Some(crate::data::ProtocolError::Io(io)) => Some(io),
+                _ => cause.downcast_ref::<std::io::Error>(),
+            };
+            io.is_some_and(|io| {
+                matches!(
+                    io.kind(),
+                    std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted
+                )
+            })
+        })
+}
+
+/// Open the serial device with TIOCEXCL and an exclusive flock so nothing else
+/// can write to the cooler while we own it
+pub fn open_exclusive(device: &str, settings: &SerialSettings) -> Result<Box<dyn SerialPort>> {
+    let mut port = match settings.builder(device)?.open_native() {
+        Ok(port) => port,
+        Err(e) => {
+            if is_busy_error(device, &e) {
+                return Err(busy_error(device).into());
+            }
+            return Err(e).context("Failed to open serial port");
+        }
+    };
+
+    port.set_exclusive(true)
+        .context("Failed to set TIOCEXCL on serial port")?;
+    lock_exclusive(&port).map_err(|_| busy_error(device))?;
+
+    Ok(Box::new(port))
+}
+
+fn lock_exclusive(port: &TTYPort) -> std::io::Result<()> {
+    let ret = unsafe { libc::flock(port.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+fn is_busy_error(device: &str, e: &serialport::Error) -> bool {
+    match e.kind() {
+        // serialport reports EBUSY and a failed flock as NoDevice
+        serialport::ErrorKind::NoDevice => Path::new(device).exists(),
+        serialport::ErrorKind::Io(kind) => matches!(
+            kind,
+            std::io::ErrorKind::ResourceBusy | std::io::ErrorKind::WouldBlock
+        ),
+        _ => false,
+    }
+}
+
+fn busy_error(device: &str) -> PortBusyError {
+    let holders = find_port_holders(device);
+    if !holders.is_empty() {
+        return PortBusyError { device: device.to_string(), holders, suspected: false };
+    }
+
+    PortBusyError {
+        device: device.to_string(),
+        holders: find_known_interferers(),
+        suspected: true,
+    }
+}
+
+/// Walk /proc/*/fd looking for processes with the device open.
+/// Only finds processes we're allowed to inspect (same user or root).
+pub fn find_port_holders(device: &str) -> Vec<PortHolder> {
+    let target = fs::canonicalize(device).unwrap_or_else(|_| device.into());
+    let own_pid = std::process::id();
+    let mut holders = Vec::new();
+
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return holders;
+    };
+
+    for entry in entries.flatten() {
+        let Some(pid) = entry.file_name().to_str().and_then(|s| s.parse::<u32>().ok()) else {
+            continue;
+        };
+        if pid == own_pid {
+            continue;
+        }
+
+        let Ok(fds) = fs::read_dir(entry.path().join("fd")) else {
+            continue;
+        };
+        let holds_port = fds
+            .flatten()
+            .filter_map(|fd| fs::read_link(fd.path()).ok())
+            .any(|link| link == target);
+
+        if holds_port {
+            holders.push(PortHolder { pid, name: process_name(pid) });
+        }
+    }
+
+    holders
+}
+
+/// Running processes that are known to probe serial devices
+fn find_known_interferers() -> Vec<PortHolder> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .map(|pid| PortHolder { pid, name: process_name(pid) })
+        .filter(|h| KNOWN_INTERFERERS.contains(&h.name.as_str()))
+        .collect()
+}
+
+pub fn process_name(pid: u32) -> String {
+    fs::read_to_string(format!("/proc/{}/comm", pid))
+        .map(|s| s.trim().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Current ttyACM/ttyUSB nodes under /dev
+pub fn list_tty_devices() -> HashSet<String> {
+    let Ok(entries) = fs::read_dir("/dev") else {
+        return HashSet::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter(|name| name.starts_with("ttyACM") || name.starts_with("ttyUSB"))
+        .map(|name| format!("/dev/{}", name))
+        .collect()
+}
+
+/// Poll /dev and call `on_appeared` whenever a serial node shows up
+/// (hotplug, cooler reboot, host resume all re-enumerate the tty)
+pub fn watch_devices(on_appeared: impl Fn(String) + Send + 'static) {
+    thread::spawn(move || {
+        let mut known = list_tty_devices();
+        loop {
+            thread::sleep(Duration::from_secs(1));
+            let current = list_tty_devices();
+            for device in current.difference(&known) {
+                log::info!("Serial device appeared: {}", device);
+                on_appeared(device.clone());
+            }
+            known = current;
+        }
+    });
+}
@@ -0,0 +1,169 @@
+// ============================================================================
+// Serial Writer
+// One thread owns the write side of the port. Frames are queued (bounded, so a
+// stalled device pushes back on the sender), spaced by a minimum gap, and
+// sysinfo updates that pile up are collapsed into the newest one.
+// ============================================================================
+
+use std::{
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use anyhow::Result;
+use crossbeam::channel::{self, Receiver, Sender};
+use serde::{Deserialize, Serialize};
+
+use crate::data::{write_frame, OutgoingFrame};
+use crate::reliability::RetryPolicy;
+use crate::serial::is_transient_write_error;
+use crate::transport::Transport;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WriterConfig {
+    /// Minimum time between the end of one write and the start of the next
+    pub min_gap_ms: u64,
+    /// Frames that may wait before senders block
+    pub queue_depth: usize,
+}
+
+impl Default for WriterConfig {
+    fn default() -> Self {
+        Self { min_gap_ms: 50, queue_depth: 16 }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct WriteFailure {
+    message: String,
+    disconnect: bool,
+}
+
+enum Job {
+    Frame(OutgoingFrame),
+    /// Send whatever is in the sysinfo slot
+    SysInfo,
+    /// Reply once everything queued before it is on the wire
+    Flush(Sender<()>),
+}
+
+pub struct SerialWriter {
+    jobs: Option<Sender<Job>>,
+    /// Newest sysinfo frame not written yet
+    sysinfo: Arc<Mutex<Option<OutgoingFrame>>>,
+    /// Set when a write failed, the thread exits afterwards
+    error: Arc<Mutex<Option<WriteFailure>>>,
+    device: String,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl SerialWriter {
+    pub fn spawn(mut port: Box<dyn Transport>, config: WriterConfig, retry: RetryPolicy) -> Self {
+        let (jobs, queue) = channel::bounded(config.queue_depth.max(1));
+        let sysinfo = Arc::new(Mutex::new(None));
+        let error = Arc::new(Mutex::new(None));
+        let device = port.name();
+
+        let handle = {
+            let sysinfo = sysinfo.clone();
+            let error = error.clone();
+            let gap = Duration::from_millis(config.min_gap_ms);
+            thread::Builder::new()
+                .name("serial-writer".to_string())
+                .spawn(move || {
+                    if let Err(e) = run(port.as_mut(), &queue, &sysinfo, gap, &retry) {
+                        log::error!("Serial writer stopped: {:#}", e);
+                        *error.lock().unwrap() = Some(WriteFailure {
+                            message: format!("{:#}", e),
+                            disconnect: crate::serial::is_disconnect(&e),
+                        });
+                    }
+                })
+                .expect("failed to spawn serial writer thread")
+        };
+
+        Self { jobs: Some(jobs), sysinfo, error, device, handle: Some(handle) }
+    }
+
+    /// Queue a frame, blocks while the queue is full
+    pub fn send(&self, frame: OutgoingFrame) -> Result<()> {
+        self.submit(Job::Frame(frame))
+    }
+
+    /// Queue a sysinfo update. If an older one is still waiting it is replaced.
+    pub fn send_sysinfo(&self, frame: OutgoingFrame) -> Result<()> {
+        let previous = self.sysinfo.lock().unwrap().replace(frame);
+        if previous.is_some() {
+            log::debug!("Coalesced a queued sysinfo update");
+            return Ok(());
+        }
+        self.submit(Job::SysInfo)
+    }
+
+    /// Wait until everything queued so far has been written
+    pub fn flush(&self) -> Result<()> {
+        let (done, wait) = channel::bounded(1);
+        self.submit(Job::Flush(done))?;
+        if wait.recv().is_err() {
+            return Err(self.failure());
+        }
+        Ok(())
+    }
+
+    fn submit(&self, job: Job) -> Result<()> {
+        let jobs = self.jobs.as_ref().expect("writer already closed");
+        jobs.send(job).map_err(|_| self.failure())
+    }
+
+    fn failure(&self) -> anyhow::Error {
+        match self.error.lock().unwrap().clone() {
+            Some(e) if e.disconnect => crate::serial::DeviceGone { device: self.device.clone(), reason: e.message }.into(),
+            Some(e) => anyhow::anyhow!("Serial write failed: {}", e.message),
+            None => anyhow::anyhow!("Serial writer is gone"),
+        }
+    }
+}
+
+impl Drop for SerialWriter {
+    /// Closing the queue lets the thread write what's left and exit
+    fn drop(&mut self) {
+        self.jobs.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run(
+    port: &mut dyn Transport,
+    queue: &Receiver<Job>,
+    sysinfo: &Mutex<Option<OutgoingFrame>>,
+    gap: Duration,
+    retry: &RetryPolicy,
+) -> Result<()> {
+    let mut last_write: Option<Instant> = None;
+    for job in queue.iter() {
+        let frame = match job {
+            Job::Frame(frame) => frame,
+            Job::SysInfo => match sysinfo.lock().unwrap().take() {
+                Some(frame) => frame,
+                None => continue,
+            },
+            Job::Flush(done) => {
+                let _ = done.send(());
+                continue;
+            }
+        };
+
+        if let Some(last) = last_write {
+            let since = last.elapsed();
+            if since < gap {
+                thread::sleep(gap - since);
+            }
+        }
+        retry.run(&format!("Writing {}", frame.cmd_type), is_transient_write_error, || Ok(write_frame(port, &frame)?))?;
+        last_write = Some(Instant::now());
+    }
+    Ok(())
+}
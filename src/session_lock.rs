@@ -0,0 +1,57 @@
+// Lock screen: logind tells us when the desktop session locks and unlocks, and
+// the heartbeat swaps the panel to a lock image, or turns it off, until the
+// session is unlocked. logind's LockedHint is polled through `loginctl`, the way
+// input_idle reads IdleHint; only sessions of the user we run as count. The
+// desktop's screen locker sets the hint, GNOME's and KDE's do, elsewhere e.g.
+// `swayidle lock 'loginctl lock-session'` with a locker that reports it.
+
+use std::{process::Command, time::Duration};
+use serde::{Deserialize, Serialize};
+
+use crate::poll::{Poll, Reader};
+use crate::screen_setup::ScreenConfig;
+
+/// How often the hint is read, and so how long the panel can lag the lock
+const POLL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LockScreenConfig {
+    pub enabled: bool,
+    /// Media already on the device to show while locked, empty turns the panel off
+    pub media: String,
+}
+
+/// Turning the panel off takes the experimental screen commands
+pub fn enabled(config: &ScreenConfig) -> bool {
+    config.lock_screen.enabled && (!config.lock_screen.media.is_empty() || config.experimental_commands)
+}
+
+/// 1 while locked. Unreadable counts as unlocked, so a hiccup restores the screen rather than hiding it.
+static LOCKED: Poll = Poll::new();
+
+/// Whether one of our sessions is locked, read in the background from the first call on
+pub fn locked() -> bool {
+    LOCKED.get("the session lock through loginctl", POLL, watch) != 0
+}
+
+fn watch() -> Option<Reader> {
+    log::info!("Watching the session lock through logind's LockedHint");
+    Some(Box::new(|| any_locked().map(u64::from)))
+}
+
+fn loginctl(args: &[&str]) -> Option<String> {
+    let output = Command::new("loginctl").args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Whether any of our sessions has LockedHint set, None if logind can't be asked
+fn any_locked() -> Option<bool> {
+    let uid = unsafe { libc::getuid() }.to_string();
+    let sessions = loginctl(&["show-user", &uid, "-p", "Sessions", "--value"])?;
+    Some(
+        sessions
+            .split_whitespace()
+            .any(|session| loginctl(&["show-session", session, "-p", "LockedHint", "--value"]).as_deref() == Some("yes")),
+    )
+}
@@ -0,0 +1,79 @@
+// GUI settings kept between runs in `$XDG_CONFIG_HOME/tryx-panorama/config.toml`:
+// the serial device, the screen config, what's selected and what the daemon
+// serves (MQTT, WebSocket) and the transfer hooks. Loaded on startup
+// and written back whenever they change. The ADB device keeps its own file,
+// shared with the command line.
+
+use std::path::PathBuf;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::screen_setup::ScreenConfig;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub serial_device: String,
+    pub selected_image: Option<PathBuf>,
+    /// Time scale for capture replay
+    pub replay_speed: f32,
+    pub screen: ScreenConfig,
+    /// Used by the daemon, which reconnects when it changes
+    pub mqtt: crate::mqtt::MqttConfig,
+    /// The daemon's status stream, likewise
+    pub websocket: crate::websocket::WebSocketConfig,
+    /// Shell commands around transfers, read when one starts
+    pub hooks: crate::hooks::HooksConfig,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            serial_device: crate::daemon::DEFAULT_DEVICE.to_string(),
+            selected_image: None,
+            replay_speed: 1.0,
+            screen: ScreenConfig::default(),
+            mqtt: Default::default(),
+            websocket: Default::default(),
+            hooks: Default::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn path() -> PathBuf {
+        crate::paths::config_dir().join("config.toml")
+    }
+
+    /// The saved settings. Before there are any, the config last applied to
+    /// the device is the best guess at what the user wants.
+    pub fn load() -> Self {
+        let path = Self::path();
+        match std::fs::read_to_string(&path) {
+            Ok(content) => match toml::from_str(&content) {
+                Ok(settings) => return settings,
+                Err(e) => log::warn!("Ignoring unreadable {}: {}", path.display(), e),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Failed to read {}: {}", path.display(), e),
+        }
+        let screen = crate::screen_setup::LastApplied::load().map(|last| last.config).unwrap_or_default();
+        Self { screen, ..Default::default() }
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// Write `content` from `to_toml`, replacing the file in one step so a
+    /// crash mid-write can't leave half a config
+    pub fn write(content: &str) -> Result<()> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp = path.with_extension("toml.tmp");
+        std::fs::write(&temp, content).with_context(|| format!("Failed to write {}", temp.display()))?;
+        std::fs::rename(&temp, &path).with_context(|| format!("Failed to replace {}", path.display()))
+    }
+}
@@ -0,0 +1,236 @@
+// ============================================================================
+// Device Simulator
+// A fake cooler behind a pseudo-terminal: point the serial device at the pty
+// path and it ACKs commands, answers GETs, keeps uploaded media and sends the
+// `conn` handshake, so the app can be worked on without the hardware
+// ============================================================================
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Write},
+    os::fd::FromRawFd,
+    path::{Path, PathBuf},
+    sync::{Arc, atomic::{AtomicBool, Ordering}},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use anyhow::{Context, Result};
+
+use crate::data::{build_message, build_reply, CommandMessageWithMethod, FrameAssembler, ResponseMessage};
+
+/// How often the fake device polls for sysinfo, like the real one does
+const SYSINFO_PULL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct Simulator {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Simulator {
+    /// Open a pty pair and start answering on the master side
+    pub fn start() -> Result<Self> {
+        let (master, path) = open_pty()?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("simulator".to_string())
+                .spawn(move || {
+                    if let Err(e) = run(master, &stop) {
+                        log::error!("Simulator stopped: {:#}", e);
+                    }
+                })
+                .context("Failed to spawn simulator thread")?
+        };
+
+        log::info!("Simulated cooler listening on {}", path.display());
+        Ok(Self { path, stop, handle: Some(handle) })
+    }
+
+    /// Slave side of the pty, use this as the serial device
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        log::info!("Simulated cooler on {} stopped", self.path.display());
+    }
+}
+
+fn open_pty() -> Result<(File, PathBuf)> {
+    unsafe {
+        let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error()).context("posix_openpt failed");
+        }
+        // Owned from here so the fd is closed on every error path
+        let master = File::from_raw_fd(fd);
+
+        if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to unlock pty");
+        }
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to make pty non-blocking");
+        }
+
+        let mut name = [0 as libc::c_char; 128];
+        if libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) != 0 {
+            return Err(std::io::Error::last_os_error()).context("ptsname_r failed");
+        }
+        let path = std::ffi::CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned();
+        Ok((master, PathBuf::from(path)))
+    }
+}
+
+/// What the fake cooler remembers
+#[derive(Default)]
+struct DeviceState {
+    /// A client has the pty open and got its handshake
+    connected: bool,
+    screen_config: Option<serde_json::Value>,
+    /// Uploads in progress, by file name
+    uploads: HashMap<String, Vec<u8>>,
+}
+
+fn run(mut master: File, stop: &AtomicBool) -> Result<()> {
+    let mut state = DeviceState::default();
+    let mut assembler = FrameAssembler::new();
+    let mut chunk = [0u8; 4096];
+    let mut last_pull = Instant::now();
+
+    while !stop.load(Ordering::Relaxed) {
+        if state.connected && last_pull.elapsed() >= SYSINFO_PULL_INTERVAL {
+            last_pull = Instant::now();
+            master.write_all(&build_message(&CommandMessageWithMethod::new("GET", "all", "{}"))?.bytes)?;
+        }
+
+        match master.read(&mut chunk) {
+            Ok(0) => thread::sleep(Duration::from_millis(20)),
+            Ok(n) => assembler.push(&chunk[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(20));
+                continue;
+            }
+            // EIO while nobody has the slave side open
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => {
+                if state.connected {
+                    log::debug!("Simulator: client closed the port");
+                }
+                state.connected = false;
+                assembler.clear();
+                thread::sleep(Duration::from_millis(100));
+                continue;
+            }
+            Err(e) => return Err(e).context("Simulator read failed"),
+        }
+
+        while let Some(request) = next_message(&mut assembler) {
+            if !state.connected {
+                state.connected = true;
+                master.write_all(&handshake()?)?;
+            }
+            for reply in handle(&mut state, &request)? {
+                master.write_all(&reply)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Next complete message, skipping bad frames
+fn next_message(assembler: &mut FrameAssembler) -> Option<ResponseMessage> {
+    while let Some(frame) = assembler.next_frame() {
+        match frame.and_then(|frame| ResponseMessage::parse(&frame.message)) {
+            Ok(message) => {
+                if let Some(message) = assembler.reassemble(message) {
+                    return Some(message);
+                }
+            }
+            Err(e) => log::warn!("Simulator: dropping bad frame: {:#}", e),
+        }
+    }
+    None
+}
+
+fn handshake() -> Result<Vec<u8>> {
+    let body = serde_json::json!({
+        "version": "simulator-1.0",
+        "support": {
+            "video": true,
+            "brightness": true,
+            "filter": true,
+            "badge": true,
+            "sysinfo": true,
+            "slideshow": true
+        }
+    });
+    let body = body.to_string();
+    Ok(build_message(&CommandMessageWithMethod::new("POST", "conn", &body))?.bytes)
+}
+
+/// Answer one request, returns the frames to send back
+fn handle(state: &mut DeviceState, request: &ResponseMessage) -> Result<Vec<Vec<u8>>> {
+    log::debug!("Simulator: {} {} Seq={}", request.method, request.cmd_type, request.seq_number);
+    let ok = serde_json::json!({ "code": 0 });
+
+    let body = match (request.method.as_str(), request.cmd_type.as_str()) {
+        // Sysinfo is fire-and-forget
+        ("STATE", _) => return Ok(Vec::new()),
+
+        ("GET", "waterBlockScreenId") => state.screen_config.clone().unwrap_or(serde_json::json!({})),
+
+        (_, "waterBlockScreenId") => {
+            state.screen_config = Some(request.json()?);
+            ok
+        }
+        (_, "mediaDelete") => ok,
+        (_, "transport") if request.content_type == "binary" => {
+            let range = request.headers.get("ContentRange").cloned().unwrap_or_default();
+            let offset = range.split('-').next().and_then(|s| s.parse::<usize>().ok()).unwrap_or(0);
+            let data = state.uploads.entry(request.file_name.clone()).or_default();
+            if data.len() < offset + request.body.len() {
+                data.resize(offset + request.body.len(), 0);
+            }
+            data[offset..offset + request.body.len()].copy_from_slice(&request.body);
+            // Chunks aren't ACKed individually
+            return Ok(Vec::new());
+        }
+        (_, "transport") => {
+            let body = request.json()?;
+            let name = body["fileName"].as_str().unwrap_or_default().to_string();
+            state.uploads.insert(name, Vec::new());
+            ok
+        }
+        (_, "transported") => {
+            let body = request.json()?;
+            let name = body["fileName"].as_str().unwrap_or_default().to_string();
+            let data = state.uploads.remove(&name).unwrap_or_default();
+            let md5 = format!("{:x}", md5::compute(&data));
+            if body["md5"].as_str() == Some(md5.as_str()) {
+                log::info!("Simulator: received {} ({} bytes)", name, data.len());
+                ok
+            } else {
+                log::warn!("Simulator: MD5 mismatch for {}", name);
+                serde_json::json!({ "code": 1, "msg": "md5 mismatch" })
+            }
+        }
+        (_, "config") => {
+            let body = request.json()?;
+            log::info!("Simulator: config {}", body);
+            ok
+        }
+        _ => serde_json::json!({ "code": -1, "msg": "unknown command" }),
+    };
+
+    Ok(vec![build_reply("POST", request, &body)?.bytes])
+}
@@ -0,0 +1,229 @@
+// Smoothing of payload values: a moving average or an exponential one per
+// metric, applied to each sample before it's sent so the display doesn't flip
+// between numbers every second
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Mutex,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::sysinfo::SysInfo;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Method {
+    #[default]
+    Off,
+    /// Mean of the last `window` samples
+    MovingAverage,
+    /// Each sample weighs 2 / (window + 1), like an EMA over `window` periods
+    Exponential,
+}
+
+impl Method {
+    pub const ALL: [Method; 3] = [Method::Off, Method::MovingAverage, Method::Exponential];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Method::Off => "Off",
+            Method::MovingAverage => "Moving average",
+            Method::Exponential => "Exponential",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Smoothing {
+    pub method: Method,
+    /// In samples
+    pub window: u32,
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Self { method: Method::Off, window: 5 }
+    }
+}
+
+/// Payload values that can be smoothed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Metric {
+    CpuTemperature,
+    CpuLoad,
+    CpuClock,
+    GpuTemperature,
+    GpuLoad,
+    MemoryLoad,
+    DiskTemperature,
+    /// Read and write speed and activity
+    DiskSpeed,
+    /// Upload and download
+    Network,
+    /// Every fan, each on its own
+    Fans,
+    CoolantTemperature,
+    Fps,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 12] = [
+        Metric::CpuTemperature,
+        Metric::CpuLoad,
+        Metric::CpuClock,
+        Metric::GpuTemperature,
+        Metric::GpuLoad,
+        Metric::MemoryLoad,
+        Metric::DiskTemperature,
+        Metric::DiskSpeed,
+        Metric::Network,
+        Metric::Fans,
+        Metric::CoolantTemperature,
+        Metric::Fps,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Metric::CpuTemperature => "CPU temperature",
+            Metric::CpuLoad => "CPU load",
+            Metric::CpuClock => "CPU clock",
+            Metric::GpuTemperature => "GPU temperature",
+            Metric::GpuLoad => "GPU load",
+            Metric::MemoryLoad => "Memory load",
+            Metric::DiskTemperature => "Disk temperature",
+            Metric::DiskSpeed => "Disk speed and activity",
+            Metric::Network => "Network",
+            Metric::Fans => "Fans",
+            Metric::CoolantTemperature => "Coolant temperature",
+            Metric::Fps => "FPS",
+        }
+    }
+
+    /// The raw values, keyed so each keeps its own history
+    fn get(self, info: &SysInfo) -> Vec<(String, f64)> {
+        let one = |value: f64| vec![(String::new(), value)];
+        match self {
+            Metric::CpuTemperature => one(info.cpu.temperature as f64),
+            Metric::CpuLoad => one(info.cpu.load as f64),
+            Metric::CpuClock => one(info.cpu.speedAverage as f64),
+            Metric::GpuTemperature => one(info.gpu.temperature as f64),
+            Metric::GpuLoad => one(info.gpu.load as f64),
+            Metric::MemoryLoad => one(info.memory.load as f64),
+            Metric::DiskTemperature => one(info.disk.temperature as f64),
+            Metric::DiskSpeed => vec![
+                ("read".to_string(), info.disk.read_speed as f64),
+                ("write".to_string(), info.disk.write_speed as f64),
+                ("activity".to_string(), info.disk.activity as f64),
+            ],
+            Metric::Network => vec![("up".to_string(), info.network.upload as f64), ("down".to_string(), info.network.download as f64)],
+            Metric::Fans => info.fans.iter().map(|fan| (fan.name.clone(), fan.value as f64)).collect(),
+            Metric::CoolantTemperature => info.liquid.iter().map(|liquid| (String::new(), liquid.temperature as f64)).collect(),
+            Metric::Fps => info.game.iter().map(|game| (String::new(), game.fps as f64)).collect(),
+        }
+    }
+
+    /// Write back what `get` returned, in the same order
+    fn set(self, info: &mut SysInfo, values: &[f64]) {
+        let byte = |value: f64| value.round().clamp(0.0, u8::MAX as f64) as u8;
+        let mut values = values.iter().copied();
+        let mut next = || values.next().unwrap_or_default();
+        match self {
+            Metric::CpuTemperature => info.cpu.temperature = byte(next()),
+            Metric::CpuLoad => {
+                info.cpu.load = byte(next());
+                info.cpu.usage = info.cpu.load;
+            }
+            Metric::CpuClock => info.cpu.speedAverage = next().round() as u32,
+            Metric::GpuTemperature => info.gpu.temperature = byte(next()),
+            Metric::GpuLoad => info.gpu.load = byte(next()),
+            Metric::MemoryLoad => info.memory.load = byte(next()),
+            Metric::DiskTemperature => info.disk.temperature = byte(next()),
+            Metric::DiskSpeed => {
+                info.disk.read_speed = next().round() as u64;
+                info.disk.write_speed = next().round() as u64;
+                info.disk.activity = byte(next());
+            }
+            Metric::Network => {
+                info.network.upload = next().round() as u64;
+                info.network.download = next().round() as u64;
+            }
+            Metric::Fans => {
+                for fan in &mut info.fans {
+                    fan.value = next().round() as u32;
+                }
+            }
+            Metric::CoolantTemperature => {
+                if let Some(liquid) = &mut info.liquid {
+                    liquid.temperature = byte(next());
+                }
+            }
+            Metric::Fps => {
+                if let Some(game) = &mut info.game {
+                    game.fps = next().round() as u32;
+                }
+            }
+        }
+    }
+}
+
+/// Recent raw values of one series, or the running average
+#[derive(Debug)]
+struct History {
+    smoothing: Smoothing,
+    samples: VecDeque<f64>,
+    average: Option<f64>,
+}
+
+impl History {
+    fn new(smoothing: Smoothing) -> Self {
+        Self { smoothing, samples: VecDeque::new(), average: None }
+    }
+
+    fn push(&mut self, value: f64) -> f64 {
+        let window = self.smoothing.window.max(1) as usize;
+        match self.smoothing.method {
+            Method::Off => value,
+            Method::MovingAverage => {
+                self.samples.push_back(value);
+                while self.samples.len() > window {
+                    self.samples.pop_front();
+                }
+                self.samples.iter().sum::<f64>() / self.samples.len() as f64
+            }
+            Method::Exponential => {
+                let alpha = 2.0 / (window as f64 + 1.0);
+                let average = self.average.map_or(value, |average| average + alpha * (value - average));
+                self.average = Some(average);
+                average
+            }
+        }
+    }
+}
+
+/// Keyed by metric and series, e.g. a fan's name
+static HISTORY: Mutex<Option<HashMap<(Metric, String), History>>> = Mutex::new(None);
+
+/// Replace the values in `info` with their smoothed ones
+pub fn apply(settings: &BTreeMap<Metric, Smoothing>, info: &mut SysInfo) {
+    let mut history = HISTORY.lock().unwrap();
+    let history = history.get_or_insert_with(Default::default);
+    // Series that are off or gone (a fan unplugged, a game quit) start over next time
+    let mut seen = Vec::new();
+    for (&metric, &smoothing) in settings.iter().filter(|(_, smoothing)| smoothing.method != Method::Off) {
+        let smoothed: Vec<f64> = metric
+            .get(info)
+            .into_iter()
+            .map(|(series, value)| {
+                let key = (metric, series);
+                seen.push(key.clone());
+                let entry = history.entry(key).or_insert_with(|| History::new(smoothing));
+                if entry.smoothing != smoothing {
+                    *entry = History::new(smoothing);
+                }
+                entry.push(value)
+            })
+            .collect();
+        metric.set(info, &smoothed);
+    }
+    history.retain(|key, _| seen.contains(key));
+}
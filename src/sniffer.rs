@@ -0,0 +1,159 @@
+// ============================================================================
+// Protocol Sniffer
+// Records every byte on the serial port into a JSON Lines capture file,
+// one record per read/write, with decoded headers for complete frames
+// ============================================================================
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::Mutex,
+    time::Instant,
+};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::data::{parse_frame, FrameParse, ResponseMessage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Tx,
+    Rx,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordKind {
+    /// Bytes exactly as read from the port, noise included
+    Raw,
+    /// One complete frame
+    Frame,
+}
+
+/// One line of a capture file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// Wall clock time, RFC 3339
+    pub time: String,
+    /// Milliseconds since the capture started, used for replay timing
+    pub offset_ms: f64,
+    pub direction: Direction,
+    pub kind: RecordKind,
+    pub len: usize,
+    pub hex: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decoded: Option<DecodedFrame>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecodedFrame {
+    pub method: String,
+    pub cmd_type: String,
+    pub headers: std::collections::BTreeMap<String, String>,
+    /// Body as text for JSON messages, omitted for binary chunks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    pub body_len: usize,
+}
+
+struct Capture {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    started: Instant,
+    records: u64,
+}
+
+static CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+
+fn capture_dir() -> PathBuf {
+    crate::paths::state_dir().join("captures")
+}
+
+/// Start writing a new capture file, stopping any previous one
+pub fn start() -> Result<PathBuf> {
+    let dir = capture_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!("capture-{}.jsonl", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+    let file = File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+
+    stop();
+    *CAPTURE.lock().unwrap() = Some(Capture {
+        path: path.clone(),
+        writer: BufWriter::new(file),
+        started: Instant::now(),
+        records: 0,
+    });
+    log::info!("Sniffer capturing to {}", path.display());
+    Ok(path)
+}
+
+/// Flush and close the current capture, returns its path
+pub fn stop() -> Option<PathBuf> {
+    let mut capture = CAPTURE.lock().unwrap().take()?;
+    if let Err(e) = capture.writer.flush() {
+        log::warn!("Failed to flush capture {}: {}", capture.path.display(), e);
+    }
+    log::info!("Sniffer stopped, {} records in {}", capture.records, capture.path.display());
+    Some(capture.path)
+}
+
+/// Path and record count of the running capture
+pub fn status() -> Option<(PathBuf, u64)> {
+    CAPTURE.lock().unwrap().as_ref().map(|c| (c.path.clone(), c.records))
+}
+
+/// Bytes as they came off the wire
+pub fn record_raw(direction: Direction, bytes: &[u8]) {
+    record(direction, RecordKind::Raw, bytes);
+}
+
+/// A complete frame, decoded into headers and body
+pub fn record_frame(direction: Direction, frame: &[u8]) {
+    record(direction, RecordKind::Frame, frame);
+}
+
+fn record(direction: Direction, kind: RecordKind, bytes: &[u8]) {
+    let mut guard = CAPTURE.lock().unwrap();
+    let Some(capture) = guard.as_mut() else {
+        return;
+    };
+
+    let entry = CaptureRecord {
+        time: chrono::Local::now().to_rfc3339(),
+        offset_ms: capture.started.elapsed().as_secs_f64() * 1000.0,
+        direction,
+        kind,
+        len: bytes.len(),
+        hex: bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+        decoded: (kind == RecordKind::Frame).then(|| decode(bytes)).flatten(),
+    };
+
+    let written = serde_json::to_writer(&mut capture.writer, &entry)
+        .map_err(std::io::Error::from)
+        .and_then(|_| capture.writer.write_all(b"\n"));
+    match written {
+        Ok(()) => capture.records += 1,
+        Err(e) => {
+            // Don't keep failing on every frame, e.g. when the disk is full
+            log::error!("Capture write failed, stopping sniffer: {}", e);
+            *guard = None;
+        }
+    }
+}
+
+fn decode(frame: &[u8]) -> Option<DecodedFrame> {
+    let FrameParse::Complete(message, _) = parse_frame(frame).ok()? else {
+        return None;
+    };
+    let parsed = ResponseMessage::parse(&message).ok()?;
+    let body = (parsed.content_type != "binary").then(|| parsed.body_str().into_owned());
+    Some(DecodedFrame {
+        method: parsed.method,
+        cmd_type: parsed.cmd_type,
+        headers: parsed.headers.into_iter().collect(),
+        body,
+        body_len: parsed.body.len(),
+    })
+}
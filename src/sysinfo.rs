@@ -2,10 +2,153 @@
 // Reads CPU/GPU temps, memory, disk stats for AIO cooler display
 
 use std::fs;
+use std::os::unix::fs::MetadataExt;
 use std::process::Command;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+/// Where CPU, memory, disk and network figures come from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backend {
+    /// Our own readers of /proc and /sys
+    #[default]
+    Proc,
+    /// The `sysinfo` crate
+    Crate,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 2] = [Backend::Proc, Backend::Crate];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Backend::Proc => "Built-in (/proc, /sys)",
+            Backend::Crate => "sysinfo crate",
+        }
+    }
+}
+
+/// Sensor choices, applied with `configure` when a session opens
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SensorConfig {
+    pub backend: Backend,
+    /// How often the background sampler refreshes the cached sample
+    pub sample_interval_ms: u64,
+    /// Block device for read/write speed and activity, e.g. `nvme0n1`. Empty
+    /// picks the disk holding the root filesystem.
+    pub disk_device: String,
+    /// Drive whose temperature is shown, empty uses `disk_device`
+    pub disk_temp_device: String,
+    /// Ask smartctl when the drive has no hwmon sensor (needs root or a sudo rule)
+    pub smartctl: bool,
+    /// `FanSensor::key`s sent as fans, empty sends every fan that spins
+    pub fans: Vec<String>,
+    /// `TempSensor::key` of the CPU temperature, empty detects it
+    pub cpu_sensor: String,
+    /// `TempSensor::key` of the GPU temperature, empty asks nvidia-smi, then
+    /// detects it
+    pub gpu_sensor: String,
+    /// `TempSensor::key` of the motherboard temperature, empty detects it
+    pub board_sensor: String,
+    /// `TempSensor::key` of the chipset (PCH) temperature, empty detects it
+    pub pch_sensor: String,
+    /// `TempSensor::key` of an AIO's coolant temperature, empty detects it
+    pub coolant_sensor: String,
+    /// `FanSensor::key` of an AIO's pump, empty detects it
+    pub pump_sensor: String,
+    /// Ask liquidctl for coolant and pump when hwmon has neither
+    pub liquidctl: bool,
+    /// MangoHud's `output_folder`, where the running game's FPS is read from.
+    /// Empty leaves FPS out.
+    pub mangohud_dir: String,
+    /// Shell commands filling payload fields, applied over the readings above
+    pub custom: Vec<crate::custom_sensor::CustomSensor>,
+    /// Add hostname, kernel, distro and uptime to the payload
+    pub send_host: bool,
+    /// Find the busiest process each sample and send it
+    pub top_process: bool,
+    /// NUT UPS (`name@host`) to report instead of the system battery
+    pub nut_ups: String,
+    /// Metrics averaged over recent samples, missing ones are sent raw
+    pub smoothing: std::collections::BTreeMap<crate::smoothing::Metric, crate::smoothing::Smoothing>,
+    /// Units the payload is sent in
+    pub units: crate::units::Units,
+    /// How much sensor history the dashboard keeps
+    pub history_minutes: u32,
+    /// Host whose round trip time is sent as `network.ping`, empty leaves it out
+    pub ping_host: String,
+    /// rhai script run over each sample, empty runs none
+    pub script: String,
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::default(),
+            sample_interval_ms: 1000,
+            disk_device: String::new(),
+            disk_temp_device: String::new(),
+            smartctl: false,
+            fans: vec![],
+            cpu_sensor: String::new(),
+            gpu_sensor: String::new(),
+            board_sensor: String::new(),
+            pch_sensor: String::new(),
+            coolant_sensor: String::new(),
+            pump_sensor: String::new(),
+            liquidctl: false,
+            mangohud_dir: String::new(),
+            custom: vec![],
+            send_host: false,
+            top_process: false,
+            nut_ups: String::new(),
+            smoothing: Default::default(),
+            units: Default::default(),
+            history_minutes: 10,
+            ping_host: String::new(),
+            script: String::new(),
+        }
+    }
+}
+
+/// smartctl spins up a process and may wake the drive, so it isn't asked every beat
+const SMARTCTL_INTERVAL: Duration = Duration::from_secs(60);
+/// liquidctl takes a second and holds the USB device meanwhile
+const LIQUIDCTL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Counters from the previous sample, rates are taken between two calls
+#[derive(Default)]
+struct State {
+    config: SensorConfig,
+    /// The `cpu` line, then `cpu0`, `cpu1`, …
+    cpu: Vec<CpuTimes>,
+    disk: Option<DiskCounters>,
+    /// Last smartctl reading: when, which drive and the temperature
+    smart: Option<(Instant, String, Option<u8>)>,
+    /// Last liquidctl reading: when, and what it found
+    liquidctl: Option<(Instant, Option<LiquidInfo>)>,
+    /// Bytes received and sent over all interfaces but loopback
+    network: Option<(Instant, u64, u64)>,
+    /// Kept between samples for `Backend::Crate`, its rates are per refresh
+    metrics: Option<Box<CrateMetrics>>,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+/// Use `config` from the next sample on
+pub fn configure(config: &SensorConfig) {
+    let mut state = STATE.lock().unwrap();
+    let state = state.get_or_insert_with(Default::default);
+    if state.config != *config {
+        *state = State { config: config.clone(), ..Default::default() };
+    }
+}
 
 /// System info payload matching APK protocol
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SysInfo {
     pub network: NetworkInfo,
     pub memory: MemoryInfo,
@@ -14,16 +157,35 @@ pub struct SysInfo {
     pub disk: DiskInfo,
     pub fans: Vec<FanInfo>,
     pub motherboard: MotherboardInfo,
+    /// AIO coolant and pump, not in the Windows app's payload, so only sent
+    /// when one of them was found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub liquid: Option<LiquidInfo>,
+    /// The running game's frame rate, likewise only sent while a game is logging
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game: Option<GameInfo>,
+    /// Which machine this is, when `send_host` is on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub host: Option<HostInfo>,
+    /// Busiest processes, when `top_process` is on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top: Option<TopInfo>,
+    /// Battery or UPS, when there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power: Option<PowerInfo>,
     pub timestamp: i64,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct NetworkInfo {
     pub upload: u64,
     pub download: u64,
+    /// Round trip to `ping_host` in ms, only sent when one is set and answers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ping: Option<u32>,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct MemoryInfo {
     pub total: u64,
     pub used: u64,
@@ -32,7 +194,7 @@ pub struct MemoryInfo {
     pub speed: u32,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[allow(non_snake_case)]
 pub struct CpuInfo {
     pub load: u8,
@@ -41,11 +203,28 @@ pub struct CpuInfo {
     pub power: u32,
     pub voltage: f32,
     pub usage: u8,
+    /// Busy share of each logical CPU, not part of the payload
+    #[serde(skip)]
+    pub core_usage: Vec<u8>,
+    /// Per-core or per-CCD sensors (`Core 3`, `Tccd1`), not part of the payload
+    #[serde(skip)]
+    pub core_temperatures: Vec<(String, u8)>,
+}
+
+impl CpuInfo {
+    /// The hottest per-core/CCD sensor
+    pub fn hottest_core(&self) -> Option<&(String, u8)> {
+        self.core_temperatures.iter().max_by_key(|(_, temp)| *temp)
+    }
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct GpuInfo {
     pub load: u8,
+    /// Whether `load` was read, not part of the payload. Without a driver that
+    /// reports it, it stays 0.
+    #[serde(skip)]
+    pub load_known: bool,
     pub temperature: u8,
     pub fan: u32,
     pub speed: u32,
@@ -53,7 +232,7 @@ pub struct GpuInfo {
     pub voltage: f32,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct DiskInfo {
     pub total: u64,
     pub used: u64,
@@ -66,7 +245,7 @@ pub struct DiskInfo {
     pub write_speed: u64,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct FanInfo {
     #[serde(rename = "onBoard")]
     pub on_board: bool,
@@ -74,7 +253,63 @@ pub struct FanInfo {
     pub value: u32,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct LiquidInfo {
+    /// Coolant temperature in °C
+    pub temperature: u8,
+    /// Pump speed in RPM
+    pub pump: u32,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GameInfo {
+    /// Program name from the MangoHud log file
+    pub name: String,
+    pub fps: u32,
+    /// Milliseconds per frame
+    pub frametime: f32,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct HostInfo {
+    pub hostname: String,
+    /// `uname -r`
+    pub kernel: String,
+    /// PRETTY_NAME from os-release
+    pub distro: String,
+    /// Seconds since boot
+    pub uptime: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TopInfo {
+    pub cpu: Option<ProcessUsage>,
+    /// Only for drivers that report per-client engine time
+    pub gpu: Option<ProcessUsage>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ProcessUsage {
+    pub pid: u32,
+    pub name: String,
+    /// % of all CPUs, or of the GPU's render engine
+    pub usage: u8,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PowerInfo {
+    /// `battery`, or the NUT UPS name
+    pub source: String,
+    /// Running from mains rather than the battery
+    pub ac: bool,
+    pub charging: bool,
+    /// Charge in %
+    pub charge: u8,
+    /// Seconds left on battery, None while on mains or when unknown
+    pub runtime: Option<u32>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct MotherboardInfo {
     pub temperature: u8,
     #[serde(rename = "pchTemperature")]
@@ -84,49 +319,128 @@ pub struct MotherboardInfo {
 impl Default for SysInfo {
     fn default() -> Self {
         Self {
-            network: NetworkInfo { upload: 0, download: 0 },
+            network: NetworkInfo { upload: 0, download: 0, ping: None },
             memory: MemoryInfo { total: 0, used: 0, load: 0, temperature: 0, speed: 0 },
-            cpu: CpuInfo { load: 0, temperature: 0, speedAverage: 0, power: 0, voltage: 0.0, usage: 0 },
-            gpu: GpuInfo { load: 0, temperature: 0, fan: 0, speed: 0, power: 0, voltage: 0.0 },
+            cpu: CpuInfo {
+                load: 0,
+                temperature: 0,
+                speedAverage: 0,
+                power: 0,
+                voltage: 0.0,
+                usage: 0,
+                core_usage: vec![],
+                core_temperatures: vec![],
+            },
+            gpu: GpuInfo { load: 0, load_known: false, temperature: 0, fan: 0, speed: 0, power: 0, voltage: 0.0 },
             disk: DiskInfo { total: 0, used: 0, load: 0, activity: 0, temperature: 0, read_speed: 0, write_speed: 0 },
             fans: vec![],
             motherboard: MotherboardInfo { temperature: 0, pch_temperature: 0 },
+            liquid: None,
+            game: None,
+            host: None,
+            top: None,
+            power: None,
             timestamp: 0,
         }
     }
 }
 
 impl SysInfo {
-    pub fn get_sysinfo() -> Self {
+    /// The sampler's latest sample, waiting for the first one when it isn't
+    /// running yet. Cheap enough for every heartbeat and frame.
+    pub fn snapshot() -> Self {
+        let mut guard = CACHE.lock().unwrap();
+        let stale_after = sample_interval() * 2;
+        loop {
+            let cache = guard.get_or_insert_with(Default::default);
+            cache.last_read = Some(Instant::now());
+            if !cache.running {
+                start_sampler(cache);
+            }
+            if let Some((at, info)) = &cache.info
+                && at.elapsed() < stale_after
+            {
+                return info.clone();
+            }
+            let (next, wait) = SAMPLED.wait_timeout(guard, SAMPLE_TIMEOUT).unwrap();
+            guard = next;
+            if wait.timed_out() {
+                // Stuck sampler (a hung nvidia-smi): better old figures than none
+                log::warn!("Sensor sampler hasn't finished a sample in {:?}", SAMPLE_TIMEOUT);
+                return guard.as_ref().and_then(|cache| cache.info.as_ref()).map(|(_, info)| info.clone()).unwrap_or_default();
+            }
+        }
+    }
+
+    /// This sample in the configured units, as it goes to the device. Samples
+    /// themselves stay in °C, MiB and KiB/s.
+    pub fn in_units(&self) -> Self {
+        let units = STATE.lock().unwrap().get_or_insert_with(Default::default).config.units;
+        units.apply(self)
+    }
+
+    /// Read everything now, `temps` and `fans` being this moment's
+    /// `temp_sensors()` and `fan_sensors()`
+    fn sample(temps: &[TempSensor], fans: &[FanSensor]) -> Self {
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_millis() as i64;
 
-        let cpu_temp = read_cpu_temp().unwrap_or(0);
-        let gpu_temp = read_gpu_temp().unwrap_or(0);
-        let (mem_total, mem_used, mem_load) = read_memory_info();
-        let (disk_total, disk_used, disk_load) = read_disk_info();
+        let cpu_temp = read_cpu_temp(temps).unwrap_or(0);
+        let nvidia = read_nvidia();
+        let gpu_temp = read_gpu_temp(temps, nvidia).unwrap_or(0);
+        let gpu_load = read_gpu_load(nvidia);
+        let disk_temp = read_disk_temp().unwrap_or(0);
+        let (backend, custom, send_host, top_process, nut_ups, ping_host, smoothing, script) = {
+            let mut state = STATE.lock().unwrap();
+            let config = &state.get_or_insert_with(Default::default).config;
+            (
+                config.backend,
+                config.custom.clone(),
+                config.send_host,
+                config.top_process,
+                config.nut_ups.clone(),
+                config.ping_host.clone(),
+                config.smoothing.clone(),
+                config.script.clone(),
+            )
+        };
+        let Usage {
+            cpu_load,
+            core_usage,
+            cpu_mhz,
+            memory: (mem_total, mem_used, mem_load),
+            disk: (disk_total, disk_used, disk_load),
+            disk_rates: (read_speed, write_speed, disk_activity),
+            network: (upload, download),
+        } = match backend {
+            Backend::Proc => sample_proc(),
+            Backend::Crate => sample_crate(),
+        };
 
-        Self {
-            network: NetworkInfo { upload: 0, download: 0 },
+        let mut info = Self {
+            network: NetworkInfo { upload, download, ping: crate::ping::latency(&ping_host) },
             memory: MemoryInfo {
                 total: mem_total,
                 used: mem_used,
                 load: mem_load,
-                temperature: 0,
-                speed: 3200, // placeholder
+                temperature: read_dimm_temp().unwrap_or(0),
+                speed: memory_speed().unwrap_or(0),
             },
             cpu: CpuInfo {
-                load: read_cpu_load().unwrap_or(0),
+                load: cpu_load,
                 temperature: cpu_temp,
-                speedAverage: 3000,
+                speedAverage: cpu_mhz,
                 power: 0,
-                voltage: 1.0,
-                usage: read_cpu_load().unwrap_or(0),
+                voltage: read_cpu_voltage().unwrap_or(0.0),
+                usage: cpu_load,
+                core_usage,
+                core_temperatures: read_core_temps(),
             },
             gpu: GpuInfo {
-                load: 0,
+                load: gpu_load.unwrap_or(0),
+                load_known: gpu_load.is_some(),
                 temperature: gpu_temp,
                 fan: 0,
                 speed: 0,
@@ -137,20 +451,187 @@ impl SysInfo {
                 total: disk_total,
                 used: disk_used,
                 load: disk_load,
-                activity: 0,
-                temperature: 0,
-                read_speed: 0,
-                write_speed: 0,
+                activity: disk_activity,
+                temperature: disk_temp,
+                read_speed,
+                write_speed,
             },
-            fans: vec![],
-            motherboard: MotherboardInfo { temperature: 0, pch_temperature: 0 },
+            fans: read_fans(fans),
+            motherboard: read_motherboard(temps),
+            liquid: read_liquid(temps, fans),
+            game: read_game(),
+            host: send_host.then(host_info),
+            top: if top_process { crate::top_process::sample() } else { None },
+            power: crate::power_supply::read(&nut_ups),
             timestamp,
+        };
+        crate::custom_sensor::apply(&custom, &mut info);
+        crate::smoothing::apply(&smoothing, &mut info);
+        crate::scripting::apply(&script, &mut info);
+        info
+    }
+}
+
+/// Figures either backend can provide, in payload units
+struct Usage {
+    cpu_load: u8,
+    core_usage: Vec<u8>,
+    cpu_mhz: u32,
+    /// Total and used MB, load %
+    memory: (u64, u64, u8),
+    /// Total and used GB of the root filesystem, load %
+    disk: (u64, u64, u8),
+    /// Read and write MB/s, activity %
+    disk_rates: (u64, u64, u8),
+    /// Upload and download KB/s
+    network: (u64, u64),
+}
+
+fn sample_proc() -> Usage {
+    let (cpu_load, core_usage) = sample_cpu_load();
+    Usage {
+        cpu_load,
+        core_usage,
+        cpu_mhz: read_cpu_mhz().unwrap_or(0),
+        memory: read_memory_info(),
+        disk: read_disk_info(),
+        disk_rates: sample_disk_rates(),
+        network: sample_network(),
+    }
+}
+
+/// `sysinfo` crate handles, refreshed on every sample
+struct CrateMetrics {
+    system: ::sysinfo::System,
+    disks: ::sysinfo::Disks,
+    networks: ::sysinfo::Networks,
+    at: Instant,
+}
+
+/// The same figures from the `sysinfo` crate. Disk activity isn't something it
+/// measures, so that still comes from /proc/diskstats. Rates and CPU usage are
+/// 0 on the first call, as with the built-in readers.
+fn sample_crate() -> Usage {
+    let activity = sample_disk_rates().2;
+    let mut state = STATE.lock().unwrap();
+    let state = state.get_or_insert_with(Default::default);
+    let device = selected_disk(&state.config.disk_device);
+    let first = state.metrics.is_none();
+    let metrics = state.metrics.get_or_insert_with(|| {
+        Box::new(CrateMetrics {
+            system: ::sysinfo::System::new(),
+            disks: ::sysinfo::Disks::new_with_refreshed_list(),
+            networks: ::sysinfo::Networks::new_with_refreshed_list(),
+            at: Instant::now(),
+        })
+    });
+    let system = &mut metrics.system;
+    system.refresh_cpu_usage();
+    system.refresh_cpu_frequency();
+    system.refresh_memory();
+    if !first {
+        metrics.disks.refresh(true);
+        metrics.networks.refresh(true);
+    }
+    let now = Instant::now();
+    let secs = now.duration_since(metrics.at).as_secs_f64();
+    metrics.at = now;
+    let per_sec = |bytes: u64, unit: f64| if first || secs <= 0.0 { 0 } else { (bytes as f64 / secs / unit).round() as u64 };
+
+    let cpus = system.cpus();
+    let cpu_mhz = match cpus.len() {
+        0 => 0,
+        n => (cpus.iter().map(|cpu| cpu.frequency()).sum::<u64>() / n as u64) as u32,
+    };
+    let percent = |part: u64, total: u64| (part * 100).checked_div(total).unwrap_or(0) as u8;
+
+    let mem_total = system.total_memory();
+    let mem_used = mem_total.saturating_sub(system.available_memory());
+
+    const GB: u64 = 1024 * 1024 * 1024;
+    let disk = metrics
+        .disks
+        .iter()
+        .find(|disk| disk.mount_point() == Path::new("/"))
+        .map(|disk| {
+            let used = disk.total_space().saturating_sub(disk.available_space());
+            (disk.total_space() / GB, used / GB, percent(used, disk.total_space()))
+        })
+        .unwrap_or((0, 0, 0));
+
+    // Partitions of the selected disk, each once however often it is mounted
+    let mut partitions = std::collections::HashSet::new();
+    let (mut read, mut written) = (0, 0);
+    for disk in metrics.disks.iter() {
+        let name = disk.name().to_string_lossy();
+        let name = name.strip_prefix("/dev/").unwrap_or(&name);
+        if !device.is_empty() && name.starts_with(device.as_str()) && partitions.insert(name.to_string()) {
+            read += disk.usage().read_bytes;
+            written += disk.usage().written_bytes;
+        }
+    }
+
+    let (mut received, mut transmitted) = (0, 0);
+    for (name, data) in &metrics.networks {
+        if name != "lo" {
+            received += data.received();
+            transmitted += data.transmitted();
         }
     }
+
+    Usage {
+        cpu_load: system.global_cpu_usage().round() as u8,
+        core_usage: cpus.iter().map(|cpu| cpu.cpu_usage().round() as u8).collect(),
+        cpu_mhz,
+        memory: (mem_total / (1024 * 1024), mem_used / (1024 * 1024), percent(mem_used, mem_total)),
+        disk,
+        disk_rates: (per_sec(read, 1024.0 * 1024.0), per_sec(written, 1024.0 * 1024.0), activity),
+        network: (per_sec(transmitted, 1024.0), per_sec(received, 1024.0)),
+    }
 }
 
-/// Read CPU temp from thermal zones
-fn read_cpu_temp() -> Option<u8> {
+/// Upload and download in KB/s over every interface but loopback, from
+/// /proc/net/dev. Zero on the first call.
+fn sample_network() -> (u64, u64) {
+    let content = fs::read_to_string("/proc/net/dev").unwrap_or_default();
+    let (mut received, mut transmitted) = (0u64, 0u64);
+    // Two header lines, then `iface: rx_bytes packets errs drop fifo frame compressed multicast tx_bytes ...`
+    for line in content.lines().skip(2) {
+        let Some((name, counters)) = line.split_once(':') else {
+            continue;
+        };
+        if name.trim() == "lo" {
+            continue;
+        }
+        let counters: Vec<u64> = counters.split_whitespace().filter_map(|value| value.parse().ok()).collect();
+        received += counters.first().copied().unwrap_or(0);
+        transmitted += counters.get(8).copied().unwrap_or(0);
+    }
+
+    let now = Instant::now();
+    let previous = STATE.lock().unwrap().get_or_insert_with(Default::default).network.replace((now, received, transmitted));
+    let Some((at, received_before, transmitted_before)) = previous else {
+        return (0, 0);
+    };
+    let secs = now.duration_since(at).as_secs_f64();
+    if secs <= 0.0 {
+        return (0, 0);
+    }
+    let rate = |before: u64, after: u64| (after.saturating_sub(before) as f64 / secs / 1024.0).round() as u64;
+    (rate(transmitted_before, transmitted), rate(received_before, received))
+}
+
+/// The configured CPU sensor, else the detected one, else the first thermal
+/// zone or hwmon temp1
+fn read_cpu_temp(temps: &[TempSensor]) -> Option<u8> {
+    match bound_sensor(TempRole::Cpu) {
+        Some(key) => temps.iter().find(|sensor| sensor.key == key).map(|sensor| sensor.celsius),
+        None => auto_sensor(TempRole::Cpu, temps).map(|sensor| sensor.celsius).or_else(first_temp),
+    }
+}
+
+/// Whatever comes first, which is often an ACPI zone rather than the CPU
+fn first_temp() -> Option<u8> {
     for i in 0..10 {
         let path = format!("/sys/class/thermal/thermal_zone{}/temp", i);
         if let Ok(content) = fs::read_to_string(&path) {
@@ -173,33 +654,49 @@ fn read_cpu_temp() -> Option<u8> {
     None
 }
 
-/// Read GPU temp (supports NVIDIA and AMD)
-fn read_gpu_temp() -> Option<u8> {
-    // Try nvidia-smi first..
-    if let Ok(output) = Command::new("nvidia-smi")
-        .args(["--query-gpu=temperature.gpu", "--format=csv,noheader,nounits"])
+/// What nvidia-smi reports for the first card
+#[derive(Clone, Copy)]
+struct NvidiaReading {
+    temperature: u8,
+    utilization: u8,
+}
+
+/// One nvidia-smi run per sample for both readings, None without the driver
+fn read_nvidia() -> Option<NvidiaReading> {
+    let output = Command::new("nvidia-smi")
+        .args(["--query-gpu=temperature.gpu,utilization.gpu", "--format=csv,noheader,nounits"])
         .output()
-    {
-        if output.status.success() {
-            if let Ok(temp) = String::from_utf8_lossy(&output.stdout).trim().parse::<u8>() {
-                return Some(temp);
-            }
-        }
+        .ok()?;
+    if !output.status.success() {
+        return None;
     }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (temperature, utilization) = stdout.lines().next()?.split_once(',')?;
+    Some(NvidiaReading { temperature: temperature.trim().parse().ok()?, utilization: utilization.trim().parse().ok()? })
+}
 
-    // Otherwise, try AMD hwmon
-    for card in &["card0", "card1"] {
-        for i in 0..5 {
-            let path = format!("/sys/class/drm/{}/device/hwmon/hwmon{}/temp1_input", card, i);
-            if let Ok(content) = fs::read_to_string(&path) {
-                if let Ok(temp_milli) = content.trim().parse::<i32>() {
-                    return Some((temp_milli / 1000) as u8);
-                }
-            }
-        }
+/// Read GPU temp (supports NVIDIA and AMD)
+fn read_gpu_temp(temps: &[TempSensor], nvidia: Option<NvidiaReading>) -> Option<u8> {
+    if let Some(key) = bound_sensor(TempRole::Gpu) {
+        return temps.iter().find(|sensor| sensor.key == key).map(|sensor| sensor.celsius);
     }
 
-    None
+    // nvidia-smi first, otherwise the hwmon of an AMD/Intel/nouveau card
+    nvidia.map(|reading| reading.temperature).or_else(|| auto_sensor(TempRole::Gpu, temps).map(|sensor| sensor.celsius))
+}
+
+/// GPU busy %, the busiest card's. amdgpu has `gpu_busy_percent` in sysfs,
+/// NVIDIA goes through nvidia-smi; None for drivers with neither (i915, nouveau).
+fn read_gpu_load(nvidia: Option<NvidiaReading>) -> Option<u8> {
+    let amd = fs::read_dir("/sys/class/drm")
+        .into_iter()
+        .flatten()
+        .flatten()
+        // card0, not its connectors (card0-DP-1)
+        .filter(|entry| entry.file_name().to_str().is_some_and(|name| name.starts_with("card") && !name.contains('-')))
+        .filter_map(|entry| fs::read_to_string(entry.path().join("device/gpu_busy_percent")).ok()?.trim().parse::<u8>().ok())
+        .max();
+    amd.or(nvidia.map(|reading| reading.utilization))
 }
 
 /// Read memory info from /proc/meminfo
@@ -230,6 +727,90 @@ fn parse_meminfo_value(line: &str) -> u64 {
         .unwrap_or(0)
 }
 
+/// Configured memory speed in MT/s, read once since it can't change while running
+fn memory_speed() -> Option<u32> {
+    static SPEED: OnceLock<Option<u32>> = OnceLock::new();
+    *SPEED.get_or_init(|| {
+        let speed = dmi_memory_speed().or_else(udev_memory_speed).or_else(dmidecode_memory_speed);
+        if speed.is_none() {
+            log::info!("Memory speed unknown: DMI tables need root and udev has no memory device properties");
+        }
+        speed
+    })
+}
+
+/// SMBIOS type 17 (memory device) entries, readable by root only
+fn dmi_memory_speed() -> Option<u32> {
+    let mut speeds = Vec::new();
+    for entry in fs::read_dir("/sys/firmware/dmi/entries").ok()?.flatten() {
+        if !entry.file_name().to_string_lossy().starts_with("17-") {
+            continue;
+        }
+        let Ok(raw) = fs::read(entry.path().join("raw")) else {
+            continue;
+        };
+        let word = |offset: usize| raw.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as u32);
+        let dword = |offset: usize| raw.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        // Size 0 is an empty slot
+        if word(0x0c).unwrap_or(0) == 0 {
+            continue;
+        }
+        // Configured speed, 0xFFFF points to the 32-bit extended field; else the rated speed
+        let speed = match word(0x20) {
+            Some(0xffff) => dword(0x58),
+            Some(0) | None => match word(0x15) {
+                Some(0xffff) => dword(0x54),
+                speed => speed,
+            },
+            speed => speed,
+        };
+        speeds.extend(speed.filter(|&speed| speed > 0));
+    }
+    speeds.into_iter().min()
+}
+
+/// systemd's dmi_memory_id copies the memory devices into udev properties anyone can read
+fn udev_memory_speed() -> Option<u32> {
+    let output = Command::new("udevadm")
+        .args(["info", "--query=property", "--path=/sys/devices/virtual/dmi/id"])
+        .output()
+        .ok()?;
+    let properties = String::from_utf8_lossy(&output.stdout);
+    let speeds = |suffix: &str| -> Vec<u32> {
+        properties
+            .lines()
+            .filter_map(|line| line.strip_prefix("MEMORY_DEVICE_")?.split_once(suffix)?.1.strip_prefix('=')?.parse().ok())
+            .filter(|&speed| speed > 0)
+            .collect()
+    };
+    let configured = speeds("_CONFIGURED_SPEED_MTS");
+    let speeds = if configured.is_empty() { speeds("_SPEED_MTS") } else { configured };
+    speeds.into_iter().min()
+}
+
+/// `dmidecode -t 17`, which also needs root but is sometimes allowed through sudoers
+fn dmidecode_memory_speed() -> Option<u32> {
+    let output = Command::new("dmidecode").args(["-t", "17"]).output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    text.lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once(": ")?;
+            matches!(key, "Configured Memory Speed" | "Configured Clock Speed").then_some(())?;
+            value.split_whitespace().next()?.parse::<u32>().ok()
+        })
+        .filter(|&speed| speed > 0)
+        .min()
+}
+
+/// Hottest DIMM from the SPD temperature sensors (jc42 on DDR4, spd5118 on DDR5)
+fn read_dimm_temp() -> Option<u8> {
+    hwmon_dirs(Path::new("/sys/class/hwmon"))
+        .into_iter()
+        .filter(|dir| fs::read_to_string(dir.join("name")).is_ok_and(|name| matches!(name.trim(), "jc42" | "spd5118")))
+        .filter_map(|dir| read_millidegrees(&dir.join("temp1_input")))
+        .max()
+}
+
 /// Read disk info for root partition
 fn read_disk_info() -> (u64, u64, u8) {
     if let Ok(output) = Command::new("df")
@@ -252,10 +833,711 @@ fn read_disk_info() -> (u64, u64, u8) {
     (0, 0, 0)
 }
 
-/// Read CPU load from /proc/stat (rough estimate for now, will probably be replaced with sysinfo eventually)
-fn read_cpu_load() -> Option<u8> {
-    let content = fs::read_to_string("/proc/loadavg").ok()?;
-    let load_1min: f32 = content.split_whitespace().next()?.parse().ok()?;
-    Some((load_1min * 25.0).min(100.0) as u8)
+/// Cumulative counters of one block device from /proc/diskstats
+#[derive(Debug, Clone)]
+struct DiskCounters {
+    at: Instant,
+    device: String,
+    sectors_read: u64,
+    sectors_written: u64,
+    /// Milliseconds spent with I/O in flight
+    io_ms: u64,
+}
+
+/// Read and write speed in MB/s and the share of time the disk was busy, the
+/// way the Windows app shows them. Zero on the first call after (re)configuring.
+fn sample_disk_rates() -> (u64, u64, u8) {
+    let mut state = STATE.lock().unwrap();
+    let state = state.get_or_insert_with(Default::default);
+    let device = selected_disk(&state.config.disk_device);
+    let current = read_disk_counters(&device);
+    let previous = std::mem::replace(&mut state.disk, current.clone());
+    let (Some(previous), Some(current)) = (previous, current) else {
+        return (0, 0, 0);
+    };
+    let secs = current.at.duration_since(previous.at).as_secs_f64();
+    if previous.device != current.device || secs <= 0.0 {
+        return (0, 0, 0);
+    }
+
+    let rate = |before: u64, after: u64| (after.saturating_sub(before) as f64 * 512.0 / secs / (1024.0 * 1024.0)).round() as u64;
+    let busy = current.io_ms.saturating_sub(previous.io_ms) as f64 / (secs * 1000.0);
+    (
+        rate(previous.sectors_read, current.sectors_read),
+        rate(previous.sectors_written, current.sectors_written),
+        (busy * 100.0).round().min(100.0) as u8,
+    )
+}
+
+fn read_disk_counters(device: &str) -> Option<DiskCounters> {
+    let content = fs::read_to_string("/proc/diskstats").ok()?;
+    // major minor name reads merged sectors ms writes merged sectors ms in-flight io_ms ...
+    let fields: Vec<&str> = content
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .find(|fields| fields.get(2) == Some(&device))?;
+    let number = |i: usize| fields.get(i).and_then(|value| value.parse::<u64>().ok());
+    Some(DiskCounters {
+        at: Instant::now(),
+        device: device.to_string(),
+        sectors_read: number(5)?,
+        sectors_written: number(9)?,
+        io_ms: number(12)?,
+    })
+}
+
+/// Whole disks that have I/O counters, for picking one in the settings
+pub fn block_devices() -> Vec<String> {
+    let mut devices: Vec<String> = fs::read_dir("/sys/block")
+        .map(|entries| entries.flatten().filter_map(|entry| entry.file_name().into_string().ok()).collect())
+        .unwrap_or_default();
+    devices.retain(|name| !["loop", "ram", "zram", "sr", "fd"].iter().any(|prefix| name.starts_with(prefix)));
+    devices.sort();
+    devices
 }
 
+/// `device`, or the root filesystem's disk when empty
+fn selected_disk(device: &str) -> String {
+    if device.is_empty() {
+        root_block_device().unwrap_or_default()
+    } else {
+        device.to_string()
+    }
+}
+
+/// Drive temperature in °C from its hwmon node, then smartctl if enabled
+fn read_disk_temp() -> Option<u8> {
+    let mut state = STATE.lock().unwrap();
+    let state = state.get_or_insert_with(Default::default);
+    let config = &state.config;
+    let device = selected_disk(if config.disk_temp_device.is_empty() { &config.disk_device } else { &config.disk_temp_device });
+    if let Some(temp) = hwmon_disk_temp(&device) {
+        return Some(temp);
+    }
+    if !config.smartctl {
+        return None;
+    }
+    match &state.smart {
+        Some((at, cached, temp)) if *cached == device && at.elapsed() < SMARTCTL_INTERVAL => *temp,
+        _ => {
+            let temp = smartctl_temp(&device);
+            state.smart = Some((Instant::now(), device, temp));
+            temp
+        }
+    }
+}
+
+/// nvme and drivetemp register a hwmon device under the disk's controller;
+/// temp1 is the composite/drive temperature
+fn hwmon_disk_temp(device: &str) -> Option<u8> {
+    if device.is_empty() {
+        return None;
+    }
+    let controller = Path::new("/sys/block").join(device).join("device");
+    hwmon_dirs(&controller)
+        .into_iter()
+        .chain(hwmon_dirs(&controller.join("hwmon")))
+        .find_map(|dir| read_millidegrees(&dir.join("temp1_input")))
+}
+
+/// `hwmonN` directories directly inside `dir`
+fn hwmon_dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.file_name().to_string_lossy().starts_with("hwmon"))
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect()
+        })
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+fn read_millidegrees(path: &Path) -> Option<u8> {
+    let milli: i32 = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    Some((milli / 1000).clamp(0, u8::MAX as i32) as u8)
+}
+
+fn smartctl_temp(device: &str) -> Option<u8> {
+    let output = Command::new("smartctl").args(["-A", "-j", &format!("/dev/{}", device)]).output().ok()?;
+    // The exit status is a bit mask that is nonzero for healthy drives too
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).ok()?;
+    let temp = report.get("temperature")?.get("current")?.as_u64()?;
+    u8::try_from(temp).ok()
+}
+
+/// Disks with a hwmon temperature and its current value, for picking one in the settings
+pub fn disk_temperatures() -> Vec<(String, u8)> {
+    block_devices().into_iter().filter_map(|device| Some((device.clone(), hwmon_disk_temp(&device)?))).collect()
+}
+
+/// A fan tachometer under /sys/class/hwmon
+#[derive(Debug, Clone)]
+pub struct FanSensor {
+    /// `chip/fanN`, stable across reboots unlike the hwmon index
+    pub key: String,
+    /// The driver's label, or the key
+    pub name: String,
+    pub rpm: u32,
+    /// On the motherboard's Super I/O or EC rather than a graphics card
+    pub on_board: bool,
+}
+
+/// hwmon drivers of graphics cards, their fans aren't the board's
+const GPU_HWMON: [&str; 4] = ["amdgpu", "radeon", "nouveau", "i915"];
+
+/// Every `fanN_input` of every hwmon chip
+pub fn fan_sensors() -> Vec<FanSensor> {
+    let mut fans = Vec::new();
+    for dir in hwmon_dirs(Path::new("/sys/class/hwmon")) {
+        let chip = fs::read_to_string(dir.join("name")).map(|name| name.trim().to_string()).unwrap_or_default();
+        let mut inputs: Vec<u32> = fs::read_dir(&dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|entry| entry.file_name().to_str()?.strip_prefix("fan")?.strip_suffix("_input")?.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        inputs.sort();
+        for n in inputs {
+            let Some(rpm) = fs::read_to_string(dir.join(format!("fan{}_input", n))).ok().and_then(|rpm| rpm.trim().parse().ok()) else {
+                continue;
+            };
+            let key = format!("{}/fan{}", chip, n);
+            let name = fs::read_to_string(dir.join(format!("fan{}_label", n)))
+                .map(|label| label.trim().to_string())
+                .ok()
+                .filter(|label| !label.is_empty())
+                .unwrap_or_else(|| key.clone());
+            fans.push(FanSensor { key, name, rpm, on_board: !GPU_HWMON.contains(&chip.as_str()) });
+        }
+    }
+    fans
+}
+
+/// The picked fans, or every fan that spins when none are picked
+fn read_fans(fans: &[FanSensor]) -> Vec<FanInfo> {
+    let selected = STATE.lock().unwrap().get_or_insert_with(Default::default).config.fans.clone();
+    fans.iter()
+        .filter(|fan| if selected.is_empty() { fan.rpm > 0 } else { selected.contains(&fan.key) })
+        .map(|fan| FanInfo { on_board: fan.on_board, name: fan.name.clone(), value: fan.rpm })
+        .collect()
+}
+
+/// A temperature input under /sys/class/hwmon, or a thermal zone without one
+#[derive(Debug, Clone)]
+pub struct TempSensor {
+    /// `chip/tempN`, `thermal/<type>` for a thermal zone
+    pub key: String,
+    pub chip: String,
+    /// The driver's label, if it has one
+    pub label: Option<String>,
+    pub celsius: u8,
+}
+
+impl TempSensor {
+    pub fn name(&self) -> String {
+        match &self.label {
+            Some(label) => format!("{} {}", self.chip, label),
+            None => self.key.clone(),
+        }
+    }
+}
+
+/// Every `tempN_input` of every hwmon chip, then the thermal zones that have
+/// no hwmon chip of their own
+pub fn temp_sensors() -> Vec<TempSensor> {
+    let mut sensors = Vec::new();
+    for dir in hwmon_dirs(Path::new("/sys/class/hwmon")) {
+        let chip = fs::read_to_string(dir.join("name")).map(|name| name.trim().to_string()).unwrap_or_default();
+        let mut inputs: Vec<u32> = fs::read_dir(&dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|entry| entry.file_name().to_str()?.strip_prefix("temp")?.strip_suffix("_input")?.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        inputs.sort();
+        for n in inputs {
+            let Some(celsius) = read_millidegrees(&dir.join(format!("temp{}_input", n))) else {
+                continue;
+            };
+            let label = fs::read_to_string(dir.join(format!("temp{}_label", n)))
+                .map(|label| label.trim().to_string())
+                .ok()
+                .filter(|label| !label.is_empty());
+            sensors.push(TempSensor { key: format!("{}/temp{}", chip, n), chip: chip.clone(), label, celsius });
+        }
+    }
+
+    // The thermal core registers most zones as a hwmon chip named after their type
+    let mut zones: Vec<(u32, PathBuf)> = fs::read_dir("/sys/class/thermal")
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| Some((entry.file_name().to_str()?.strip_prefix("thermal_zone")?.parse().ok()?, entry.path())))
+                .collect()
+        })
+        .unwrap_or_default();
+    zones.sort();
+    let mut seen: Vec<String> = Vec::new();
+    for (_, zone) in zones {
+        let Ok(kind) = fs::read_to_string(zone.join("type")).map(|kind| kind.trim().to_string()) else {
+            continue;
+        };
+        if sensors.iter().any(|sensor| sensor.chip == kind) {
+            continue;
+        }
+        let Some(celsius) = read_millidegrees(&zone.join("temp")) else {
+            continue;
+        };
+        // Zones of the same type are told apart by their order
+        let count = seen.iter().filter(|other| **other == kind).count();
+        let key = if count == 0 { format!("thermal/{}", kind) } else { format!("thermal/{}.{}", kind, count + 1) };
+        seen.push(kind.clone());
+        sensors.push(TempSensor { key, chip: "thermal".to_string(), label: Some(kind), celsius });
+    }
+    sensors
+}
+
+/// Payload fields a `TempSensor` can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TempRole {
+    Cpu,
+    Gpu,
+    Board,
+    Pch,
+    Coolant,
+}
+
+/// CPU package labels, best first: k10temp/zenpower's `Tdie` (without
+/// Threadripper's offset), `Tctl`, coretemp's package, lowercase
+const CPU_LABELS: [&str; 3] = ["tdie", "tctl", "package id 0"];
+/// Chips or zone types whose first input is the CPU (ARM SoCs, Intel's package zone)
+const CPU_CHIPS: [&str; 3] = ["cpu_thermal", "soc_thermal", "x86_pkg_temp"];
+/// GPU temperature labels, the die edge rather than the hotspot or memory
+const GPU_LABELS: [&str; 2] = ["edge", "gpu"];
+
+/// The configured key for `role`, None when it is detected
+fn bound_sensor(role: TempRole) -> Option<String> {
+    let mut state = STATE.lock().unwrap();
+    let config = &state.get_or_insert_with(Default::default).config;
+    let key = match role {
+        TempRole::Cpu => &config.cpu_sensor,
+        TempRole::Gpu => &config.gpu_sensor,
+        TempRole::Board => &config.board_sensor,
+        TempRole::Pch => &config.pch_sensor,
+        TempRole::Coolant => &config.coolant_sensor,
+    };
+    Some(key.clone()).filter(|key| !key.is_empty())
+}
+
+/// The sensor picked for `role` when none is configured
+pub fn auto_sensor(role: TempRole, sensors: &[TempSensor]) -> Option<&TempSensor> {
+    let label = |sensor: &TempSensor| sensor.label.as_deref().unwrap_or_default().to_lowercase();
+    let chip_is = |sensor: &TempSensor, chips: &[&str]| chips.iter().any(|chip| sensor.chip.starts_with(chip) || label(sensor) == *chip);
+    match role {
+        TempRole::Cpu => CPU_LABELS
+            .iter()
+            .find_map(|wanted| sensors.iter().find(|sensor| label(sensor) == *wanted))
+            .or_else(|| sensors.iter().find(|sensor| chip_is(sensor, &CPU_CHIPS))),
+        TempRole::Gpu => {
+            let gpus: Vec<&TempSensor> = sensors.iter().filter(|sensor| GPU_HWMON.contains(&sensor.chip.as_str())).collect();
+            gpus.iter().find(|sensor| GPU_LABELS.contains(&label(sensor).as_str())).or(gpus.first()).copied()
+        }
+        TempRole::Board => sensors
+            .iter()
+            .find(|sensor| BOARD_LABELS.contains(&label(sensor).as_str()))
+            .or_else(|| sensors.iter().find(|sensor| chip_is(sensor, &BOARD_CHIPS) && sensor.key.ends_with("/temp1"))),
+        // nct67xx PCH_CHIP_TEMP, ASUS "Chipset", Intel's pch_* thermal driver
+        TempRole::Pch => sensors.iter().find(|sensor| {
+            let label = label(sensor);
+            label.starts_with("pch_chip") || label.starts_with("chipset") || sensor.chip.starts_with("pch_") || label.starts_with("pch_")
+        }),
+        // kraken3/ryujin "Coolant temp", aquacomputer "Coolant temp", boards' "Water In"
+        TempRole::Coolant => sensors.iter().find(|sensor| COOLANT_LABELS.iter().any(|wanted| label(sensor).contains(wanted))),
+    }
+}
+
+/// Words in the labels of coolant temperature inputs, lowercase
+const COOLANT_LABELS: [&str; 3] = ["coolant", "liquid", "water"];
+
+/// The pump when none is configured: a fan labeled as one (kraken3's `Pump speed`,
+/// ASUS `AIO Pump`, `W_PUMP+`)
+pub fn auto_pump(fans: &[FanSensor]) -> Option<&FanSensor> {
+    fans.iter().find(|fan| fan.name.to_lowercase().contains("pump"))
+}
+
+/// Coolant temperature and pump speed: the configured or detected hwmon inputs,
+/// then liquidctl for whatever is still missing when that is enabled. None when
+/// there's neither, i.e. no AIO is visible to us.
+fn read_liquid(temps: &[TempSensor], fans: &[FanSensor]) -> Option<LiquidInfo> {
+    let (pump_key, use_liquidctl) = {
+        let mut state = STATE.lock().unwrap();
+        let config = &state.get_or_insert_with(Default::default).config;
+        (config.pump_sensor.clone(), config.liquidctl)
+    };
+    let mut temperature = match bound_sensor(TempRole::Coolant) {
+        Some(key) => temps.iter().find(|sensor| sensor.key == key),
+        None => auto_sensor(TempRole::Coolant, temps),
+    }
+    .map(|sensor| sensor.celsius);
+    let mut pump = if pump_key.is_empty() { auto_pump(fans) } else { fans.iter().find(|fan| fan.key == pump_key) }.map(|fan| fan.rpm);
+
+    if use_liquidctl && (temperature.is_none() || pump.is_none()) {
+        let mut state = STATE.lock().unwrap();
+        let state = state.get_or_insert_with(Default::default);
+        let reading = match &state.liquidctl {
+            Some((at, reading)) if at.elapsed() < LIQUIDCTL_INTERVAL => reading.clone(),
+            _ => {
+                let reading = liquidctl_status();
+                state.liquidctl = Some((Instant::now(), reading.clone()));
+                reading
+            }
+        };
+        if let Some(reading) = reading {
+            temperature = temperature.or(Some(reading.temperature).filter(|temp| *temp > 0));
+            pump = pump.or(Some(reading.pump).filter(|rpm| *rpm > 0));
+        }
+    }
+    if temperature.is_none() && pump.is_none() {
+        return None;
+    }
+    Some(LiquidInfo { temperature: temperature.unwrap_or(0), pump: pump.unwrap_or(0) })
+}
+
+/// Identity of this machine. Hostname and uptime are read every time, the
+/// kernel and distro once.
+pub fn host_info() -> HostInfo {
+    static FIXED: OnceLock<(String, String)> = OnceLock::new();
+    let (kernel, distro) = FIXED.get_or_init(|| {
+        let kernel = fs::read_to_string("/proc/sys/kernel/osrelease").map(|kernel| kernel.trim().to_string()).unwrap_or_default();
+        (kernel, os_release_name().unwrap_or_else(|| "Linux".to_string()))
+    });
+    HostInfo {
+        hostname: fs::read_to_string("/proc/sys/kernel/hostname").map(|name| name.trim().to_string()).unwrap_or_default(),
+        kernel: kernel.clone(),
+        distro: distro.clone(),
+        uptime: fs::read_to_string("/proc/uptime")
+            .ok()
+            .and_then(|uptime| uptime.split_whitespace().next()?.parse::<f64>().ok())
+            .map_or(0, |secs| secs as u64),
+    }
+}
+
+/// PRETTY_NAME, else NAME, from /etc/os-release or its fallback in /usr/lib
+fn os_release_name() -> Option<String> {
+    let content = fs::read_to_string("/etc/os-release").or_else(|_| fs::read_to_string("/usr/lib/os-release")).ok()?;
+    let value = |key: &str| {
+        content.lines().find_map(|line| {
+            let value = line.strip_prefix(key)?.strip_prefix('=')?;
+            Some(value.trim().trim_matches('"').to_string()).filter(|value| !value.is_empty())
+        })
+    };
+    value("PRETTY_NAME").or_else(|| value("NAME"))
+}
+
+/// FPS from MangoHud's frame log, when a folder is set and a game is logging
+fn read_game() -> Option<GameInfo> {
+    let dir = STATE.lock().unwrap().get_or_insert_with(Default::default).config.mangohud_dir.clone();
+    if dir.trim().is_empty() {
+        return None;
+    }
+    crate::mangohud::read(Path::new(dir.trim()))
+}
+
+/// `liquidctl status --json`: the first device reporting a liquid temperature
+/// or pump speed
+fn liquidctl_status() -> Option<LiquidInfo> {
+    let output = Command::new("liquidctl").args(["status", "--json"]).output().ok()?;
+    if !output.status.success() {
+        log::debug!("liquidctl: {}", String::from_utf8_lossy(&output.stderr).trim());
+        return None;
+    }
+    let devices: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).ok()?;
+    devices.iter().find_map(|device| {
+        let mut info = LiquidInfo::default();
+        for item in device.get("status")?.as_array()? {
+            let key = item.get("key").and_then(|key| key.as_str()).unwrap_or_default().to_lowercase();
+            let Some(value) = item.get("value").and_then(|value| value.as_f64()) else {
+                continue;
+            };
+            if key.contains("liquid temperature") || key.contains("coolant") {
+                info.temperature = value.round().clamp(0.0, u8::MAX as f64) as u8;
+            } else if key.contains("pump speed") {
+                info.pump = value.round() as u32;
+            }
+        }
+        (info.temperature > 0 || info.pump > 0).then_some(info)
+    })
+}
+
+/// Labels of the board (system) temperature on Super I/O and ASUS EC/WMI chips, lowercase
+const BOARD_LABELS: [&str; 4] = ["systin", "motherboard", "motherboard temperature", "system"];
+/// Chips whose unlabeled temp1 is the board sensor
+const BOARD_CHIPS: [&str; 2] = ["it87", "gigabyte_wmi"];
+
+/// Board and chipset temperatures: the configured sensors, else the ones found by label
+fn read_motherboard(temps: &[TempSensor]) -> MotherboardInfo {
+    let read = |role| match bound_sensor(role) {
+        Some(key) => temps.iter().find(|sensor| sensor.key == key).map(|sensor| sensor.celsius),
+        None => auto_sensor(role, temps).map(|sensor| sensor.celsius),
+    };
+    MotherboardInfo { temperature: read(TempRole::Board).unwrap_or(0), pch_temperature: read(TempRole::Pch).unwrap_or(0) }
+}
+
+/// Disk the root filesystem lives on, the first listed disk when that can't be
+/// told (btrfs subvolumes report an anonymous device)
+fn root_block_device() -> Option<String> {
+    let from_root = || {
+        let dev = fs::metadata("/").ok()?.dev();
+        let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+        let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+        let path = fs::canonicalize(format!("/sys/dev/block/{}:{}", major, minor)).ok()?;
+        // Count the whole disk, not just the root partition
+        let disk = if path.join("partition").exists() { path.parent()? } else { path.as_path() };
+        disk.file_name()?.to_str().map(str::to_string)
+    };
+    from_root().or_else(|| block_devices().into_iter().next())
+}
+
+/// Mean current clock of the online cores in MHz, from cpufreq or /proc/cpuinfo
+fn read_cpu_mhz() -> Option<u32> {
+    let mut khz = Vec::new();
+    for entry in fs::read_dir("/sys/devices/system/cpu").ok()?.flatten() {
+        let name = entry.file_name();
+        let Some(index) = name.to_str().and_then(|name| name.strip_prefix("cpu")) else {
+            continue;
+        };
+        if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        // cpu0 usually has no `online` file, it can't be taken offline
+        let online = fs::read_to_string(entry.path().join("online")).map(|online| online.trim() == "1").unwrap_or(true);
+        if !online {
+            continue;
+        }
+        if let Some(freq) = fs::read_to_string(entry.path().join("cpufreq/scaling_cur_freq")).ok().and_then(|freq| freq.trim().parse::<u64>().ok()) {
+            khz.push(freq);
+        }
+    }
+    if !khz.is_empty() {
+        return Some((khz.iter().sum::<u64>() / khz.len() as u64 / 1000) as u32);
+    }
+
+    // No cpufreq driver (VMs, some ARM boards)
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").ok()?;
+    let mhz: Vec<f64> = cpuinfo
+        .lines()
+        .filter(|line| line.starts_with("cpu MHz"))
+        .filter_map(|line| line.split(':').nth(1)?.trim().parse().ok())
+        .collect();
+    if mhz.is_empty() {
+        return None;
+    }
+    Some((mhz.iter().sum::<f64>() / mhz.len() as f64).round() as u32)
+}
+
+/// Jiffies from a `cpu` line of /proc/stat
+#[derive(Debug, Clone, Copy)]
+struct CpuTimes {
+    total: u64,
+    /// idle + iowait
+    idle: u64,
+}
+
+/// The `cpu` line of /proc/stat, then one per online logical CPU
+fn read_cpu_times() -> Vec<CpuTimes> {
+    let content = fs::read_to_string("/proc/stat").unwrap_or_default();
+    content
+        .lines()
+        .take_while(|line| line.starts_with("cpu"))
+        .filter_map(|line| {
+            let values: Vec<u64> = line.split_whitespace().skip(1).filter_map(|value| value.parse().ok()).collect();
+            // user nice system idle iowait irq softirq steal, guest time is already in user
+            let total = values.iter().take(8).sum();
+            let idle = values.get(3)? + values.get(4).unwrap_or(&0);
+            Some(CpuTimes { total, idle })
+        })
+        .collect()
+}
+
+/// Share of time all CPUs and each one were busy since the previous call
+/// (since boot on the first, or when CPUs went on/offline)
+fn sample_cpu_load() -> (u8, Vec<u8>) {
+    let current = read_cpu_times();
+    let previous = std::mem::replace(&mut STATE.lock().unwrap().get_or_insert_with(Default::default).cpu, current.clone());
+    let boot = CpuTimes { total: 0, idle: 0 };
+    let busy = |i: usize| {
+        let previous = if previous.len() == current.len() { previous[i] } else { boot };
+        let total = current[i].total.saturating_sub(previous.total);
+        let idle = current[i].idle.saturating_sub(previous.idle);
+        (total.saturating_sub(idle) * 100).checked_div(total).unwrap_or(0) as u8
+    };
+    if current.is_empty() {
+        return (0, vec![]);
+    }
+    (busy(0), (1..current.len()).map(busy).collect())
+}
+
+/// hwmon drivers with per-core (`Core N`) or per-CCD (`TccdN`) temperatures
+const CORE_TEMP_CHIPS: [&str; 3] = ["coretemp", "k10temp", "zenpower"];
+
+/// Voltage input labels that are the CPU core rail (asus-ec-sensors, zenpower, boards
+/// with a sensors config), lowercase
+const VCORE_LABELS: [&str; 4] = ["vcore", "cpu core", "svi2_core", "cpu_core"];
+/// Super I/O chips that wire in0 to Vcore by convention, for unlabeled inputs
+const SUPER_IO_PREFIXES: [&str; 4] = ["nct6", "it8", "f718", "w836"];
+
+/// CPU core voltage in V from hwmon: a Vcore-labeled input, else a Super I/O's in0
+fn read_cpu_voltage() -> Option<f32> {
+    let chips: Vec<(String, PathBuf)> = hwmon_dirs(Path::new("/sys/class/hwmon"))
+        .into_iter()
+        .map(|dir| (fs::read_to_string(dir.join("name")).map(|name| name.trim().to_string()).unwrap_or_default(), dir))
+        .collect();
+    let read_millivolts = |path: PathBuf| -> Option<f32> {
+        let millivolts: f32 = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+        Some(millivolts.round() / 1000.0)
+    };
+
+    let labeled = chips.iter().find_map(|(_, dir)| {
+        fs::read_dir(dir).ok()?.flatten().find_map(|entry| {
+            let name = entry.file_name();
+            let input = name.to_str()?.strip_suffix("_label").filter(|input| input.starts_with("in"))?.to_string();
+            let label = fs::read_to_string(entry.path()).ok()?.trim().to_lowercase();
+            VCORE_LABELS.contains(&label.as_str()).then(|| dir.join(format!("{}_input", input)))
+        })
+    });
+    if let Some(voltage) = labeled.and_then(read_millivolts) {
+        return Some(voltage);
+    }
+    chips
+        .iter()
+        .filter(|(chip, _)| SUPER_IO_PREFIXES.iter().any(|prefix| chip.starts_with(prefix)))
+        .find_map(|(_, dir)| read_millivolts(dir.join("in0_input")))
+}
+
+/// Labeled per-core/CCD temperatures, in chip and sensor order
+fn read_core_temps() -> Vec<(String, u8)> {
+    let mut temps = Vec::new();
+    for dir in hwmon_dirs(Path::new("/sys/class/hwmon")) {
+        let chip = fs::read_to_string(dir.join("name")).unwrap_or_default();
+        if !CORE_TEMP_CHIPS.contains(&chip.trim()) {
+            continue;
+        }
+        let mut sensors: Vec<(u32, String)> = fs::read_dir(&dir)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|entry| {
+                        let n = entry.file_name().to_str()?.strip_prefix("temp")?.strip_suffix("_label")?.parse().ok()?;
+                        Some((n, fs::read_to_string(entry.path()).ok()?.trim().to_string()))
+                    })
+                    .filter(|(_, label)| label.starts_with("Core ") || label.starts_with("Tccd"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        sensors.sort();
+        for (n, label) in sensors {
+            if let Some(temp) = read_millidegrees(&dir.join(format!("temp{}_input", n))) {
+                temps.push((label, temp));
+            }
+        }
+    }
+    temps
+}
+
+// ============================================================================
+// Background sampler
+// One thread reads the sensors every `sample_interval_ms` and keeps the result,
+// so heartbeats, device pulls and the settings share one consistent sample
+// instead of each opening every hwmon file and running nvidia-smi. It stops
+// once nobody has asked for a sample in a while and restarts on the next read.
+// ============================================================================
+
+/// The sampler stops after this long without readers
+const SAMPLER_IDLE: Duration = Duration::from_secs(10);
+/// Longest a reader waits for a sample before settling for the last one
+const SAMPLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct Cache {
+    /// Latest sample and when it was finished
+    info: Option<(Instant, SysInfo)>,
+    readings: Readings,
+    last_read: Option<Instant>,
+    running: bool,
+}
+
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+/// Signalled after every sample
+static SAMPLED: Condvar = Condvar::new();
+
+/// Every sensor with its reading as of the latest sample, for the settings
+#[derive(Debug, Clone, Default)]
+pub struct Readings {
+    pub temps: Vec<TempSensor>,
+    pub fans: Vec<FanSensor>,
+    /// Drives with a hwmon sensor and their temperature
+    pub disk_temps: Vec<(String, u8)>,
+}
+
+/// Sensor lists from the sampler without waiting, empty until its first sample
+pub fn readings() -> Readings {
+    let mut guard = CACHE.lock().unwrap();
+    let cache = guard.get_or_insert_with(Default::default);
+    cache.last_read = Some(Instant::now());
+    if !cache.running {
+        start_sampler(cache);
+    }
+    cache.readings.clone()
+}
+
+/// Keep the sampler running without reading anything, for the history
+pub fn keep_sampling() {
+    let mut guard = CACHE.lock().unwrap();
+    let cache = guard.get_or_insert_with(Default::default);
+    cache.last_read = Some(Instant::now());
+    if !cache.running {
+        start_sampler(cache);
+    }
+}
+
+fn sample_interval() -> Duration {
+    let ms = STATE.lock().unwrap().get_or_insert_with(Default::default).config.sample_interval_ms;
+    Duration::from_millis(ms.max(100))
+}
+
+fn history_span() -> Duration {
+    let minutes = STATE.lock().unwrap().get_or_insert_with(Default::default).config.history_minutes;
+    Duration::from_secs(minutes.max(1) as u64 * 60)
+}
+
+fn start_sampler(cache: &mut Cache) {
+    cache.running = true;
+    std::thread::spawn(|| loop {
+        let temps = temp_sensors();
+        let fans = fan_sensors();
+        let info = SysInfo::sample(&temps, &fans);
+        let readings = Readings { temps, fans, disk_temps: disk_temperatures() };
+        crate::history::record(&info, history_span());
+
+        {
+            let mut guard = CACHE.lock().unwrap();
+            let cache = guard.get_or_insert_with(Default::default);
+            cache.info = Some((Instant::now(), info));
+            cache.readings = readings;
+            SAMPLED.notify_all();
+            if cache.last_read.is_none_or(|at| at.elapsed() > SAMPLER_IDLE) {
+                cache.running = false;
+                return;
+            }
+        }
+        std::thread::sleep(sample_interval());
+    });
+}
@@ -0,0 +1,125 @@
+// ============================================================================
+// Sysinfo export
+// `sysinfo [--output FILE]` prints the sysinfo payload exactly as a heartbeat
+// would send it, using the sensor settings of the last applied config.
+// `--follow` keeps going at the sample interval, one JSON object per line
+// (NDJSON); `--csv` writes flattened columns instead. FILE defaults to stdout.
+// ============================================================================
+
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::PathBuf,
+    time::Duration,
+};
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::data::DeviceCommand;
+use crate::screen_setup::LastApplied;
+use crate::sysinfo::SysInfo;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+pub struct Options {
+    /// None writes to stdout
+    pub path: Option<PathBuf>,
+    pub follow: bool,
+    pub format: Format,
+}
+
+pub fn run(options: &Options) -> i32 {
+    match dump(options) {
+        Ok(()) => 0,
+        // `| head` closing the pipe is how a follow usually ends
+        Err(e) if e.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) => 0,
+        Err(e) => {
+            eprintln!("Failed to dump sysinfo: {:#}", e);
+            1
+        }
+    }
+}
+
+fn dump(options: &Options) -> Result<()> {
+    let config = LastApplied::load().map(|last| last.config).unwrap_or_default();
+    crate::sysinfo::configure(&config.sensors);
+    let mut out: Box<dyn Write> = match &options.path {
+        // Appending, so a follow can be restarted into the same log
+        Some(path) => Box::new(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open {}", path.display()))?,
+        ),
+        None => Box::new(io::stdout().lock()),
+    };
+    let interval = Duration::from_millis(config.sensors.sample_interval_ms.max(100));
+
+    let mut columns: Option<Vec<String>> = None;
+    loop {
+        let payload = payload()?;
+        match options.format {
+            Format::Json if options.follow => writeln!(out, "{}", serde_json::to_string(&payload)?)?,
+            Format::Json => writeln!(out, "{}", serde_json::to_string_pretty(&payload)?)?,
+            Format::Csv => {
+                let mut row = Vec::new();
+                flatten("", &payload, &mut row);
+                // The first sample fixes the columns; fans or a game showing up
+                // later would otherwise shift every value after them
+                let columns = columns.get_or_insert_with(|| {
+                    let header: Vec<String> = row.iter().map(|(key, _)| key.clone()).collect();
+                    let _ = writeln!(out, "{}", header.iter().map(|key| csv_field(key)).collect::<Vec<_>>().join(","));
+                    header
+                });
+                let values: Vec<String> = columns
+                    .iter()
+                    .map(|column| row.iter().find(|(key, _)| key == column).map_or(String::new(), |(_, value)| csv_field(value)))
+                    .collect();
+                writeln!(out, "{}", values.join(","))?;
+            }
+        }
+        out.flush()?;
+        if !options.follow {
+            return Ok(());
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+/// The message body a heartbeat sends
+fn payload() -> Result<Value> {
+    Ok(DeviceCommand::SysInfo(Box::new(SysInfo::snapshot().in_units())).payload()?)
+}
+
+/// `{"cpu":{"load":12},"fans":[{"value":900}]}` → `cpu.load`=12, `fans.0.value`=900
+fn flatten(prefix: &str, value: &Value, out: &mut Vec<(String, String)>) {
+    let key = |name: &str| if prefix.is_empty() { name.to_string() } else { format!("{}.{}", prefix, name) };
+    match value {
+        Value::Object(map) => {
+            for (name, value) in map {
+                flatten(&key(name), value, out);
+            }
+        }
+        Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                flatten(&key(&i.to_string()), value, out);
+            }
+        }
+        Value::Null => out.push((prefix.to_string(), String::new())),
+        Value::String(text) => out.push((prefix.to_string(), text.clone())),
+        other => out.push((prefix.to_string(), other.to_string())),
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
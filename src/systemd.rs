@@ -0,0 +1,152 @@
+// systemd integration for the daemon: sd_notify readiness, status and
+// watchdog messages over $NOTIFY_SOCKET, and `install-service` to write the
+// user unit, which can also be enabled for login from the GUI. Outside a
+// `Type=notify` service the notify calls are no-ops.
+
+use std::{
+    os::unix::net::UnixDatagram,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use anyhow::{Context, Result};
+
+pub const UNIT_NAME: &str = "tryx-panorama.service";
+
+/// Send `state` (`READY=1`, `STATUS=…`, newline separated) to the service manager
+pub fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    let sent = (|| -> std::io::Result<()> {
+        let socket = UnixDatagram::unbound()?;
+        let path = path.to_string_lossy();
+        // `@name` is a socket in the abstract namespace
+        if let Some(name) = path.strip_prefix('@') {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &address)?;
+        } else {
+            socket.send_to(state.as_bytes(), path.as_ref())?;
+        }
+        Ok(())
+    })();
+    if let Err(e) = sent {
+        log::debug!("sd_notify {:?} failed: {}", state, e);
+    }
+}
+
+pub fn status(text: &str) {
+    notify(&format!("STATUS={}", text));
+}
+
+/// How often the service manager expects `WATCHDOG=1`: half of `WatchdogSec=`,
+/// None when the watchdog is off or meant for another process
+fn watchdog_period() -> Option<Duration> {
+    if let Some(pid) = std::env::var("WATCHDOG_PID").ok().and_then(|pid| pid.parse::<u32>().ok())
+        && pid != std::process::id()
+    {
+        return None;
+    }
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+static LAST_PING: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Tell the watchdog we're alive, at most once per period. Called from the
+/// heartbeat, the daemon's waits and every step of a transfer that can outlast
+/// the period (the before hook, hashing, ADB and serial chunks), so a hung
+/// port gets the service restarted but a large video doesn't.
+pub fn watchdog() {
+    let Some(period) = watchdog_period() else {
+        return;
+    };
+    let mut last = LAST_PING.lock().unwrap();
+    if last.is_none_or(|at| at.elapsed() >= period) {
+        *last = Some(Instant::now());
+        notify("WATCHDOG=1");
+    }
+}
+
+/// `install-service`: write the user unit that runs this binary as the
+/// daemon. Returns where it was written.
+pub fn install_service(device: &str) -> Result<PathBuf> {
+    let exe = std::env::current_exe().context("Could not find this executable")?;
+    let mut exec = format!("{} daemon", exe.display());
+    if device != crate::daemon::DEFAULT_DEVICE {
+        exec.push_str(&format!(" --device {}", device));
+    }
+    let unit = format!(
+        "[Unit]\n\
+         Description=Tryx Panorama AIO display\n\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart={}\n\
+         ExecReload=/bin/kill -HUP $MAINPID\n\
+         WatchdogSec=30\n\
+         Restart=on-failure\n\
+         RestartSec=5\n\
+         # Room for the exit action after SIGTERM\n\
+         TimeoutStopSec=15\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        exec
+    );
+    let dir = crate::paths::systemd_user_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(UNIT_NAME);
+    std::fs::write(&path, unit).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// The user unit is set to start at login
+pub fn service_enabled() -> bool {
+    std::process::Command::new("systemctl")
+        .args(["--user", "--quiet", "is-enabled", UNIT_NAME])
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// Write the unit and enable it for the next login, or disable it. Doesn't
+/// start or stop the daemon now.
+pub fn enable_service(enable: bool, device: &str) -> Result<()> {
+    let systemctl = |args: &[&str]| -> Result<()> {
+        let output = std::process::Command::new("systemctl")
+            .arg("--user")
+            .args(args)
+            .output()
+            .context("Failed to run systemctl")?;
+        anyhow::ensure!(
+            output.status.success(),
+            "systemctl --user {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        Ok(())
+    };
+    if enable {
+        install_service(device)?;
+        systemctl(&["daemon-reload"])?;
+        systemctl(&["enable", UNIT_NAME])
+    } else {
+        systemctl(&["disable", UNIT_NAME])
+    }
+}
+
+/// `install-service [--device PATH]` from the command line
+pub fn run_install(device: &str) -> i32 {
+    match install_service(device) {
+        Ok(path) => {
+            println!("Wrote {}", path.display());
+            println!("Enable it with:\n  systemctl --user daemon-reload\n  systemctl --user enable --now {}", UNIT_NAME);
+            0
+        }
+        Err(e) => {
+            eprintln!("Failed to install the service: {:#}", e);
+            1
+        }
+    }
+}
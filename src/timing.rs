@@ -0,0 +1,44 @@
+// Per-stage timing for transfers, so slowness can be pinned on adb, serial or hashing
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct StageTiming {
+    pub name: String,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TransferReport {
+    pub stages: Vec<StageTiming>,
+}
+
+impl TransferReport {
+    pub fn record(&mut self, name: &str, elapsed: Duration) {
+        self.stages.push(StageTiming { name: name.to_string(), elapsed });
+    }
+
+    /// Run `f` and record how long it took, whether or not it succeeded
+    pub fn time<T>(&mut self, name: &str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(name, start.elapsed());
+        result
+    }
+
+    pub fn extend(&mut self, other: TransferReport) {
+        self.stages.extend(other.stages);
+    }
+
+    pub fn total(&self) -> Duration {
+        self.stages.iter().map(|s| s.elapsed).sum()
+    }
+
+    pub fn summary(&self) -> String {
+        let mut out = format!("Transfer timings (total {:.2}s):", self.total().as_secs_f32());
+        for stage in &self.stages {
+            out.push_str(&format!("\n  {:<28} {:>8.1} ms", stage.name, stage.elapsed.as_secs_f64() * 1000.0));
+        }
+        out
+    }
+}
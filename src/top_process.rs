@@ -0,0 +1,112 @@
+// Busiest process: who is using the most CPU, and GPU where the driver reports
+// per-client engine time in fdinfo (amdgpu, i915, nouveau, msm). Usage is
+// taken between two samples, like the CPU load.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    sync::Mutex,
+    time::Instant,
+};
+
+use crate::sysinfo::{ProcessUsage, TopInfo};
+
+/// Per-pid counters of the previous sample
+struct Previous {
+    at: Instant,
+    /// utime + stime in clock ticks
+    cpu: HashMap<u32, u64>,
+    /// Busy nanoseconds of the render/graphics engines
+    gpu: HashMap<u32, u64>,
+}
+
+static PREVIOUS: Mutex<Option<Previous>> = Mutex::new(None);
+
+/// The top CPU and GPU users since the previous call, None on the first
+pub fn sample() -> Option<TopInfo> {
+    let mut cpu = HashMap::new();
+    let mut gpu = HashMap::new();
+    for pid in pids() {
+        if let Some(ticks) = cpu_ticks(pid) {
+            cpu.insert(pid, ticks);
+        }
+        if let Some(ns) = gpu_ns(pid) {
+            gpu.insert(pid, ns);
+        }
+    }
+    let now = Instant::now();
+    let previous = PREVIOUS.lock().unwrap().replace(Previous { at: now, cpu: cpu.clone(), gpu: gpu.clone() })?;
+    let secs = now.duration_since(previous.at).as_secs_f64();
+    if secs <= 0.0 {
+        return None;
+    }
+
+    // Share of the whole machine, like `load`, not of one core as top shows it
+    let tick_rate = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as f64;
+    let cores = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) }.max(1) as f64;
+    let busiest = |current: &HashMap<u32, u64>, before: &HashMap<u32, u64>, per_sec: f64| {
+        current
+            .iter()
+            .filter_map(|(pid, value)| Some((*pid, value.checked_sub(*before.get(pid)?)?)))
+            .max_by_key(|(_, delta)| *delta)
+            .filter(|(_, delta)| *delta > 0)
+            .map(|(pid, delta)| ProcessUsage {
+                pid,
+                name: crate::serial::process_name(pid),
+                usage: (delta as f64 / secs / per_sec * 100.0).round().min(100.0) as u8,
+            })
+    };
+    Some(TopInfo {
+        cpu: busiest(&cpu, &previous.cpu, tick_rate * cores),
+        gpu: busiest(&gpu, &previous.gpu, 1e9),
+    })
+}
+
+pub fn pids() -> Vec<u32> {
+    fs::read_dir("/proc")
+        .map(|entries| entries.flatten().filter_map(|entry| entry.file_name().to_str()?.parse().ok()).collect())
+        .unwrap_or_default()
+}
+
+fn cpu_ticks(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The name in parentheses may contain spaces; fields after it start at `state`
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// Engine time of the process's DRM clients, each client counted once however
+/// many fds share it. None for processes without a GPU fd we may read.
+fn gpu_ns(pid: u32) -> Option<u64> {
+    let mut clients = HashSet::new();
+    let mut total = 0;
+    let mut found = false;
+    for entry in fs::read_dir(format!("/proc/{}/fd", pid)).ok()?.flatten() {
+        let Ok(target) = fs::read_link(entry.path()) else {
+            continue;
+        };
+        if !target.starts_with("/dev/dri") {
+            continue;
+        }
+        let Ok(info) = fs::read_to_string(format!("/proc/{}/fdinfo/{}", pid, entry.file_name().to_string_lossy())) else {
+            continue;
+        };
+        let field = |key: &str| info.lines().find_map(|line| line.strip_prefix(key)?.split_whitespace().next()?.parse::<u64>().ok());
+        let Some(client) = field("drm-client-id:") else {
+            continue;
+        };
+        if !clients.insert(client) {
+            continue;
+        }
+        // amdgpu `gfx`, i915 `render`, nouveau/msm `gpu`
+        for key in ["drm-engine-gfx:", "drm-engine-render:", "drm-engine-gpu:"] {
+            if let Some(ns) = field(key) {
+                total += ns;
+                found = true;
+            }
+        }
+    }
+    found.then_some(total)
+}
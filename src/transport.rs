@@ -0,0 +1,402 @@
+// ============================================================================
+// Transports
+// What the protocol code reads frames from and writes them to: the serial port
+// normally, a TCP socket for a network bridge, or in tests an in-memory mock
+// ============================================================================
+
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+use anyhow::{Context, Result};
+
+use crate::serial::{open_exclusive, SerialSettings};
+
+#[cfg(test)]
+pub use mock::MockTransport;
+
+pub trait Transport: Read + Write + Send {
+    /// Open `device`: a tty path for serial, `host:port` for TCP
+    fn open(device: &str, settings: &SerialSettings) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Second handle on the same connection, for the writer thread
+    fn try_clone(&self) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Whether `device` looks reachable, polled while waiting to reconnect
+    fn is_present(_device: &str) -> bool
+    where
+        Self: Sized,
+    {
+        true
+    }
+
+    fn name(&self) -> String;
+
+    fn read_timeout(&self) -> Duration;
+
+    /// Reads that get nothing within `timeout` fail with `ErrorKind::TimedOut`
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+    /// Throw away anything buffered, e.g. stale bytes from a previous session
+    fn discard_buffers(&mut self) -> io::Result<()>;
+}
+
+// ----------------------------------------------------------------------------
+// Serial
+// ----------------------------------------------------------------------------
+
+pub struct SerialTransport {
+    port: Box<dyn serialport::SerialPort>,
+}
+
+impl Transport for SerialTransport {
+    fn open(device: &str, settings: &SerialSettings) -> Result<Self> {
+        Ok(Self { port: open_exclusive(device, settings)? })
+    }
+
+    fn try_clone(&self) -> Result<Self> {
+        let port = self.port.try_clone().context("Failed to clone serial port")?;
+        Ok(Self { port })
+    }
+
+    /// A tty node disappears when the cooler drops off the bus
+    fn is_present(device: &str) -> bool {
+        std::path::Path::new(device).exists()
+    }
+
+    fn name(&self) -> String {
+        self.port.name().unwrap_or_default()
+    }
+
+    fn read_timeout(&self) -> Duration {
+        self.port.timeout()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        Ok(self.port.set_timeout(timeout)?)
+    }
+
+    fn discard_buffers(&mut self) -> io::Result<()> {
+        Ok(self.port.clear(serialport::ClearBuffer::All)?)
+    }
+}
+
+impl Read for SerialTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.port.read(buf)
+    }
+}
+
+impl Write for SerialTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.port.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.port.flush()
+    }
+}
+
+// ----------------------------------------------------------------------------
+// TCP, for a bridge that forwards the tty over the network (e.g. ser2net in raw mode)
+// ----------------------------------------------------------------------------
+
+/// Nothing opens one outside the tests yet, it's there for a network bridge
+#[allow(dead_code)]
+pub struct TcpTransport {
+    stream: TcpStream,
+    address: String,
+}
+
+impl Transport for TcpTransport {
+    fn open(device: &str, settings: &SerialSettings) -> Result<Self> {
+        let address = device.strip_prefix("tcp://").unwrap_or(device).to_string();
+        let timeout = Duration::from_millis(settings.timeout_ms.max(1));
+        let target = address
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve {}", address))?
+            .next()
+            .with_context(|| format!("{} resolved to no address", address))?;
+
+        let stream = TcpStream::connect_timeout(&target, timeout)
+            .with_context(|| format!("Failed to connect to {}", address))?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(timeout))?;
+        Ok(Self { stream, address })
+    }
+
+    fn try_clone(&self) -> Result<Self> {
+        let stream = self.stream.try_clone().context("Failed to clone TCP stream")?;
+        Ok(Self { stream, address: self.address.clone() })
+    }
+
+    fn name(&self) -> String {
+        self.address.clone()
+    }
+
+    fn read_timeout(&self) -> Duration {
+        self.stream.read_timeout().ok().flatten().unwrap_or(Duration::ZERO)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        // Zero would mean "block forever" to the socket
+        self.stream.set_read_timeout(Some(timeout.max(Duration::from_millis(1))))
+    }
+
+    fn discard_buffers(&mut self) -> io::Result<()> {
+        self.stream.set_nonblocking(true)?;
+        let mut scratch = [0u8; 1024];
+        let drained = loop {
+            match self.stream.read(&mut scratch) {
+                Ok(0) => break Ok(()),
+                Ok(_) => continue,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        drained
+    }
+}
+
+impl Read for TcpTransport {
+    /// Socket timeouts come back as WouldBlock and a closed peer as Ok(0),
+    /// map them to what the serial path reports
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.stream.read(buf) {
+            Ok(0) if !buf.is_empty() => Err(io::Error::new(io::ErrorKind::NotConnected, "connection closed by peer")),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(io::Error::new(io::ErrorKind::TimedOut, e)),
+            other => other,
+        }
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Mock: bytes in and out live in memory. `register` a device name first, then
+// anything that opens that name shares the same buffers.
+// ----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod mock {
+    use std::{
+        collections::{HashMap, VecDeque},
+        io::{self, Read, Write},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+    use anyhow::{Context, Result};
+
+    use super::Transport;
+    use crate::serial::SerialSettings;
+
+    #[derive(Clone)]
+    pub struct MockTransport {
+        name: String,
+        /// Bytes waiting to be read by the protocol code
+        inbound: Arc<Mutex<VecDeque<u8>>>,
+        /// Everything the protocol code wrote
+        outbound: Arc<Mutex<Vec<u8>>>,
+        timeout: Duration,
+    }
+
+    static MOCKS: Mutex<Option<HashMap<String, MockTransport>>> = Mutex::new(None);
+
+    impl MockTransport {
+        pub fn register(device: &str) -> Self {
+            let mock = Self {
+                name: device.to_string(),
+                inbound: Default::default(),
+                outbound: Default::default(),
+                timeout: Duration::from_millis(100),
+            };
+            MOCKS.lock().unwrap().get_or_insert_with(HashMap::new).insert(device.to_string(), mock.clone());
+            mock
+        }
+
+        /// Queue bytes as if the device had sent them
+        pub fn feed(&self, bytes: &[u8]) {
+            self.inbound.lock().unwrap().extend(bytes);
+        }
+
+        /// Bytes written since the last call
+        pub fn take_written(&self) -> Vec<u8> {
+            std::mem::take(&mut *self.outbound.lock().unwrap())
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn open(device: &str, _settings: &SerialSettings) -> Result<Self> {
+            MOCKS
+                .lock()
+                .unwrap()
+                .as_ref()
+                .and_then(|mocks| mocks.get(device).cloned())
+                .with_context(|| format!("No mock transport registered for {}", device))
+        }
+
+        fn try_clone(&self) -> Result<Self> {
+            Ok(self.clone())
+        }
+
+        fn is_present(device: &str) -> bool {
+            MOCKS.lock().unwrap().as_ref().is_some_and(|mocks| mocks.contains_key(device))
+        }
+
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+
+        fn read_timeout(&self) -> Duration {
+            self.timeout
+        }
+
+        fn set_read_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+            self.timeout = timeout;
+            Ok(())
+        }
+
+        fn discard_buffers(&mut self) -> io::Result<()> {
+            self.inbound.lock().unwrap().clear();
+            Ok(())
+        }
+    }
+
+    impl Read for MockTransport {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut inbound = self.inbound.lock().unwrap();
+            if inbound.is_empty() {
+                drop(inbound);
+                // Behave like a quiet port: wait out the timeout, briefly
+                std::thread::sleep(self.timeout.min(Duration::from_millis(10)));
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "no data"));
+            }
+            let n = buf.len().min(inbound.len());
+            for (slot, byte) in buf.iter_mut().zip(inbound.drain(..n)) {
+                *slot = byte;
+            }
+            Ok(n)
+        }
+    }
+
+    impl Write for MockTransport {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.outbound.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{ErrorKind, Read, Write},
+        net::TcpListener,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{MockTransport, TcpTransport};
+    use crate::data::{build_reply, DeviceCommand, FrameAssembler, MediaDeletePayload, ResponseMessage};
+    use crate::screen_setup::AioCoolerController;
+    use crate::serial::SerialSettings;
+
+    /// Answer every request `take` hands over with `body` through `feed` until
+    /// one has been answered and a second passes quietly, returning what was received
+    fn answer(
+        mut take: impl FnMut() -> Vec<u8>,
+        mut feed: impl FnMut(&[u8]),
+        body: &serde_json::Value,
+    ) -> Vec<ResponseMessage> {
+        let mut assembler = FrameAssembler::new();
+        let mut received = Vec::new();
+        let mut quiet_since = Instant::now();
+        while quiet_since.elapsed() < Duration::from_secs(1) || received.is_empty() {
+            assembler.push(&take());
+            while let Some(frame) = assembler.next_frame() {
+                let request = ResponseMessage::parse(&frame.unwrap().message).unwrap();
+                feed(&build_reply("POST", &request, body).unwrap().bytes);
+                received.push(request);
+                quiet_since = Instant::now();
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        received
+    }
+
+    fn fake_device(mock: MockTransport, body: serde_json::Value) -> thread::JoinHandle<Vec<ResponseMessage>> {
+        thread::spawn(move || answer(|| mock.take_written(), |bytes| mock.feed(bytes), &body))
+    }
+
+    fn delete_command() -> DeviceCommand {
+        DeviceCommand::MediaDelete(MediaDeletePayload { exclude: vec!["keep.mp4".to_string()] })
+    }
+
+    #[test]
+    fn send_once_gets_status() {
+        let mock = MockTransport::register("mock-status-ok");
+        let device = fake_device(mock, serde_json::json!({ "code": 0 }));
+        let controller = AioCoolerController::<MockTransport>::with_transport("mock-status-ok");
+        controller.send_once(&delete_command(), &SerialSettings::default()).unwrap();
+
+        let received = device.join().unwrap();
+        assert_eq!(received.len(), 1, "sent more than once");
+        assert_eq!(received[0].cmd_type, "mediaDelete");
+        assert_eq!(received[0].json().unwrap()["exclude"][0], "keep.mp4");
+    }
+
+    #[test]
+    fn send_once_reports_rejection() {
+        let mock = MockTransport::register("mock-status-nack");
+        let device = fake_device(mock, serde_json::json!({ "code": 3, "msg": "storage full" }));
+        let controller = AioCoolerController::<MockTransport>::with_transport("mock-status-nack");
+        let error = controller.send_once(&delete_command(), &SerialSettings::default()).unwrap_err();
+        assert!(format!("{:#}", error).contains("storage full"), "{:#}", error);
+        device.join().unwrap();
+    }
+
+    #[test]
+    fn send_once_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let device = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            stream.set_read_timeout(Some(Duration::from_millis(5))).unwrap();
+            let mut reader = stream.try_clone().unwrap();
+            let mut writer = stream;
+            let take = move || {
+                let mut buffer = [0u8; 4096];
+                match reader.read(&mut buffer) {
+                    Ok(n) => buffer[..n].to_vec(),
+                    Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Vec::new(),
+                    Err(e) => panic!("{}", e),
+                }
+            };
+            answer(take, |bytes| writer.write_all(bytes).unwrap(), &serde_json::json!({ "code": 0 }))
+        });
+        let controller = AioCoolerController::<TcpTransport>::with_transport(&format!("tcp://{}", address));
+        controller.send_once(&delete_command(), &SerialSettings::default()).unwrap();
+
+        let received = device.join().unwrap();
+        assert_eq!(received.len(), 1, "sent more than once");
+        assert_eq!(received[0].cmd_type, "mediaDelete");
+    }
+}
@@ -0,0 +1,152 @@
+// Unit preferences: the sampler works in °C, MiB and KiB/s; these convert a
+// sample just before it's sent or shown, so thresholds, smoothing and alerts
+// keep working in the units they were written for
+
+use serde::{Deserialize, Serialize};
+
+use crate::sysinfo::SysInfo;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Units {
+    pub temperature: Temperature,
+    pub memory: Memory,
+    /// Network upload and download
+    pub rate: Rate,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Temperature {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl Temperature {
+    pub const ALL: [Temperature; 2] = [Temperature::Celsius, Temperature::Fahrenheit];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Temperature::Celsius => "°C",
+            Temperature::Fahrenheit => "°F",
+        }
+    }
+
+    /// From °C, capped to what the payload's byte holds (255°F is about 124°C).
+    /// 0 stays 0, it means the sensor is missing.
+    pub fn convert(self, celsius: u8) -> u8 {
+        match self {
+            Temperature::Celsius => celsius,
+            Temperature::Fahrenheit if celsius == 0 => 0,
+            Temperature::Fahrenheit => (celsius as f64 * 9.0 / 5.0 + 32.0).round().min(u8::MAX as f64) as u8,
+        }
+    }
+
+    /// `47°F`, for the settings
+    pub fn show(self, celsius: u8) -> String {
+        format!("{}{}", self.convert(celsius), self.label())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Memory {
+    /// 1024², what the payload has always carried
+    #[default]
+    Mebibytes,
+    Megabytes,
+    Gibibytes,
+    Gigabytes,
+}
+
+impl Memory {
+    pub const ALL: [Memory; 4] = [Memory::Mebibytes, Memory::Megabytes, Memory::Gibibytes, Memory::Gigabytes];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Memory::Mebibytes => "MiB",
+            Memory::Megabytes => "MB",
+            Memory::Gibibytes => "GiB",
+            Memory::Gigabytes => "GB",
+        }
+    }
+
+    fn bytes(self) -> f64 {
+        match self {
+            Memory::Mebibytes => 1024.0 * 1024.0,
+            Memory::Megabytes => 1e6,
+            Memory::Gibibytes => 1024.0 * 1024.0 * 1024.0,
+            Memory::Gigabytes => 1e9,
+        }
+    }
+
+    /// From MiB, rounded to a whole unit
+    pub fn convert(self, mebibytes: u64) -> u64 {
+        (mebibytes as f64 * Memory::Mebibytes.bytes() / self.bytes()).round() as u64
+    }
+}
+
+/// Per second
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rate {
+    /// 1024 bytes, what the payload has always carried
+    #[default]
+    Kibibytes,
+    Kilobytes,
+    Megabytes,
+    Kilobits,
+    Megabits,
+}
+
+impl Rate {
+    pub const ALL: [Rate; 5] = [Rate::Kibibytes, Rate::Kilobytes, Rate::Megabytes, Rate::Kilobits, Rate::Megabits];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Rate::Kibibytes => "KiB/s",
+            Rate::Kilobytes => "KB/s",
+            Rate::Megabytes => "MB/s",
+            Rate::Kilobits => "kbit/s",
+            Rate::Megabits => "Mbit/s",
+        }
+    }
+
+    fn bytes(self) -> f64 {
+        match self {
+            Rate::Kibibytes => 1024.0,
+            Rate::Kilobytes => 1e3,
+            Rate::Megabytes => 1e6,
+            Rate::Kilobits => 1e3 / 8.0,
+            Rate::Megabits => 1e6 / 8.0,
+        }
+    }
+
+    /// From KiB/s, rounded to a whole unit
+    pub fn convert(self, kibibytes: u64) -> u64 {
+        (kibibytes as f64 * Rate::Kibibytes.bytes() / self.bytes()).round() as u64
+    }
+}
+
+impl Units {
+    /// `info` in these units
+    pub fn apply(&self, info: &SysInfo) -> SysInfo {
+        let mut info = info.clone();
+        let temp = |celsius: &mut u8| *celsius = self.temperature.convert(*celsius);
+        temp(&mut info.cpu.temperature);
+        temp(&mut info.gpu.temperature);
+        temp(&mut info.memory.temperature);
+        temp(&mut info.disk.temperature);
+        temp(&mut info.motherboard.temperature);
+        temp(&mut info.motherboard.pch_temperature);
+        for (_, celsius) in &mut info.cpu.core_temperatures {
+            temp(celsius);
+        }
+        if let Some(liquid) = &mut info.liquid {
+            temp(&mut liquid.temperature);
+        }
+        info.memory.total = self.memory.convert(info.memory.total);
+        info.memory.used = self.memory.convert(info.memory.used);
+        info.network.upload = self.rate.convert(info.network.upload);
+        info.network.download = self.rate.convert(info.network.download);
+        info
+    }
+}
@@ -0,0 +1,58 @@
+// Field-by-field comparison of the config we sent with what the device reports back,
+// to catch firmware silently ignoring values it doesn't support
+
+use serde_json::Value;
+
+#[derive(Debug, Clone)]
+pub struct ConfigMismatch {
+    /// Dotted path of the field, e.g. `settings.filter.opacity`
+    pub field: String,
+    pub sent: Value,
+    pub actual: Option<Value>,
+}
+
+impl std::fmt::Display for ConfigMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.actual {
+            Some(actual) => write!(f, "{}: sent {}, device has {}", self.field, self.sent, actual),
+            None => write!(f, "{}: sent {}, missing on device", self.field, self.sent),
+        }
+    }
+}
+
+/// Every field in `sent` that differs in `actual`. Extra fields the device
+/// reports on its own are ignored.
+pub fn compare(sent: &Value, actual: &Value) -> Vec<ConfigMismatch> {
+    let mut mismatches = Vec::new();
+    compare_at("", sent, Some(actual), &mut mismatches);
+    mismatches
+}
+
+fn compare_at(path: &str, sent: &Value, actual: Option<&Value>, out: &mut Vec<ConfigMismatch>) {
+    match (sent, actual) {
+        (Value::Object(sent_map), Some(Value::Object(actual_map))) => {
+            for (key, sent_value) in sent_map {
+                let child = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                compare_at(&child, sent_value, actual_map.get(key), out);
+            }
+        }
+        (sent, Some(actual)) if values_match(sent, actual) => {}
+        (sent, actual) => out.push(ConfigMismatch {
+            field: if path.is_empty() { "<root>".to_string() } else { path.to_string() },
+            sent: sent.clone(),
+            actual: actual.cloned(),
+        }),
+    }
+}
+
+/// Numbers can come back as strings or floats depending on the firmware's JSON library
+fn values_match(sent: &Value, actual: &Value) -> bool {
+    if sent == actual {
+        return true;
+    }
+    match (sent.as_f64(), actual.as_f64(), actual.as_str()) {
+        (Some(a), Some(b), _) => a == b,
+        (Some(a), None, Some(s)) => s.trim().parse::<f64>().is_ok_and(|b| a == b),
+        _ => false,
+    }
+}
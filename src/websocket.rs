@@ -0,0 +1,383 @@
+// ============================================================================
+// WebSocket status stream
+// The daemon serves what it's doing to browsers: a web dashboard or an OBS
+// browser source connects to ws://<address>/ and gets one JSON text message
+// per `broadcast::Status`, starting with the current connection state and the
+// last sysinfo. Read only, anything the client sends other than ping and
+// close is ignored. RFC 6455 over plain TCP, no extensions or TLS, and a few
+// clients at a time. Browsers let any page open a WebSocket anywhere, so only
+// local pages and the origins listed in the settings get the upgrade.
+// Commands go through the IPC socket, the command line or MQTT.
+// ============================================================================
+
+use std::{
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+use anyhow::{Context, Result};
+use crossbeam::channel::Receiver;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::broadcast::Broadcast;
+
+/// Appended to the client's key before hashing, from the RFC
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// For the client to finish its upgrade request
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a read waits, and so the most an update waits to go out
+const POLL: Duration = Duration::from_millis(100);
+/// Clients only send control frames, more than this unread is a misbehaving one
+const MAX_CLIENT_BUFFER: usize = 64 * 1024;
+/// Each client has a thread, a page reconnecting in a loop mustn't pile them up
+const MAX_CLIENTS: usize = 16;
+
+const TEXT: u8 = 0x1;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebSocketConfig {
+    pub enabled: bool,
+    /// `host:port` to listen on, loopback unless other machines should see it
+    pub address: String,
+    /// Origins of dashboards hosted elsewhere, e.g. `https://dash.example.com`.
+    /// localhost pages, local files and non-browser clients are always let in.
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        Self { enabled: false, address: "127.0.0.1:8765".to_string(), allowed_origins: Vec::new() }
+    }
+}
+
+/// Listen on a thread while config.toml enables it, moving when the address changes
+pub fn spawn(broadcast: Broadcast) {
+    thread::spawn(move || {
+        let path = crate::settings::Settings::path();
+        let changes = crate::file_watch::watch(&path).ok();
+        loop {
+            let config = crate::settings::Settings::load().websocket;
+            if !config.enabled {
+                wait_for_change(changes.as_ref());
+                continue;
+            }
+            let listener = match TcpListener::bind(&config.address).and_then(|listener| {
+                listener.set_nonblocking(true)?;
+                Ok(listener)
+            }) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    log::warn!("WebSocket: can't listen on {}: {}", config.address, e);
+                    wait_for_change(changes.as_ref());
+                    continue;
+                }
+            };
+            log::info!("WebSocket status stream on ws://{}/", config.address);
+            accept(&listener, &broadcast, changes.as_ref(), &config);
+        }
+    });
+}
+
+fn wait_for_change(changes: Option<&Receiver<()>>) {
+    match changes {
+        Some(changes) => {
+            let _ = changes.recv();
+        }
+        // Without inotify the settings are only read once
+        None => loop {
+            thread::park();
+        },
+    }
+}
+
+/// Counts a client while its thread runs
+struct Client(Arc<AtomicUsize>);
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Serve clients until the WebSocket settings change
+fn accept(listener: &TcpListener, broadcast: &Broadcast, changes: Option<&Receiver<()>>, config: &WebSocketConfig) {
+    let clients = Arc::new(AtomicUsize::new(0));
+    loop {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                if clients.fetch_add(1, Ordering::Relaxed) >= MAX_CLIENTS {
+                    clients.fetch_sub(1, Ordering::Relaxed);
+                    log::warn!("WebSocket: refusing {}, already {} clients", peer, MAX_CLIENTS);
+                    let _ = refuse(&stream, "503 Service Unavailable", "Too many clients\n");
+                    continue;
+                }
+                let client = Client(clients.clone());
+                let broadcast = broadcast.clone();
+                let allowed_origins = config.allowed_origins.clone();
+                thread::spawn(move || {
+                    let _client = client;
+                    log::debug!("WebSocket client {}", peer);
+                    if let Err(e) = serve(stream, &broadcast, &allowed_origins) {
+                        log::debug!("WebSocket client {}: {:#}", peer, e);
+                    }
+                });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                let changed = match changes {
+                    Some(changes) => changes.recv_timeout(POLL * 2).is_ok(),
+                    None => {
+                        thread::sleep(POLL * 2);
+                        false
+                    }
+                };
+                if changed && crate::settings::Settings::load().websocket != *config {
+                    log::info!("WebSocket settings changed");
+                    return;
+                }
+            }
+            Err(e) => log::warn!("WebSocket accept failed: {}", e),
+        }
+    }
+}
+
+fn serve(stream: TcpStream, broadcast: &Broadcast, allowed_origins: &[String]) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut key = None;
+    let mut upgrade = false;
+    let mut connection_upgrade = false;
+    let mut origin = None;
+    loop {
+        let mut line = String::new();
+        anyhow::ensure!(reader.read_line(&mut line)? > 0, "Connection closed during the handshake");
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "sec-websocket-key" => key = Some(value.trim().to_string()),
+                "upgrade" => upgrade = value.trim().eq_ignore_ascii_case("websocket"),
+                // A token list, browsers send `keep-alive, Upgrade`
+                "connection" => connection_upgrade = value.split(',').any(|token| token.trim().eq_ignore_ascii_case("upgrade")),
+                "origin" => origin = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+    let Some(key) = key.filter(|_| upgrade && connection_upgrade && request.starts_with("GET ")) else {
+        return refuse(&stream, "426 Upgrade Required", "This address streams the cooler's status over a WebSocket\n");
+    };
+    if let Some(origin) = origin.filter(|origin| !origin_allowed(origin, allowed_origins)) {
+        log::warn!("WebSocket: refusing a page from {}, add it to the allowed origins to let it in", origin);
+        return refuse(&stream, "403 Forbidden", "Pages from this origin may not connect\n");
+    }
+    let accept = crate::adb_auth::base64_encode(&Sha1::digest(format!("{}{}", key, GUID).as_bytes()));
+    write!(
+        &stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept
+    )?;
+    // Anything the client sent right after its request
+    let mut buffer = reader.buffer().to_vec();
+    stream.set_read_timeout(Some(POLL))?;
+
+    let updates = broadcast.subscribe();
+    let mut chunk = [0u8; 4096];
+    loop {
+        for status in updates.try_iter() {
+            write_frame(&stream, TEXT, serde_json::to_string(&status)?.as_bytes())?;
+        }
+        match (&stream).read(&mut chunk) {
+            Ok(0) => return Ok(()),
+            Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => {}
+            Err(e) => return Err(e).context("Read failed"),
+        }
+        while let Some((opcode, payload)) = take_frame(&mut buffer) {
+            match opcode {
+                CLOSE => {
+                    // Echo the status code, then we're done
+                    let _ = write_frame(&stream, CLOSE, &payload[..payload.len().min(2)]);
+                    return Ok(());
+                }
+                PING => write_frame(&stream, PONG, &payload)?,
+                _ => {}
+            }
+        }
+        anyhow::ensure!(buffer.len() <= MAX_CLIENT_BUFFER, "Client sent too much");
+    }
+}
+
+/// Whether a page from `origin` may connect: local files (`null` or
+/// `file://`), http(s) pages on this machine, and anything in `allowed`
+fn origin_allowed(origin: &str, allowed: &[String]) -> bool {
+    if origin == "null" || origin.starts_with("file://") {
+        return true;
+    }
+    let host = origin.strip_prefix("http://").or_else(|| origin.strip_prefix("https://"));
+    // Drop the port, leaving `[::1]` whole
+    let host = host.map(|host| match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    });
+    if host.is_some_and(|host| ["localhost", "127.0.0.1", "[::1]"].contains(&host.to_ascii_lowercase().as_str())) {
+        return true;
+    }
+    allowed.iter().any(|allowed| allowed.trim().trim_end_matches('/').eq_ignore_ascii_case(origin))
+}
+
+/// Plain HTTP answer for a request we won't upgrade
+fn refuse(mut stream: &TcpStream, status: &str, body: &str) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nUpgrade: websocket\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+/// A final, unmasked frame, as servers send them
+fn write_frame(mut stream: &TcpStream, opcode: u8, payload: &[u8]) -> Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        length @ 0..=125 => frame.push(length as u8),
+        length @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend((length as u16).to_be_bytes());
+        }
+        length => {
+            frame.push(127);
+            frame.extend((length as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame).context("Client went away")
+}
+
+/// Split the first whole frame off `buffer`: opcode and unmasked payload
+fn take_frame(buffer: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+    let opcode = buffer.first()? & 0x0F;
+    let second = *buffer.get(1)?;
+    let (length, mut offset) = match second & 0x7F {
+        126 => (u16::from_be_bytes(buffer.get(2..4)?.try_into().ok()?) as usize, 4),
+        127 => (u64::from_be_bytes(buffer.get(2..10)?.try_into().ok()?) as usize, 10),
+        length => (length as usize, 2),
+    };
+    let mask: Option<[u8; 4]> = if second & 0x80 != 0 {
+        let mask = buffer.get(offset..offset + 4)?.try_into().ok()?;
+        offset += 4;
+        Some(mask)
+    } else {
+        None
+    };
+    let mut payload = buffer.get(offset..offset.checked_add(length)?)?.to_vec();
+    if let Some(mask) = mask {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+    buffer.drain(..offset + length);
+    Some((opcode, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broadcast::Status;
+
+    /// Serve one connection on a loopback port, returning the client end
+    fn connect(broadcast: &Broadcast, request: &str) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let broadcast = broadcast.clone();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let _ = serve(stream, &broadcast, &["https://dash.example.com".to_string()]);
+        });
+        let mut client = TcpStream::connect(address).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+        client
+    }
+
+    /// Up to the blank line, byte by byte so a frame right behind it stays unread
+    fn response_head(mut client: &TcpStream) -> String {
+        let mut head = Vec::new();
+        let mut byte = [0u8];
+        while !head.ends_with(b"\r\n\r\n") && client.read(&mut byte).unwrap() > 0 {
+            head.push(byte[0]);
+        }
+        String::from_utf8(head).unwrap()
+    }
+
+    #[test]
+    fn upgrades_and_streams() {
+        let broadcast = Broadcast::default();
+        // A new client starts with the connection state
+        broadcast.send(Status::Connection { state: "Streaming".to_string(), error: None });
+        // The sample handshake from RFC 6455
+        let client = connect(
+            &broadcast,
+            "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: keep-alive, Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        );
+        let head = response_head(&client);
+        assert!(head.starts_with("HTTP/1.1 101 "), "{}", head);
+        assert!(head.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"), "{}", head);
+
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 256];
+        let frame = loop {
+            if let Some(frame) = take_frame(&mut buffer) {
+                break frame;
+            }
+            let read = (&client).read(&mut chunk).unwrap();
+            assert!(read > 0, "closed before the update");
+            buffer.extend_from_slice(&chunk[..read]);
+        };
+        assert_eq!(frame, (TEXT, br#"{"type":"connection","state":"Streaming","error":null}"#.to_vec()));
+    }
+
+    #[test]
+    fn refuses_without_connection_upgrade() {
+        let client = connect(
+            &Broadcast::default(),
+            "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        );
+        assert!(response_head(&client).starts_with("HTTP/1.1 426 "));
+    }
+
+    #[test]
+    fn refuses_foreign_origins() {
+        let request = |origin: &str| {
+            format!(
+                "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                 Origin: {}\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+                origin
+            )
+        };
+        let status = |origin: &str| response_head(&connect(&Broadcast::default(), &request(origin)))[9..12].to_string();
+        assert_eq!(status("https://evil.example"), "403");
+        assert_eq!(status("http://localhost.evil.example"), "403");
+        assert_eq!(status("http://localhost:3000"), "101");
+        assert_eq!(status("http://[::1]:8080"), "101");
+        assert_eq!(status("null"), "101");
+        assert_eq!(status("https://dash.example.com"), "101");
+    }
+}